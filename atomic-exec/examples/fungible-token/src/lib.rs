@@ -336,6 +336,9 @@ impl Actor {
                 })?,
                 value: TokenAmount::default(),
                 nonce: 0,
+                memo: Vec::new(),
+                valid_until_epoch: None,
+                correlation_id: None,
             },
             wrapped: true,
         };
@@ -426,6 +429,9 @@ impl Actor {
                 })?,
                 value: TokenAmount::default(),
                 nonce: 0,
+                memo: Vec::new(),
+                valid_until_epoch: None,
+                correlation_id: None,
             },
             wrapped: true,
         };
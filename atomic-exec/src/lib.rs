@@ -108,6 +108,9 @@ impl Actor {
                             params: exec_id.clone(),
                             value: TokenAmount::default(),
                             nonce: 0,
+                            memo: Vec::new(),
+                            valid_until_epoch: None,
+                            correlation_id: None,
                         },
                         wrapped: true,
                     });
@@ -191,6 +194,9 @@ impl Actor {
                         params: exec_id.clone(),
                         value: TokenAmount::default(),
                         nonce: 0,
+                        memo: Vec::new(),
+                        valid_until_epoch: None,
+                        correlation_id: None,
                     },
                     wrapped: true,
                 }))
@@ -170,6 +170,9 @@ fn wrap_cross_msg(
             params,
             value: TokenAmount::default(),
             nonce: 0,
+            memo: Vec::new(),
+            valid_until_epoch: None,
+            correlation_id: None,
         },
         wrapped: true,
     }
@@ -0,0 +1,113 @@
+//! Value accounting used to check that the gateway conserves value.
+//!
+//! It is only built with the `value-accounting` feature. The value held by the
+//! gateway is derived from its state, so there is nothing to keep in sync with
+//! the flows of the actor, and `assert_value_conserved` checks it against the
+//! actual balance of the actor after every operation in tests.
+
+use anyhow::anyhow;
+use fil_actors_runtime::runtime::Runtime;
+use fvm_shared::econ::TokenAmount;
+
+use crate::{State, MAX_NONCE};
+
+/// Value held by the gateway, as accounted by its state.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Ledger {
+    /// Stake and circulating supply of the registered subnets.
+    pub subnets: TokenAmount,
+    /// Fees of top-down messages and child checkpoints not distributed yet.
+    pub pending_fees: TokenAmount,
+    /// Fees held for the top-down messages not applied yet.
+    pub topdown_fees: TokenAmount,
+    /// Fees of bottom-up messages kept in the checkpoints of the gateway.
+    pub checkpoint_fees: TokenAmount,
+    /// Value of the bottom-up messages committed by child subnets that haven't
+    /// been applied yet.
+    pub pending_bottomup: TokenAmount,
+    /// Value of the cross-messages waiting in the postbox.
+    pub postbox: TokenAmount,
+    /// Value of the cross-messages in the dead-letter queue.
+    pub dead_letters: TokenAmount,
+}
+
+impl Ledger {
+    /// Returns the total value held by the gateway.
+    pub fn held(&self) -> TokenAmount {
+        &self.subnets
+            + &self.pending_fees
+            + &self.topdown_fees
+            + &self.checkpoint_fees
+            + &self.pending_bottomup
+            + &self.postbox
+            + &self.dead_letters
+    }
+}
+
+/// Derives the ledger of the gateway from its current state.
+pub fn ledger(rt: &impl Runtime) -> anyhow::Result<Ledger> {
+    let st: State = rt
+        .state()
+        .map_err(|e| anyhow!("failed to load gateway state: {}", e))?;
+    let store = rt.store();
+    let mut ledger = Ledger {
+        pending_fees: st.pending_fee_distribution.clone(),
+        ..Default::default()
+    };
+
+    st.subnets.load(store)?.for_each(|_, sub| {
+        ledger.subnets += &sub.stake;
+        ledger.subnets += &sub.circ_supply;
+        Ok(())
+    })?;
+    st.topdown_fees.load(store)?.for_each(|_, fee| {
+        ledger.topdown_fees += fee;
+        Ok(())
+    })?;
+    st.checkpoints.load(store)?.for_each(|_, ch| {
+        if let Some(meta) = ch.cross_msgs() {
+            ledger.checkpoint_fees += &meta.fee;
+        }
+        Ok(())
+    })?;
+    // the metas up to the watermark are the ones already applied
+    let applied = st.applied_bottomup_nonce;
+    st.bottomup_msg_meta
+        .load(store)?
+        .for_each(|nonce, meta| {
+            if applied == MAX_NONCE || nonce > applied {
+                ledger.pending_bottomup += &meta.msgs_value;
+            }
+            Ok(())
+        })
+        .map_err(|e| anyhow!("failed to iterate crossmsg meta array: {:?}", e))?;
+    st.postbox.load(store)?.for_each(|_, item| {
+        ledger.postbox += &item.cross_msg.msg.value;
+        Ok(())
+    })?;
+    st.dead_letters.load(store)?.for_each(|_, cross_msg| {
+        ledger.dead_letters += &cross_msg.msg.value;
+        Ok(())
+    })?;
+
+    Ok(ledger)
+}
+
+/// Checks that `balance`, the actual balance of the gateway, is the value
+/// accounted by its state. It is taken explicitly because the runtime only
+/// exposes the balance of the actor while it's being called.
+///
+/// It is meant to be checked between operations, once every bottom-up meta up
+/// to the watermark has been fully applied and no message is waiting to be
+/// retried, as the state doesn't keep the value of the messages in between.
+/// Checkpoints pruned from the gateway take the fees they keep with them, so
+/// the check can't be used with `checkpoint_retention` either.
+pub fn assert_value_conserved(rt: &impl Runtime, balance: &TokenAmount) {
+    let ledger = ledger(rt).expect("failed to derive the ledger of the gateway");
+    assert_eq!(
+        balance,
+        &ledger.held(),
+        "balance of the gateway doesn't match the value in its state: {:?}",
+        ledger
+    );
+}
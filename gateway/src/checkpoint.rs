@@ -2,10 +2,13 @@ use anyhow::anyhow;
 use cid::multihash::Code;
 use cid::multihash::MultihashDigest;
 use cid::Cid;
-use fvm_ipld_encoding::{serde_bytes, to_vec};
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{serde_bytes, to_vec, CborStore};
+use fvm_shared::address::Address;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use ipc_sdk::subnet_id::SubnetID;
+use num_traits::Zero;
 use primitives::{TCid, TLink};
 use serde::{Deserialize, Serialize};
 
@@ -77,6 +80,27 @@ impl Checkpoint {
         self.data.cross_msgs.as_mut()
     }
 
+    /// Adds a fee to the cross-msgs of the checkpoint, without any message,
+    /// so it is paid to the validators of the subnet when committed.
+    pub fn add_fee(&mut self, fee: &TokenAmount) {
+        let meta = self
+            .data
+            .cross_msgs
+            .get_or_insert_with(CrossMsgMeta::default);
+        meta.value += fee;
+        meta.fee += fee;
+    }
+
+    /// return the reward distribution manifest included in the checkpoint.
+    pub fn rewards(&self) -> &Vec<RewardEntry> {
+        &self.data.rewards
+    }
+
+    /// set the reward distribution manifest of the checkpoint.
+    pub fn set_rewards(&mut self, rewards: Vec<RewardEntry>) {
+        self.data.rewards = rewards;
+    }
+
     /// Add the cid of a checkpoint from a child subnet for further propagation
     /// to the upper layerse of the hierarchy.
     pub fn add_child_check(&mut self, commit: &Checkpoint) -> anyhow::Result<()> {
@@ -120,6 +144,19 @@ pub struct CheckData {
     pub prev_check: TCid<TLink<Checkpoint>>,
     pub children: Vec<ChildCheck>,
     pub cross_msgs: Option<CrossMsgMeta>,
+    /// Optional manifest with the rewards to distribute to validators
+    /// from the fees accrued in the checkpoint.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rewards: Vec<RewardEntry>,
+    /// Number of top-down messages from the parent applied in the source
+    /// subnet. The parent uses it as the watermark of the top-down messages
+    /// the subnet has already applied.
+    #[serde(default, skip_serializing_if = "Zero::is_zero")]
+    pub applied_topdown_nonce: u64,
+    /// Nonces of the top-down messages from the parent skipped by the source
+    /// subnet without applying them, acknowledging their cancellation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cancelled_topdown_msgs: Vec<u64>,
 }
 impl CheckData {
     pub fn new(id: SubnetID, epoch: ChainEpoch) -> Self {
@@ -130,6 +167,9 @@ impl CheckData {
             prev_check: TCid::default(),
             children: Vec::new(),
             cross_msgs: None,
+            rewards: Vec::new(),
+            applied_topdown_nonce: 0,
+            cancelled_topdown_msgs: Vec::new(),
         }
     }
 }
@@ -142,6 +182,14 @@ pub struct CrossMsgMeta {
     pub nonce: u64,
     pub value: TokenAmount,
     pub fee: TokenAmount,
+    /// Number of cross-messages included in the meta. Metas created before
+    /// it was tracked decode it as zero.
+    #[serde(default, skip_serializing_if = "Zero::is_zero")]
+    pub num_msgs: u64,
+    /// Value carried by the cross-messages included in the meta, without
+    /// the fees paid for them.
+    #[serde(default, skip_serializing_if = "TokenAmount::is_zero")]
+    pub msgs_value: TokenAmount,
 }
 
 impl CrossMsgMeta {
@@ -152,6 +200,87 @@ impl CrossMsgMeta {
     pub fn set_nonce(&mut self, nonce: u64) {
         self.nonce = nonce;
     }
+
+    /// Checks that the fee declared in the meta is the one paid by its messages.
+    ///
+    /// Fees are accounted in the value of the meta, so the fee must be the part
+    /// of the value not carried by any message. The messages are resolved from
+    /// the store to check that `msgs_value` is the value they actually carry,
+    /// and metas whose messages can't be resolved are rejected as their fee
+    /// can't be verified. A meta without messages only carries fees.
+    ///
+    /// Metas including messages must declare how many. Those that don't, as
+    /// the ones created before `num_msgs` was tracked, are rejected instead of
+    /// being checked as a meta without messages.
+    pub fn verify_fee<BS: Blockstore>(&self, store: &BS) -> anyhow::Result<()> {
+        if self.msgs_cid == TCid::default() {
+            if self.num_msgs != 0 || !self.msgs_value.is_zero() {
+                return Err(anyhow!(
+                    "meta without messages declares {} messages carrying {}",
+                    self.num_msgs,
+                    self.msgs_value
+                ));
+            }
+        } else {
+            if self.num_msgs == 0 {
+                return Err(anyhow!(
+                    "meta includes cross-msgs {} without declaring how many",
+                    self.msgs_cid.cid()
+                ));
+            }
+
+            let crossmsgs: CrossMsgs = match store.get_cbor(&self.msgs_cid.cid())? {
+                Some(crossmsgs) => crossmsgs,
+                None => {
+                    return Err(anyhow!(
+                        "cross-msgs {} of the meta can't be resolved",
+                        self.msgs_cid.cid()
+                    ))
+                }
+            };
+            if crossmsgs.msgs.len() as u64 != self.num_msgs {
+                return Err(anyhow!(
+                    "meta declares {} messages but includes {}",
+                    self.num_msgs,
+                    crossmsgs.msgs.len()
+                ));
+            }
+            let carried = crossmsgs
+                .msgs
+                .iter()
+                .fold(TokenAmount::zero(), |acc, m| acc + &m.msg.value);
+            if self.msgs_value != carried {
+                return Err(anyhow!(
+                    "meta declares its messages carry {} but they carry {}",
+                    self.msgs_value,
+                    carried
+                ));
+            }
+        }
+
+        if self.msgs_value > self.value {
+            return Err(anyhow!(
+                "value carried by the messages {} exceeds the value {} of the meta",
+                self.msgs_value,
+                self.value
+            ));
+        }
+        let paid = &self.value - &self.msgs_value;
+        if self.fee != paid {
+            return Err(anyhow!(
+                "declared fee {} doesn't match the fees paid by the messages {}",
+                self.fee,
+                paid
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub struct RewardEntry {
+    pub addr: Address,
+    pub amount: TokenAmount,
 }
 
 #[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
@@ -178,3 +307,43 @@ pub fn window_epoch(epoch: ChainEpoch, period: ChainEpoch) -> ChainEpoch {
     let ind = epoch / period;
     period * (ind + 1)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::checkpoint::*;
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_ipld_encoding::from_slice;
+
+    /// Layout of `CrossMsgMeta` before the number of messages was tracked.
+    #[derive(Serialize)]
+    struct LegacyCrossMsgMeta {
+        msgs_cid: TCid<TLink<CrossMsgs>>,
+        nonce: u64,
+        value: TokenAmount,
+        fee: TokenAmount,
+    }
+
+    #[test]
+    fn test_legacy_meta_fee() {
+        let legacy = |msgs_cid| LegacyCrossMsgMeta {
+            msgs_cid,
+            nonce: 3,
+            value: TokenAmount::from_atto(10),
+            fee: TokenAmount::from_atto(10),
+        };
+
+        // a legacy meta with only fees is still valid
+        let meta: CrossMsgMeta = from_slice(&to_vec(&legacy(TCid::default())).unwrap()).unwrap();
+        assert_eq!(meta.nonce, 3);
+        assert_eq!(meta.num_msgs, 0);
+        assert!(meta.msgs_value.is_zero());
+        let store = MemoryBlockstore::new();
+        meta.verify_fee(&store).unwrap();
+
+        // but one including messages can't be checked as a meta without them
+        let msgs_cid = TCid::from(Checkpoint::new(SubnetID::default(), 0).cid());
+        let meta: CrossMsgMeta = from_slice(&to_vec(&legacy(msgs_cid)).unwrap()).unwrap();
+        assert_eq!(meta.num_msgs, 0);
+        assert!(meta.verify_fee(&store).is_err());
+    }
+}
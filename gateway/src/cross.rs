@@ -1,14 +1,19 @@
+use crate::checkpoint::RewardEntry;
 use crate::ApplyMsgParams;
+use crate::DistributeRewardsParams;
 use crate::State;
-use crate::SUBNET_ACTOR_REWARD_METHOD;
+use crate::{SUBNET_ACTOR_DISTRIBUTE_REWARDS_METHOD, SUBNET_ACTOR_REWARD_METHOD};
 use anyhow::anyhow;
+use cid::Cid;
 use fil_actors_runtime::runtime::Runtime;
-use fil_actors_runtime::ActorError;
 use fil_actors_runtime::BURNT_FUNDS_ACTOR_ADDR;
+use fil_actors_runtime::{actor_error, ActorError};
 use fvm_ipld_blockstore::MemoryBlockstore;
 use fvm_ipld_encoding::ipld_block::IpldBlock;
 use fvm_ipld_encoding::RawBytes;
 use fvm_shared::address::Address;
+use fvm_shared::bigint::Zero;
+use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::MethodNum;
 use fvm_shared::METHOD_SEND;
@@ -33,6 +38,19 @@ pub struct StorableMsg {
     pub params: RawBytes,
     pub value: TokenAmount,
     pub nonce: u64,
+    /// Opaque data attached by the sender to correlate the message in its
+    /// destination. It doesn't affect how the message is routed or charged.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub memo: Vec<u8>,
+    /// Last epoch in which a top-down message can be executed in its destination.
+    /// Expired messages are not executed and their value is sent back to the sender.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_until_epoch: Option<ChainEpoch>,
+    /// Id to correlate the message along its path through the hierarchy. It is
+    /// set once in the subnet where the message originates, see
+    /// [`CrossMsg::set_correlation_id`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<Cid>,
 }
 
 #[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
@@ -48,6 +66,37 @@ pub enum IPCMsgType {
 }
 
 impl CrossMsg {
+    /// Returns the cid identifying the cross-message.
+    pub fn cid(&self) -> anyhow::Result<Cid> {
+        Ok(TCid::<TLink<CrossMsg>>::new_link(&MemoryBlockstore::new(), self)?.cid())
+    }
+
+    /// Returns the id to correlate the cross-message along its path through the
+    /// hierarchy. Messages committed before the id was introduced fall back to
+    /// their cid.
+    pub fn correlation_id(&self) -> anyhow::Result<Cid> {
+        match self.msg.correlation_id {
+            Some(corr) => Ok(corr),
+            None => self.cid(),
+        }
+    }
+
+    /// Sets the correlation id of a message committed in its originating subnet.
+    ///
+    /// The nonce is assigned again in every hop, so the id is computed once from
+    /// the cid of the message with the nonce assigned in its origin, and kept
+    /// as-is in every other hop. This way two identical messages sent from the
+    /// same subnet still get different ids.
+    pub fn set_correlation_id(&mut self, origin_nonce: u64) -> anyhow::Result<()> {
+        if self.msg.correlation_id.is_some() {
+            return Ok(());
+        }
+        let mut msg = self.clone();
+        msg.msg.nonce = origin_nonce;
+        self.msg.correlation_id = Some(msg.cid()?);
+        Ok(())
+    }
+
     pub fn send(self, rt: &mut impl Runtime, rto: &Address) -> Result<RawBytes, ActorError> {
         let blk = if !self.wrapped {
             let msg = self.msg;
@@ -73,13 +122,31 @@ impl StorableMsg {
         value: TokenAmount,
         nonce: u64,
     ) -> anyhow::Result<Self> {
-        let to = IPCAddress::new(
-            &match sub_id.parent() {
-                Some(s) => s,
-                None => return Err(anyhow!("error getting parent for subnet addr")),
-            },
-            sig_addr,
-        )?;
+        let parent = match sub_id.parent() {
+            Some(s) => s,
+            None => return Err(anyhow!("error getting parent for subnet addr")),
+        };
+        Self::new_release_to_msg(sub_id, &parent, sig_addr, value, nonce)
+    }
+
+    /// Creates a release message from `sub_id` to `target`, which needs
+    /// to be an ancestor of `sub_id`. The message is propagated bottom-up
+    /// through every subnet in between until it reaches `target`.
+    pub fn new_release_to_msg(
+        sub_id: &SubnetID,
+        target: &SubnetID,
+        sig_addr: &Address,
+        value: TokenAmount,
+        nonce: u64,
+    ) -> anyhow::Result<Self> {
+        if target == sub_id || !is_ancestor(target, sub_id) {
+            return Err(anyhow!(
+                "release target {} is not an ancestor of {}",
+                target,
+                sub_id
+            ));
+        }
+        let to = IPCAddress::new(target, sig_addr)?;
         let from = IPCAddress::new(sub_id, &BURNT_FUNDS_ACTOR_ADDR)?;
         Ok(Self {
             from,
@@ -88,13 +155,50 @@ impl StorableMsg {
             params: RawBytes::default(),
             value,
             nonce,
+            memo: Vec::new(),
+            valid_until_epoch: None,
+            correlation_id: None,
         })
     }
 
+    /// Creates the message returning the value of a message that couldn't be executed
+    /// to its sender, in the subnet the message came from. It is sent from `curr`, the
+    /// subnet where the message was meant to be executed.
+    pub fn new_refund_msg(&self, curr: &SubnetID) -> anyhow::Result<Self> {
+        let from = IPCAddress::new(curr, &BURNT_FUNDS_ACTOR_ADDR)?;
+        Ok(Self {
+            from,
+            to: self.from.clone(),
+            method: METHOD_SEND,
+            params: RawBytes::default(),
+            value: self.value.clone(),
+            nonce: 0,
+            memo: self.memo.clone(),
+            valid_until_epoch: None,
+            correlation_id: None,
+        })
+    }
+
+    /// Checks if the validity window of the message is over at `epoch`.
+    pub fn is_expired(&self, epoch: ChainEpoch) -> bool {
+        matches!(self.valid_until_epoch, Some(until) if epoch > until)
+    }
+
     pub fn new_fund_msg(
         sub_id: &SubnetID,
         sig_addr: &Address,
         value: TokenAmount,
+    ) -> anyhow::Result<Self> {
+        Self::new_fund_to_msg(sub_id, sig_addr, sig_addr, value)
+    }
+
+    /// Creates a fund message from `sig_addr` in the parent that credits
+    /// `beneficiary` in the subnet.
+    pub fn new_fund_to_msg(
+        sub_id: &SubnetID,
+        sig_addr: &Address,
+        beneficiary: &Address,
+        value: TokenAmount,
     ) -> anyhow::Result<Self> {
         let from = IPCAddress::new(
             &match sub_id.parent() {
@@ -103,7 +207,7 @@ impl StorableMsg {
             },
             sig_addr,
         )?;
-        let to = IPCAddress::new(sub_id, sig_addr)?;
+        let to = IPCAddress::new(sub_id, beneficiary)?;
         // the nonce and the rest of message fields are set when the message is committed.
         Ok(Self {
             from,
@@ -112,6 +216,9 @@ impl StorableMsg {
             params: RawBytes::default(),
             value,
             nonce: 0,
+            memo: Vec::new(),
+            valid_until_epoch: None,
+            correlation_id: None,
         })
     }
 
@@ -145,6 +252,51 @@ pub fn is_bottomup(from: &SubnetID, to: &SubnetID) -> bool {
     Path::new(&a).components().count() - 1 > index
 }
 
+/// Route followed by a cross-message in the gateway of a network.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum RouteDecision {
+    /// The message is directed to the current network.
+    Local,
+    /// The message is propagated down to the next subnet towards its destination.
+    CommitTopDown,
+    /// The message is propagated up to the parent of the current network.
+    CommitBottomUp,
+    /// The current network is not in the path from the source to the destination.
+    Unroutable,
+}
+
+/// Computes the route of a cross-message from subnet `from` to subnet `to` when
+/// it is committed in the gateway of `network_name`.
+///
+/// Messages travel up from their source to the nearest common parent, and
+/// from there down to their destination.
+pub fn route_cross_msg(network_name: &SubnetID, from: &SubnetID, to: &SubnetID) -> RouteDecision {
+    if to == network_name {
+        return RouteDecision::Local;
+    }
+    let nearest_common_parent = match from.common_parent(to) {
+        Some((_, p)) => p,
+        None => return RouteDecision::Unroutable,
+    };
+    if !is_ancestor(&nearest_common_parent, network_name) {
+        return RouteDecision::Unroutable;
+    }
+
+    if network_name != &nearest_common_parent && is_ancestor(network_name, from) {
+        RouteDecision::CommitBottomUp
+    } else if is_ancestor(network_name, to) {
+        RouteDecision::CommitTopDown
+    } else {
+        RouteDecision::Unroutable
+    }
+}
+
+/// Returns true if `ancestor` is `id` itself or one of its ancestors.
+pub(crate) fn is_ancestor(ancestor: &SubnetID, id: &SubnetID) -> bool {
+    id.common_parent(ancestor)
+        .map_or(false, |(_, p)| &p == ancestor)
+}
+
 #[derive(PartialEq, Eq, Clone, Debug, Default, Serialize, Deserialize)]
 pub struct CrossMsgs {
     // FIXME: Consider to make this an AMT if we expect
@@ -171,35 +323,31 @@ impl CrossMsgs {
     }
 }
 
-/// Transaction side-effects from the commitment of a cross-net message. It burns funds
-/// and propagates the corresponding rewards.
+/// Logs an event in the lifecycle of a cross-message tagged with its correlation
+/// id, so the message can be followed across hops.
+pub(crate) fn log_cross_msg_event(event: &str, cross_msg: &CrossMsg) {
+    match cross_msg.correlation_id() {
+        Ok(corr) => log::info!("{}: corr={} nonce={}", event, corr, cross_msg.msg.nonce),
+        Err(e) => log::error!("failed to compute correlation id of cross-msg: {}", e),
+    }
+}
+
+/// Transaction side-effects from the commitment of a cross-net message. It burns
+/// the funds of bottom-up messages.
 pub(crate) fn cross_msg_side_effects(
     rt: &mut impl Runtime,
     cross_msg: &CrossMsg,
     do_burn: bool,
-    top_down_fee: &TokenAmount,
 ) -> Result<(), ActorError> {
     // if this is a bottom-up message funds of the
     // cross-message need to be burnt
     if do_burn {
-        burn_bu_funds(rt, cross_msg.msg.value.clone())?;
-    }
-
-    // distribute top-down fee if any
-    if !top_down_fee.is_zero() {
-        distribute_crossmsg_fee(
-            rt,
-            &cross_msg
-                .msg
-                .to
-                .subnet()
-                .unwrap()
-                // TODO: double-check if rt.state() is an expensive operation in terms of gas
-                .down(&rt.state::<State>()?.network_name)
-                .unwrap()
-                .subnet_actor(),
-            top_down_fee.clone(),
-        )?;
+        let source = cross_msg
+            .msg
+            .from
+            .subnet()
+            .map_err(|_| actor_error!(illegal_argument, "error getting subnet from msg"))?;
+        burn_bu_funds(rt, &source, cross_msg.msg.value.clone())?;
     }
 
     Ok(())
@@ -211,13 +359,57 @@ pub(crate) fn distribute_crossmsg_fee(
     fee: TokenAmount,
 ) -> Result<(), ActorError> {
     if !fee.is_zero() {
-        rt.send(&subnet_actor, SUBNET_ACTOR_REWARD_METHOD, None, fee)?;
+        rt.send(&subnet_actor, SUBNET_ACTOR_REWARD_METHOD, None, fee.clone())?;
+        // the fee has been forwarded, remove it from the pending distribution
+        rt.transaction(|st: &mut State, _| {
+            st.pending_fee_distribution -= &fee;
+            Ok(())
+        })?;
     }
     Ok(())
 }
 
-pub(crate) fn burn_bu_funds(rt: &mut impl Runtime, value: TokenAmount) -> Result<(), ActorError> {
-    rt.send(&BURNT_FUNDS_ACTOR_ADDR, METHOD_SEND, None, value)?;
+/// Forwards the rewards of a checkpoint manifest to the subnet actor for their
+/// distribution. It returns the total amount of rewards distributed.
+pub(crate) fn distribute_reward_manifest(
+    rt: &mut impl Runtime,
+    subnet_actor: &Address,
+    rewards: Vec<RewardEntry>,
+) -> Result<TokenAmount, ActorError> {
+    let total = rewards
+        .iter()
+        .fold(TokenAmount::zero(), |acc, r| acc + &r.amount);
+    if total.is_zero() {
+        return Ok(total);
+    }
+
+    rt.send(
+        subnet_actor,
+        SUBNET_ACTOR_DISTRIBUTE_REWARDS_METHOD,
+        IpldBlock::serialize_cbor(&DistributeRewardsParams { rewards })?,
+        total.clone(),
+    )?;
+    rt.transaction(|st: &mut State, _| {
+        st.pending_fee_distribution -= &total;
+        Ok(())
+    })?;
+    Ok(total)
+}
+
+/// Burns the funds of a bottom-up message sent from the `source` subnet, keeping
+/// track of the total amount burnt by the gateway.
+pub(crate) fn burn_bu_funds(
+    rt: &mut impl Runtime,
+    source: &SubnetID,
+    value: TokenAmount,
+) -> Result<(), ActorError> {
+    rt.send(&BURNT_FUNDS_ACTOR_ADDR, METHOD_SEND, None, value.clone())?;
+    rt.transaction(|st: &mut State, _| {
+        st.total_burned += &value;
+        Ok(())
+    })?;
+
+    log::info!("FundsBurned: amount={} source={}", value, source);
     Ok(())
 }
 
@@ -235,6 +427,72 @@ mod tests {
         bottom_up("/root/f01/f02", "/root/f01/f02", false);
         bottom_up("/root/f01/f02", "/root/f01/f02/f03", false);
     }
+
+    #[test]
+    fn test_route_cross_msg() {
+        use RouteDecision::*;
+
+        // directed to the current network
+        route("/root/f01", "/root", "/root/f01", Local);
+        // descendant: propagated down towards the destination
+        route("/root", "/root", "/root/f01/f02", CommitTopDown);
+        route("/root/f01", "/root", "/root/f01/f02", CommitTopDown);
+        // ancestor: propagated up towards the destination
+        route("/root/f01/f02", "/root/f01/f02", "/root", CommitBottomUp);
+        route("/root/f01", "/root/f01/f02", "/root", CommitBottomUp);
+        // sibling: up to the common parent and then down
+        route("/root/f01", "/root/f01", "/root/f02", CommitBottomUp);
+        route("/root", "/root/f01", "/root/f02", CommitTopDown);
+        route("/root/f02", "/root/f01", "/root/f02/f03", CommitTopDown);
+        // the current network is not in the path of the message
+        route("/root/f03", "/root/f01", "/root/f02", Unroutable);
+        route("/root/f01/f02", "/root", "/root/f01/f03", Unroutable);
+        route("/root/f01", "/root/f02", "/root/f03", Unroutable);
+        // unrelated hierarchies
+        route("/root", "/root/f01", "/other/f02", Unroutable);
+    }
+
+    #[test]
+    fn test_correlation_id() {
+        let mut cross_msg = CrossMsg {
+            msg: StorableMsg::new_fund_msg(
+                &SubnetID::from_str("/root/f01").unwrap(),
+                &Address::new_id(1000),
+                TokenAmount::from_atto(1),
+            )
+            .unwrap(),
+            wrapped: false,
+        };
+        // messages committed before the id was set are identified by their cid
+        assert_eq!(
+            cross_msg.correlation_id().unwrap(),
+            cross_msg.cid().unwrap()
+        );
+
+        // identical messages get different ids from their origin nonce
+        let mut other = cross_msg.clone();
+        cross_msg.set_correlation_id(0).unwrap();
+        other.set_correlation_id(1).unwrap();
+        let corr = cross_msg.correlation_id().unwrap();
+        assert_ne!(corr, other.correlation_id().unwrap());
+
+        // and the id is kept in every other hop
+        cross_msg.msg.nonce = 10;
+        cross_msg.set_correlation_id(10).unwrap();
+        assert_eq!(cross_msg.correlation_id().unwrap(), corr);
+    }
+
+    fn route(curr: &str, from: &str, to: &str, res: RouteDecision) {
+        assert_eq!(
+            route_cross_msg(
+                &SubnetID::from_str(curr).unwrap(),
+                &SubnetID::from_str(from).unwrap(),
+                &SubnetID::from_str(to).unwrap()
+            ),
+            res
+        );
+    }
+
     fn bottom_up(a: &str, b: &str, res: bool) {
         assert_eq!(
             is_bottomup(
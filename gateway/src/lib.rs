@@ -1,11 +1,18 @@
 #![feature(let_chains)] // For some simpler syntax for if let Some conditions
 
-pub use self::checkpoint::{Checkpoint, CrossMsgMeta};
-pub use self::cross::{is_bottomup, CrossMsg, CrossMsgs, IPCMsgType, StorableMsg};
+pub use self::checkpoint::{Checkpoint, CrossMsgMeta, RewardEntry};
+pub use self::cross::{
+    is_bottomup, route_cross_msg, CrossMsg, CrossMsgs, IPCMsgType, RouteDecision, StorableMsg,
+};
+pub use self::migration::{StateV1, StateV2, STATE_VERSION};
 pub use self::state::*;
 pub use self::subnet::*;
 pub use self::types::*;
-use cross::{burn_bu_funds, cross_msg_side_effects, distribute_crossmsg_fee};
+use cid::Cid;
+use cross::{
+    burn_bu_funds, cross_msg_side_effects, distribute_crossmsg_fee, distribute_reward_manifest,
+    is_ancestor, log_cross_msg_event,
+};
 use fil_actors_runtime::runtime::fvm::resolve_secp_bls;
 use fil_actors_runtime::runtime::{ActorCode, Runtime};
 use fil_actors_runtime::{
@@ -14,8 +21,9 @@ use fil_actors_runtime::{
 };
 use fvm_ipld_encoding::ipld_block::IpldBlock;
 use fvm_ipld_encoding::RawBytes;
-use fvm_shared::address::Address;
+use fvm_shared::address::{Address, Protocol};
 use fvm_shared::bigint::Zero;
+use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
 use fvm_shared::METHOD_SEND;
@@ -30,11 +38,14 @@ use primitives::TCid;
 #[cfg(feature = "fil-gateway-actor")]
 fil_actors_runtime::wasm_trampoline!(Actor);
 
+#[cfg(feature = "value-accounting")]
+pub mod accounting;
 pub mod checkpoint;
 mod cross;
 mod error;
 #[doc(hidden)]
 pub mod ext;
+mod migration;
 mod state;
 pub mod subnet;
 mod types;
@@ -45,6 +56,9 @@ lazy_static! {
 }
 
 /// Gateway actor methods available
+///
+/// Method numbers are the discriminants of the enum, so two methods hashing
+/// to the same number are rejected at compile time.
 #[derive(FromPrimitive)]
 #[repr(u64)]
 pub enum Method {
@@ -56,13 +70,126 @@ pub enum Method {
     Kill = frc42_dispatch::method_hash!("Kill"),
     CommitChildCheckpoint = frc42_dispatch::method_hash!("CommitChildCheckpoint"),
     Fund = frc42_dispatch::method_hash!("Fund"),
+    FundMulti = frc42_dispatch::method_hash!("FundMulti"),
     Release = frc42_dispatch::method_hash!("Release"),
+    ReleaseToSubnet = frc42_dispatch::method_hash!("ReleaseToSubnet"),
     SendCross = frc42_dispatch::method_hash!("SendCross"),
     ApplyMessage = frc42_dispatch::method_hash!("ApplyMessage"),
     Propagate = frc42_dispatch::method_hash!("Propagate"),
     WhiteListPropagator = frc42_dispatch::method_hash!("WhiteListPropagator"),
+    ReplacePostboxOwners = frc42_dispatch::method_hash!("ReplacePostboxOwners"),
+    GetPrevCheckpoint = frc42_dispatch::method_hash!("GetPrevCheckpoint"),
+    GetLastCommittedEpoch = frc42_dispatch::method_hash!("GetLastCommittedEpoch"),
+    CancelFund = frc42_dispatch::method_hash!("CancelFund"),
+    BulkRegister = frc42_dispatch::method_hash!("BulkRegister"),
+    GetPendingFeeDistribution = frc42_dispatch::method_hash!("GetPendingFeeDistribution"),
+    AllowRegistration = frc42_dispatch::method_hash!("AllowRegistration"),
+    DisallowRegistration = frc42_dispatch::method_hash!("DisallowRegistration"),
+    SetAppliedTopDownNonce = frc42_dispatch::method_hash!("SetAppliedTopDownNonce"),
+    ListDeadLetters = frc42_dispatch::method_hash!("ListDeadLetters"),
+    RetryDeadLetter = frc42_dispatch::method_hash!("RetryDeadLetter"),
+    RefundDeadLetter = frc42_dispatch::method_hash!("RefundDeadLetter"),
+    MigrateState = frc42_dispatch::method_hash!("MigrateState"),
+    GetFeeSchedule = frc42_dispatch::method_hash!("GetFeeSchedule"),
+    FreezeSubnet = frc42_dispatch::method_hash!("FreezeSubnet"),
+    UnfreezeSubnet = frc42_dispatch::method_hash!("UnfreezeSubnet"),
+    DryRunCrossMsg = frc42_dispatch::method_hash!("DryRunCrossMsg"),
+    GetTotalBurned = frc42_dispatch::method_hash!("GetTotalBurned"),
+    GetQueueDepths = frc42_dispatch::method_hash!("GetQueueDepths"),
+    PruneCheckpoints = frc42_dispatch::method_hash!("PruneCheckpoints"),
+    GetActorInfo = frc42_dispatch::method_hash!("GetActorInfo"),
+    GetCrossMsgStatus = frc42_dispatch::method_hash!("GetCrossMsgStatus"),
+    GetCollateralization = frc42_dispatch::method_hash!("GetCollateralization"),
+    FundWithMemo = frc42_dispatch::method_hash!("FundWithMemo"),
+    AddFeeExemption = frc42_dispatch::method_hash!("AddFeeExemption"),
+    RemoveFeeExemption = frc42_dispatch::method_hash!("RemoveFeeExemption"),
+    PropagateBatch = frc42_dispatch::method_hash!("PropagateBatch"),
+    ReactivateSubnet = frc42_dispatch::method_hash!("ReactivateSubnet"),
+    SetSafeMode = frc42_dispatch::method_hash!("SetSafeMode"),
+    ComputeSubnetID = frc42_dispatch::method_hash!("ComputeSubnetID"),
+    ForceKill = frc42_dispatch::method_hash!("ForceKill"),
+    GetGatewayStats = frc42_dispatch::method_hash!("GetGatewayStats"),
+    FundWithTarget = frc42_dispatch::method_hash!("FundWithTarget"),
+    ApplyMessages = frc42_dispatch::method_hash!("ApplyMessages"),
+    GetAncestry = frc42_dispatch::method_hash!("GetAncestry"),
+    GetTotalFeesCollected = frc42_dispatch::method_hash!("GetTotalFeesCollected"),
+    GetStateRoot = frc42_dispatch::method_hash!("GetStateRoot"),
+    SetAllowReregister = frc42_dispatch::method_hash!("SetAllowReregister"),
+    AdjustCircSupply = frc42_dispatch::method_hash!("AdjustCircSupply"),
+    SetGovernors = frc42_dispatch::method_hash!("SetGovernors"),
+    ProposeGovAction = frc42_dispatch::method_hash!("ProposeGovAction"),
+    ApproveGovAction = frc42_dispatch::method_hash!("ApproveGovAction"),
+    GetBottomUpMsg = frc42_dispatch::method_hash!("GetBottomUpMsg"),
+    SetMaxTopDownValue = frc42_dispatch::method_hash!("SetMaxTopDownValue"),
+    FundOnBehalf = frc42_dispatch::method_hash!("FundOnBehalf"),
+    SkipTopDownMsg = frc42_dispatch::method_hash!("SkipTopDownMsg"),
 }
 
+/// Methods handled by the gateway actor, as reported by `GetActorInfo`.
+/// It needs to be kept in sync with the `actor_dispatch!` table.
+pub const SUPPORTED_METHODS: &[MethodNum] = &[
+    Method::Constructor as MethodNum,
+    Method::Register as MethodNum,
+    Method::AddStake as MethodNum,
+    Method::ReleaseStake as MethodNum,
+    Method::Kill as MethodNum,
+    Method::CommitChildCheckpoint as MethodNum,
+    Method::Fund as MethodNum,
+    Method::FundMulti as MethodNum,
+    Method::Release as MethodNum,
+    Method::ReleaseToSubnet as MethodNum,
+    Method::SendCross as MethodNum,
+    Method::ApplyMessage as MethodNum,
+    Method::Propagate as MethodNum,
+    Method::WhiteListPropagator as MethodNum,
+    Method::ReplacePostboxOwners as MethodNum,
+    Method::GetPrevCheckpoint as MethodNum,
+    Method::GetLastCommittedEpoch as MethodNum,
+    Method::CancelFund as MethodNum,
+    Method::BulkRegister as MethodNum,
+    Method::GetPendingFeeDistribution as MethodNum,
+    Method::AllowRegistration as MethodNum,
+    Method::DisallowRegistration as MethodNum,
+    Method::SetAppliedTopDownNonce as MethodNum,
+    Method::ListDeadLetters as MethodNum,
+    Method::RetryDeadLetter as MethodNum,
+    Method::RefundDeadLetter as MethodNum,
+    Method::MigrateState as MethodNum,
+    Method::GetFeeSchedule as MethodNum,
+    Method::FreezeSubnet as MethodNum,
+    Method::UnfreezeSubnet as MethodNum,
+    Method::DryRunCrossMsg as MethodNum,
+    Method::GetTotalBurned as MethodNum,
+    Method::GetQueueDepths as MethodNum,
+    Method::PruneCheckpoints as MethodNum,
+    Method::GetActorInfo as MethodNum,
+    Method::GetCrossMsgStatus as MethodNum,
+    Method::GetCollateralization as MethodNum,
+    Method::FundWithMemo as MethodNum,
+    Method::AddFeeExemption as MethodNum,
+    Method::RemoveFeeExemption as MethodNum,
+    Method::PropagateBatch as MethodNum,
+    Method::ReactivateSubnet as MethodNum,
+    Method::SetSafeMode as MethodNum,
+    Method::ComputeSubnetID as MethodNum,
+    Method::ForceKill as MethodNum,
+    Method::GetGatewayStats as MethodNum,
+    Method::FundWithTarget as MethodNum,
+    Method::ApplyMessages as MethodNum,
+    Method::GetAncestry as MethodNum,
+    Method::GetTotalFeesCollected as MethodNum,
+    Method::GetStateRoot as MethodNum,
+    Method::SetAllowReregister as MethodNum,
+    Method::AdjustCircSupply as MethodNum,
+    Method::SetGovernors as MethodNum,
+    Method::ProposeGovAction as MethodNum,
+    Method::ApproveGovAction as MethodNum,
+    Method::GetBottomUpMsg as MethodNum,
+    Method::SetMaxTopDownValue as MethodNum,
+    Method::FundOnBehalf as MethodNum,
+    Method::SkipTopDownMsg as MethodNum,
+];
+
 /// Gateway Actor
 pub struct Actor;
 
@@ -71,6 +198,8 @@ impl Actor {
     fn constructor(rt: &mut impl Runtime, params: ConstructorParams) -> Result<(), ActorError> {
         rt.validate_immediate_caller_is(std::iter::once(&INIT_ACTOR_ADDR))?;
 
+        params.validate()?;
+
         let st = State::new(rt.store(), params).map_err(|e| {
             e.downcast_default(
                 ExitCode::USR_ILLEGAL_STATE,
@@ -87,8 +216,24 @@ impl Actor {
         rt.validate_immediate_caller_accept_any()?;
 
         let subnet_addr = rt.message().caller();
+        if Self::is_reserved_address(rt, &subnet_addr) {
+            return Err(actor_error!(
+                illegal_argument,
+                "subnet actor address {} is reserved",
+                subnet_addr
+            ));
+        }
+
         let mut shid = SubnetID::default();
         rt.transaction(|st: &mut State, rt| {
+            if !st.can_register(&subnet_addr) {
+                return Err(actor_error!(
+                    forbidden,
+                    "subnet actor {} not allowed to register",
+                    subnet_addr
+                ));
+            }
+
             shid = SubnetID::new_from_parent(&st.network_name, subnet_addr);
             let sub = st.get_subnet(rt.store(), &shid).map_err(|e| {
                 e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
@@ -102,6 +247,29 @@ impl Actor {
                     ));
                 }
                 None => {
+                    let killed = st.get_killed_subnet(rt.store(), &shid).map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            "failed to load killed subnets",
+                        )
+                    })?;
+                    if let Some(epoch) = killed {
+                        if !st.allow_reregister {
+                            return Err(actor_error!(
+                                illegal_argument,
+                                "subnet with id {} was killed in epoch {} and can't register again",
+                                shid,
+                                epoch
+                            ));
+                        }
+                        st.rm_killed_subnet(rt.store(), &shid).map_err(|e| {
+                            e.downcast_default(
+                                ExitCode::USR_ILLEGAL_STATE,
+                                "failed to update killed subnets",
+                            )
+                        })?;
+                        log::info!("SubnetReregistered: id={} killed={}", shid, epoch);
+                    }
                     st.register_subnet(rt, &shid).map_err(|e| {
                         e.downcast_default(
                             ExitCode::USR_ILLEGAL_ARGUMENT,
@@ -118,177 +286,985 @@ impl Actor {
         Ok(shid)
     }
 
-    /// Add stake adds stake to the collateral of a subnet.
-    fn add_stake(rt: &mut impl Runtime) -> Result<(), ActorError> {
-        rt.validate_immediate_caller_accept_any()?;
-
-        let subnet_addr = rt.message().caller();
-
-        let val = rt.message().value_received();
-        if val <= TokenAmount::zero() {
-            return Err(actor_error!(illegal_argument, "no stake to add"));
+    /// BulkRegister registers several subnets atomically. It is used to bootstrap
+    /// a hierarchy in genesis and can only be called by the system or init actors.
+    ///
+    /// The stake of the subnets is expected to be already held by the gateway.
+    fn bulk_register(rt: &mut impl Runtime, params: BulkRegisterParams) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_is([&SYSTEM_ACTOR_ADDR as &Address, &INIT_ACTOR_ADDR])?;
+
+        let total_stake = params
+            .subnets
+            .iter()
+            .fold(TokenAmount::zero(), |acc, s| acc + &s.stake);
+        if rt.current_balance() < total_stake {
+            return Err(actor_error!(
+                illegal_state,
+                "gateway balance doesn't cover the stake of the subnets registered"
+            ));
         }
 
         rt.transaction(|st: &mut State, rt| {
-            let shid = SubnetID::new_from_parent(&st.network_name, subnet_addr);
-            let sub = st.get_subnet(rt.store(), &shid).map_err(|e| {
-                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
-            })?;
-            match sub {
-                Some(mut sub) => {
-                    sub.add_stake(rt, st, &val).map_err(|e| {
-                        e.downcast_default(
-                            ExitCode::USR_ILLEGAL_STATE,
-                            "Failed to add stake to subnet",
-                        )
-                    })?;
+            st.bulk_register_subnets(rt.store(), &params.subnets)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_ARGUMENT, "failed to register subnets")
+                })
+        })?;
+
+        log::debug!("registered {} subnets in bulk", params.subnets.len());
+        Ok(())
+    }
+
+    /// AllowRegistration adds a subnet actor to the registration allowlist.
+    ///
+    /// It can only be called by the governor and when the registration is permissioned.
+    fn allow_registration(rt: &mut impl Runtime, params: Address) -> Result<(), ActorError> {
+        Self::validate_governor(rt)?;
+
+        rt.transaction(
+            |st: &mut State, _| match st.registration_allowlist.as_mut() {
+                Some(allowlist) => {
+                    if !allowlist.contains(&params) {
+                        allowlist.push(params);
+                    }
+                    Ok(())
                 }
-                None => {
-                    return Err(actor_error!(
-                        illegal_argument,
-                        "subnet with id {} not registered",
-                        shid
-                    ));
+                None => Err(actor_error!(
+                    illegal_state,
+                    "registration is permissionless in this gateway"
+                )),
+            },
+        )
+    }
+
+    /// DisallowRegistration removes a subnet actor from the registration allowlist.
+    ///
+    /// It can only be called by the governor and when the registration is permissioned.
+    fn disallow_registration(rt: &mut impl Runtime, params: Address) -> Result<(), ActorError> {
+        Self::validate_governor(rt)?;
+
+        rt.transaction(
+            |st: &mut State, _| match st.registration_allowlist.as_mut() {
+                Some(allowlist) => {
+                    allowlist.retain(|a| a != &params);
+                    Ok(())
                 }
+                None => Err(actor_error!(
+                    illegal_state,
+                    "registration is permissionless in this gateway"
+                )),
+            },
+        )
+    }
+
+    /// AddFeeExemption exempts an address from paying cross-message fees.
+    ///
+    /// It can only be called by the governor.
+    fn add_fee_exemption(rt: &mut impl Runtime, params: Address) -> Result<(), ActorError> {
+        Self::validate_governor(rt)?;
+
+        rt.transaction(|st: &mut State, _| {
+            if !st.fee_exempt.contains(&params) {
+                st.fee_exempt.push(params);
             }
+            Ok(())
+        })
+    }
+
+    /// RemoveFeeExemption makes an exempted address pay cross-message fees again.
+    ///
+    /// It can only be called by the governor.
+    fn remove_fee_exemption(rt: &mut impl Runtime, params: Address) -> Result<(), ActorError> {
+        Self::validate_governor(rt)?;
+
+        rt.transaction(|st: &mut State, _| {
+            st.fee_exempt.retain(|a| a != &params);
+            Ok(())
+        })
+    }
+
+    /// SetSafeMode enables or disables the safe mode of the gateway. In safe mode
+    /// cross-messages carrying value are rejected, while zero-value messages still flow.
+    ///
+    /// It can only be called by the governor.
+    fn set_safe_mode(rt: &mut impl Runtime, params: bool) -> Result<(), ActorError> {
+        Self::validate_governor(rt)?;
 
+        rt.transaction(|st: &mut State, _| {
+            st.safe_mode = params;
             Ok(())
         })?;
 
+        log::info!("safe mode set by governor: {}", params);
         Ok(())
     }
 
-    /// Release stake recovers some collateral of the subnet
-    fn release_stake(rt: &mut impl Runtime, params: FundParams) -> Result<(), ActorError> {
-        rt.validate_immediate_caller_accept_any()?;
+    /// SetAllowReregister sets whether subnets that were killed can register
+    /// again. Re-registration is blocked by default, as the id of a killed
+    /// subnet may still be referenced by stale cross-messages and checkpoints.
+    ///
+    /// It can only be called by the governor.
+    fn set_allow_reregister(rt: &mut impl Runtime, params: bool) -> Result<(), ActorError> {
+        Self::validate_governor(rt)?;
 
-        let subnet_addr = rt.message().caller();
+        rt.transaction(|st: &mut State, _| {
+            st.allow_reregister = params;
+            Ok(())
+        })?;
 
-        let send_val = params.value;
+        log::info!(
+            "re-registration of killed subnets set by governor: {}",
+            params
+        );
+        Ok(())
+    }
 
-        if send_val <= TokenAmount::zero() {
-            return Err(actor_error!(
-                illegal_argument,
-                "no funds to release in params"
-            ));
-        }
+    /// FreezeSubnet blocks the flow of funds to and from a child subnet during an
+    /// incident. The stake of the subnet can still be released so validators can exit.
+    ///
+    /// It can only be called by the governor.
+    fn freeze_subnet(rt: &mut impl Runtime, params: SubnetID) -> Result<(), ActorError> {
+        Self::set_subnet_frozen(rt, params, true)
+    }
 
-        rt.transaction(|st: &mut State, rt| {
-            let shid = SubnetID::new_from_parent(&st.network_name, subnet_addr);
-            let sub = st.get_subnet(rt.store(), &shid).map_err(|e| {
-                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
-            })?;
-            match sub {
-                Some(mut sub) => {
-                    if sub.stake < send_val {
-                        return Err(actor_error!(
-                            illegal_state,
-                            "subnet actor not allowed to release so many funds"
-                        ));
-                    }
-                    // sanity-check: see if the actor has enough balance.
-                    if rt.current_balance() < send_val {
-                        return Err(actor_error!(
-                            illegal_state,
-                            "something went really wrong! the actor doesn't have enough balance to release"
-                        ));
-                    }
-                    sub.add_stake(rt, st, &-send_val.clone()).map_err(|e| {
-                        e.downcast_default(
-                            ExitCode::USR_ILLEGAL_STATE,
-                            "Failed to add stake to subnet",
-                        )
-                    })?;
-                }
-                None => {
-                    return Err(actor_error!(
-                        illegal_argument,
-                        "subnet with id {} not registered",
-                        shid
-                    ));
-                }
-            }
+    /// UnfreezeSubnet restores the flow of funds to and from a frozen child subnet.
+    ///
+    /// It can only be called by the governor.
+    fn unfreeze_subnet(rt: &mut impl Runtime, params: SubnetID) -> Result<(), ActorError> {
+        Self::set_subnet_frozen(rt, params, false)
+    }
 
-            Ok(())
+    fn set_subnet_frozen(
+        rt: &mut impl Runtime,
+        id: SubnetID,
+        frozen: bool,
+    ) -> Result<(), ActorError> {
+        Self::validate_governor(rt)?;
+
+        rt.transaction(|st: &mut State, rt| {
+            st.set_subnet_frozen(rt.store(), &id, frozen).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_ARGUMENT, "failed to freeze subnet")
+            })
         })?;
 
-        rt.send(&subnet_addr, METHOD_SEND, None, send_val.clone())?;
+        log::info!("subnet {} frozen by governor: {}", id, frozen);
         Ok(())
     }
 
-    /// Kill propagates the kill signal from a subnet actor to unregister it from th
-    /// hierarchy.
-    fn kill(rt: &mut impl Runtime) -> Result<(), ActorError> {
-        rt.validate_immediate_caller_accept_any()?;
-
-        let subnet_addr = rt.message().caller();
-        let mut send_val = TokenAmount::zero();
+    /// SetMaxTopDownValue limits the value of each top-down message into a child
+    /// subnet, capping the funds at risk if the subnet misbehaves.
+    ///
+    /// It can only be called by the governor.
+    fn set_max_topdown_value(
+        rt: &mut impl Runtime,
+        params: SetMaxTopDownValueParams,
+    ) -> Result<(), ActorError> {
+        Self::validate_governor(rt)?;
 
+        let SetMaxTopDownValueParams { subnet, max } = params;
+        if let Some(max) = &max {
+            if max < &TokenAmount::zero() {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "maximum top-down value can't be negative"
+                ));
+            }
+        }
         rt.transaction(|st: &mut State, rt| {
-            let shid = SubnetID::new_from_parent(&st.network_name, subnet_addr);
-            let sub = st.get_subnet(rt.store(), &shid).map_err(|e| {
-                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+            st.set_max_topdown_value(rt.store(), &subnet, max.clone())
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_ARGUMENT,
+                        "failed to set maximum top-down value",
+                    )
+                })
+        })?;
+
+        log::info!(
+            "maximum top-down value of subnet {} set by governor: {:?}",
+            subnet,
+            max
+        );
+        Ok(())
+    }
+
+    /// SetAppliedTopDownNonce is a break-glass tool for the governor to recover from
+    /// an applied top-down nonce out of sync with the messages committed in the parent.
+    ///
+    /// The nonce can only be moved forward to prevent replaying top-down messages.
+    fn set_applied_topdown_nonce(rt: &mut impl Runtime, params: u64) -> Result<(), ActorError> {
+        Self::validate_governor(rt)?;
+
+        let prev = rt.transaction(|st: &mut State, _| {
+            let prev = st.applied_topdown_nonce;
+            st.set_applied_topdown_nonce(params).map_err(|e| {
+                e.downcast_default(
+                    ExitCode::USR_ILLEGAL_ARGUMENT,
+                    "failed to set applied top-down nonce",
+                )
             })?;
-            match sub {
-                Some(sub) => {
-                    if rt.current_balance() < sub.stake {
-                        return Err(actor_error!(
-                            illegal_state,
-                            "something went really wrong! the actor doesn't have enough balance to release"
-                        ));
-                    }
-                    if sub.circ_supply > TokenAmount::zero() {
-                        return Err(actor_error!(
-                            illegal_state,
-                            "cannot kill a subnet that still holds user funds in its circ. supply"
-                        ));
-                    }
-                    send_val = sub.stake;
-                    // delete subnet
-                    st.rm_subnet(rt.store(), &shid).map_err(|e| {
-                        e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
-                    })?;
-                }
-                None => {
-                    return Err(actor_error!(
-                        illegal_argument,
-                        "subnet with id {} not registered",
-                        shid
-                    ));
-                }
-            }
+            Ok(prev)
+        })?;
 
-            Ok(())
+        log::info!(
+            "applied top-down nonce set by governor: prev={} new={}",
+            prev,
+            params
+        );
+        Ok(())
+    }
+
+    /// SetGovernors replaces the single governor by a set of governors, so
+    /// privileged methods are only executed once `threshold` distinct governors
+    /// approve them through `ProposeGovAction` and `ApproveGovAction`. Setting an
+    /// empty set restores the single governor.
+    ///
+    /// It can only be called by the governor, which once governors are set means
+    /// through an approved governance action.
+    fn set_governors(rt: &mut impl Runtime, params: SetGovernorsParams) -> Result<(), ActorError> {
+        Self::validate_governor(rt)?;
+
+        let SetGovernorsParams {
+            governors,
+            threshold,
+        } = params;
+        let count = governors.len();
+        rt.transaction(|st: &mut State, rt| {
+            st.set_governors(rt.store(), governors, threshold)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_ARGUMENT, "failed to set governors")
+                })
         })?;
 
-        rt.send(&subnet_addr, METHOD_SEND, None, send_val.clone())?;
+        log::info!("GovernorsSet: governors={} threshold={}", count, threshold);
         Ok(())
     }
 
-    /// CommitChildCheck propagates the commitment of a checkpoint from a child subnet,
-    /// process the cross-messages directed to the subnet.
-    fn commit_child_check(rt: &mut impl Runtime, params: Checkpoint) -> Result<(), ActorError> {
-        rt.validate_immediate_caller_accept_any()?;
+    /// ProposeGovAction proposes the execution of a privileged method of the
+    /// gateway, counting as the approval of the proposer. Returns the id of the
+    /// governance action.
+    ///
+    /// It can only be called by one of the governors.
+    fn propose_gov_action(
+        rt: &mut impl Runtime,
+        params: ProposeGovActionParams,
+    ) -> Result<u64, ActorError> {
+        let proposer = Self::validate_governance_member(rt)?;
 
-        let subnet_addr = rt.message().caller();
-        let commit = params;
-        let subnet_actor = commit.source().subnet_actor();
+        let (id, approved) = rt.transaction(|st: &mut State, rt| {
+            st.propose_gov_action(rt.store(), proposer, params)
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_STATE,
+                        "failed to propose governance action",
+                    )
+                })
+        })?;
 
-        // check if the checkpoint belongs to the subnet
-        if subnet_addr != subnet_actor {
+        log::info!("GovActionProposed: id={} proposer={}", id, proposer);
+        if let Some(proposal) = approved {
+            Self::execute_gov_action(rt, id, proposal)?;
+        }
+        Ok(id)
+    }
+
+    /// ApproveGovAction approves a pending governance action, executing it once
+    /// the threshold of distinct governors is reached. Returns whether the action
+    /// was executed.
+    ///
+    /// It can only be called by one of the governors.
+    fn approve_gov_action(rt: &mut impl Runtime, params: u64) -> Result<bool, ActorError> {
+        let approver = Self::validate_governance_member(rt)?;
+
+        let approved = rt.transaction(|st: &mut State, rt| {
+            st.approve_gov_action(rt.store(), params, approver)
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_ARGUMENT,
+                        "failed to approve governance action",
+                    )
+                })
+        })?;
+
+        log::info!("GovActionApproved: id={} approver={}", params, approver);
+        match approved {
+            Some(proposal) => {
+                Self::execute_gov_action(rt, params, proposal)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// AdjustCircSupply is a break-glass tool for the governor to correct the
+    /// circulating supply of a subnet that drifted from the funds actually in it,
+    /// which would otherwise prevent killing the subnet.
+    ///
+    /// It can only be used in safe mode, and the supply can't become negative.
+    fn adjust_circ_supply(
+        rt: &mut impl Runtime,
+        params: AdjustCircSupplyParams,
+    ) -> Result<(), ActorError> {
+        Self::validate_governor(rt)?;
+
+        let AdjustCircSupplyParams { subnet, delta } = params;
+        if delta.is_zero() {
             return Err(actor_error!(
                 illegal_argument,
-                "source in checkpoint doesn't belong to subnet"
+                "no circulating supply adjustment"
             ));
         }
+        let (prev, new) = rt.transaction(|st: &mut State, rt| {
+            if !st.safe_mode {
+                return Err(actor_error!(
+                    illegal_state,
+                    "circulating supply can only be adjusted in safe mode"
+                ));
+            }
+            st.adjust_circ_supply(rt.store(), &subnet, &delta)
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_ARGUMENT,
+                        "failed to adjust circulating supply",
+                    )
+                })
+        })?;
 
-        let fee = rt.transaction(|st: &mut State, rt| {
-            let shid = SubnetID::new_from_parent(&st.network_name, subnet_addr);
-            let sub = st.get_subnet(rt.store(), &shid).map_err(|e| {
-                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
-            })?;
+        log::info!(
+            "CircSupplyAdjusted: subnet={} prev={} new={}",
+            subnet,
+            prev,
+            new
+        );
+        Ok(())
+    }
+
+    /// ListDeadLetters returns the cross-messages that couldn't be applied after
+    /// the maximum number of attempts.
+    fn list_dead_letters(rt: &mut impl Runtime) -> Result<Vec<DeadLetter>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        st.list_dead_letters(rt.store()).map_err(|e| {
+            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load dead letters")
+        })
+    }
+
+    /// RetryDeadLetter removes a cross-message from the dead-letter queue and
+    /// tries to execute it again. It can be triggered by the governor or by
+    /// the sender of the message.
+    fn retry_dead_letter(rt: &mut impl Runtime, params: Cid) -> Result<RawBytes, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let cross_msg = Self::take_dead_letter(rt, &params)?;
+        let rto =
+            cross_msg.msg.to.raw_addr().map_err(|_| {
+                actor_error!(illegal_argument, "error getting raw address from msg")
+            })?;
+        cross_msg.send(rt, &rto)
+    }
+
+    /// RefundDeadLetter removes a cross-message from the dead-letter queue and
+    /// returns its value to the sender. It can be triggered by the governor or by
+    /// the sender of the message.
+    ///
+    /// The sender lives in the subnet the message came from, so the refund is a
+    /// cross-message to that subnet stored in the postbox, like the refund of an
+    /// expired message. It returns the cid of the postbox item, or nothing if the
+    /// message didn't carry any value.
+    fn refund_dead_letter(rt: &mut impl Runtime, params: Cid) -> Result<RawBytes, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let cross_msg = Self::take_dead_letter(rt, &params)?;
+        if cross_msg.msg.value.is_zero() {
+            return Ok(RawBytes::default());
+        }
+        let postbox_cid = rt.transaction(|st: &mut State, rt| {
+            let msg = cross_msg
+                .msg
+                .new_refund_msg(&st.network_name)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error creating refund message")
+                })?;
+            let refund = CrossMsg {
+                msg,
+                wrapped: false,
+            };
+            st.insert_postbox(rt.store(), None, refund, rt.curr_epoch())
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error storing refund message")
+                })
+        })?;
+        Ok(RawBytes::new(postbox_cid.to_bytes()))
+    }
+
+    /// Removes a cross-message from the dead-letter queue checking that the caller
+    /// is either the governor, as accepted by `validate_governor`, or the sender of
+    /// the message.
+    fn take_dead_letter(rt: &mut impl Runtime, cid: &Cid) -> Result<CrossMsg, ActorError> {
+        let caller = rt.message().caller();
+        rt.transaction(|st: &mut State, rt| {
+            let governor = Self::governor_caller(rt, st);
+            let cross_msg = st.take_dead_letter(rt.store(), cid).map_err(|e| {
+                e.downcast_default(ExitCode::USR_NOT_FOUND, "failed to load dead letter")
+            })?;
+            if caller != governor && cross_msg.msg.from.raw_addr().ok() != Some(caller) {
+                return Err(actor_error!(
+                    forbidden,
+                    "only the governor or the sender can handle a dead letter"
+                ));
+            }
+            Ok(cross_msg)
+        })
+    }
+
+    /// Executes a cross-message directed to the current network.
+    ///
+    /// A failing execution doesn't abort the application: `revert` undoes the state
+    /// transition of the message so it can be applied again, until the maximum number
+    /// of attempts is reached and the message is moved to the dead-letter queue.
+    fn execute_msg(
+        rt: &mut impl Runtime,
+        cross_msg: CrossMsg,
+        rto: &Address,
+        revert: impl FnOnce(&mut State),
+    ) -> Result<RawBytes, ActorError> {
+        let cid = cross_msg.cid().map_err(|e| {
+            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error computing cross-msg cid")
+        })?;
+
+        match cross_msg.clone().send(rt, rto) {
+            Ok(ret) => {
+                rt.transaction(|st: &mut State, rt| {
+                    st.clear_apply_attempts(rt.store(), &cid).map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            "error clearing apply attempts",
+                        )
+                    })
+                })?;
+                Ok(ret)
+            }
+            Err(err) => {
+                let dead = rt.transaction(|st: &mut State, rt| {
+                    let dead = st
+                        .record_failed_apply(rt.store(), &cid, &cross_msg)
+                        .map_err(|e| {
+                            e.downcast_default(
+                                ExitCode::USR_ILLEGAL_STATE,
+                                "error recording failed application",
+                            )
+                        })?;
+                    if !dead {
+                        revert(st);
+                    }
+                    Ok(dead)
+                })?;
+                log::info!(
+                    "failed applying cross-msg {}: {}, moved to dead-letter queue: {}",
+                    cid,
+                    err,
+                    dead
+                );
+                // the error is not propagated so the failed attempt is persisted.
+                Ok(RawBytes::default())
+            }
+        }
+    }
+
+    /// Moves the applied top-down nonce to the next message.
+    fn advance_topdown_nonce(st: &mut State) -> Result<(), ActorError> {
+        st.applied_topdown_nonce = next_nonce(st.applied_topdown_nonce).map_err(|e| {
+            e.downcast_default(
+                ExitCode::USR_ILLEGAL_STATE,
+                "failed to increment applied top-down nonce",
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Refunds a top-down message whose validity window is over instead of executing it.
+    ///
+    /// A bottom-up message returning its value to the sender is stored in the postbox,
+    /// and it can be propagated by anyone. It returns the cid of the postbox item, or
+    /// nothing if the message didn't carry any value.
+    fn refund_expired_msg(
+        rt: &mut impl Runtime,
+        cross_msg: CrossMsg,
+        cid: &Cid,
+    ) -> Result<RawBytes, ActorError> {
+        let postbox_cid = rt.transaction(|st: &mut State, rt| {
+            st.clear_apply_attempts(rt.store(), cid).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error clearing apply attempts")
+            })?;
+            if cross_msg.msg.value.is_zero() {
+                return Ok(None);
+            }
+            let msg = cross_msg
+                .msg
+                .new_refund_msg(&st.network_name)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error creating refund message")
+                })?;
+            let refund = CrossMsg {
+                msg,
+                wrapped: false,
+            };
+            let postbox_cid = st
+                .insert_postbox(rt.store(), None, refund, rt.curr_epoch())
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error storing refund message")
+                })?;
+            Ok(Some(postbox_cid))
+        })?;
+        log_cross_msg_event("CrossMsgExpired", &cross_msg);
+
+        Ok(match postbox_cid {
+            Some(cid) => RawBytes::new(cid.to_bytes()),
+            None => RawBytes::default(),
+        })
+    }
+
+    /// MigrateState upgrades the state of the gateway to the current layout.
+    /// It is a no-op if the state is already up to date.
+    fn migrate_state(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let root = rt.get_state_root()?;
+        let migrated = migration::migrate_state(rt.store(), &root).map_err(|e| {
+            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to migrate state")
+        })?;
+        if let Some(root) = migrated {
+            rt.set_state_root(&root)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the caller is entitled to apply cross-messages.
+    ///
+    /// Only the system actor can apply cross-messages. Builds with the `apply-simulation`
+    /// feature accept any caller so the apply path can be exercised in isolation.
+    fn validate_apply_caller(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        if cfg!(feature = "apply-simulation") {
+            return rt.validate_immediate_caller_accept_any();
+        }
+        rt.validate_immediate_caller_is([&SYSTEM_ACTOR_ADDR as &Address])
+    }
+
+    /// Checks if an address can't be used by a subnet actor, either because it
+    /// is the gateway itself or one of the reserved system actors.
+    fn is_reserved_address(rt: &impl Runtime, addr: &Address) -> bool {
+        if *addr == rt.message().receiver() {
+            return true;
+        }
+        match addr.id() {
+            Ok(id) => id < FIRST_NON_SINGLETON_ADDR,
+            Err(_) => false,
+        }
+    }
+
+    /// Checks that the caller of a privileged method is the governor of the gateway.
+    /// If governors are set, privileged methods can only be reached through an
+    /// approved governance action, which the gateway executes on itself.
+    fn validate_governor(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        let st: State = rt.state()?;
+        let governor = Self::governor_caller(rt, &st);
+        rt.validate_immediate_caller_is(std::iter::once(&governor))
+    }
+
+    /// Returns the address acting as the governor: the single governor or, once
+    /// governors are set, the gateway itself executing an approved governance action.
+    fn governor_caller(rt: &impl Runtime, st: &State) -> Address {
+        if st.governors.is_empty() {
+            st.governor
+        } else {
+            rt.message().receiver()
+        }
+    }
+
+    /// Checks that the caller is one of the governors that approve governance actions.
+    fn validate_governance_member(rt: &mut impl Runtime) -> Result<Address, ActorError> {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is(st.governors.iter())?;
+        Ok(rt.message().caller())
+    }
+
+    /// Executes an approved governance action by calling the gateway itself.
+    fn execute_gov_action(
+        rt: &mut impl Runtime,
+        id: u64,
+        proposal: GovProposal,
+    ) -> Result<(), ActorError> {
+        let gateway = rt.message().receiver();
+        rt.send(
+            &gateway,
+            proposal.method,
+            proposal.params.into(),
+            TokenAmount::zero(),
+        )?;
+        log::info!(
+            "GovActionExecuted: id={} method={} approvals={}",
+            id,
+            proposal.method,
+            proposal.approvals.len()
+        );
+        Ok(())
+    }
+
+    /// Rejects cross-messages carrying value while the gateway is in safe mode.
+    fn check_value_transfer(st: &State, value: &TokenAmount) -> Result<(), ActorError> {
+        st.check_value_transfer(value)
+            .map_err(|e| e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "cross-message rejected"))
+    }
+
+    /// Rejects dust cross-messages with a value below the minimum once the fee is deducted.
+    fn check_min_cross_value(st: &State, value: &TokenAmount) -> Result<(), ActorError> {
+        st.check_min_cross_value(value).map_err(|e| {
+            e.downcast_default(ExitCode::USR_ILLEGAL_ARGUMENT, "cross-message rejected")
+        })
+    }
+
+    /// Checks the value left by funds sent to `Fund` or `Release` once the fee is
+    /// deducted, before the caller is resolved. Values that don't cover the fee
+    /// are rejected when it is collected.
+    fn check_min_fund_value(
+        st: &State,
+        value: &TokenAmount,
+        fee: &TokenAmount,
+    ) -> Result<(), ActorError> {
+        if value < fee {
+            return Ok(());
+        }
+        Self::check_min_cross_value(st, &(value - fee))
+    }
+
+    /// Checks that the child subnet routing funds to `subnet` is registered and
+    /// active, so funds aren't stranded in a killed or unregistered subnet.
+    fn check_fundable_subnet(
+        rt: &impl Runtime,
+        st: &State,
+        subnet: &SubnetID,
+    ) -> Result<(), ActorError> {
+        let child = subnet.down(&st.network_name).ok_or_else(|| {
+            actor_error!(
+                illegal_argument,
+                "subnet {} is not a descendant of {}",
+                subnet,
+                st.network_name
+            )
+        })?;
+        let sub = st.get_subnet(rt.store(), &child).map_err(|e| {
+            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+        })?;
+        match sub {
+            Some(sub) if sub.status == Status::Active => Ok(()),
+            Some(_) => Err(actor_error!(
+                illegal_argument,
+                "can't fund inactive subnet {}",
+                child
+            )),
+            None => Err(actor_error!(
+                illegal_argument,
+                "subnet with id {} not registered",
+                child
+            )),
+        }
+    }
+
+    /// Add stake adds stake to the collateral of a subnet.
+    fn add_stake(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let subnet_addr = rt.message().caller();
+
+        let val = rt.message().value_received();
+        if val <= TokenAmount::zero() {
+            return Err(actor_error!(illegal_argument, "no stake to add"));
+        }
+
+        let (shid, reactivated) = rt.transaction(|st: &mut State, rt| {
+            let shid = SubnetID::new_from_parent(&st.network_name, subnet_addr);
+            let sub = st.get_subnet(rt.store(), &shid).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+            })?;
+            match sub {
+                Some(mut sub) => {
+                    let reactivated = sub.add_stake(rt, st, &val).map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            "Failed to add stake to subnet",
+                        )
+                    })?;
+                    Ok((shid, reactivated))
+                }
+                None => Err(actor_error!(
+                    illegal_argument,
+                    "subnet with id {} not registered",
+                    shid
+                )),
+            }
+        })?;
+
+        if reactivated {
+            log::info!("SubnetReactivated: id={}", shid);
+        }
+        Ok(())
+    }
+
+    /// ReactivateSubnet flips an inactive subnet back to active if it holds
+    /// enough stake. Reactivating an active subnet is a no-op.
+    ///
+    /// It returns true if the subnet was reactivated by the call.
+    fn reactivate_subnet(rt: &mut impl Runtime) -> Result<bool, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let subnet_addr = rt.message().caller();
+
+        let (shid, reactivated) = rt.transaction(|st: &mut State, rt| {
+            let shid = SubnetID::new_from_parent(&st.network_name, subnet_addr);
+            let sub = st.get_subnet(rt.store(), &shid).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+            })?;
+            let mut sub = match sub {
+                Some(sub) => sub,
+                None => {
+                    return Err(actor_error!(
+                        illegal_argument,
+                        "subnet with id {} not registered",
+                        shid
+                    ));
+                }
+            };
+            if sub.stake < st.min_stake {
+                return Err(actor_error!(
+                    illegal_state,
+                    "subnet {} doesn't hold enough stake to be active",
+                    shid
+                ));
+            }
+            let reactivated = sub.update_status(&st.min_stake);
+            if reactivated {
+                st.flush_subnet(rt.store(), &sub).map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to flush subnet")
+                })?;
+            }
+            Ok((shid, reactivated))
+        })?;
+
+        if reactivated {
+            log::info!("SubnetReactivated: id={}", shid);
+        }
+        Ok(reactivated)
+    }
+
+    /// Release stake recovers some collateral of the subnet
+    fn release_stake(rt: &mut impl Runtime, params: FundParams) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let subnet_addr = rt.message().caller();
+
+        let send_val = params.value;
+
+        if send_val <= TokenAmount::zero() {
+            return Err(actor_error!(
+                illegal_argument,
+                "no funds to release in params"
+            ));
+        }
+
+        rt.transaction(|st: &mut State, rt| {
+            let shid = SubnetID::new_from_parent(&st.network_name, subnet_addr);
+            let sub = st.get_subnet(rt.store(), &shid).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+            })?;
+            match sub {
+                Some(mut sub) => {
+                    if sub.stake < send_val {
+                        return Err(actor_error!(
+                            illegal_state,
+                            "subnet actor not allowed to release so many funds"
+                        ));
+                    }
+                    // sanity-check: see if the actor has enough balance.
+                    if rt.current_balance() < send_val {
+                        return Err(actor_error!(
+                            illegal_state,
+                            "something went really wrong! the actor doesn't have enough balance to release"
+                        ));
+                    }
+                    sub.add_stake(rt, st, &-send_val.clone()).map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            "Failed to add stake to subnet",
+                        )
+                    })?;
+                }
+                None => {
+                    return Err(actor_error!(
+                        illegal_argument,
+                        "subnet with id {} not registered",
+                        shid
+                    ));
+                }
+            }
+
+            Ok(())
+        })?;
+
+        rt.send(&subnet_addr, METHOD_SEND, None, send_val.clone())?;
+        Ok(())
+    }
+
+    /// Kill propagates the kill signal from a subnet actor to unregister it from th
+    /// hierarchy.
+    fn kill(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let subnet_addr = rt.message().caller();
+        let mut send_val = TokenAmount::zero();
+        let mut held_fee = TokenAmount::zero();
+
+        rt.transaction(|st: &mut State, rt| {
+            let shid = SubnetID::new_from_parent(&st.network_name, subnet_addr);
+            let sub = st.get_subnet(rt.store(), &shid).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+            })?;
+            match sub {
+                Some(mut sub) => {
+                    if rt.current_balance() < sub.stake {
+                        return Err(actor_error!(
+                            illegal_state,
+                            "something went really wrong! the actor doesn't have enough balance to release"
+                        ));
+                    }
+                    if sub.circ_supply > TokenAmount::zero() {
+                        return Err(actor_error!(
+                            illegal_state,
+                            "cannot kill a subnet that still holds user funds in its circ. supply"
+                        ));
+                    }
+                    held_fee = st
+                        .release_unapplied_topdown_fees(rt.store(), &mut sub)
+                        .map_err(|e| {
+                            e.downcast_default(
+                                ExitCode::USR_ILLEGAL_STATE,
+                                "failed to release top-down fees",
+                            )
+                        })?;
+                    send_val = sub.stake;
+                    // delete subnet
+                    st.rm_subnet(rt.store(), &shid, rt.curr_epoch())
+                        .map_err(|e| {
+                            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+                        })?;
+                }
+                None => {
+                    return Err(actor_error!(
+                        illegal_argument,
+                        "subnet with id {} not registered",
+                        shid
+                    ));
+                }
+            }
+
+            Ok(())
+        })?;
+
+        rt.send(&subnet_addr, METHOD_SEND, None, send_val.clone())?;
+        distribute_crossmsg_fee(rt, &subnet_addr, held_fee)
+    }
+
+    /// ForceKill unregisters a stuck subnet that still holds funds in its circulating
+    /// supply. The residual supply is swept to a recovery address and the stake is
+    /// returned to the subnet actor, along with the fees held for the top-down
+    /// messages it didn't apply.
+    ///
+    /// It can only be called by the governor.
+    fn force_kill(rt: &mut impl Runtime, params: ForceKillParams) -> Result<(), ActorError> {
+        Self::validate_governor(rt)?;
+
+        let ForceKillParams {
+            subnet: shid,
+            recovery_addr,
+        } = params;
+        let (sub, held_fee) = rt.transaction(|st: &mut State, rt| {
+            let sub = st.get_subnet(rt.store(), &shid).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+            })?;
+            let mut sub = match sub {
+                Some(sub) => sub,
+                None => {
+                    return Err(actor_error!(
+                        illegal_argument,
+                        "subnet with id {} not registered",
+                        shid
+                    ));
+                }
+            };
+            if rt.current_balance() < &sub.stake + &sub.circ_supply {
+                return Err(actor_error!(
+                    illegal_state,
+                    "something went really wrong! the actor doesn't have enough balance to release"
+                ));
+            }
+            let held_fee = st
+                .release_unapplied_topdown_fees(rt.store(), &mut sub)
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_STATE,
+                        "failed to release top-down fees",
+                    )
+                })?;
+            st.rm_subnet(rt.store(), &shid, rt.curr_epoch())
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to remove subnet")
+                })?;
+            Ok((sub, held_fee))
+        })?;
+
+        if !sub.circ_supply.is_zero() {
+            rt.send(&recovery_addr, METHOD_SEND, None, sub.circ_supply.clone())?;
+        }
+        rt.send(&shid.subnet_actor(), METHOD_SEND, None, sub.stake.clone())?;
+        distribute_crossmsg_fee(rt, &shid.subnet_actor(), held_fee)?;
+
+        log::info!(
+            "SubnetForceKilled: id={} swept={} recovery={}",
+            shid,
+            sub.circ_supply,
+            recovery_addr
+        );
+        Ok(())
+    }
+
+    /// CommitChildCheck propagates the commitment of a checkpoint from a child subnet,
+    /// process the cross-messages directed to the subnet.
+    fn commit_child_check(rt: &mut impl Runtime, params: Checkpoint) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let subnet_addr = rt.message().caller();
+        let commit = params;
+        let subnet_actor = commit.source().subnet_actor();
+
+        // check if the checkpoint belongs to the subnet
+        if subnet_addr != subnet_actor {
+            return Err(actor_error!(
+                illegal_argument,
+                "source in checkpoint doesn't belong to subnet"
+            ));
+        }
+
+        let rewards = commit.rewards().clone();
+        let (fee, forwarded, topdown_fee, refunds) = rt.transaction(|st: &mut State, rt| {
+            let shid = SubnetID::new_from_parent(&st.network_name, subnet_addr);
+            let sub = st.get_subnet(rt.store(), &shid).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+            })?;
 
             let mut fee = TokenAmount::zero();
+            let mut forwarded = TokenAmount::zero();
+            let mut topdown_fee = TokenAmount::zero();
+            let mut refunds = Vec::new();
             match sub {
                 Some(mut sub) => {
                     // check if subnet active
@@ -299,6 +1275,52 @@ impl Actor {
                         ));
                     }
 
+                    // if this is not the first checkpoint we need to perform some
+                    // additional verifications.
+                    sub.verify_prev_checkpoint(&commit).map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_ARGUMENT,
+                            "error verifying previous checkpoint",
+                        )
+                    })?;
+
+                    // track the top-down messages applied by the subnet
+                    let applied = commit.data.applied_topdown_nonce;
+                    if applied > sub.nonce {
+                        return Err(actor_error!(
+                            illegal_argument,
+                            "checkpoint reports {} top-down messages applied, but only {} were committed",
+                            applied,
+                            sub.nonce
+                        ));
+                    }
+
+                    // cancel the top-down messages the subnet acknowledged it skipped
+                    // and release the fees held for the ones it applied.
+                    for nonce in commit.data.cancelled_topdown_msgs.iter() {
+                        let refund = st
+                            .cancel_topdown_msg(rt.store(), &mut sub, *nonce, applied)
+                            .map_err(|e| {
+                                e.downcast_default(
+                                    ExitCode::USR_ILLEGAL_ARGUMENT,
+                                    "error cancelling top-down message",
+                                )
+                            })?;
+                        refunds.push(refund);
+                    }
+                    if applied > sub.applied_topdown_nonce {
+                        let prev = sub.applied_topdown_nonce;
+                        topdown_fee = st
+                            .release_topdown_fees(rt.store(), &mut sub, prev, applied)
+                            .map_err(|e| {
+                                e.downcast_default(
+                                    ExitCode::USR_ILLEGAL_STATE,
+                                    "error releasing top-down fees",
+                                )
+                            })?;
+                        sub.applied_topdown_nonce = applied;
+                    }
+
                     // get window checkpoint being populated to include child info
                     let mut ch = st
                         .get_window_checkpoint(rt.store(), rt.curr_epoch())
@@ -309,50 +1331,57 @@ impl Actor {
                             )
                         })?;
 
-                    // if this is not the first checkpoint we need to perform some
-                    // additional verifications.
-                    if let Some(ref prev_checkpoint) = sub.prev_checkpoint {
-                        if prev_checkpoint.epoch() > commit.epoch() {
-                            return Err(actor_error!(
-                                illegal_argument,
-                                "checkpoint being committed belongs to the past"
-                            ));
-                        }
-                        // check that the previous cid is consistent with the previous one
-                        if commit.prev_check().cid() != prev_checkpoint.cid() {
+                    // all the checks and updates are performed in memory before
+                    // any of them is flushed, so a failure never leaves the
+                    // commitment half-applied.
+                    if let Some(cross_msg) = commit.cross_msgs() {
+                        // prevent checkpoints carrying more messages than the ones
+                        // that can be safely processed in a single commitment.
+                        if cross_msg.num_msgs > st.max_msgs_per_checkpoint {
                             return Err(actor_error!(
                                 illegal_argument,
-                                "previous checkpoint not consistente with previous one"
+                                "checkpoint carries {} cross-msgs, exceeding the maximum of {}",
+                                cross_msg.num_msgs,
+                                st.max_msgs_per_checkpoint
                             ));
                         }
+                        // the fees distributed must be the ones paid by the messages.
+                        // This also rejects metas including messages without declaring
+                        // them, which would otherwise skip the limit above.
+                        cross_msg.verify_fee(rt.store()).map_err(|e| {
+                            e.downcast_default(
+                                ExitCode::USR_ILLEGAL_ARGUMENT,
+                                "error verifying checkpoint fee",
+                            )
+                        })?;
+
+                        // release circulating supply
+                        sub.release_supply(&cross_msg.value).map_err(|e| {
+                            e.downcast_default(
+                                ExitCode::USR_ILLEGAL_STATE,
+                                "error releasing circulating supply",
+                            )
+                        })?;
+
+                        fee = cross_msg.fee.clone();
                     }
 
-                    // commit cross-message in checkpoint to either execute them or
-                    // queue them for propagation if there are cross-msgs availble.
-                    match commit.cross_msgs() {
-                        Some(cross_msg) => {
-                            // if tcid not default it means cross-msgs are being propagated.
-                            if cross_msg.msgs_cid != TCid::default() {
-                                st.store_bottomup_msg(rt.store(), cross_msg).map_err(|e| {
-                                    e.downcast_default(
-                                        ExitCode::USR_ILLEGAL_STATE,
-                                        "error storing bottom_up messages from checkpoint",
-                                    )
-                                })?;
-                            }
-
-                            // release circulating supply
-                            sub.release_supply(&cross_msg.value).map_err(|e| {
-                                e.downcast_default(
-                                    ExitCode::USR_ILLEGAL_STATE,
-                                    "error releasing circulating supply",
-                                )
-                            })?;
+                    // keep the share of the fees for the validators of this subnet
+                    // in its own checkpoint, following the bottom-up fee policy.
+                    forwarded = st.forwarded_fee(&fee);
 
-                            // distribute fee
-                            fee = cross_msg.fee.clone();
-                        }
-                        None => {}
+                    // the rewards distributed can't exceed the fees paid to the child
+                    let child_fee = &fee - &forwarded;
+                    let total_rewards = rewards
+                        .iter()
+                        .fold(TokenAmount::zero(), |acc, r| acc + &r.amount);
+                    if total_rewards > child_fee {
+                        return Err(actor_error!(
+                            illegal_argument,
+                            "rewards in checkpoint ({}) exceed the fees accrued ({})",
+                            total_rewards,
+                            child_fee
+                        ));
                     }
 
                     // append new checkpoint to the list of childs
@@ -362,6 +1391,26 @@ impl Actor {
                             "error adding child checkpoint",
                         )
                     })?;
+                    if !forwarded.is_zero() {
+                        ch.add_fee(&forwarded);
+                    }
+
+                    // commit cross-message in checkpoint to either execute them or
+                    // queue them for propagation if there are cross-msgs availble.
+                    // if tcid not default it means cross-msgs are being propagated.
+                    if let Some(cross_msg) = commit.cross_msgs() {
+                        if cross_msg.msgs_cid != TCid::default() {
+                            st.store_bottomup_msg(rt.store(), cross_msg).map_err(|e| {
+                                e.downcast_default(
+                                    ExitCode::USR_ILLEGAL_STATE,
+                                    "error storing bottom_up messages from checkpoint",
+                                )
+                            })?;
+                        }
+                    }
+                    // the fees paid to the child are distributed after the commitment
+                    st.pending_fee_distribution += &child_fee;
+                    st.pending_fee_distribution += &topdown_fee;
 
                     // flush checkpoint
                     st.flush_checkpoint(rt.store(), &ch).map_err(|e| {
@@ -384,11 +1433,290 @@ impl Actor {
                 }
             }
 
-            Ok(fee)
+            Ok((fee, forwarded, topdown_fee, refunds))
+        })?;
+
+        // distribute rewards following the manifest and the remainder of
+        // the fees through the default distribution.
+        let distributed = distribute_reward_manifest(rt, &subnet_actor, rewards)?;
+        distribute_crossmsg_fee(
+            rt,
+            &subnet_actor,
+            fee - forwarded - distributed + topdown_fee,
+        )?;
+
+        // refund the value and fee of the top-down messages cancelled
+        for (from, refund) in refunds {
+            rt.send(&from, METHOD_SEND, None, refund)?;
+        }
+        Ok(())
+    }
+
+    /// PruneCheckpoints removes the checkpoints older than the retention window
+    /// configured in the gateway, returning the number of checkpoints pruned.
+    fn prune_checkpoints(rt: &mut impl Runtime) -> Result<u64, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        rt.transaction(|st: &mut State, rt| {
+            st.prune_checkpoints(rt.store(), rt.curr_epoch())
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to prune checkpoints")
+                })
+        })
+    }
+
+    /// GetPrevCheckpoint returns the latest checkpoint committed by a child subnet,
+    /// if any. Relayers use it to set the right `prev_check` in the next checkpoint.
+    fn get_prev_checkpoint(
+        rt: &mut impl Runtime,
+        params: SubnetID,
+    ) -> Result<Option<Checkpoint>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let sub = st.get_subnet(rt.store(), &params).map_err(|e| {
+            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+        })?;
+        match sub {
+            Some(sub) => Ok(sub.prev_checkpoint),
+            None => Err(actor_error!(
+                illegal_argument,
+                "subnet with id {} not registered",
+                params
+            )),
+        }
+    }
+
+    /// GetLastCommittedEpoch returns the epoch of the latest checkpoint committed
+    /// by a child subnet, or `None` if it hasn't committed any yet.
+    fn get_last_committed_epoch(
+        rt: &mut impl Runtime,
+        params: SubnetID,
+    ) -> Result<Option<ChainEpoch>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let sub = st.get_subnet(rt.store(), &params).map_err(|e| {
+            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+        })?;
+        match sub {
+            Some(sub) => Ok(sub.prev_checkpoint.map(|c| c.epoch())),
+            None => Err(actor_error!(
+                illegal_argument,
+                "subnet with id {} not registered",
+                params
+            )),
+        }
+    }
+
+    /// GetCollateralization returns the stake of a child subnet compared to its
+    /// circulating supply. Subnets with a low ratio are under-collateralized.
+    fn get_collateralization(
+        rt: &mut impl Runtime,
+        params: SubnetID,
+    ) -> Result<Collateralization, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let sub = st.get_subnet(rt.store(), &params).map_err(|e| {
+            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
         })?;
+        match sub {
+            Some(sub) => Ok(sub.collateralization()),
+            None => Err(actor_error!(
+                illegal_argument,
+                "subnet with id {} not registered",
+                params
+            )),
+        }
+    }
+
+    /// GetAncestry returns the ancestors of a subnet below the current network,
+    /// from its immediate parent up to the root.
+    fn get_ancestry(rt: &mut impl Runtime, params: SubnetID) -> Result<Vec<SubnetID>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        if params == st.network_name || !is_ancestor(&st.network_name, &params) {
+            return Err(actor_error!(
+                illegal_argument,
+                "subnet {} is not a descendant of {}",
+                params,
+                st.network_name
+            ));
+        }
+
+        let mut ancestry = Vec::new();
+        let mut curr = params.parent();
+        while let Some(parent) = curr {
+            curr = parent.parent();
+            ancestry.push(parent);
+        }
+        Ok(ancestry)
+    }
+
+    /// ComputeSubnetID returns the id the gateway assigns to the subnet of a subnet
+    /// actor when it registers. Subnet actors register with their ID address, so
+    /// that is the address expected.
+    fn compute_subnet_id(rt: &mut impl Runtime, params: Address) -> Result<SubnetID, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        Ok(SubnetID::new_from_parent(&st.network_name, params))
+    }
+
+    /// GetPendingFeeDistribution returns the top-down fees collected by the gateway
+    /// that haven't been forwarded to their subnet actors yet.
+    fn get_pending_fee_distribution(rt: &mut impl Runtime) -> Result<TokenAmount, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        Ok(st.pending_fee_distribution)
+    }
+
+    /// GetTotalBurned returns the total amount of funds burnt by the gateway
+    /// for bottom-up messages leaving the current network.
+    fn get_total_burned(rt: &mut impl Runtime) -> Result<TokenAmount, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        Ok(st.total_burned)
+    }
+
+    /// GetStateRoot returns the cid of the current state of the gateway, so
+    /// light clients can anchor to the exact root committed.
+    fn get_state_root(rt: &mut impl Runtime) -> Result<Cid, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+        rt.get_state_root()
+    }
+
+    /// GetTotalFeesCollected returns the total amount of cross-message fees
+    /// collected by the gateway since genesis.
+    fn get_total_fees_collected(rt: &mut impl Runtime) -> Result<TokenAmount, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        Ok(st.total_fees_collected)
+    }
+
+    /// GetActorInfo returns the version of the actor, the methods it supports
+    /// and the optional features enabled in its state, so clients can negotiate
+    /// the capabilities of the gateway they are talking to.
+    fn get_actor_info(rt: &mut impl Runtime) -> Result<ActorInfo, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        Ok(ActorInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            state_version: st.version,
+            methods: SUPPORTED_METHODS.to_vec(),
+            features: st.enabled_features(),
+        })
+    }
+
+    /// GetQueueDepths returns the number of top-down and bottom-up messages
+    /// pending to be relayed, to help detect stalled relayers.
+    fn get_queue_depths(rt: &mut impl Runtime) -> Result<QueueDepths, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        st.queue_depths(rt.store(), rt.curr_epoch()).map_err(|e| {
+            e.downcast_default(
+                ExitCode::USR_ILLEGAL_STATE,
+                "failed to compute queue depths",
+            )
+        })
+    }
+
+    /// GetGatewayStats returns a snapshot of the health of the gateway: its
+    /// subnets, the funds they hold, the queues of cross-messages pending, the
+    /// funds burnt and the nonces applied.
+    fn get_gateway_stats(rt: &mut impl Runtime) -> Result<GatewayStats, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        st.stats(rt.store(), rt.curr_epoch()).map_err(|e| {
+            e.downcast_default(
+                ExitCode::USR_ILLEGAL_STATE,
+                "failed to compute gateway stats",
+            )
+        })
+    }
+
+    /// GetCrossMsgStatus returns where a cross-message is in its lifecycle so
+    /// the senders of `Fund` or `SendCross` messages can track them.
+    fn get_cross_msg_status(
+        rt: &mut impl Runtime,
+        params: GetCrossMsgStatusParams,
+    ) -> Result<CrossMsgStatus, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        st.cross_msg_status(rt.store(), &params).map_err(|e| {
+            e.downcast_default(
+                ExitCode::USR_ILLEGAL_STATE,
+                "failed to look up cross-message",
+            )
+        })
+    }
+
+    /// GetBottomUpMsg returns the bottom-up message with the given nonce committed
+    /// in the checkpoint of an epoch, so relayers can fetch a single message
+    /// instead of the whole window.
+    fn get_bottomup_msg(
+        rt: &mut impl Runtime,
+        params: GetBottomUpMsgParams,
+    ) -> Result<Option<CrossMsg>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        st.get_bottomup_msg_by_nonce(rt.store(), params.epoch, params.nonce)
+            .map_err(|e| {
+                e.downcast_default(
+                    ExitCode::USR_ILLEGAL_ARGUMENT,
+                    "failed to get bottom-up message",
+                )
+            })
+    }
+
+    /// GetFeeSchedule returns the fees charged for cross-messages so clients can
+    /// compute the exact cost of a message before submitting it.
+    ///
+    /// Cross-messages are charged a flat fee that depends on their kind and is fully
+    /// distributed to the subnet actors. The base fee is the one of `SendCross`.
+    /// Addresses exempted by the governor aren't charged.
+    fn get_fee_schedule(rt: &mut impl Runtime) -> Result<FeeSchedule, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let fees = st.cross_msg_fees;
+        Ok(FeeSchedule {
+            base_fee: fees.send_cross.clone(),
+            min_fee: fees.min().clone(),
+            max_fee: fees.max().clone(),
+            msg_fees: fees,
+        })
+    }
+
+    /// DryRunCrossMsg returns the route that the cross-message would follow if it
+    /// was committed in the current network, without committing it.
+    fn dry_run_cross_msg(
+        rt: &mut impl Runtime,
+        params: StorableMsg,
+    ) -> Result<RouteDecision, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let sto = params
+            .to
+            .subnet()
+            .map_err(|_| actor_error!(illegal_argument, "error getting subnet from msg"))?;
+        let sfrom = params
+            .from
+            .subnet()
+            .map_err(|_| actor_error!(illegal_argument, "error getting subnet from msg"))?;
 
-        // distribute rewards
-        distribute_crossmsg_fee(rt, &subnet_actor, fee)
+        let st: State = rt.state()?;
+        Ok(route_cross_msg(&st.network_name, &sfrom, &sto))
     }
 
     /// Fund injects new funds from an account of the parent chain to a subnet.
@@ -399,8 +1727,65 @@ impl Actor {
     /// picked up by miners to include it in the next possible block.
     /// - The cross-message nonce is updated.
     fn fund(rt: &mut impl Runtime, params: SubnetID) -> Result<(), ActorError> {
+        Self::fund_subnet(rt, params, Vec::new(), None, None)
+    }
+
+    /// FundWithMemo works like `Fund`, attaching a memo to the fund message so
+    /// it can be correlated in the destination subnet.
+    fn fund_with_memo(rt: &mut impl Runtime, params: FundWithMemoParams) -> Result<(), ActorError> {
+        Self::fund_subnet(rt, params.subnet, params.memo, None, None)
+    }
+
+    /// FundWithTarget works like `Fund`, injecting exactly `target_amount` in the
+    /// subnet. The funds sent need to cover the target and the cross-message fee,
+    /// and the surplus is refunded to the caller.
+    fn fund_with_target(
+        rt: &mut impl Runtime,
+        params: FundWithTargetParams,
+    ) -> Result<(), ActorError> {
+        Self::fund_subnet(
+            rt,
+            params.subnet,
+            Vec::new(),
+            Some(params.target_amount),
+            None,
+        )
+    }
+
+    /// FundOnBehalf works like `Fund`, but credits `beneficiary` in the subnet
+    /// instead of the caller. It is meant for custodial bridges funding subnets on
+    /// behalf of their users. The fund message is wrapped so the original sender
+    /// is preserved when the message is applied in the subnet.
+    fn fund_on_behalf(rt: &mut impl Runtime, params: FundOnBehalfParams) -> Result<(), ActorError> {
+        Self::fund_subnet(
+            rt,
+            params.subnet,
+            Vec::new(),
+            None,
+            Some(params.beneficiary),
+        )
+    }
+
+    fn fund_subnet(
+        rt: &mut impl Runtime,
+        params: SubnetID,
+        memo: Vec<u8>,
+        target_amount: Option<TokenAmount>,
+        beneficiary: Option<Address>,
+    ) -> Result<(), ActorError> {
         // funds can only be moved between subnets by signable addresses
         rt.validate_immediate_caller_type(CALLER_TYPES_SIGNABLE.iter())?;
+        validate_memo(&memo)?;
+        if let Some(b) = &beneficiary {
+            // ID addresses are not portable across subnets
+            if b.protocol() == Protocol::ID {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "fund beneficiary {} can't be an ID address",
+                    b
+                ));
+            }
+        }
 
         let mut value = rt.message().value_received();
         if value <= TokenAmount::zero() {
@@ -410,36 +1795,179 @@ impl Actor {
             ));
         }
 
-        let sig_addr = resolve_secp_bls(rt, &rt.message().caller())?;
+        let st: State = rt.state()?;
+        Self::check_value_transfer(&st, &value)?;
+        let fee = st.cross_msg_fee(&rt.message().caller(), CrossMsgKind::Fund);
+
+        let mut surplus = TokenAmount::zero();
+        if let Some(target) = target_amount {
+            if target <= TokenAmount::zero() {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "fund target must be positive"
+                ));
+            }
+            let required = &target + &fee;
+            if value < required {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "funds sent {} don't cover the fund target and the fee {}",
+                    value,
+                    required
+                ));
+            }
+            surplus = value - required;
+            value = &target + &fee;
+        }
+        Self::check_min_fund_value(&st, &value, &fee)?;
+        Self::check_fundable_subnet(rt, &st, &params)?;
 
-        let fee = CROSS_MSG_FEE.clone();
+        let sig_addr = resolve_secp_bls(rt, &rt.message().caller())?;
         rt.transaction(|st: &mut State, rt| {
             st.collect_cross_fee(&mut value, &fee)?;
             // Create fund message
+            let to = beneficiary.unwrap_or(sig_addr);
             let mut f_msg = CrossMsg {
-                msg: StorableMsg::new_fund_msg(&params, &sig_addr, value).map_err(|e| {
+                msg: StorableMsg::new_fund_to_msg(&params, &sig_addr, &to, value).map_err(|e| {
                     e.downcast_default(
                         ExitCode::USR_ILLEGAL_STATE,
                         "error creating fund cross-message",
                     )
                 })?,
-                wrapped: false,
+                wrapped: beneficiary.is_some(),
             };
+            f_msg.msg.memo = memo;
+
+            log::debug!("fund cross msg is: {:?}", f_msg);
+
+            // Commit top-down message.
+            st.commit_topdown_msg(rt.store(), &mut f_msg, &fee)
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_STATE,
+                        "error committing top-down message",
+                    )
+                })?;
+            Ok(())
+        })?;
+
+        // refund any funds sent over the fund target and fee
+        if !surplus.is_zero() {
+            let caller = rt.message().caller();
+            rt.send(&caller, METHOD_SEND, None, surplus)?;
+        }
+        Ok(())
+    }
+
+    /// FundMulti funds several subnets from a single call. Each entry specifies the
+    /// amount to be injected in the destination subnet, and the value received must
+    /// cover the sum of all amounts plus the cross-message fee of every entry.
+    ///
+    /// All the top-down messages are committed in the same transaction, so if any
+    /// of the destinations is invalid no subnet is funded.
+    fn fund_multi(
+        rt: &mut impl Runtime,
+        params: Vec<(SubnetID, TokenAmount)>,
+    ) -> Result<(), ActorError> {
+        // funds can only be moved between subnets by signable addresses
+        rt.validate_immediate_caller_type(CALLER_TYPES_SIGNABLE.iter())?;
+
+        if params.is_empty() {
+            return Err(actor_error!(
+                illegal_argument,
+                "no destinations included in fund message"
+            ));
+        }
+        if params
+            .iter()
+            .any(|(_, amount)| amount <= &TokenAmount::zero())
+        {
+            return Err(actor_error!(
+                illegal_argument,
+                "fund amounts must be positive"
+            ));
+        }
+
+        let st: State = rt.state()?;
+        Self::check_value_transfer(&st, &rt.message().value_received())?;
+        let fee = st.cross_msg_fee(&rt.message().caller(), CrossMsgKind::Fund);
+        let expected = params
+            .iter()
+            .fold(TokenAmount::zero(), |acc, (_, amount)| acc + amount + &fee);
+        let value = rt.message().value_received();
+        if value != expected {
+            return Err(actor_error!(
+                illegal_argument,
+                "value received {} doesn't match funds plus fees {}",
+                value,
+                expected
+            ));
+        }
+        for (subnet, amount) in params.iter() {
+            Self::check_min_cross_value(&st, amount)?;
+            Self::check_fundable_subnet(rt, &st, subnet)?;
+        }
+
+        let sig_addr = resolve_secp_bls(rt, &rt.message().caller())?;
+
+        rt.transaction(|st: &mut State, rt| {
+            for (subnet, amount) in params.iter() {
+                let mut charged = amount + &fee;
+                st.collect_cross_fee(&mut charged, &fee)?;
+                let mut f_msg = CrossMsg {
+                    msg: StorableMsg::new_fund_msg(subnet, &sig_addr, amount.clone()).map_err(
+                        |e| {
+                            e.downcast_default(
+                                ExitCode::USR_ILLEGAL_STATE,
+                                "error creating fund cross-message",
+                            )
+                        },
+                    )?,
+                    wrapped: false,
+                };
+
+                st.commit_topdown_msg(rt.store(), &mut f_msg, &fee)
+                    .map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            format!("error committing top-down message to {}", subnet),
+                        )
+                    })?;
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// CancelFund requests the cancellation of a fund message that hasn't been
+    /// applied yet in the subnet.
+    ///
+    /// The gateway can't tell if the subnet already applied the message, so it
+    /// is only cancelled once the subnet acknowledges in a checkpoint that it
+    /// skipped it. The message is then removed from the top-down queue and its
+    /// value and fee are refunded to the original sender.
+    fn cancel_fund(rt: &mut impl Runtime, params: CancelFundParams) -> Result<(), ActorError> {
+        // funds can only be moved between subnets by signable addresses
+        rt.validate_immediate_caller_type(CALLER_TYPES_SIGNABLE.iter())?;
 
-            log::debug!("fund cross msg is: {:?}", f_msg);
+        let sig_addr = resolve_secp_bls(rt, &rt.message().caller())?;
 
-            // Commit top-down message.
-            st.commit_topdown_msg(rt.store(), &mut f_msg).map_err(|e| {
-                e.downcast_default(
-                    ExitCode::USR_ILLEGAL_STATE,
-                    "error committing top-down message",
-                )
-            })?;
-            Ok(())
+        rt.transaction(|st: &mut State, rt| {
+            st.request_topdown_cancel(rt.store(), &params.subnet, params.nonce, &sig_addr)
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_ARGUMENT,
+                        "error requesting the cancellation of top-down message",
+                    )
+                })
         })?;
 
-        // distribute top-down message fee to validators.
-        distribute_crossmsg_fee(rt, &params.subnet_actor(), fee)
+        log::info!(
+            "cancellation of top-down message {} to {} requested",
+            params.nonce,
+            params.subnet
+        );
+        Ok(())
     }
 
     /// Release creates a new check message to release funds in parent chain
@@ -448,6 +1976,19 @@ impl Actor {
     /// and propagates a new checkpoint message to the parent chain to signal
     /// the amount of funds that can be released for a specific address.
     fn release(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        Self::release_funds(rt, None)
+    }
+
+    /// ReleaseToSubnet releases funds to an ancestor of the current subnet
+    /// further up the hierarchy than the immediate parent.
+    ///
+    /// The release message is committed bottom-up as in Release, and every
+    /// subnet in between forwards it until it reaches the target.
+    fn release_to_subnet(rt: &mut impl Runtime, target: SubnetID) -> Result<(), ActorError> {
+        Self::release_funds(rt, Some(target))
+    }
+
+    fn release_funds(rt: &mut impl Runtime, target: Option<SubnetID>) -> Result<(), ActorError> {
         // funds can only be moved between subnets by signable addresses
         rt.validate_immediate_caller_type(CALLER_TYPES_SIGNABLE.iter())?;
 
@@ -462,16 +2003,29 @@ impl Actor {
             ));
         }
 
-        let sig_addr = resolve_secp_bls(rt, &rt.message().caller())?;
+        let st: State = rt.state()?;
+        Self::check_value_transfer(&st, &value)?;
+        let fee = st.cross_msg_fee(&rt.message().caller(), CrossMsgKind::Release);
+        Self::check_min_fund_value(&st, &value, &fee)?;
 
+        let sig_addr = resolve_secp_bls(rt, &rt.message().caller())?;
         rt.transaction(|st: &mut State, rt| {
-            let fee = &CROSS_MSG_FEE;
             // collect fees
             st.collect_cross_fee(&mut value, &fee)?;
 
             // Create release message
-            let r_msg = CrossMsg {
-                msg: StorableMsg::new_release_msg(
+            let msg = match &target {
+                Some(target) => StorableMsg::new_release_to_msg(
+                    &st.network_name,
+                    target,
+                    &sig_addr,
+                    value.clone(),
+                    st.nonce,
+                )
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_ARGUMENT, "invalid release target")
+                })?,
+                None => StorableMsg::new_release_msg(
                     &st.network_name,
                     &sig_addr,
                     value.clone(),
@@ -483,11 +2037,14 @@ impl Actor {
                         "error creating release cross-message",
                     )
                 })?,
+            };
+            let mut r_msg = CrossMsg {
+                msg,
                 wrapped: false,
             };
 
             // Commit bottom-up message.
-            st.commit_bottomup_msg(rt.store(), &r_msg, &fee, rt.curr_epoch())
+            st.commit_bottomup_msg(rt.store(), &mut r_msg, &fee, rt.curr_epoch())
                 .map_err(|e| {
                     e.downcast_default(
                         ExitCode::USR_ILLEGAL_STATE,
@@ -498,7 +2055,8 @@ impl Actor {
         })?;
 
         // burn funds that are send as bottom-up
-        burn_bu_funds(rt, value)
+        let st: State = rt.state()?;
+        burn_bu_funds(rt, &st.network_name, value)
     }
 
     /// SendCross sends an arbitrary cross-message to other subnet in the hierarchy.
@@ -529,7 +2087,17 @@ impl Actor {
             mut cross_msg,
             destination,
         } = params;
-        let (mut do_burn, mut top_down_fee) = (false, TokenAmount::zero());
+        validate_memo(&cross_msg.msg.memo)?;
+
+        let st: State = rt.state()?;
+        if destination == st.network_name && st.allow_local_cross {
+            Self::check_value_transfer(&st, &cross_msg.msg.value)?;
+            return Self::send_local(rt, cross_msg);
+        }
+        let fee = st.cross_msg_fee(&rt.message().caller(), CrossMsgKind::SendCross);
+
+        let mut do_burn = false;
+        let mut surplus = TokenAmount::zero();
 
         rt.transaction(|st: &mut State, rt| {
             if destination == st.network_name {
@@ -542,6 +2110,16 @@ impl Actor {
             // message.
             let msg = &mut cross_msg.msg;
             let to = msg.to.raw_addr().map_err(|_| actor_error!(illegal_argument, "invalid to addr"))?;
+            if matches!(to.protocol(), Protocol::Secp256k1 | Protocol::BLS) {
+                if st.require_actor_destination {
+                    return Err(actor_error!(
+                        illegal_argument,
+                        "cross-msg destination {} is an account, not an actor",
+                        to
+                    ));
+                }
+                log::warn!("cross-msg sent to account address {} in {}", to, destination);
+            }
             msg.to = match IPCAddress::new(&destination, &to) {
                 Ok(addr) => addr,
                 Err(_) => {
@@ -561,33 +2139,83 @@ impl Actor {
                 }
             };
 
-            // check that the right funds were sent in message
-            // TODO: The cross_message fee will be deducted from the value of the
-            // cross-message. Should we deduct it before this check? Or should we even
-            // remove this check and return the remainder of the value sent in the message
-            // and the cross-fee to the originating contract?
-            if rt.message().value_received() != msg.value {
-                return Err(actor_error!(
-                    illegal_argument,
-                    "the funds in cross-msg params are not equal to the ones sent in the message"
-                ));
+            // check that the right funds were sent in message and collect cross-fee
+            let value_received = rt.message().value_received();
+            match st.send_cross_fee_policy {
+                SendCrossFeePolicy::Deduct => {
+                    if value_received != msg.value {
+                        return Err(actor_error!(
+                            illegal_argument,
+                            "the funds in cross-msg params are not equal to the ones sent in the message"
+                        ));
+                    }
+                    st.collect_cross_fee(&mut msg.value, &fee)?;
+                }
+                SendCrossFeePolicy::Surplus => {
+                    let required = &msg.value + &fee;
+                    if value_received < required {
+                        return Err(actor_error!(
+                            illegal_argument,
+                            "the funds sent ({}) don't cover the cross-msg value and fee ({})",
+                            value_received,
+                            required
+                        ));
+                    }
+                    let mut paid = value_received;
+                    st.collect_cross_fee(&mut paid, &fee)?;
+                    surplus = paid - &msg.value;
+                }
             }
-
-            // collect cross-fee
-            let fee = CROSS_MSG_FEE.clone();
-            st.collect_cross_fee(&mut msg.value, &fee)?;
+            // only the value of the message is checked, the fee still flows in safe mode
+            Self::check_value_transfer(st, &msg.value)?;
+            Self::check_min_cross_value(st, &msg.value)?;
 
             // commit cross-message for propagation
-            (do_burn, top_down_fee) = Self::commit_cross_message(rt, st, &mut cross_msg, fee)?;
+            do_burn = Self::commit_cross_message(rt, st, &mut cross_msg, fee.clone())?;
             Ok(())
         })?;
 
+        log_cross_msg_event("CrossMsgSent", &cross_msg);
+
         // side-effects sent without any remainders
-        cross_msg_side_effects(rt, &cross_msg, do_burn, &top_down_fee)?;
+        cross_msg_side_effects(rt, &cross_msg, do_burn)?;
+
+        // refund any funds sent over the value and fee of the message
+        if !surplus.is_zero() {
+            let caller = rt.message().caller();
+            rt.send(&caller, METHOD_SEND, None, surplus)?;
+        }
 
         Ok(())
     }
 
+    /// Delivers a cross-message addressed to the current network as a plain
+    /// message. No cross-message fee is charged as it isn't propagated.
+    fn send_local(rt: &mut impl Runtime, cross_msg: CrossMsg) -> Result<(), ActorError> {
+        if rt.message().value_received() != cross_msg.msg.value {
+            return Err(actor_error!(
+                illegal_argument,
+                "the funds in cross-msg params are not equal to the ones sent in the message"
+            ));
+        }
+        let to = cross_msg
+            .msg
+            .to
+            .raw_addr()
+            .map_err(|_| actor_error!(illegal_argument, "invalid to addr"))?;
+        // the caller is the from of the message, sending it to itself would be a
+        // pointless loop.
+        if to == rt.message().caller() {
+            return Err(actor_error!(
+                illegal_argument,
+                "cross-msg from and to are the same address {}",
+                to
+            ));
+        }
+        cross_msg.send(rt, &to)?;
+        Ok(())
+    }
+
     /// ApplyMessage triggers the execution of a cross-subnet message validated through the consensus.
     ///
     /// This function can only be triggered using `ApplyImplicitMessage`, and the source needs to
@@ -597,9 +2225,113 @@ impl Actor {
     /// - Performs the corresponding state changes.
     /// - And updated the latest nonce applied for future checks.
     fn apply_msg(rt: &mut impl Runtime, params: ApplyMsgParams) -> Result<RawBytes, ActorError> {
-        rt.validate_immediate_caller_is([&SYSTEM_ACTOR_ADDR as &Address])?;
+        Self::validate_apply_caller(rt)?;
+        Self::apply_cross_msg(rt, params.cross_msg)
+    }
+
+    /// SkipTopDownMsg acknowledges the cancellation of a top-down message requested
+    /// in the parent by skipping it instead of applying it.
+    ///
+    /// As `ApplyMessage`, it can only be triggered by the SystemActor. The message
+    /// needs to be the next top-down message to apply, and it can't have been
+    /// attempted before, as its funds are minted in the first attempt. The nonce
+    /// skipped is reported in the checkpoint of the current window, so the parent
+    /// can remove the message and refund its sender.
+    fn skip_topdown_msg(rt: &mut impl Runtime, params: ApplyMsgParams) -> Result<(), ActorError> {
+        Self::validate_apply_caller(rt)?;
+
+        let cross_msg = params.cross_msg;
+        let cid = cross_msg.cid().map_err(|e| {
+            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error computing cross-msg cid")
+        })?;
+        rt.transaction(|st: &mut State, rt| {
+            if !matches!(
+                cross_msg.msg.apply_type(&st.network_name),
+                Ok(IPCMsgType::TopDown)
+            ) {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "only top-down messages can be skipped"
+                ));
+            }
+            if st.applied_topdown_nonce != cross_msg.msg.nonce {
+                return Err(actor_error!(
+                    illegal_state,
+                    "the top-down message being skipped doesn't hold the subsequent nonce"
+                ));
+            }
+            let attempts = st.get_apply_attempts(rt.store(), &cid).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error loading apply attempts")
+            })?;
+            if attempts > 0 {
+                return Err(actor_error!(
+                    illegal_state,
+                    "top-down message {} was already attempted",
+                    cid
+                ));
+            }
+
+            Self::advance_topdown_nonce(st)?;
+            st.store_skipped_topdown_msg(rt.store(), cross_msg.msg.nonce, rt.curr_epoch())
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_STATE,
+                        "error storing skipped top-down message in checkpoint",
+                    )
+                })
+        })?;
+
+        log_cross_msg_event("CrossMsgSkipped", &cross_msg);
+        Ok(())
+    }
 
-        let ApplyMsgParams { cross_msg } = params;
+    /// ApplyMessages applies a batch of cross-messages in order, as if each of them
+    /// was applied through `ApplyMessage`.
+    ///
+    /// Messages whose execution fails follow the dead-letter policy as usual, and
+    /// the ones that can't be applied are reported in their result without
+    /// aborting the rest of the batch.
+    fn apply_msgs(
+        rt: &mut impl Runtime,
+        params: ApplyMsgsParams,
+    ) -> Result<Vec<ApplyMsgResult>, ActorError> {
+        Self::validate_apply_caller(rt)?;
+
+        if params.cross_msgs.is_empty() {
+            return Err(actor_error!(illegal_argument, "no cross-messages to apply"));
+        }
+
+        let mut results = Vec::with_capacity(params.cross_msgs.len());
+        for cross_msg in params.cross_msgs {
+            // the state changes of a message that can't be applied are undone,
+            // as it would happen if it was applied on its own.
+            let root = rt.get_state_root()?;
+            let res = match Self::apply_cross_msg(rt, cross_msg) {
+                Ok(ret) => ApplyMsgResult {
+                    exit_code: ExitCode::OK,
+                    ret,
+                },
+                Err(e) => {
+                    log::info!("failed to apply cross-msg in batch: {}", e);
+                    rt.set_state_root(&root)?;
+                    ApplyMsgResult {
+                        exit_code: e.exit_code(),
+                        ret: RawBytes::default(),
+                    }
+                }
+            };
+            results.push(res);
+        }
+        Ok(results)
+    }
+
+    /// Applies a cross-message once the caller has been validated.
+    ///
+    /// Funds for top-down messages are only minted once the steps that can fail
+    /// have gone through, so a message that can't be applied doesn't leave
+    /// minted funds behind to be minted again when it's retried.
+    fn apply_cross_msg(rt: &mut impl Runtime, cross_msg: CrossMsg) -> Result<RawBytes, ActorError> {
+        log_cross_msg_event("CrossMsgApplying", &cross_msg);
 
         let rto = match cross_msg.msg.to.raw_addr() {
             Ok(to) => to,
@@ -624,62 +2356,80 @@ impl Actor {
 
         log::debug!("sto: {:?}, network: {:?}", sto, st.network_name);
 
-        match cross_msg.msg.apply_type(&st.network_name) {
+        let mint_attempts = match cross_msg.msg.apply_type(&st.network_name) {
             Ok(IPCMsgType::BottomUp) => {
+                // funds can't leave a frozen subnet.
+                let sfrom =
+                    cross_msg.msg.from.subnet().map_err(|_| {
+                        actor_error!(illegal_argument, "error getting subnet from msg")
+                    })?;
+                let frozen = st.is_route_frozen(rt.store(), &sfrom).map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+                })?;
+                if frozen {
+                    return Err(actor_error!(
+                        forbidden,
+                        "bottom-up message from frozen subnet {}",
+                        sfrom
+                    ));
+                }
+
                 // if directed to current network, execute message.
                 if sto == st.network_name {
-                    rt.transaction(|st: &mut State, _| {
+                    let prev_nonce = rt.transaction(|st: &mut State, _| {
+                        let prev_nonce = st.applied_bottomup_nonce;
                         st.bottomup_state_transition(&cross_msg.msg).map_err(|e| {
                             e.downcast_default(
                                 ExitCode::USR_ILLEGAL_STATE,
                                 "failed applying bottomup message",
                             )
                         })?;
-                        Ok(())
+                        Ok(prev_nonce)
                     })?;
-                    return cross_msg.send(rt, &rto);
+                    return Self::execute_msg(rt, cross_msg, &rto, |st| {
+                        st.applied_bottomup_nonce = prev_nonce
+                    });
                 }
+                None
             }
             Ok(IPCMsgType::TopDown) => {
-                // Mint funds for the gateway, as any topdown message
-                // including tokens traversing the subnet will provide
-                // the gateway with additional balance (circ_supply).
-                let params = ext::reward::FundingParams {
-                    // curr gateway address
-                    addr: rt.message().receiver(),
-                    value: cross_msg.msg.value.clone(),
-                };
-                // FIXME: This assumes the ability to mint new FIL from
-                // the RewardActor. This is no longer needed,
-                // instead we can provide with the total
-                // circulating supply to the gateway in genesis (so we don't
-                // require changes to the RewardActor)
-                // See: https://github.com/consensus-shipyard/ipc-actors/issues/45
-                if cross_msg.msg.value > TokenAmount::zero() {
-                    rt.send(
-                        &REWARD_ACTOR_ADDR,
-                        ext::reward::EXTERNAL_FUNDING_METHOD,
-                        IpldBlock::serialize_cbor(&params)?,
-                        TokenAmount::zero(),
-                    )?;
+                // top-down messages are applied in order, also the ones that are only
+                // traversing the subnet on their way further down.
+                if st.applied_topdown_nonce != cross_msg.msg.nonce {
+                    return Err(actor_error!(
+                        illegal_state,
+                        "the top-down message being applied doesn't hold the subsequent nonce"
+                    ));
                 }
 
+                // Funds are only minted in the first attempt to apply the message.
+                let cid = cross_msg.cid().map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error computing cross-msg cid")
+                })?;
+                let attempts = st.get_apply_attempts(rt.store(), &cid).map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error loading apply attempts")
+                })?;
+
                 if sto == st.network_name {
-                    if st.applied_topdown_nonce != cross_msg.msg.nonce {
-                        return Err(actor_error!(
-                            illegal_state,
-                            "the top-down message being applied doesn't hold the subsequent nonce"
-                        ));
+                    rt.transaction(|st: &mut State, _| Self::advance_topdown_nonce(st))?;
+
+                    // expired messages consume their nonce without being executed
+                    // so the ones after them can still be applied.
+                    if cross_msg.msg.is_expired(rt.curr_epoch()) {
+                        let value = cross_msg.msg.value.clone();
+                        let ret = Self::refund_expired_msg(rt, cross_msg, &cid)?;
+                        Self::mint_topdown_funds(rt, &value, attempts)?;
+                        return Ok(ret);
                     }
 
-                    rt.transaction(|st: &mut State, _| {
-                        st.applied_topdown_nonce += 1;
-                        Ok(())
-                    })?;
-
+                    // the funds need to be minted before they can be sent.
+                    Self::mint_topdown_funds(rt, &cross_msg.msg.value, attempts)?;
                     // We can return the send result
-                    return cross_msg.send(rt, &rto);
+                    return Self::execute_msg(rt, cross_msg, &rto, |st| {
+                        st.applied_topdown_nonce -= 1
+                    });
                 }
+                Some(attempts)
             }
             _ => {
                 return Err(actor_error!(
@@ -689,24 +2439,65 @@ impl Actor {
             }
         };
 
+        let value = cross_msg.msg.value.clone();
         let cid = rt.transaction(|st: &mut State, rt| {
+            // the nonce of top-down messages traversing the subnet is consumed
+            // as they are stored for propagation.
+            if mint_attempts.is_some() {
+                Self::advance_topdown_nonce(st)?;
+            }
             let owner = cross_msg
                 .msg
                 .from
                 .raw_addr()
                 .map_err(|_| actor_error!(illegal_argument, "invalid address"))?;
             let r = st
-                .insert_postbox(rt.store(), Some(vec![owner]), cross_msg)
+                .insert_postbox(rt.store(), Some(vec![owner]), cross_msg, rt.curr_epoch())
                 .map_err(|e| {
                     e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "error save topdown messages")
                 })?;
             Ok(r)
         })?;
+        if let Some(attempts) = mint_attempts {
+            Self::mint_topdown_funds(rt, &value, attempts)?;
+        }
 
         // it is safe to just unwrap. If `transaction` fails, cid is None and wont reach here.
         Ok(RawBytes::new(cid.to_bytes()))
     }
 
+    /// Mints the funds carried by a top-down message for the gateway, as any
+    /// top-down message including tokens traversing the subnet provides the
+    /// gateway with additional balance (circ_supply). Funds are only minted
+    /// in the first attempt to apply the message.
+    fn mint_topdown_funds(
+        rt: &mut impl Runtime,
+        value: &TokenAmount,
+        attempts: u64,
+    ) -> Result<(), ActorError> {
+        if value.is_zero() || attempts > 0 {
+            return Ok(());
+        }
+        // FIXME: This assumes the ability to mint new FIL from
+        // the RewardActor. This is no longer needed,
+        // instead we can provide with the total
+        // circulating supply to the gateway in genesis (so we don't
+        // require changes to the RewardActor)
+        // See: https://github.com/consensus-shipyard/ipc-actors/issues/45
+        let params = ext::reward::FundingParams {
+            // curr gateway address
+            addr: rt.message().receiver(),
+            value: value.clone(),
+        };
+        rt.send(
+            &REWARD_ACTOR_ADDR,
+            ext::reward::EXTERNAL_FUNDING_METHOD,
+            IpldBlock::serialize_cbor(&params)?,
+            TokenAmount::zero(),
+        )?;
+        Ok(())
+    }
+
     /// Whitelist a series of addresses as propagator of a cross net message.
     /// This is basically adding this list of addresses to the `PostBoxItem::owners`.
     /// Only existing owners can perform this operation.
@@ -756,6 +2547,61 @@ impl Actor {
         Ok(())
     }
 
+    /// Replaces the owners of a postbox item, transferring the responsibility to
+    /// propagate the cross net message. Only existing owners can perform this operation.
+    fn replace_postbox_owners(
+        rt: &mut impl Runtime,
+        params: ReplacePostboxOwnersParams,
+    ) -> Result<(), ActorError> {
+        // does not really need check as we are checking against the PostboxItem.owners
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        let ReplacePostboxOwnersParams {
+            postbox_cid,
+            new_owners,
+        } = params;
+
+        // an empty set of owners would orphan the message in the postbox.
+        if new_owners.is_empty() {
+            return Err(actor_error!(
+                illegal_argument,
+                "postbox item needs at least one owner"
+            ));
+        }
+
+        rt.transaction(|st: &mut State, rt| {
+            let mut postbox_item = st.load_from_postbox(rt.store(), postbox_cid).map_err(|e| {
+                log::error!("encountered error loading from postbox: {:?}", e);
+                actor_error!(unhandled_message, "cannot load from postbox")
+            })?;
+
+            match postbox_item.owners {
+                None => {
+                    return Err(actor_error!(
+                        illegal_state,
+                        "postbox item cannot replace owners for now"
+                    ));
+                }
+                Some(ref owners) if !owners.contains(&caller) => {
+                    return Err(actor_error!(illegal_state, "not owner"));
+                }
+                _ => {}
+            }
+            postbox_item.owners = Some(new_owners);
+
+            st.swap_postbox_item(rt.store(), postbox_cid, postbox_item)
+                .map_err(|e| {
+                    log::error!("encountered error loading from postbox: {:?}", e);
+                    actor_error!(unhandled_message, "cannot load from postbox")
+                })?;
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
     fn propagate(rt: &mut impl Runtime, params: PropagateParams) -> Result<(), ActorError> {
         // does not really need check as we are checking against the PostboxItem.owners
         rt.validate_immediate_caller_accept_any()?;
@@ -763,9 +2609,79 @@ impl Actor {
         let PropagateParams { postbox_cid } = params;
         let owner = rt.message().caller();
         let mut value = rt.message().value_received();
-        let (mut do_burn, mut top_down_fee) = (false, TokenAmount::zero());
+        let st: State = rt.state()?;
+        let fee = st.cross_msg_fee(&owner, CrossMsgKind::SendCross);
+
+        let (cross_msg, do_burn) =
+            Self::propagate_postbox_item(rt, postbox_cid, owner, &fee, &mut value)?;
+
+        // trigger cross-message side-effects returning the remainder of the fee
+        // to the source.
+        cross_msg_side_effects(rt, &cross_msg, do_burn)?;
+        // return fee remainder to owner
+        if !value.is_zero() {
+            rt.send(&owner, METHOD_SEND, None, value.clone())?;
+        }
+        Ok(())
+    }
+
+    /// PropagateBatch propagates several postbox items in a single call. Items that
+    /// can't be committed are left in the postbox to be retried later instead of
+    /// failing the whole call, and only the items propagated are charged the fee.
+    fn propagate_batch(
+        rt: &mut impl Runtime,
+        params: PropagateBatchParams,
+    ) -> Result<PropagateBatchReturn, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        if params.postbox_cids.is_empty() {
+            return Err(actor_error!(
+                illegal_argument,
+                "no postbox items to propagate"
+            ));
+        }
+
+        let owner = rt.message().caller();
+        let mut value = rt.message().value_received();
+        let st: State = rt.state()?;
+        let fee = st.cross_msg_fee(&owner, CrossMsgKind::SendCross);
+
+        let mut ret = PropagateBatchReturn {
+            propagated: Vec::new(),
+            pending: Vec::new(),
+        };
+        for postbox_cid in params.postbox_cids {
+            match Self::propagate_postbox_item(rt, postbox_cid, owner, &fee, &mut value) {
+                Ok((cross_msg, do_burn)) => {
+                    cross_msg_side_effects(rt, &cross_msg, do_burn)?;
+                    ret.propagated.push(postbox_cid);
+                }
+                Err(e) => {
+                    log::info!("failed to propagate postbox item {}: {}", postbox_cid, e);
+                    ret.pending.push(postbox_cid);
+                }
+            }
+        }
+
+        // return the fees of the items not propagated and any remainder to owner
+        if !value.is_zero() {
+            rt.send(&owner, METHOD_SEND, None, value.clone())?;
+        }
+        Ok(ret)
+    }
 
-        let cross_msg = rt.transaction(|st: &mut State, rt| {
+    /// Commits the cross-message of a postbox item and removes it from the
+    /// postbox. The fee is only collected from `value` if the item is committed.
+    ///
+    /// It returns the message committed with the output of `commit_cross_message`.
+    fn propagate_postbox_item(
+        rt: &mut impl Runtime,
+        postbox_cid: Cid,
+        owner: Address,
+        fee: &TokenAmount,
+        value: &mut TokenAmount,
+    ) -> Result<(CrossMsg, bool), ActorError> {
+        let res = rt.transaction(|st: &mut State, rt| {
             let postbox_item = st.load_from_postbox(rt.store(), postbox_cid).map_err(|e| {
                 log::error!("encountered error loading from postbox: {:?}", e);
                 actor_error!(unhandled_message, "cannot load from postbox")
@@ -774,30 +2690,26 @@ impl Actor {
             if let Some(owners) = postbox_item.owners && !owners.contains(&owner) {
                 return Err(actor_error!(illegal_state, "owner not match"));
             }
+            Self::check_value_transfer(st, &postbox_item.cross_msg.msg.value)?;
 
             // collect cross-fee
-            let fee = CROSS_MSG_FEE.clone();
-            st.collect_cross_fee(&mut value, &fee)?;
+            let mut remainder = value.clone();
+            st.collect_cross_fee(&mut remainder, fee)?;
 
             let PostBoxItem { mut cross_msg, .. } = postbox_item;
-            (do_burn, top_down_fee) = Self::commit_cross_message(rt, st, &mut cross_msg, fee)?;
+            let do_burn = Self::commit_cross_message(rt, st, &mut cross_msg, fee.clone())?;
             st.remove_from_postbox(rt.store(), postbox_cid)?;
-            Ok(cross_msg)
+            Ok((cross_msg, do_burn, remainder))
         })?;
-
-        // trigger cross-message side-effects returning the remainder of the fee
-        // to the source.
-        cross_msg_side_effects(rt, &cross_msg, do_burn, &top_down_fee)?;
-        // return fee remainder to owner
-        if !value.is_zero() {
-            rt.send(&owner, METHOD_SEND, None, value.clone())?;
-        }
-        Ok(())
+        let (cross_msg, do_burn, remainder) = res;
+        *value = remainder;
+        log_cross_msg_event("CrossMsgPropagated", &cross_msg);
+        Ok((cross_msg, do_burn))
     }
 
     /// Commit the cross message to storage. It outputs a flag signaling
     /// if the committed messages was bottom-up and some funds need to be
-    /// burnt or if a top-down message fee needs to be distributed.
+    /// burnt. The fee of top-down messages is held until they are applied.
     ///
     /// NOTE: This function should always be called inside an `rt.transaction`
     fn commit_cross_message(
@@ -805,68 +2717,49 @@ impl Actor {
         st: &mut State,
         cross_msg: &mut CrossMsg,
         fee: TokenAmount,
-    ) -> Result<(bool, TokenAmount), ActorError> {
-        let mut do_burn = false;
-
+    ) -> Result<bool, ActorError> {
         let sto = cross_msg
             .msg
             .to
             .subnet()
             .map_err(|_| actor_error!(illegal_argument, "error getting subnet from msg"))?;
-        if sto == st.network_name {
-            return Err(actor_error!(illegal_state, "should already be committed"));
-        }
+        let sfrom = cross_msg
+            .msg
+            .from
+            .subnet()
+            .map_err(|_| actor_error!(illegal_argument, "error getting subnet from msg"))?;
 
-        match cross_msg.msg.apply_type(&st.network_name).map_err(|e| {
-            e.downcast_default(
-                ExitCode::USR_ILLEGAL_STATE,
-                "cannot convert cross message type",
-            )
-        })? {
-            IPCMsgType::BottomUp => {
-                let mut top_down_fee = TokenAmount::zero();
-                let sfrom =
-                    cross_msg.msg.from.subnet().map_err(|_| {
-                        actor_error!(illegal_argument, "error getting subnet from msg")
+        match route_cross_msg(&st.network_name, &sfrom, &sto) {
+            RouteDecision::Local => Err(actor_error!(illegal_state, "should already be committed")),
+            RouteDecision::Unroutable => Err(actor_error!(
+                illegal_state,
+                "cross-message from {} to {} can't be routed through {}",
+                sfrom,
+                sto,
+                st.network_name
+            )),
+            RouteDecision::CommitBottomUp => {
+                let do_burn = cross_msg.msg.value > TokenAmount::zero();
+                st.commit_bottomup_msg(rt.store(), cross_msg, &fee, rt.curr_epoch())
+                    .map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            "error committing bottom-up messages",
+                        )
                     })?;
-                let nearest_common_parent = sto.common_parent(&sfrom).unwrap().1;
-
-                log::debug!(
-                    "nearest common parent: {:?}, current network: {:?}",
-                    nearest_common_parent,
-                    st.network_name
-                );
-
-                // if the message is a bottom-up message and it reached the common-parent
-                // then we need to start propagating it down to the destination.
-                let r = if nearest_common_parent == st.network_name {
-                    top_down_fee = fee;
-                    st.commit_topdown_msg(rt.store(), cross_msg)
-                } else {
-                    if cross_msg.msg.value > TokenAmount::zero() {
-                        do_burn = true;
-                    }
-                    st.commit_bottomup_msg(rt.store(), cross_msg, &fee, rt.curr_epoch())
-                };
-
-                r.map_err(|e| {
-                    e.downcast_default(
-                        ExitCode::USR_ILLEGAL_STATE,
-                        "error committing bottom-up messages",
-                    )
-                })?;
-
-                Ok((do_burn, top_down_fee))
+                log_cross_msg_event("CrossMsgCommittedBottomUp", cross_msg);
+                Ok(do_burn)
             }
-            IPCMsgType::TopDown => {
-                st.applied_topdown_nonce += 1;
-                st.commit_topdown_msg(rt.store(), cross_msg).map_err(|e| {
-                    e.downcast_default(
-                        ExitCode::USR_ILLEGAL_STATE,
-                        "error committing top-down message while applying it",
-                    )
-                })?;
-                Ok((do_burn, fee))
+            RouteDecision::CommitTopDown => {
+                st.commit_topdown_msg(rt.store(), cross_msg, &fee)
+                    .map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            "error committing top-down message while applying it",
+                        )
+                    })?;
+                log_cross_msg_event("CrossMsgCommittedTopDown", cross_msg);
+                Ok(false)
             }
         }
     }
@@ -883,10 +2776,58 @@ impl ActorCode for Actor {
         Kill => kill,
         CommitChildCheckpoint => commit_child_check,
         Fund => fund,
+        FundMulti => fund_multi,
         Release => release,
+        ReleaseToSubnet => release_to_subnet,
         SendCross => send_cross,
         ApplyMessage => apply_msg,
         Propagate => propagate,
         WhiteListPropagator => whitelist_propagator,
+        ReplacePostboxOwners => replace_postbox_owners,
+        GetPrevCheckpoint => get_prev_checkpoint,
+        GetLastCommittedEpoch => get_last_committed_epoch,
+        CancelFund => cancel_fund,
+        BulkRegister => bulk_register,
+        GetPendingFeeDistribution => get_pending_fee_distribution,
+        AllowRegistration => allow_registration,
+        DisallowRegistration => disallow_registration,
+        SetAppliedTopDownNonce => set_applied_topdown_nonce,
+        ListDeadLetters => list_dead_letters,
+        RetryDeadLetter => retry_dead_letter,
+        RefundDeadLetter => refund_dead_letter,
+        MigrateState => migrate_state,
+        GetFeeSchedule => get_fee_schedule,
+        FreezeSubnet => freeze_subnet,
+        UnfreezeSubnet => unfreeze_subnet,
+        DryRunCrossMsg => dry_run_cross_msg,
+        GetTotalBurned => get_total_burned,
+        GetQueueDepths => get_queue_depths,
+        PruneCheckpoints => prune_checkpoints,
+        GetActorInfo => get_actor_info,
+        GetCrossMsgStatus => get_cross_msg_status,
+        GetCollateralization => get_collateralization,
+        FundWithMemo => fund_with_memo,
+        AddFeeExemption => add_fee_exemption,
+        RemoveFeeExemption => remove_fee_exemption,
+        PropagateBatch => propagate_batch,
+        ReactivateSubnet => reactivate_subnet,
+        SetSafeMode => set_safe_mode,
+        ComputeSubnetID => compute_subnet_id,
+        ForceKill => force_kill,
+        GetGatewayStats => get_gateway_stats,
+        FundWithTarget => fund_with_target,
+        ApplyMessages => apply_msgs,
+        GetAncestry => get_ancestry,
+        GetTotalFeesCollected => get_total_fees_collected,
+        GetStateRoot => get_state_root,
+        SetAllowReregister => set_allow_reregister,
+        AdjustCircSupply => adjust_circ_supply,
+        SetGovernors => set_governors,
+        ProposeGovAction => propose_gov_action,
+        ApproveGovAction => approve_gov_action,
+        GetBottomUpMsg => get_bottomup_msg,
+        SetMaxTopDownValue => set_max_topdown_value,
+        FundOnBehalf => fund_on_behalf,
+        SkipTopDownMsg => skip_topdown_msg,
     }
 }
@@ -0,0 +1,229 @@
+use anyhow::anyhow;
+use cid::multihash::Code;
+use cid::Cid;
+use fil_actors_runtime::SYSTEM_ACTOR_ADDR;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::CborStore;
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use ipc_sdk::subnet_id::SubnetID;
+use num_traits::Zero;
+use primitives::{TAmt, TCid, THamt, TLink};
+use serde::{Deserialize, Serialize};
+
+use crate::checkpoint::{Checkpoint, CrossMsgMeta};
+use crate::cross::{CrossMsg, CrossMsgs};
+use crate::state::State;
+use crate::subnet::Subnet;
+use crate::types::*;
+
+/// Version of the layout of the gateway `State` written by this actor.
+pub const STATE_VERSION: u8 = 3;
+
+/// Original, unversioned, layout of the gateway state.
+#[derive(Serialize, Deserialize)]
+pub struct StateV1 {
+    pub network_name: SubnetID,
+    pub total_subnets: u64,
+    pub min_stake: TokenAmount,
+    pub subnets: TCid<THamt<SubnetID, Subnet>>,
+    pub check_period: ChainEpoch,
+    pub checkpoints: TCid<THamt<ChainEpoch, Checkpoint>>,
+    pub check_msg_registry: TCid<THamt<TCid<TLink<CrossMsgs>>, CrossMsgs>>,
+    pub postbox: TCid<THamt<Cid, Vec<u8>>>,
+    pub nonce: u64,
+    pub bottomup_nonce: u64,
+    pub bottomup_msg_meta: TCid<TAmt<CrossMsgMeta, CROSSMSG_AMT_BITWIDTH>>,
+    pub applied_bottomup_nonce: u64,
+    pub applied_topdown_nonce: u64,
+}
+
+/// Layout of the gateway state in version 2.
+#[derive(Serialize, Deserialize)]
+pub struct StateV2 {
+    pub version: u8,
+    pub network_name: SubnetID,
+    pub total_subnets: u64,
+    pub min_stake: TokenAmount,
+    pub subnets: TCid<THamt<SubnetID, Subnet>>,
+    pub check_period: ChainEpoch,
+    pub max_msgs_per_checkpoint: u64,
+    pub checkpoints: TCid<THamt<ChainEpoch, Checkpoint>>,
+    pub check_msg_registry: TCid<THamt<TCid<TLink<CrossMsgs>>, CrossMsgs>>,
+    pub postbox: TCid<THamt<Cid, Vec<u8>>>,
+    pub nonce: u64,
+    pub bottomup_nonce: u64,
+    pub bottomup_msg_meta: TCid<TAmt<CrossMsgMeta, CROSSMSG_AMT_BITWIDTH>>,
+    pub applied_bottomup_nonce: u64,
+    pub applied_topdown_nonce: u64,
+    pub pending_fee_distribution: TokenAmount,
+    pub governor: Address,
+    pub registration_allowlist: Option<Vec<Address>>,
+    pub max_apply_attempts: u64,
+    pub apply_attempts: TCid<THamt<Cid, u64>>,
+    pub dead_letters: TCid<THamt<Cid, CrossMsg>>,
+}
+
+/// Used to peek the version of a serialized state. States written before
+/// versioning was introduced don't include the field and are version 1.
+#[derive(Deserialize)]
+struct StateVersion {
+    #[serde(default = "legacy_version")]
+    version: u8,
+}
+
+fn legacy_version() -> u8 {
+    1
+}
+
+/// Upgrades the state stored in `root` to the current layout.
+///
+/// Returns the root of the migrated state, or `None` if the state is
+/// already up to date. Unknown future versions are rejected.
+pub fn migrate_state<BS: Blockstore>(store: &BS, root: &Cid) -> anyhow::Result<Option<Cid>> {
+    let StateVersion { version } = store
+        .get_cbor(root)?
+        .ok_or_else(|| anyhow!("state not found for root {}", root))?;
+
+    let st = match version {
+        STATE_VERSION => return Ok(None),
+        1 => {
+            let v1: StateV1 = store
+                .get_cbor(root)?
+                .ok_or_else(|| anyhow!("state not found for root {}", root))?;
+            migrate_v2(store, migrate_v1(store, v1)?)?
+        }
+        2 => {
+            let v2: StateV2 = store
+                .get_cbor(root)?
+                .ok_or_else(|| anyhow!("state not found for root {}", root))?;
+            migrate_v2(store, v2)?
+        }
+        v => return Err(anyhow!("unknown state version {}", v)),
+    };
+
+    Ok(Some(store.put_cbor(&st, Code::Blake2b256)?))
+}
+
+/// Fields introduced in version 2 are initialized to their defaults. The governor
+/// is set to the system actor, so privileged methods are only reachable through
+/// network upgrades until a governor is configured.
+fn migrate_v1<BS: Blockstore>(store: &BS, v1: StateV1) -> anyhow::Result<StateV2> {
+    Ok(StateV2 {
+        version: 2,
+        network_name: v1.network_name,
+        total_subnets: v1.total_subnets,
+        min_stake: v1.min_stake,
+        subnets: v1.subnets,
+        check_period: v1.check_period,
+        max_msgs_per_checkpoint: DEFAULT_MAX_MSGS_PER_CHECKPOINT,
+        checkpoints: v1.checkpoints,
+        check_msg_registry: v1.check_msg_registry,
+        postbox: v1.postbox,
+        nonce: v1.nonce,
+        bottomup_nonce: v1.bottomup_nonce,
+        bottomup_msg_meta: v1.bottomup_msg_meta,
+        applied_bottomup_nonce: v1.applied_bottomup_nonce,
+        applied_topdown_nonce: v1.applied_topdown_nonce,
+        pending_fee_distribution: TokenAmount::zero(),
+        governor: SYSTEM_ACTOR_ADDR,
+        registration_allowlist: None,
+        max_apply_attempts: DEFAULT_MAX_APPLY_ATTEMPTS,
+        apply_attempts: TCid::new_hamt(store)?,
+        dead_letters: TCid::new_hamt(store)?,
+    })
+}
+
+/// Fields introduced in version 3 are initialized to their defaults, and the
+/// items in the postbox are counted.
+///
+/// Subnets and checkpoints stored by previous versions decode the fields added
+/// since with their defaults. The exception are the cross-msgs of the checkpoints
+/// of the gateway, which now declare the number of messages they include and the
+/// value they carry. They are recomputed from the registry so the parent can
+/// verify the fee of the checkpoints still to be committed.
+fn migrate_v2<BS: Blockstore>(store: &BS, v2: StateV2) -> anyhow::Result<State> {
+    let mut st = State {
+        version: STATE_VERSION,
+        network_name: v2.network_name,
+        total_subnets: v2.total_subnets,
+        min_stake: v2.min_stake,
+        subnets: v2.subnets,
+        check_period: v2.check_period,
+        max_msgs_per_checkpoint: v2.max_msgs_per_checkpoint,
+        checkpoints: v2.checkpoints,
+        check_msg_registry: v2.check_msg_registry,
+        postbox: v2.postbox,
+        nonce: v2.nonce,
+        bottomup_nonce: v2.bottomup_nonce,
+        bottomup_msg_meta: v2.bottomup_msg_meta,
+        applied_bottomup_nonce: v2.applied_bottomup_nonce,
+        applied_topdown_nonce: v2.applied_topdown_nonce,
+        pending_fee_distribution: v2.pending_fee_distribution,
+        topdown_fees: TCid::new_hamt(store)?,
+        governor: v2.governor,
+        governors: Vec::new(),
+        governance_threshold: 0,
+        gov_proposals: TCid::new_hamt(store)?,
+        next_gov_proposal_id: 0,
+        registration_allowlist: v2.registration_allowlist,
+        max_apply_attempts: v2.max_apply_attempts,
+        apply_attempts: v2.apply_attempts,
+        dead_letters: v2.dead_letters,
+        total_burned: TokenAmount::zero(),
+        total_fees_collected: TokenAmount::zero(),
+        send_cross_fee_policy: SendCrossFeePolicy::Deduct,
+        checkpoint_retention: 0,
+        allow_local_cross: false,
+        bottomup_fee_policy: BottomUpFeePolicy::Committer,
+        fee_exempt: Vec::new(),
+        safe_mode: false,
+        killed_subnets: TCid::new_hamt(store)?,
+        allow_reregister: false,
+        max_postbox_items: 0,
+        postbox_overflow_policy: PostboxOverflowPolicy::EvictExpired,
+        postbox_len: 0,
+        min_cross_value: TokenAmount::zero(),
+        cross_msg_fees: CrossMsgFees::default(),
+        require_actor_destination: false,
+    };
+    let mut postbox_len = 0;
+    st.postbox.load(store)?.for_each(|_, _| {
+        postbox_len += 1;
+        Ok(())
+    })?;
+    st.postbox_len = postbox_len;
+    migrate_checkpoint_metas(store, &mut st)?;
+    Ok(st)
+}
+
+/// Fills the number of messages and the value carried by the cross-msgs of the
+/// checkpoints of the gateway from the messages in the registry.
+fn migrate_checkpoint_metas<BS: Blockstore>(store: &BS, st: &mut State) -> anyhow::Result<()> {
+    let registry = st.check_msg_registry.load(store)?;
+    let mut migrated = Vec::new();
+    st.checkpoints.load(store)?.for_each(|_, ch| {
+        if let Some(meta) = ch.cross_msgs() {
+            if meta.msgs_cid == TCid::default() {
+                return Ok(());
+            }
+            let crossmsgs = registry
+                .get(&meta.msgs_cid.cid().to_bytes())?
+                .ok_or_else(|| anyhow!("no cross-msgs found for cid {}", meta.msgs_cid.cid()))?;
+            let mut ch = ch.clone();
+            let meta = ch.cross_msgs_mut().unwrap();
+            meta.num_msgs = crossmsgs.msgs.len() as u64;
+            meta.msgs_value = crossmsgs
+                .msgs
+                .iter()
+                .fold(TokenAmount::zero(), |acc, m| acc + &m.msg.value);
+            migrated.push(ch);
+        }
+        Ok(())
+    })?;
+    for ch in migrated.iter() {
+        st.flush_checkpoint(store, ch)?;
+    }
+    Ok(())
+}
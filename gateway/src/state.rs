@@ -14,12 +14,14 @@ use lazy_static::lazy_static;
 use num_traits::Zero;
 use primitives::{TAmt, TCid, THamt, TLink};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::str::FromStr;
 
 use ipc_sdk::subnet_id::SubnetID;
 
 use super::checkpoint::*;
 use super::cross::*;
+use super::migration::STATE_VERSION;
 use super::subnet::*;
 use super::types::*;
 
@@ -31,11 +33,14 @@ type PostBox = TCid<THamt<Cid, Vec<u8>>>;
 /// Storage power actor state
 #[derive(Serialize, Deserialize)]
 pub struct State {
+    /// Version of the layout of the state, see `STATE_VERSION`.
+    pub version: u8,
     pub network_name: SubnetID,
     pub total_subnets: u64,
     pub min_stake: TokenAmount,
     pub subnets: TCid<THamt<SubnetID, Subnet>>,
     pub check_period: ChainEpoch,
+    pub max_msgs_per_checkpoint: u64,
     pub checkpoints: TCid<THamt<ChainEpoch, Checkpoint>>,
     pub check_msg_registry: TCid<THamt<TCid<TLink<CrossMsgs>>, CrossMsgs>>,
     /// `postbox` keeps track for an EOA of all the cross-net messages triggered by
@@ -46,6 +51,51 @@ pub struct State {
     pub bottomup_msg_meta: TCid<TAmt<CrossMsgMeta, CROSSMSG_AMT_BITWIDTH>>,
     pub applied_bottomup_nonce: u64,
     pub applied_topdown_nonce: u64,
+    /// Top-down cross-message fees collected that haven't been distributed
+    /// to the corresponding subnet actor yet.
+    pub pending_fee_distribution: TokenAmount,
+    /// Fees of the top-down messages committed, by the cid of the message. They
+    /// are held until the subnet reports the message applied, so they can be
+    /// refunded if the message is cancelled.
+    pub topdown_fees: TCid<THamt<Cid, TokenAmount>>,
+    /// Address entitled to call the privileged methods of the gateway.
+    pub governor: Address,
+    /// If not empty, privileged methods can only be executed through governance
+    /// actions approved by `governance_threshold` of these governors.
+    pub governors: Vec<Address>,
+    pub governance_threshold: u64,
+    pub gov_proposals: TCid<THamt<u64, GovProposal>>,
+    pub next_gov_proposal_id: u64,
+    /// Subnet actors allowed to register. If `None` registration is permissionless.
+    pub registration_allowlist: Option<Vec<Address>>,
+    pub max_apply_attempts: u64,
+    /// Number of failed applications of the cross-messages being retried.
+    pub apply_attempts: TCid<THamt<Cid, u64>>,
+    /// Cross-messages that exhausted their application attempts.
+    pub dead_letters: TCid<THamt<Cid, CrossMsg>>,
+    /// Total amount of funds burnt for bottom-up messages.
+    pub total_burned: TokenAmount,
+    /// Total amount of cross-message fees collected since genesis.
+    pub total_fees_collected: TokenAmount,
+    pub send_cross_fee_policy: SendCrossFeePolicy,
+    pub checkpoint_retention: u64,
+    pub allow_local_cross: bool,
+    pub bottomup_fee_policy: BottomUpFeePolicy,
+    pub fee_exempt: Vec<Address>,
+    /// While in safe mode cross-messages carrying value are rejected.
+    pub safe_mode: bool,
+    /// Subnets removed by `Kill` or `ForceKill`, with the epoch they were killed.
+    pub killed_subnets: TCid<THamt<SubnetID, ChainEpoch>>,
+    /// If set, killed subnets are allowed to register again.
+    pub allow_reregister: bool,
+    pub max_postbox_items: u64,
+    pub postbox_overflow_policy: PostboxOverflowPolicy,
+    /// Number of items in the postbox, so it doesn't need to be walked to
+    /// enforce `max_postbox_items`.
+    pub postbox_len: u64,
+    pub min_cross_value: TokenAmount,
+    pub cross_msg_fees: CrossMsgFees,
+    pub require_actor_destination: bool,
 }
 
 lazy_static! {
@@ -55,6 +105,7 @@ lazy_static! {
 impl State {
     pub fn new<BS: Blockstore>(store: &BS, params: ConstructorParams) -> anyhow::Result<State> {
         Ok(State {
+            version: STATE_VERSION,
             network_name: SubnetID::from_str(&params.network_name)?,
             total_subnets: Default::default(),
             min_stake: MIN_SUBNET_COLLATERAL.clone(),
@@ -63,6 +114,10 @@ impl State {
                 true => params.checkpoint_period,
                 false => DEFAULT_CHECKPOINT_PERIOD,
             },
+            max_msgs_per_checkpoint: match params.max_msgs_per_checkpoint {
+                0 => DEFAULT_MAX_MSGS_PER_CHECKPOINT,
+                max => max,
+            },
             checkpoints: TCid::new_hamt(store)?,
             check_msg_registry: TCid::new_hamt(store)?,
             postbox: TCid::new_hamt(store)?,
@@ -73,9 +128,73 @@ impl State {
             // We first increase to the subsequent and then execute for bottom-up messages
             applied_bottomup_nonce: MAX_NONCE,
             applied_topdown_nonce: Default::default(),
+            pending_fee_distribution: TokenAmount::zero(),
+            topdown_fees: TCid::new_hamt(store)?,
+            governor: params.governor,
+            governors: Vec::new(),
+            governance_threshold: 0,
+            gov_proposals: TCid::new_hamt(store)?,
+            next_gov_proposal_id: 0,
+            registration_allowlist: params.registration_allowlist,
+            max_apply_attempts: match params.max_apply_attempts {
+                0 => DEFAULT_MAX_APPLY_ATTEMPTS,
+                max => max,
+            },
+            apply_attempts: TCid::new_hamt(store)?,
+            dead_letters: TCid::new_hamt(store)?,
+            total_burned: TokenAmount::zero(),
+            total_fees_collected: TokenAmount::zero(),
+            send_cross_fee_policy: params.send_cross_fee_policy,
+            checkpoint_retention: params.checkpoint_retention,
+            allow_local_cross: params.allow_local_cross,
+            bottomup_fee_policy: params.bottomup_fee_policy,
+            fee_exempt: params.fee_exempt,
+            safe_mode: false,
+            killed_subnets: TCid::new_hamt(store)?,
+            allow_reregister: false,
+            max_postbox_items: params.max_postbox_items,
+            postbox_overflow_policy: params.postbox_overflow_policy,
+            postbox_len: 0,
+            min_cross_value: params.min_cross_value,
+            cross_msg_fees: params.cross_msg_fees,
+            require_actor_destination: params.require_actor_destination,
         })
     }
 
+    /// Returns the share of the fees of a child checkpoint that is kept for
+    /// the validators of the current subnet, following `bottomup_fee_policy`.
+    ///
+    /// The share is rounded down, so the remainder of an uneven split is paid
+    /// to the child and no fees are lost to rounding.
+    pub fn forwarded_fee(&self, fee: &TokenAmount) -> TokenAmount {
+        match self.bottomup_fee_policy {
+            BottomUpFeePolicy::Split if self.network_name.parent().is_some() => fee.div_floor(2),
+            _ => TokenAmount::zero(),
+        }
+    }
+
+    /// Checks that a cross-message carrying `value` can be sent. Value transfers
+    /// are disabled while the gateway is in safe mode.
+    pub fn check_value_transfer(&self, value: &TokenAmount) -> anyhow::Result<()> {
+        if self.safe_mode && !value.is_zero() {
+            return Err(anyhow!("value transfers are disabled in safe mode"));
+        }
+        Ok(())
+    }
+
+    /// Checks that the value of a cross-message, once the fee is deducted, is
+    /// not below `min_cross_value`.
+    pub fn check_min_cross_value(&self, value: &TokenAmount) -> anyhow::Result<()> {
+        if value < &self.min_cross_value {
+            return Err(anyhow!(
+                "cross-message value {} is below the minimum {}",
+                value,
+                self.min_cross_value
+            ));
+        }
+        Ok(())
+    }
+
     /// Get content for a child subnet.
     pub fn get_subnet<BS: Blockstore>(
         &self,
@@ -98,18 +217,67 @@ impl State {
             return Err(anyhow!("call to register doesn't include enough funds"));
         }
 
-        let inserted = self.subnets.modify(rt.store(), |subnets| {
+        self.insert_subnet(rt.store(), id, val)
+    }
+
+    /// Register a list of subnets atomically.
+    ///
+    /// All the subnets need to be direct children of the current network, hold
+    /// enough stake and not be registered already, or none of them is registered.
+    pub(crate) fn bulk_register_subnets<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        subnets: &[GenesisSubnet],
+    ) -> anyhow::Result<()> {
+        let mut seen = HashSet::new();
+        for sub in subnets {
+            if !seen.insert(&sub.id) {
+                return Err(anyhow!("subnet {} included more than once", sub.id));
+            }
+            if sub.id.parent().as_ref() != Some(&self.network_name) {
+                return Err(anyhow!(
+                    "subnet {} is not a child of {}",
+                    sub.id,
+                    self.network_name
+                ));
+            }
+            if sub.stake < self.min_stake {
+                return Err(anyhow!("subnet {} doesn't include enough stake", sub.id));
+            }
+            if self.get_subnet(store, &sub.id)?.is_some() {
+                return Err(anyhow!("subnet with id {} already registered", sub.id));
+            }
+        }
+
+        for sub in subnets {
+            self.insert_subnet(store, &sub.id, sub.stake.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Insert a new subnet with the given stake if it doesn't exist yet.
+    fn insert_subnet<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        id: &SubnetID,
+        stake: TokenAmount,
+    ) -> anyhow::Result<()> {
+        let inserted = self.subnets.modify(store, |subnets| {
             if get_subnet(subnets, id)?.is_some() {
                 Ok(false)
             } else {
                 let subnet = Subnet {
                     id: id.clone(),
-                    stake: val,
-                    top_down_msgs: TCid::new_amt(rt.store())?,
+                    stake,
+                    top_down_msgs: TCid::new_amt(store)?,
                     circ_supply: TokenAmount::zero(),
                     status: Status::Active,
                     nonce: 0,
                     prev_checkpoint: None,
+                    frozen: false,
+                    max_topdown_value: None,
+                    applied_topdown_nonce: 0,
+                    cancel_requests: Vec::new(),
                 };
                 set_subnet(subnets, id, subnet)?;
                 Ok(true)
@@ -122,11 +290,94 @@ impl State {
         Ok(())
     }
 
-    /// Remove a subnet from the map of subnets and flush.
+    /// Checks if a subnet actor is allowed to register in the gateway.
+    pub fn can_register(&self, subnet_actor: &Address) -> bool {
+        match &self.registration_allowlist {
+            Some(allowlist) => allowlist.contains(subnet_actor),
+            None => true,
+        }
+    }
+
+    /// Freezes or unfreezes the flow of funds to and from a subnet.
+    pub(crate) fn set_subnet_frozen<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        id: &SubnetID,
+        frozen: bool,
+    ) -> anyhow::Result<()> {
+        let mut sub = match self.get_subnet(store, id)? {
+            Some(sub) => sub,
+            None => return Err(anyhow!("subnet with id {} not registered", id)),
+        };
+        sub.frozen = frozen;
+        self.flush_subnet(store, &sub)
+    }
+
+    /// Sets the maximum value of a single top-down message into a subnet.
+    pub(crate) fn set_max_topdown_value<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        id: &SubnetID,
+        max: Option<TokenAmount>,
+    ) -> anyhow::Result<()> {
+        let mut sub = match self.get_subnet(store, id)? {
+            Some(sub) => sub,
+            None => return Err(anyhow!("subnet with id {} not registered", id)),
+        };
+        sub.max_topdown_value = max;
+        self.flush_subnet(store, &sub)
+    }
+
+    /// Adds `delta` to the circulating supply of a subnet, returning the
+    /// previous and the new supply. The supply can't become negative.
+    pub(crate) fn adjust_circ_supply<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        id: &SubnetID,
+        delta: &TokenAmount,
+    ) -> anyhow::Result<(TokenAmount, TokenAmount)> {
+        let mut sub = match self.get_subnet(store, id)? {
+            Some(sub) => sub,
+            None => return Err(anyhow!("subnet with id {} not registered", id)),
+        };
+        let prev = sub.circ_supply.clone();
+        let new = &prev + delta;
+        if new < TokenAmount::zero() {
+            return Err(anyhow!(
+                "circulating supply of {} can't become negative: current={} delta={}",
+                id,
+                prev,
+                delta
+            ));
+        }
+        sub.circ_supply = new.clone();
+        self.flush_subnet(store, &sub)?;
+        Ok((prev, new))
+    }
+
+    /// Returns true if the child subnet through which a message from or to
+    /// `id` is routed is frozen.
+    pub fn is_route_frozen<BS: Blockstore>(
+        &self,
+        store: &BS,
+        id: &SubnetID,
+    ) -> anyhow::Result<bool> {
+        let child = match id.down(&self.network_name) {
+            Some(child) => child,
+            None => return Ok(false),
+        };
+        Ok(self
+            .get_subnet(store, &child)?
+            .map_or(false, |sub| sub.frozen))
+    }
+
+    /// Remove a subnet from the map of subnets and flush. The subnet is
+    /// recorded as killed in `epoch`.
     pub(crate) fn rm_subnet<BS: Blockstore>(
         &mut self,
         store: &BS,
         id: &SubnetID,
+        epoch: ChainEpoch,
     ) -> anyhow::Result<()> {
         let deleted = self.subnets.modify(store, |subnets| {
             subnets
@@ -136,10 +387,141 @@ impl State {
         })?;
         if deleted {
             self.total_subnets -= 1;
+            self.killed_subnets.update(store, |killed| {
+                killed.set(id.to_bytes().into(), epoch)?;
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Returns the epoch a subnet was killed in, `None` if it was never killed
+    /// or registered again since.
+    pub fn get_killed_subnet<BS: Blockstore>(
+        &self,
+        store: &BS,
+        id: &SubnetID,
+    ) -> anyhow::Result<Option<ChainEpoch>> {
+        let killed = self.killed_subnets.load(store)?;
+        Ok(killed.get(&BytesKey::from(id.to_bytes()))?.copied())
+    }
+
+    /// Forgets that a subnet was killed, once it is registered again.
+    pub(crate) fn rm_killed_subnet<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        id: &SubnetID,
+    ) -> anyhow::Result<()> {
+        self.killed_subnets.update(store, |killed| {
+            killed.delete(&BytesKey::from(id.to_bytes()))?;
+            Ok(())
+        })
+    }
+
+    /// Replaces the governors of the gateway and their approval threshold.
+    /// Pending governance actions are dropped, as their approvals may come from
+    /// governors that were removed.
+    pub(crate) fn set_governors<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        governors: Vec<Address>,
+        threshold: u64,
+    ) -> anyhow::Result<()> {
+        let unique: HashSet<&Address> = governors.iter().collect();
+        if unique.len() != governors.len() {
+            return Err(anyhow!("duplicate governors"));
+        }
+        if governors.is_empty() && threshold != 0 {
+            return Err(anyhow!("threshold set without governors"));
         }
+        if !governors.is_empty() && (threshold == 0 || threshold > governors.len() as u64) {
+            return Err(anyhow!(
+                "threshold {} out of range for {} governors",
+                threshold,
+                governors.len()
+            ));
+        }
+        self.governors = governors;
+        self.governance_threshold = threshold;
+        self.gov_proposals = TCid::new_hamt(store)?;
         Ok(())
     }
 
+    /// Creates a new governance action approved by its proposer and returns its
+    /// id. If the approval of the proposer already reaches the threshold the
+    /// action is returned to be executed instead of being stored.
+    pub(crate) fn propose_gov_action<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        proposer: Address,
+        params: ProposeGovActionParams,
+    ) -> anyhow::Result<(u64, Option<GovProposal>)> {
+        let id = self.next_gov_proposal_id;
+        self.next_gov_proposal_id += 1;
+        let proposal = GovProposal {
+            method: params.method,
+            params: params.params,
+            approvals: vec![proposer],
+        };
+        if self.governance_threshold <= 1 {
+            return Ok((id, Some(proposal)));
+        }
+        self.gov_proposals.update(store, |proposals| {
+            proposals.set(BytesKey::from(id.to_ne_bytes().to_vec()), proposal)?;
+            Ok(())
+        })?;
+        Ok((id, None))
+    }
+
+    /// Records the approval of a governance action by a governor. Once the
+    /// threshold is reached the action is removed and returned to be executed.
+    pub(crate) fn approve_gov_action<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        id: u64,
+        approver: Address,
+    ) -> anyhow::Result<Option<GovProposal>> {
+        let key = BytesKey::from(id.to_ne_bytes().to_vec());
+        let mut proposal = match self.gov_proposals.load(store)?.get(&key)? {
+            Some(p) => p.clone(),
+            None => return Err(anyhow!("governance action {} not found", id)),
+        };
+        if proposal.approvals.contains(&approver) {
+            return Err(anyhow!(
+                "governance action {} already approved by {}",
+                id,
+                approver
+            ));
+        }
+        proposal.approvals.push(approver);
+
+        if (proposal.approvals.len() as u64) < self.governance_threshold {
+            let pending = proposal.clone();
+            self.gov_proposals.update(store, |proposals| {
+                proposals.set(key, pending)?;
+                Ok(())
+            })?;
+            return Ok(None);
+        }
+        self.gov_proposals.update(store, |proposals| {
+            proposals.delete(&key)?;
+            Ok(())
+        })?;
+        Ok(Some(proposal))
+    }
+
+    /// Returns a governance action waiting for approvals.
+    pub fn get_gov_proposal<BS: Blockstore>(
+        &self,
+        store: &BS,
+        id: u64,
+    ) -> anyhow::Result<Option<GovProposal>> {
+        let proposals = self.gov_proposals.load(store)?;
+        Ok(proposals
+            .get(&BytesKey::from(id.to_ne_bytes().to_vec()))?
+            .cloned())
+    }
+
     /// flush a subnet
     pub(crate) fn flush_subnet<BS: Blockstore>(
         &mut self,
@@ -160,6 +542,94 @@ impl State {
             .update(store, |checkpoints| set_checkpoint(checkpoints, ch.clone()))
     }
 
+    /// Removes the checkpoints of windows older than the last `checkpoint_retention`
+    /// ones, returning the number of checkpoints pruned.
+    ///
+    /// The cross-messages of the pruned checkpoints are removed from the registry in
+    /// the same pass, and the metas of bottom-up messages already applied from
+    /// `bottomup_msg_meta`. Checkpoints including the latest checkpoint committed by
+    /// a child subnet, referenced by its `prev_checkpoint`, are never pruned.
+    pub(crate) fn prune_checkpoints<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        curr_epoch: ChainEpoch,
+    ) -> anyhow::Result<u64> {
+        if self.checkpoint_retention == 0 {
+            return Ok(0);
+        }
+        let retention =
+            ChainEpoch::try_from(self.checkpoint_retention)?.saturating_mul(self.check_period);
+        let cutoff = checkpoint_epoch(curr_epoch, self.check_period).saturating_sub(retention);
+
+        let mut referenced = HashSet::new();
+        self.subnets.load(store)?.for_each(|_, sub| {
+            if let Some(ch) = &sub.prev_checkpoint {
+                referenced.insert(ch.cid());
+            }
+            Ok(())
+        })?;
+
+        let mut to_prune = Vec::new();
+        let mut registry_keys = Vec::new();
+        self.checkpoints.load(store)?.for_each(|k, ch| {
+            let is_referenced = ch
+                .data
+                .children
+                .iter()
+                .flat_map(|c| c.checks.iter())
+                .any(|c| referenced.contains(&c.cid()));
+            if ch.epoch() < cutoff && !is_referenced {
+                to_prune.push(k.clone());
+                if let Some(meta) = ch.cross_msgs() {
+                    registry_keys.push(meta.msgs_cid.cid().to_bytes());
+                }
+            }
+            Ok(())
+        })?;
+
+        let pruned = to_prune.len() as u64;
+        if pruned == 0 {
+            return Ok(0);
+        }
+        self.checkpoints.update(store, |checkpoints| {
+            for k in to_prune.iter() {
+                checkpoints.delete(k)?;
+            }
+            Ok(())
+        })?;
+        if !registry_keys.is_empty() {
+            self.check_msg_registry.update(store, |registry| {
+                for k in registry_keys.iter() {
+                    registry.delete(k)?;
+                }
+                Ok(())
+            })?;
+        }
+
+        // the meta being applied and the ones after it are kept
+        if self.applied_bottomup_nonce != MAX_NONCE {
+            let applied = self.applied_bottomup_nonce;
+            self.bottomup_msg_meta.update(store, |metas| {
+                let mut to_remove = Vec::new();
+                metas
+                    .for_each(|nonce, _| {
+                        if nonce < applied {
+                            to_remove.push(nonce);
+                        }
+                        Ok(())
+                    })
+                    .map_err(|e| anyhow!("failed to iterate crossmsg meta array: {:?}", e))?;
+                for nonce in to_remove {
+                    metas
+                        .delete(nonce)
+                        .map_err(|e| anyhow!("failed to delete crossmsg meta: {:?}", e))?;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(pruned)
+    }
+
     /// get checkpoint being populated in the current window.
     pub fn get_window_checkpoint<BS: Blockstore>(
         &self,
@@ -172,10 +642,13 @@ impl State {
         let ch_epoch = checkpoint_epoch(epoch, self.check_period);
         let checkpoints = self.checkpoints.load(store)?;
 
-        Ok(match get_checkpoint(&checkpoints, &ch_epoch)? {
+        let mut ch = match get_checkpoint(&checkpoints, &ch_epoch)? {
             Some(ch) => ch.clone(),
             None => Checkpoint::new(self.network_name.clone(), ch_epoch),
-        })
+        };
+        // report the top-down messages applied so far to the parent
+        ch.data.applied_topdown_nonce = self.applied_topdown_nonce;
+        Ok(ch)
     }
 
     /// store a cross-message in a checkpoint
@@ -195,6 +668,8 @@ impl State {
                 msgmeta.msgs_cid = m_cid;
                 msgmeta.value += &cross_msg.msg.value + fee;
                 msgmeta.fee += fee;
+                msgmeta.msgs_value += &cross_msg.msg.value;
+                msgmeta.num_msgs += 1;
             }
             None => self.check_msg_registry.modify(store, |cross_reg| {
                 let mut msgmeta = CrossMsgMeta::default();
@@ -204,6 +679,8 @@ impl State {
                 msgmeta.msgs_cid = m_cid;
                 msgmeta.value += &cross_msg.msg.value + fee;
                 msgmeta.fee += fee;
+                msgmeta.msgs_value += &cross_msg.msg.value;
+                msgmeta.num_msgs = 1;
                 ch.set_cross_msgs(msgmeta);
                 Ok(())
             })?,
@@ -258,7 +735,7 @@ impl State {
     ) -> anyhow::Result<()> {
         let mut new_meta = meta.clone();
         new_meta.nonce = self.bottomup_nonce;
-        self.bottomup_nonce += 1;
+        self.bottomup_nonce = next_nonce(self.bottomup_nonce)?;
         self.bottomup_msg_meta.update(store, |crossmsgs| {
             crossmsgs
                 .set(new_meta.nonce, new_meta)
@@ -267,10 +744,14 @@ impl State {
     }
 
     /// commit topdown messages for their execution in the subnet
+    ///
+    /// The fee paid by the message is held until the subnet reports it applied,
+    /// so it can be refunded with its value if the message is cancelled.
     pub(crate) fn commit_topdown_msg<BS: Blockstore>(
         &mut self,
         store: &BS,
         cross_msg: &mut CrossMsg,
+        fee: &TokenAmount,
     ) -> anyhow::Result<()> {
         let msg = &cross_msg.msg;
         let sto = msg.to.subnet()?;
@@ -288,9 +769,30 @@ impl State {
             })?;
         match sub {
             Some(mut sub) => {
+                if sub.frozen {
+                    return Err(anyhow!("subnet {} is frozen", sub.id));
+                }
+                if let Some(max) = &sub.max_topdown_value {
+                    if &cross_msg.msg.value > max {
+                        return Err(anyhow!(
+                            "top-down message value {} exceeds the maximum {} of subnet {}",
+                            cross_msg.msg.value,
+                            max,
+                            sub.id
+                        ));
+                    }
+                }
                 cross_msg.msg.nonce = sub.nonce;
+                cross_msg.set_correlation_id(sub.nonce)?;
                 sub.store_topdown_msg(store, cross_msg)?;
-                sub.nonce += 1;
+                if !fee.is_zero() {
+                    let cid = cross_msg.cid()?;
+                    self.topdown_fees.update(store, |fees| {
+                        fees.set(BytesKey::from(cid.to_bytes()), fee.clone())?;
+                        Ok(())
+                    })?;
+                }
+                sub.nonce = next_nonce(sub.nonce)?;
                 sub.circ_supply += &cross_msg.msg.value;
                 self.flush_subnet(store, &sub)?;
             }
@@ -303,22 +805,202 @@ impl State {
         Ok(())
     }
 
+    /// request the cancellation of a top-down message that hasn't been applied
+    /// yet in the subnet.
+    ///
+    /// The watermark reported by the checkpoints of the subnet lags behind the
+    /// messages it actually applied, so the message is only cancelled once the
+    /// subnet acknowledges in a checkpoint that it skipped it, see
+    /// `cancel_topdown_msg`. Messages below the watermark are known to be
+    /// applied and can't be cancelled.
+    pub(crate) fn request_topdown_cancel<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        id: &SubnetID,
+        nonce: u64,
+        from: &Address,
+    ) -> anyhow::Result<()> {
+        let mut sub = match self.get_subnet(store, id)? {
+            Some(sub) => sub,
+            None => return Err(anyhow!("subnet with id {} not registered", id)),
+        };
+        if nonce < sub.applied_topdown_nonce {
+            return Err(anyhow!(
+                "top-down message with nonce {} already applied",
+                nonce
+            ));
+        }
+
+        let crossmsgs = sub.top_down_msgs.load(store)?;
+        let msg = match get_topdown_msg(&crossmsgs, nonce)? {
+            Some(msg) => msg,
+            None => return Err(anyhow!("no top-down message found for nonce {}", nonce)),
+        };
+        if msg.from.raw_addr()? != *from {
+            return Err(anyhow!("only the sender of the message can cancel it"));
+        }
+        if msg.value.is_zero() {
+            return Err(anyhow!("top-down message has no funds to cancel"));
+        }
+        if sub.cancel_requests.contains(&nonce) {
+            return Err(anyhow!(
+                "cancellation of top-down message {} already requested",
+                nonce
+            ));
+        }
+
+        sub.cancel_requests.push(nonce);
+        self.flush_subnet(store, &sub)
+    }
+
+    /// cancel a top-down message that the subnet acknowledged it skipped without
+    /// applying it, where `applied` is the watermark reported by the subnet.
+    ///
+    /// The message is removed from the top-down queue of the subnet. It returns
+    /// the sender of the message with its value and fee to be refunded.
+    pub(crate) fn cancel_topdown_msg<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        sub: &mut Subnet,
+        nonce: u64,
+        applied: u64,
+    ) -> anyhow::Result<(Address, TokenAmount)> {
+        if !sub.cancel_requests.contains(&nonce) {
+            return Err(anyhow!(
+                "cancellation of top-down message {} wasn't requested",
+                nonce
+            ));
+        }
+        if nonce >= applied {
+            return Err(anyhow!(
+                "top-down message {} can't be cancelled above the applied nonce {}",
+                nonce,
+                applied
+            ));
+        }
+
+        let cross_msg = match sub
+            .top_down_msgs
+            .load(store)?
+            .get(nonce)
+            .map_err(|e| anyhow!("failed to get msg by nonce: {:?}", e))?
+        {
+            Some(m) => m.clone(),
+            None => return Err(anyhow!("no top-down message found for nonce {}", nonce)),
+        };
+        sub.top_down_msgs.update(store, |crossmsgs| {
+            crossmsgs
+                .delete(nonce)
+                .map_err(|e| anyhow!("failed to delete top-down message: {:?}", e))?;
+            Ok(())
+        })?;
+        sub.cancel_requests.retain(|n| *n != nonce);
+        sub.release_supply(&cross_msg.msg.value)?;
+
+        let fee = self.take_topdown_fee(store, &cross_msg)?;
+        Ok((cross_msg.msg.from.raw_addr()?, &cross_msg.msg.value + fee))
+    }
+
+    /// Releases the fees held for the top-down messages of the subnet with a
+    /// nonce in `[from, to)`, returning their total. The subnet applied them, so
+    /// their pending cancellation requests are dropped.
+    pub(crate) fn release_topdown_fees<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        sub: &mut Subnet,
+        from: u64,
+        to: u64,
+    ) -> anyhow::Result<TokenAmount> {
+        let crossmsgs = sub.top_down_msgs.load(store)?;
+        let mut released = TokenAmount::zero();
+        for nonce in from..to {
+            // cancelled messages are no longer in the queue
+            if let Some(cross_msg) = crossmsgs
+                .get(nonce)
+                .map_err(|e| anyhow!("failed to get msg by nonce: {:?}", e))?
+            {
+                released += self.take_topdown_fee(store, cross_msg)?;
+            }
+        }
+        sub.cancel_requests.retain(|n| *n < from || *n >= to);
+        Ok(released)
+    }
+
+    /// Releases the fees held for the top-down messages that a subnet being
+    /// removed didn't apply, so they are distributed to its validators as the
+    /// fees of the messages it applied. It returns the fees released.
+    pub(crate) fn release_unapplied_topdown_fees<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        sub: &mut Subnet,
+    ) -> anyhow::Result<TokenAmount> {
+        let (from, to) = (sub.applied_topdown_nonce, sub.nonce);
+        let released = self.release_topdown_fees(store, sub, from, to)?;
+        self.pending_fee_distribution += &released;
+        Ok(released)
+    }
+
+    /// Removes the fee held for a top-down message, returning it.
+    fn take_topdown_fee<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        cross_msg: &CrossMsg,
+    ) -> anyhow::Result<TokenAmount> {
+        let cid = cross_msg.cid()?;
+        self.topdown_fees.modify(store, |fees| {
+            Ok(fees
+                .delete(&cid.to_bytes())?
+                .map(|(_, fee)| fee)
+                .unwrap_or_default())
+        })
+    }
+
+    /// store a top-down message skipped in the checkpoint of the current window,
+    /// acknowledging its cancellation to the parent.
+    pub(crate) fn store_skipped_topdown_msg<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        nonce: u64,
+        curr_epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        let mut ch = self.get_window_checkpoint(store, curr_epoch)?;
+        ch.data.cancelled_topdown_msgs.push(nonce);
+        self.flush_checkpoint(store, &ch)
+    }
+
     /// commit bottomup messages for their execution in the subnet
     pub(crate) fn commit_bottomup_msg<BS: Blockstore>(
         &mut self,
         store: &BS,
-        msg: &CrossMsg,
+        msg: &mut CrossMsg,
         fee: &TokenAmount,
         curr_epoch: ChainEpoch,
     ) -> anyhow::Result<()> {
+        msg.set_correlation_id(self.nonce)?;
         // store bottom-up msg and fee in checkpoint for propagation
         self.store_msg_in_checkpoint(store, msg, fee, curr_epoch)?;
         // increment nonce
-        self.nonce += 1;
+        self.nonce = next_nonce(self.nonce)?;
 
         Ok(())
     }
 
+    /// Moves the watermark of applied top-down messages forward.
+    ///
+    /// The watermark can never be moved backwards to prevent top-down messages
+    /// from being replayed.
+    pub(crate) fn set_applied_topdown_nonce(&mut self, nonce: u64) -> anyhow::Result<()> {
+        if nonce <= self.applied_topdown_nonce {
+            return Err(anyhow!(
+                "applied top-down nonce can only move forward: current={} new={}",
+                self.applied_topdown_nonce,
+                nonce
+            ));
+        }
+        self.applied_topdown_nonce = nonce;
+        Ok(())
+    }
+
     pub fn bottomup_state_transition(&mut self, msg: &StorableMsg) -> anyhow::Result<()> {
         // Bottom-up messages include the nonce of their message meta. Several messages
         // will include the same nonce. They need to be applied in order of nonce.
@@ -342,6 +1024,257 @@ impl State {
         Ok(())
     }
 
+    /// Returns the number of failed applications registered for a cross-message.
+    pub fn get_apply_attempts<BS: Blockstore>(&self, store: &BS, cid: &Cid) -> anyhow::Result<u64> {
+        let attempts = self.apply_attempts.load(store)?;
+        Ok(attempts.get(&cid.to_bytes())?.copied().unwrap_or_default())
+    }
+
+    /// Registers a failed application of a cross-message. Once the message reaches
+    /// the maximum number of attempts it is moved to the dead-letter queue.
+    ///
+    /// Returns `true` if the message was moved to the dead-letter queue.
+    pub fn record_failed_apply<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        cid: &Cid,
+        cross_msg: &CrossMsg,
+    ) -> anyhow::Result<bool> {
+        let attempts = self.get_apply_attempts(store, cid)? + 1;
+        if attempts < self.max_apply_attempts {
+            self.apply_attempts.update(store, |map| {
+                map.set(BytesKey::from(cid.to_bytes()), attempts)?;
+                Ok(())
+            })?;
+            return Ok(false);
+        }
+
+        self.clear_apply_attempts(store, cid)?;
+        self.dead_letters.update(store, |map| {
+            map.set(BytesKey::from(cid.to_bytes()), cross_msg.clone())?;
+            Ok(())
+        })?;
+        Ok(true)
+    }
+
+    /// Removes the failed applications registered for a cross-message.
+    pub fn clear_apply_attempts<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        cid: &Cid,
+    ) -> anyhow::Result<()> {
+        self.apply_attempts.update(store, |map| {
+            map.delete(&cid.to_bytes())?;
+            Ok(())
+        })
+    }
+
+    /// Returns the names of the optional features enabled in the gateway.
+    pub fn enabled_features(&self) -> Vec<String> {
+        let mut features = Vec::new();
+        if self.registration_allowlist.is_some() {
+            features.push(String::from("registration-allowlist"));
+        }
+        if self.send_cross_fee_policy == SendCrossFeePolicy::Surplus {
+            features.push(String::from("send-cross-surplus-fee"));
+        }
+        if self.checkpoint_retention > 0 {
+            features.push(String::from("checkpoint-pruning"));
+        }
+        if self.allow_local_cross {
+            features.push(String::from("local-cross-msgs"));
+        }
+        if self.bottomup_fee_policy == BottomUpFeePolicy::Split {
+            features.push(String::from("split-bottomup-fees"));
+        }
+        if !self.fee_exempt.is_empty() {
+            features.push(String::from("fee-exemptions"));
+        }
+        if self.max_postbox_items > 0 {
+            features.push(String::from("bounded-postbox"));
+        }
+        if !self.min_cross_value.is_zero() {
+            features.push(String::from("min-cross-value"));
+        }
+        if self.require_actor_destination {
+            features.push(String::from("actor-destinations-only"));
+        }
+        features
+    }
+
+    /// Counts the cross-messages pending to be relayed. Only subnets and the
+    /// checkpoint of the current window are loaded, not the messages themselves.
+    ///
+    /// As in `cancel_topdown_msg`, top-down messages with a nonce below the
+    /// `applied_topdown_nonce` reported by the child subnet are considered
+    /// applied.
+    pub fn queue_depths<BS: Blockstore>(
+        &self,
+        store: &BS,
+        curr_epoch: ChainEpoch,
+    ) -> anyhow::Result<QueueDepths> {
+        let subnets = self.subnets.load(store)?;
+        let mut topdown = 0;
+        subnets.for_each(|_, sub| {
+            topdown += sub.nonce.saturating_sub(sub.applied_topdown_nonce);
+            Ok(())
+        })?;
+
+        let ch = self.get_window_checkpoint(store, curr_epoch)?;
+        let bottomup = ch.cross_msgs().map_or(0, |meta| meta.num_msgs);
+
+        Ok(QueueDepths { topdown, bottomup })
+    }
+
+    /// Computes the stats of the gateway. The subnets are traversed in a single
+    /// pass, so the cost is bounded by the number of subnets registered.
+    pub fn stats<BS: Blockstore>(
+        &self,
+        store: &BS,
+        curr_epoch: ChainEpoch,
+    ) -> anyhow::Result<GatewayStats> {
+        let subnets = self.subnets.load(store)?;
+        let mut total_stake = TokenAmount::zero();
+        let mut total_circ_supply = TokenAmount::zero();
+        let mut topdown = 0;
+        subnets.for_each(|_, sub| {
+            total_stake += &sub.stake;
+            total_circ_supply += &sub.circ_supply;
+            topdown += sub.nonce.saturating_sub(sub.applied_topdown_nonce);
+            Ok(())
+        })?;
+
+        let ch = self.get_window_checkpoint(store, curr_epoch)?;
+        let bottomup = ch.cross_msgs().map_or(0, |meta| meta.num_msgs);
+
+        Ok(GatewayStats {
+            total_subnets: self.total_subnets,
+            total_stake,
+            total_circ_supply,
+            queue_depths: QueueDepths { topdown, bottomup },
+            total_burned: self.total_burned.clone(),
+            applied_topdown_nonce: self.applied_topdown_nonce,
+            applied_bottomup_nonce: self.applied_bottomup_nonce,
+        })
+    }
+
+    /// Looks for a cross-message in the dead-letter queue and the postbox and,
+    /// when the caller says where it was committed, in the top-down queue of
+    /// the child subnet or in the checkpoint of the given window. Only keyed
+    /// lookups are performed so the cost doesn't grow with the gateway state.
+    pub fn cross_msg_status<BS: Blockstore>(
+        &self,
+        store: &BS,
+        params: &GetCrossMsgStatusParams,
+    ) -> anyhow::Result<CrossMsgStatus> {
+        let key = BytesKey::from(params.cid.to_bytes());
+        if self.dead_letters.load(store)?.contains_key(&key)? {
+            return Ok(CrossMsgStatus::Failed);
+        }
+        if self.postbox.load(store)?.contains_key(&key)? {
+            return Ok(CrossMsgStatus::InPostbox);
+        }
+
+        if let Some((subnet, nonce)) = &params.topdown {
+            if let Some(sub) = self.get_subnet(store, subnet)? {
+                let found = match sub
+                    .top_down_msgs
+                    .load(store)?
+                    .get(*nonce)
+                    .map_err(|e| anyhow!("failed to get msg by nonce: {:?}", e))?
+                {
+                    Some(m) => m.cid()? == params.cid,
+                    None => false,
+                };
+                if found {
+                    let (subnet, nonce) = (subnet.clone(), *nonce);
+                    return Ok(match nonce < sub.applied_topdown_nonce {
+                        true => CrossMsgStatus::TopDownApplied { subnet, nonce },
+                        false => CrossMsgStatus::TopDownCommitted { subnet, nonce },
+                    });
+                }
+            }
+        }
+
+        if let Some(epoch) = params.epoch {
+            if epoch < 0 {
+                return Err(anyhow!("epoch can't be negative"));
+            }
+            let checkpoints = self.checkpoints.load(store)?;
+            if let Some(ch) =
+                get_checkpoint(&checkpoints, &checkpoint_epoch(epoch, self.check_period))?
+            {
+                if let Some(meta) = ch.cross_msgs() {
+                    let registry = self.check_msg_registry.load(store)?;
+                    if let Some(crossmsgs) = registry.get(&meta.msgs_cid.cid().to_bytes())? {
+                        for m in crossmsgs.msgs.iter() {
+                            if m.cid()? == params.cid {
+                                return Ok(CrossMsgStatus::BottomUpCommitted { epoch: ch.epoch() });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(CrossMsgStatus::NotFound)
+    }
+
+    /// Returns the bottom-up message with `nonce` committed in the checkpoint
+    /// of the window `epoch` belongs to, if any.
+    pub fn get_bottomup_msg_by_nonce<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: ChainEpoch,
+        nonce: u64,
+    ) -> anyhow::Result<Option<CrossMsg>> {
+        if epoch < 0 {
+            return Err(anyhow!("epoch can't be negative"));
+        }
+        let checkpoints = self.checkpoints.load(store)?;
+        let meta = match get_checkpoint(&checkpoints, &checkpoint_epoch(epoch, self.check_period))?
+            .and_then(|ch| ch.cross_msgs())
+        {
+            Some(meta) => meta,
+            None => return Ok(None),
+        };
+        let registry = self.check_msg_registry.load(store)?;
+        Ok(registry
+            .get(&meta.msgs_cid.cid().to_bytes())?
+            .and_then(|crossmsgs| crossmsgs.msgs.iter().find(|m| m.msg.nonce == nonce))
+            .cloned())
+    }
+
+    /// Returns all the cross-messages in the dead-letter queue.
+    pub fn list_dead_letters<BS: Blockstore>(&self, store: &BS) -> anyhow::Result<Vec<DeadLetter>> {
+        let dead_letters = self.dead_letters.load(store)?;
+        let mut out = Vec::new();
+        dead_letters.for_each(|k, cross_msg| {
+            out.push(DeadLetter {
+                cid: Cid::try_from(k.0.as_slice())?,
+                cross_msg: cross_msg.clone(),
+            });
+            Ok(())
+        })?;
+        Ok(out)
+    }
+
+    /// Removes a cross-message from the dead-letter queue and returns it.
+    pub fn take_dead_letter<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        cid: &Cid,
+    ) -> anyhow::Result<CrossMsg> {
+        self.dead_letters
+            .modify(store, |map| match map.delete(&cid.to_bytes())? {
+                Some((_, cross_msg)) => Ok(cross_msg),
+                None => Err(anyhow!(
+                    "cross-message {} not found in dead-letter queue",
+                    cid
+                )),
+            })
+    }
+
     /// Insert a cross message to the `postbox` before propagate can be called for the
     /// message to be propagated upwards or downwards.
     ///
@@ -351,24 +1284,91 @@ impl State {
     ///              anyone can propagate this message. Allows multiple owners.
     /// * `gas` - The gas needed to propagate this message
     /// * `msg` - The actual cross msg to store in `postbox`
+    /// * `curr_epoch` - The current epoch, to find the expired items if the postbox is full
     pub fn insert_postbox<BS: Blockstore>(
         &mut self,
         st: &BS,
         owners: Option<Vec<Address>>,
         msg: CrossMsg,
+        curr_epoch: ChainEpoch,
     ) -> anyhow::Result<Cid> {
+        self.make_room_in_postbox(st, curr_epoch)?;
         let item = PostBoxItem::new(msg, owners);
         let (cid, bytes) = item
             .serialize_with_cid()
             .map_err(|_| anyhow!("cannot serialize postbox item"))?;
+        let mut added = false;
         self.postbox.update(st, |postbox| {
             let key = BytesKey::from(cid.to_bytes());
-            postbox.set(key, bytes)?;
+            added = postbox.set(key, bytes)?.is_none();
             Ok(())
         })?;
+        if added {
+            self.postbox_len += 1;
+        }
         Ok(cid)
     }
 
+    /// Checks that a new item fits in the postbox under `max_postbox_items`, evicting
+    /// the item that expired first to the dead-letter queue if the policy allows it.
+    ///
+    /// The postbox is only walked to find the expired items once it is full.
+    fn make_room_in_postbox<BS: Blockstore>(
+        &mut self,
+        st: &BS,
+        curr_epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        if self.max_postbox_items == 0 || self.postbox_len < self.max_postbox_items {
+            return Ok(());
+        }
+        if self.postbox_overflow_policy != PostboxOverflowPolicy::EvictExpired {
+            return Err(anyhow!(
+                "postbox is full with {} items",
+                self.max_postbox_items
+            ));
+        }
+
+        let mut oldest_expired: Option<(BytesKey, CrossMsg)> = None;
+        self.postbox.load(st)?.for_each(|k, bytes| {
+            let item = PostBoxItem::deserialize(bytes.clone())
+                .map_err(|_| anyhow!("cannot parse postbox item"))?;
+            if !item.cross_msg.msg.is_expired(curr_epoch) {
+                return Ok(());
+            }
+            let older = match &oldest_expired {
+                Some((_, oldest)) => {
+                    item.cross_msg.msg.valid_until_epoch < oldest.msg.valid_until_epoch
+                }
+                None => true,
+            };
+            if older {
+                oldest_expired = Some((k.clone(), item.cross_msg));
+            }
+            Ok(())
+        })?;
+
+        match oldest_expired {
+            Some((key, cross_msg)) => {
+                self.postbox.update(st, |postbox| {
+                    postbox.delete(&key)?;
+                    Ok(())
+                })?;
+                self.postbox_len -= 1;
+                let cid = cross_msg.cid()?;
+                self.dead_letters.update(st, |map| {
+                    map.set(BytesKey::from(cid.to_bytes()), cross_msg)?;
+                    Ok(())
+                })?;
+                log::info!("PostboxItemEvicted: cid={}", cid);
+                Ok(())
+            }
+            None => Err(anyhow!(
+                "postbox is full with {} items",
+                self.max_postbox_items
+            )),
+        }
+    }
+
     pub fn load_from_postbox<BS: Blockstore>(
         &self,
         st: &BS,
@@ -391,7 +1391,7 @@ impl State {
         cid: Cid,
         item: PostBoxItem,
     ) -> anyhow::Result<()> {
-        self.postbox.modify(st, |postbox| {
+        let merged = self.postbox.modify(st, |postbox| {
             let previous = postbox.delete(&BytesKey::from(cid.to_bytes()))?;
             if previous.is_none() {
                 return Err(anyhow!("cid not found in postbox"));
@@ -400,10 +1400,12 @@ impl State {
                 .serialize_with_cid()
                 .map_err(|_| anyhow!("cannot serialize postbox item"))?;
             let key = BytesKey::from(cid.to_bytes());
-            postbox.set(key, bytes)?;
-
-            Ok(())
+            Ok(postbox.set(key, bytes)?.is_some())
         })?;
+        // the new item may be identical to one already in the postbox
+        if merged {
+            self.postbox_len -= 1;
+        }
 
         Ok(())
     }
@@ -417,20 +1419,34 @@ impl State {
         st: &BS,
         cid: Cid,
     ) -> Result<(), ActorError> {
-        self.postbox
+        let removed = self
+            .postbox
             .modify(st, |postbox| {
-                postbox.delete(&BytesKey::from(cid.to_bytes()))?;
-                Ok(())
+                Ok(postbox.delete(&BytesKey::from(cid.to_bytes()))?.is_some())
             })
             .map_err(|e| {
                 log::error!("encountered error deleting from postbox: {:?}", e);
                 actor_error!(unhandled_message, "cannot delete from postbox")
             })?;
+        if removed {
+            self.postbox_len -= 1;
+        }
         Ok(())
     }
 
+    /// Returns the fee charged to `from` for a cross-message of the given kind,
+    /// zero if it is exempt.
+    pub fn cross_msg_fee(&self, from: &Address, kind: CrossMsgKind) -> TokenAmount {
+        if self.fee_exempt.contains(from) {
+            TokenAmount::zero()
+        } else {
+            self.cross_msg_fees.get(kind).clone()
+        }
+    }
+
     /// Collects cross-fee and reduces the corresponding
-    /// balances from which the fee is collected.
+    /// balances from which the fee is collected. The fee is added
+    /// to `total_fees_collected`.
     pub fn collect_cross_fee(
         &mut self,
         balance: &mut TokenAmount,
@@ -446,6 +1462,7 @@ impl State {
 
         // update balance after collecting the fee
         *balance -= fee;
+        self.total_fees_collected += fee;
         Ok(())
     }
 }
@@ -533,3 +1550,11 @@ pub fn get_topdown_msg<'m, BS: Blockstore>(
         .map(|c| &c.msg);
     Ok(r)
 }
+
+/// Returns the nonce following `nonce`. It fails instead of wrapping around
+/// on overflow, as a wrapped nonce would allow replaying previous messages.
+pub(crate) fn next_nonce(nonce: u64) -> anyhow::Result<u64> {
+    nonce
+        .checked_add(1)
+        .ok_or_else(|| anyhow!("nonce overflow: {} can't be incremented", nonce))
+}
@@ -5,7 +5,7 @@ use fvm_shared::econ::TokenAmount;
 use primitives::{TAmt, TCid};
 use serde::{Deserialize, Serialize};
 
-use crate::CROSSMSG_AMT_BITWIDTH;
+use crate::{Collateralization, CROSSMSG_AMT_BITWIDTH};
 use ipc_sdk::subnet_id::SubnetID;
 
 use super::checkpoint::*;
@@ -29,21 +29,59 @@ pub struct Subnet {
     pub circ_supply: TokenAmount,
     pub status: Status,
     pub prev_checkpoint: Option<Checkpoint>,
+    /// Frozen subnets can't receive or send funds through cross-messages.
+    #[serde(default)]
+    pub frozen: bool,
+    /// Maximum value of a single top-down message into the subnet. If `None`
+    /// the value is unlimited.
+    #[serde(default)]
+    pub max_topdown_value: Option<TokenAmount>,
+    /// Number of top-down messages applied in the subnet, as reported by its
+    /// latest committed checkpoint.
+    #[serde(default)]
+    pub applied_topdown_nonce: u64,
+    /// Nonces of the top-down messages their senders asked to cancel. They are
+    /// only cancelled once the subnet acknowledges it skipped them.
+    #[serde(default)]
+    pub cancel_requests: Vec<u64>,
 }
 
 impl Subnet {
+    /// Adds stake to the subnet (or releases it for negative values) updating
+    /// its status. It returns true if the subnet was reactivated.
     pub(crate) fn add_stake(
         &mut self,
         rt: &impl Runtime,
         st: &mut State,
         value: &TokenAmount,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<bool> {
         self.stake += value;
-        if self.stake < st.min_stake {
+        let reactivated = self.update_status(&st.min_stake);
+        st.flush_subnet(rt.store(), self)?;
+        Ok(reactivated)
+    }
+
+    /// Updates the status of the subnet according to its stake. Subnets below
+    /// the minimum stake are inactivated, and inactive subnets holding enough
+    /// stake are reactivated.
+    ///
+    /// It returns true only on the inactive to active transition.
+    pub(crate) fn update_status(&mut self, min_stake: &TokenAmount) -> bool {
+        if &self.stake < min_stake {
             self.status = Status::Inactive;
+            return false;
         }
-        st.flush_subnet(rt.store(), self)?;
-        Ok(())
+        if self.status == Status::Inactive {
+            self.status = Status::Active;
+            return true;
+        }
+        false
+    }
+
+    /// Returns the collateralization of the subnet from its current stake
+    /// and circulating supply.
+    pub fn collateralization(&self) -> Collateralization {
+        Collateralization::new(self.stake.clone(), self.circ_supply.clone())
     }
 
     /// store topdown messages for their execution in the subnet
@@ -60,6 +98,23 @@ impl Subnet {
         })
     }
 
+    /// Verifies that a checkpoint being committed for the subnet is consistent
+    /// with the previous checkpoint committed, if any.
+    pub fn verify_prev_checkpoint(&self, commit: &Checkpoint) -> anyhow::Result<()> {
+        if let Some(ref prev_checkpoint) = self.prev_checkpoint {
+            if prev_checkpoint.epoch() > commit.epoch() {
+                return Err(anyhow!("checkpoint being committed belongs to the past"));
+            }
+            // check that the previous cid is consistent with the previous one
+            if commit.prev_check().cid() != prev_checkpoint.cid() {
+                return Err(anyhow!(
+                    "previous checkpoint not consistent with previous one"
+                ));
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn release_supply(&mut self, value: &TokenAmount) -> anyhow::Result<()> {
         if &self.circ_supply < value {
             return Err(anyhow!(
@@ -70,3 +125,45 @@ impl Subnet {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::subnet::*;
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use num_traits::Zero;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_update_status() {
+        let min_stake = TokenAmount::from_atto(10);
+        let mut sub = Subnet {
+            id: SubnetID::from_str("/root/f01").unwrap(),
+            stake: TokenAmount::from_atto(10),
+            top_down_msgs: TCid::new_amt(&MemoryBlockstore::new()).unwrap(),
+            nonce: 0,
+            circ_supply: TokenAmount::zero(),
+            status: Status::Active,
+            prev_checkpoint: None,
+            frozen: false,
+            max_topdown_value: None,
+            applied_topdown_nonce: 0,
+            cancel_requests: Vec::new(),
+        };
+
+        // active subnets with enough stake are left untouched
+        assert!(!sub.update_status(&min_stake));
+        assert_eq!(sub.status, Status::Active);
+
+        // below the minimum stake
+        sub.stake = TokenAmount::from_atto(5);
+        assert!(!sub.update_status(&min_stake));
+        assert_eq!(sub.status, Status::Inactive);
+
+        // restoring the stake reactivates the subnet only once
+        sub.stake = TokenAmount::from_atto(15);
+        assert!(sub.update_status(&min_stake));
+        assert_eq!(sub.status, Status::Active);
+        assert!(!sub.update_status(&min_stake));
+        assert_eq!(sub.status, Status::Active);
+    }
+}
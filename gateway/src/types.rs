@@ -1,25 +1,40 @@
 use cid::multihash::Code;
 use cid::{multihash, Cid};
-use fil_actors_runtime::{cbor, ActorError, Array};
+use fil_actors_runtime::{actor_error, cbor, ActorError, Array};
 use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
 use fvm_ipld_encoding::{RawBytes, DAG_CBOR};
 use fvm_shared::address::Address;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use fvm_shared::{ActorID, MethodNum, METHOD_SEND};
+use ipc_sdk::address::IPCAddress;
 use ipc_sdk::subnet_id::SubnetID;
 use multihash::MultihashDigest;
+use num_traits::{ToPrimitive, Zero};
 use primitives::CodeType;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
-use crate::checkpoint::{Checkpoint, CrossMsgMeta};
-use crate::cross::CrossMsg;
+use crate::checkpoint::{Checkpoint, CrossMsgMeta, RewardEntry};
+use crate::cross::{CrossMsg, StorableMsg};
+use crate::CROSS_MSG_FEE;
 
 pub const CROSSMSG_AMT_BITWIDTH: u32 = 3;
 pub const DEFAULT_CHECKPOINT_PERIOD: ChainEpoch = 10;
+pub const DEFAULT_MAX_MSGS_PER_CHECKPOINT: u64 = 1000;
+pub const DEFAULT_MAX_APPLY_ATTEMPTS: u64 = 3;
 pub const MAX_NONCE: u64 = u64::MAX;
 pub const MIN_COLLATERAL_AMOUNT: u64 = 10_u64.pow(18);
+/// Actor IDs below this one are reserved for the system (singleton) actors.
+pub const FIRST_NON_SINGLETON_ADDR: ActorID = 100;
+pub const BPS_DENOMINATOR: u64 = 10_000;
+/// Maximum length in bytes of the memo attached to a cross-message.
+pub const MAX_MEMO_LENGTH: usize = 256;
 
 pub const SUBNET_ACTOR_REWARD_METHOD: u64 = frc42_dispatch::method_hash!("Reward");
+pub const SUBNET_ACTOR_DISTRIBUTE_REWARDS_METHOD: u64 =
+    frc42_dispatch::method_hash!("DistributeRewards");
 
 pub type CrossMsgMetaArray<'bs, BS> = Array<'bs, CrossMsgMeta, BS>;
 pub type CrossMsgArray<'bs, BS> = Array<'bs, CrossMsg, BS>;
@@ -28,6 +43,160 @@ pub type CrossMsgArray<'bs, BS> = Array<'bs, CrossMsg, BS>;
 pub struct ConstructorParams {
     pub network_name: String,
     pub checkpoint_period: ChainEpoch,
+    /// Maximum number of cross-messages a child checkpoint can carry.
+    /// If zero, `DEFAULT_MAX_MSGS_PER_CHECKPOINT` is used.
+    pub max_msgs_per_checkpoint: u64,
+    /// Number of failed applications after which a cross-message is moved
+    /// to the dead-letter queue. If zero, `DEFAULT_MAX_APPLY_ATTEMPTS` is used.
+    pub max_apply_attempts: u64,
+    /// Address entitled to call the privileged methods of the gateway.
+    pub governor: Address,
+    /// Subnet actors allowed to register in the gateway. If `None` anyone can.
+    pub registration_allowlist: Option<Vec<Address>>,
+    pub send_cross_fee_policy: SendCrossFeePolicy,
+    /// Number of past checkpoint windows kept by `PruneCheckpoints`.
+    /// If zero, checkpoints are never pruned.
+    pub checkpoint_retention: u64,
+    /// If set, cross-messages sent to the current network are delivered as
+    /// plain messages instead of being rejected.
+    pub allow_local_cross: bool,
+    pub bottomup_fee_policy: BottomUpFeePolicy,
+    /// Addresses that don't pay the cross-message fee.
+    pub fee_exempt: Vec<Address>,
+    /// Maximum number of items in the postbox. If zero, the postbox is unbounded.
+    pub max_postbox_items: u64,
+    pub postbox_overflow_policy: PostboxOverflowPolicy,
+    /// Minimum value, once the fee is deducted, of the cross-messages sent
+    /// through `Fund`, `Release` and `SendCross`.
+    pub min_cross_value: TokenAmount,
+    /// Fee charged for each kind of cross-message.
+    pub cross_msg_fees: CrossMsgFees,
+    /// If set, `SendCross` rejects cross-messages whose destination is an
+    /// account (secp256k1 or BLS) address rather than an actor.
+    pub require_actor_destination: bool,
+}
+
+/// How `SendCross` charges the cross-message fee to the caller.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SendCrossFeePolicy {
+    /// The funds sent need to match the value of the message, and the fee
+    /// is deducted from that value.
+    #[default]
+    Deduct,
+    /// The fee is paid on top of the value of the message, and any surplus
+    /// sent is refunded to the caller.
+    Surplus,
+}
+
+/// Who is paid the fees of the bottom-up messages in a child checkpoint.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BottomUpFeePolicy {
+    /// The fees are paid to the validators of the child subnet that
+    /// committed the checkpoint, the level where the messages are finalized.
+    #[default]
+    Committer,
+    /// The fees are split between the child subnet and the current one,
+    /// whose share is added to the fee of its own checkpoint so it is paid
+    /// by the parent. Every level traversed by the messages keeps half of
+    /// the fees it receives, except for the root network, which has no
+    /// parent and pays them entirely to the child.
+    Split,
+}
+
+/// What the gateway does when a cross-message is stored in a full postbox.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PostboxOverflowPolicy {
+    /// The item whose validity window ended first is evicted to the dead-letter
+    /// queue, where its value can be refunded. If no item has expired the new
+    /// message is rejected.
+    #[default]
+    EvictExpired,
+    /// The new message is rejected.
+    Reject,
+}
+
+/// Kind of cross-message initiated in the gateway, used to look up the
+/// fee it is charged.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrossMsgKind {
+    /// Top-down messages sent through `Fund` and its variants.
+    Fund,
+    /// Bottom-up messages sent through `Release` and `ReleaseToSubnet`.
+    Release,
+    /// Arbitrary messages sent through `SendCross` or propagated from the postbox.
+    SendCross,
+}
+
+/// Fees charged for each kind of cross-message. All of them are
+/// `CROSS_MSG_FEE` by default.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct CrossMsgFees {
+    pub fund: TokenAmount,
+    pub release: TokenAmount,
+    pub send_cross: TokenAmount,
+}
+
+impl Default for CrossMsgFees {
+    fn default() -> Self {
+        Self {
+            fund: CROSS_MSG_FEE.clone(),
+            release: CROSS_MSG_FEE.clone(),
+            send_cross: CROSS_MSG_FEE.clone(),
+        }
+    }
+}
+
+impl CrossMsgFees {
+    /// Returns the fee charged for cross-messages of the given kind.
+    pub fn get(&self, kind: CrossMsgKind) -> &TokenAmount {
+        match kind {
+            CrossMsgKind::Fund => &self.fund,
+            CrossMsgKind::Release => &self.release,
+            CrossMsgKind::SendCross => &self.send_cross,
+        }
+    }
+
+    fn all(&self) -> [&TokenAmount; 3] {
+        [&self.fund, &self.release, &self.send_cross]
+    }
+
+    pub fn min(&self) -> &TokenAmount {
+        self.all().into_iter().min().unwrap()
+    }
+
+    pub fn max(&self) -> &TokenAmount {
+        self.all().into_iter().max().unwrap()
+    }
+}
+
+impl ConstructorParams {
+    /// Checks that the parameters used to deploy the gateway are sane,
+    /// returning an error pointing to the misconfigured field otherwise.
+    pub fn validate(&self) -> Result<(), ActorError> {
+        if self.network_name.is_empty() {
+            return Err(actor_error!(illegal_argument, "network name is empty"));
+        }
+        if SubnetID::from_str(&self.network_name).is_err() {
+            return Err(actor_error!(
+                illegal_argument,
+                "network name {} is not a well-formed subnet id",
+                self.network_name
+            ));
+        }
+        if self.checkpoint_period < 0 {
+            return Err(actor_error!(
+                illegal_argument,
+                "checkpoint period can't be negative"
+            ));
+        }
+        if self.cross_msg_fees.min() < &TokenAmount::zero() {
+            return Err(actor_error!(
+                illegal_argument,
+                "cross-message fees can't be negative"
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -35,6 +204,216 @@ pub struct FundParams {
     pub value: TokenAmount,
 }
 
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct GenesisSubnet {
+    pub id: SubnetID,
+    pub stake: TokenAmount,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct BulkRegisterParams {
+    pub subnets: Vec<GenesisSubnet>,
+}
+
+/// Fees charged by the gateway for cross-messages.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct FeeSchedule {
+    /// Fee charged for every cross-message.
+    pub base_fee: TokenAmount,
+    pub min_fee: TokenAmount,
+    pub max_fee: TokenAmount,
+    /// Fee charged for each kind of cross-message.
+    pub msg_fees: CrossMsgFees,
+}
+
+/// Capabilities of a deployed gateway actor.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct ActorInfo {
+    /// Version of the actor code.
+    pub version: String,
+    /// Version of the layout of the actor state.
+    pub state_version: u8,
+    /// Method numbers handled by the actor.
+    pub methods: Vec<MethodNum>,
+    /// Optional features enabled in the actor.
+    pub features: Vec<String>,
+}
+
+/// Number of cross-messages waiting to be relayed by the gateway.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct QueueDepths {
+    /// Top-down messages committed for child subnets that haven't been applied.
+    pub topdown: u64,
+    /// Bottom-up messages committed in the checkpoint of the current window.
+    pub bottomup: u64,
+}
+
+/// Snapshot of the health of the gateway returned by `GetGatewayStats`.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct GatewayStats {
+    pub total_subnets: u64,
+    /// Stake of all the registered subnets.
+    pub total_stake: TokenAmount,
+    /// Circulating supply of all the registered subnets.
+    pub total_circ_supply: TokenAmount,
+    pub queue_depths: QueueDepths,
+    pub total_burned: TokenAmount,
+    pub applied_topdown_nonce: u64,
+    pub applied_bottomup_nonce: u64,
+}
+
+/// Collateral of a subnet compared to the funds it has in circulation.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct Collateralization {
+    pub stake: TokenAmount,
+    pub circ_supply: TokenAmount,
+    /// `stake / circ_supply` in basis points, saturated to `u64::MAX`. `None`
+    /// if the subnet has no circulating supply.
+    pub ratio_bps: Option<u64>,
+}
+
+impl Collateralization {
+    pub fn new(stake: TokenAmount, circ_supply: TokenAmount) -> Self {
+        let ratio_bps = if circ_supply.is_zero() {
+            None
+        } else {
+            let ratio = stake.atto() * BPS_DENOMINATOR / circ_supply.atto();
+            Some(ratio.to_u64().unwrap_or(u64::MAX))
+        };
+        Collateralization {
+            stake,
+            circ_supply,
+            ratio_bps,
+        }
+    }
+}
+
+/// Location of a cross-message in its lifecycle, as tracked by the gateway.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum CrossMsgStatus {
+    /// Top-down message committed for a child subnet, waiting to be applied.
+    TopDownCommitted { subnet: SubnetID, nonce: u64 },
+    /// Top-down message with a nonce below the `applied_topdown_nonce` last
+    /// reported by the child subnet in its checkpoints.
+    TopDownApplied { subnet: SubnetID, nonce: u64 },
+    /// Bottom-up message committed in the checkpoint of the given epoch.
+    BottomUpCommitted { epoch: ChainEpoch },
+    /// Message waiting in the postbox to be propagated.
+    InPostbox,
+    /// Message moved to the dead-letter queue after failing to be applied.
+    Failed,
+    /// The message is not tracked by the gateway.
+    NotFound,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct FundWithMemoParams {
+    /// The subnet to fund
+    pub subnet: SubnetID,
+    /// The memo attached to the fund message
+    pub memo: Vec<u8>,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct FundWithTargetParams {
+    /// The subnet to fund
+    pub subnet: SubnetID,
+    /// The amount to inject in the subnet. Any funds sent over it and the
+    /// cross-message fee are refunded.
+    pub target_amount: TokenAmount,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct FundOnBehalfParams {
+    /// The subnet to fund
+    pub subnet: SubnetID,
+    /// The address credited in the subnet instead of the caller.
+    pub beneficiary: Address,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct GetCrossMsgStatusParams {
+    /// Cid of the cross-message, or of its postbox item.
+    pub cid: Cid,
+    /// Child subnet and nonce the message was committed with, if top-down.
+    pub topdown: Option<(SubnetID, u64)>,
+    /// Epoch of the checkpoint window the message was committed in, if bottom-up.
+    pub epoch: Option<ChainEpoch>,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct GetBottomUpMsgParams {
+    /// Epoch of the checkpoint window in which the message was committed.
+    pub epoch: ChainEpoch,
+    pub nonce: u64,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct SetGovernorsParams {
+    /// Governors entitled to propose and approve governance actions. If empty,
+    /// privileged methods are gated by the single `governor` again.
+    pub governors: Vec<Address>,
+    /// Number of distinct approvals needed to execute a governance action.
+    pub threshold: u64,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct ProposeGovActionParams {
+    /// Privileged method of the gateway to execute.
+    pub method: MethodNum,
+    pub params: RawBytes,
+}
+
+/// Governance action waiting for the approval of the governors.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct GovProposal {
+    pub method: MethodNum,
+    pub params: RawBytes,
+    pub approvals: Vec<Address>,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct AdjustCircSupplyParams {
+    pub subnet: SubnetID,
+    /// Amount added to the circulating supply of the subnet, negative to reduce it.
+    pub delta: TokenAmount,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct SetMaxTopDownValueParams {
+    pub subnet: SubnetID,
+    /// Maximum value of a single top-down message, `None` to remove the limit.
+    pub max: Option<TokenAmount>,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct ForceKillParams {
+    /// The subnet to kill
+    pub subnet: SubnetID,
+    /// Address the residual circulating supply of the subnet is swept to
+    pub recovery_addr: Address,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct CancelFundParams {
+    /// The subnet the fund message was directed to
+    pub subnet: SubnetID,
+    /// The nonce of the top-down fund message to cancel
+    pub nonce: u64,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct DistributeRewardsParams {
+    pub rewards: Vec<RewardEntry>,
+}
+
+/// Cross-message that couldn't be applied after the maximum number of attempts.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct DeadLetter {
+    pub cid: Cid,
+    pub cross_msg: CrossMsg,
+}
+
 #[derive(Debug, Serialize_tuple, Deserialize_tuple)]
 pub struct CheckpointParams {
     pub checkpoint: Checkpoint,
@@ -46,17 +425,229 @@ pub struct CrossMsgParams {
     pub destination: SubnetID,
 }
 
+impl CrossMsgParams {
+    pub fn builder(destination: SubnetID) -> CrossMsgParamsBuilder {
+        CrossMsgParamsBuilder::new(destination)
+    }
+
+    /// Checks that the params are ready to be submitted to `SendCross`,
+    /// returning an error pointing to the misconfigured field otherwise.
+    pub fn validate(&self) -> Result<(), ActorError> {
+        if self.destination == SubnetID::default() {
+            return Err(actor_error!(
+                illegal_argument,
+                "no destination for cross-message explicitly set"
+            ));
+        }
+        let msg = &self.cross_msg.msg;
+        if msg.from.subnet().is_err() || msg.from.raw_addr().is_err() {
+            return Err(actor_error!(
+                illegal_argument,
+                "from address of cross-message is not well-formed"
+            ));
+        }
+        match msg.to.subnet() {
+            Ok(sub) if sub == self.destination => {}
+            Ok(_) => {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "to address of cross-message doesn't belong to the destination"
+                ));
+            }
+            Err(_) => {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "to address of cross-message is not well-formed"
+                ));
+            }
+        }
+        if msg.to.raw_addr().is_err() {
+            return Err(actor_error!(
+                illegal_argument,
+                "to address of cross-message is not well-formed"
+            ));
+        }
+        if msg.value < TokenAmount::zero() {
+            return Err(actor_error!(
+                illegal_argument,
+                "value of cross-message can't be negative"
+            ));
+        }
+        validate_memo(&msg.memo)
+    }
+}
+
+/// Checks that the memo of a cross-message doesn't exceed `MAX_MEMO_LENGTH`.
+pub fn validate_memo(memo: &[u8]) -> Result<(), ActorError> {
+    if memo.len() > MAX_MEMO_LENGTH {
+        return Err(actor_error!(
+            illegal_argument,
+            "memo of cross-message exceeds the maximum length: {} > {}",
+            memo.len(),
+            MAX_MEMO_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// Builds the params of a `SendCross` call.
+///
+/// The value set needs to match the funds sent with the call, see
+/// `SendCrossFeePolicy` for how the fee is charged.
+#[derive(Clone, Debug)]
+pub struct CrossMsgParamsBuilder {
+    destination: SubnetID,
+    from: Option<IPCAddress>,
+    to: Option<Address>,
+    method: MethodNum,
+    params: RawBytes,
+    value: TokenAmount,
+    nonce: u64,
+    memo: Vec<u8>,
+    valid_until_epoch: Option<ChainEpoch>,
+    wrapped: bool,
+}
+
+impl CrossMsgParamsBuilder {
+    pub fn new(destination: SubnetID) -> Self {
+        Self {
+            destination,
+            from: None,
+            to: None,
+            method: METHOD_SEND,
+            params: RawBytes::default(),
+            value: TokenAmount::zero(),
+            nonce: 0,
+            memo: Vec::new(),
+            valid_until_epoch: None,
+            wrapped: false,
+        }
+    }
+
+    pub fn from(mut self, from: IPCAddress) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    /// Sets the recipient of the message in the destination subnet.
+    pub fn to(mut self, to: Address) -> Self {
+        self.to = Some(to);
+        self
+    }
+
+    pub fn method(mut self, method: MethodNum) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn params(mut self, params: RawBytes) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn value(mut self, value: TokenAmount) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn memo(mut self, memo: Vec<u8>) -> Self {
+        self.memo = memo;
+        self
+    }
+
+    /// Sets the last epoch in which the message can be executed in the destination.
+    pub fn valid_until_epoch(mut self, epoch: ChainEpoch) -> Self {
+        self.valid_until_epoch = Some(epoch);
+        self
+    }
+
+    pub fn wrapped(mut self, wrapped: bool) -> Self {
+        self.wrapped = wrapped;
+        self
+    }
+
+    pub fn build(self) -> Result<CrossMsgParams, ActorError> {
+        let from = self
+            .from
+            .ok_or_else(|| actor_error!(illegal_argument, "from address not set"))?;
+        let to = self
+            .to
+            .ok_or_else(|| actor_error!(illegal_argument, "to address not set"))?;
+        let to = IPCAddress::new(&self.destination, &to).map_err(|_| {
+            actor_error!(
+                illegal_argument,
+                "error setting IPC address in cross-msg to param"
+            )
+        })?;
+        let params = CrossMsgParams {
+            cross_msg: CrossMsg {
+                msg: StorableMsg {
+                    from,
+                    to,
+                    method: self.method,
+                    params: self.params,
+                    value: self.value,
+                    nonce: self.nonce,
+                    memo: self.memo,
+                    valid_until_epoch: self.valid_until_epoch,
+                    correlation_id: None,
+                },
+                wrapped: self.wrapped,
+            },
+            destination: self.destination,
+        };
+        params.validate()?;
+        Ok(params)
+    }
+}
+
 #[derive(Serialize_tuple, Deserialize_tuple, Clone)]
 pub struct ApplyMsgParams {
     pub cross_msg: CrossMsg,
 }
 
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct ApplyMsgsParams {
+    /// The cross-messages to apply, in order
+    pub cross_msgs: Vec<CrossMsg>,
+}
+
+/// Outcome of each of the cross-messages applied by `ApplyMessages`.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct ApplyMsgResult {
+    /// `OK` if the message was applied, even if its execution failed and it was
+    /// moved to the dead-letter queue. Otherwise, the reason it was rejected.
+    pub exit_code: ExitCode,
+    /// What `ApplyMessage` would have returned for the message.
+    pub ret: RawBytes,
+}
+
 #[derive(Serialize_tuple, Deserialize_tuple, Clone)]
 pub struct PropagateParams {
     /// The postbox message cid
     pub postbox_cid: Cid,
 }
 
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct PropagateBatchParams {
+    /// The cids of the postbox messages to propagate
+    pub postbox_cids: Vec<Cid>,
+}
+
+/// Outcome of `PropagateBatch` for each of the postbox items.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct PropagateBatchReturn {
+    /// Items committed and removed from the postbox.
+    pub propagated: Vec<Cid>,
+    /// Items that failed to be committed and are kept in the postbox.
+    pub pending: Vec<Cid>,
+}
+
 #[derive(Serialize_tuple, Deserialize_tuple, Clone)]
 pub struct WhitelistPropagatorParams {
     /// The postbox message cid
@@ -65,6 +656,14 @@ pub struct WhitelistPropagatorParams {
     pub to_add: Vec<Address>,
 }
 
+#[derive(Serialize_tuple, Deserialize_tuple, Clone)]
+pub struct ReplacePostboxOwnersParams {
+    /// The postbox message cid
+    pub postbox_cid: Cid,
+    /// The owners replacing the current ones
+    pub new_owners: Vec<Address>,
+}
+
 /// The item to store in the `State::postbox`
 #[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
 pub struct PostBoxItem {
@@ -99,14 +698,40 @@ impl PostBoxItem {
 
 #[cfg(test)]
 mod tests {
-    use crate::ConstructorParams;
+    use crate::{
+        BottomUpFeePolicy, ConstructorParams, CrossMsgFees, CrossMsgParams, PostboxOverflowPolicy,
+        SendCrossFeePolicy,
+    };
     use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+    use ipc_sdk::address::IPCAddress;
+    use ipc_sdk::subnet_id::SubnetID;
+    use std::str::FromStr;
 
     #[test]
     fn serialize_params() {
         let p = ConstructorParams {
             network_name: "/root".to_string(),
             checkpoint_period: 100,
+            max_msgs_per_checkpoint: 10,
+            max_apply_attempts: 5,
+            governor: Address::new_id(100),
+            registration_allowlist: Some(vec![Address::new_id(101)]),
+            send_cross_fee_policy: SendCrossFeePolicy::Surplus,
+            checkpoint_retention: 100,
+            allow_local_cross: true,
+            bottomup_fee_policy: BottomUpFeePolicy::Split,
+            fee_exempt: vec![Address::new_id(102)],
+            max_postbox_items: 100,
+            postbox_overflow_policy: PostboxOverflowPolicy::Reject,
+            min_cross_value: TokenAmount::from_atto(1000),
+            cross_msg_fees: CrossMsgFees {
+                fund: TokenAmount::from_atto(1),
+                release: TokenAmount::from_atto(2),
+                send_cross: TokenAmount::from_atto(3),
+            },
+            require_actor_destination: true,
         };
         let bytes = fil_actors_runtime::util::cbor::serialize(&p, "").unwrap();
         let serialized = base64::encode(bytes.bytes());
@@ -118,5 +743,81 @@ mod tests {
 
         assert_eq!(p.network_name, deserialized.network_name);
         assert_eq!(p.checkpoint_period, deserialized.checkpoint_period);
+        assert_eq!(
+            p.max_msgs_per_checkpoint,
+            deserialized.max_msgs_per_checkpoint
+        );
+        assert_eq!(p.max_apply_attempts, deserialized.max_apply_attempts);
+        assert_eq!(p.governor, deserialized.governor);
+        assert_eq!(
+            p.registration_allowlist,
+            deserialized.registration_allowlist
+        );
+        assert_eq!(p.send_cross_fee_policy, deserialized.send_cross_fee_policy);
+        assert_eq!(p.checkpoint_retention, deserialized.checkpoint_retention);
+        assert_eq!(p.allow_local_cross, deserialized.allow_local_cross);
+        assert_eq!(p.bottomup_fee_policy, deserialized.bottomup_fee_policy);
+        assert_eq!(p.fee_exempt, deserialized.fee_exempt);
+        assert_eq!(p.cross_msg_fees, deserialized.cross_msg_fees);
+        assert_eq!(
+            p.require_actor_destination,
+            deserialized.require_actor_destination
+        );
+    }
+
+    #[test]
+    fn build_cross_msg_params() {
+        let destination = SubnetID::from_str("/root/f0101").unwrap();
+        let from =
+            IPCAddress::new(&SubnetID::from_str("/root").unwrap(), &Address::new_id(100)).unwrap();
+        let to = Address::new_id(101);
+
+        let p = CrossMsgParams::builder(destination.clone())
+            .from(from.clone())
+            .to(to)
+            .value(TokenAmount::from_atto(10))
+            .nonce(1)
+            .build()
+            .unwrap();
+        assert_eq!(p.destination, destination);
+        assert_eq!(p.cross_msg.msg.from, from);
+        assert_eq!(
+            p.cross_msg.msg.to,
+            IPCAddress::new(&destination, &to).unwrap()
+        );
+        assert_eq!(p.cross_msg.msg.value, TokenAmount::from_atto(10));
+        assert_eq!(p.cross_msg.msg.nonce, 1);
+
+        let cases: Vec<(Box<dyn Fn(&mut CrossMsgParams)>, &str)> = vec![
+            (
+                Box::new(|p| p.destination = SubnetID::default()),
+                "no destination for cross-message explicitly set",
+            ),
+            (
+                Box::new(|p| p.destination = SubnetID::from_str("/root/f0102").unwrap()),
+                "to address of cross-message doesn't belong to the destination",
+            ),
+            (
+                Box::new(|p| p.cross_msg.msg.value = TokenAmount::from_atto(-1)),
+                "value of cross-message can't be negative",
+            ),
+        ];
+        for (modify, msg) in cases {
+            let mut params = p.clone();
+            modify(&mut params);
+            let err = params.validate().unwrap_err();
+            assert_eq!(err.msg(), msg);
+        }
+
+        let err = CrossMsgParams::builder(destination.clone())
+            .to(to)
+            .build()
+            .unwrap_err();
+        assert_eq!(err.msg(), "from address not set");
+        let err = CrossMsgParams::builder(destination)
+            .from(from)
+            .build()
+            .unwrap_err();
+        assert_eq!(err.msg(), "to address not set");
     }
 }
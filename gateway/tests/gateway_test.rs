@@ -1,21 +1,40 @@
+use cid::multihash::MultihashDigest;
 use cid::Cid;
+use fil_actors_runtime::builtin::HAMT_BIT_WIDTH;
 use fil_actors_runtime::runtime::Runtime;
-use fil_actors_runtime::{BURNT_FUNDS_ACTOR_ADDR, REWARD_ACTOR_ADDR};
+use fil_actors_runtime::test_utils::{
+    expect_abort, MockRuntime, ACCOUNT_ACTOR_CODE_ID, INIT_ACTOR_CODE_ID, SUBNET_ACTOR_CODE_ID,
+    SYSTEM_ACTOR_CODE_ID,
+};
+use fil_actors_runtime::{
+    deserialize_block, make_empty_map, BURNT_FUNDS_ACTOR_ADDR, INIT_ACTOR_ADDR, REWARD_ACTOR_ADDR,
+    SYSTEM_ACTOR_ADDR,
+};
 use fvm_ipld_encoding::ipld_block::IpldBlock;
-use fvm_ipld_encoding::RawBytes;
+use fvm_ipld_encoding::{serde_bytes, to_vec, CborStore, RawBytes};
 use fvm_shared::address::Address;
 use fvm_shared::bigint::Zero;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
-use fvm_shared::METHOD_SEND;
+use fvm_shared::{MethodNum, METHOD_SEND};
+use ipc_gateway::checkpoint::ChildCheck;
 use ipc_gateway::Status::{Active, Inactive};
 use ipc_gateway::{
-    ext, get_topdown_msg, Checkpoint, CrossMsg, IPCAddress, State, StorableMsg, CROSS_MSG_FEE,
-    DEFAULT_CHECKPOINT_PERIOD, SUBNET_ACTOR_REWARD_METHOD,
+    ext, get_topdown_msg, route_cross_msg, Actor, ActorInfo, ApplyMsgParams, BottomUpFeePolicy,
+    Checkpoint, ConstructorParams, CrossMsg, CrossMsgFees, CrossMsgMeta, CrossMsgParams,
+    CrossMsgStatus, CrossMsgs, DistributeRewardsParams, FeeSchedule, ForceKillParams,
+    FundWithMemoParams, GatewayStats, GenesisSubnet, IPCAddress, Method, PostboxOverflowPolicy,
+    PropagateBatchParams, QueueDepths, RewardEntry, RouteDecision, SendCrossFeePolicy, State,
+    StateV1, StateV2, Status, StorableMsg, CROSSMSG_AMT_BITWIDTH, CROSS_MSG_FEE,
+    DEFAULT_CHECKPOINT_PERIOD, DEFAULT_MAX_APPLY_ATTEMPTS, DEFAULT_MAX_MSGS_PER_CHECKPOINT,
+    FIRST_NON_SINGLETON_ADDR, MAX_MEMO_LENGTH, MAX_NONCE, STATE_VERSION,
+    SUBNET_ACTOR_DISTRIBUTE_REWARDS_METHOD, SUBNET_ACTOR_REWARD_METHOD, SUPPORTED_METHODS,
 };
 use ipc_sdk::subnet_id::SubnetID;
-use primitives::TCid;
+use num_traits::FromPrimitive;
+use primitives::{TAmt, TCid, TLink};
+use serde::Serialize;
 use std::ops::Mul;
 use std::str::FromStr;
 
@@ -30,6 +49,55 @@ fn construct() {
     h.check_state();
 }
 
+#[test]
+fn construct_invalid_params() {
+    let cases = vec![
+        ("", 10, "network name is empty".to_string()),
+        (
+            "not-a-subnet",
+            10,
+            "network name not-a-subnet is not a well-formed subnet id".to_string(),
+        ),
+        (
+            "/root",
+            -1,
+            "checkpoint period can't be negative".to_string(),
+        ),
+    ];
+
+    for (network_name, checkpoint_period, msg) in cases {
+        let mut rt = new_runtime();
+        rt.set_caller(*INIT_ACTOR_CODE_ID, INIT_ACTOR_ADDR);
+        rt.expect_validate_caller_addr(vec![INIT_ACTOR_ADDR]);
+        let params = ConstructorParams {
+            network_name: network_name.to_string(),
+            checkpoint_period,
+            max_msgs_per_checkpoint: 0,
+            max_apply_attempts: 0,
+            governor: *GOVERNOR,
+            registration_allowlist: None,
+            send_cross_fee_policy: SendCrossFeePolicy::Deduct,
+            checkpoint_retention: 0,
+            allow_local_cross: false,
+            bottomup_fee_policy: BottomUpFeePolicy::Committer,
+            fee_exempt: Vec::new(),
+            max_postbox_items: 0,
+            postbox_overflow_policy: PostboxOverflowPolicy::EvictExpired,
+            min_cross_value: TokenAmount::zero(),
+            cross_msg_fees: CrossMsgFees::default(),
+            require_actor_destination: false,
+        };
+        let err = rt
+            .call::<Actor>(
+                Method::Constructor as MethodNum,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            )
+            .unwrap_err();
+        assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_ARGUMENT);
+        assert_eq!(err.msg(), msg);
+    }
+}
+
 #[test]
 fn register_subnet() {
     let (h, mut rt) = setup_root();
@@ -80,6 +148,215 @@ fn register_subnet() {
     h.check_state();
 }
 
+#[test]
+fn compute_subnet_id() {
+    let (h, mut rt) = setup(SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE));
+
+    // the id computed matches the one assigned when registering
+    let computed = h.compute_subnet_id(&mut rt, &SUBNET_TWO);
+    assert_eq!(computed.parent().unwrap(), h.net_name);
+    rt.set_caller(*SUBNET_ACTOR_CODE_ID, *SUBNET_TWO);
+    rt.set_value(TokenAmount::from_atto(10_u64.pow(18)));
+    rt.set_balance(TokenAmount::from_atto(10_u64.pow(18)));
+    rt.expect_validate_caller_any();
+    let ret = rt
+        .call::<Actor>(Method::Register as MethodNum, None)
+        .unwrap();
+    rt.verify();
+    let registered: SubnetID = deserialize_block(ret).unwrap();
+    assert_eq!(computed, registered);
+    assert_eq!(h.get_subnet(&rt, &computed).unwrap().id, registered);
+}
+
+#[test]
+fn get_ancestry() {
+    let sub1 = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(sub1.clone());
+
+    // the ancestry goes from the immediate parent up to the root
+    let sub2 = SubnetID::new_from_parent(&sub1, *SUBNET_TWO);
+    let sub3 = SubnetID::new_from_parent(&sub2, *SUBNET_THR);
+    let ancestry = h.get_ancestry(&mut rt, &sub3, ExitCode::OK).unwrap();
+    assert_eq!(
+        ancestry,
+        vec![sub2.clone(), sub1.clone(), ROOTNET_ID.clone()]
+    );
+    let ancestry = h.get_ancestry(&mut rt, &sub2, ExitCode::OK).unwrap();
+    assert_eq!(ancestry, vec![sub1.clone(), ROOTNET_ID.clone()]);
+
+    // only descendants of the current network can be queried
+    let sibling = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_TWO);
+    for id in [sub1, ROOTNET_ID.clone(), sibling] {
+        h.get_ancestry(&mut rt, &id, ExitCode::USR_ILLEGAL_ARGUMENT)
+            .unwrap();
+    }
+}
+
+#[test]
+fn register_reserved_address() {
+    let (h, mut rt) = setup_root();
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+
+    // the gateway itself and the system actors can't register as subnets
+    for addr in [*ACTOR, SYSTEM_ACTOR_ADDR, BURNT_FUNDS_ACTOR_ADDR] {
+        h.register(&mut rt, &addr, &value, ExitCode::USR_ILLEGAL_ARGUMENT)
+            .unwrap();
+    }
+    let st: State = rt.get_state();
+    assert_eq!(st.total_subnets, 0);
+
+    // the first non-reserved id is accepted
+    h.register(
+        &mut rt,
+        &Address::new_id(FIRST_NON_SINGLETON_ADDR),
+        &value,
+        ExitCode::OK,
+    )
+    .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.total_subnets, 1);
+}
+
+#[test]
+fn register_allowlist() {
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+
+    // permissionless mode, anyone can register
+    let (h, mut rt) = setup_root();
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    h.allow_registration(
+        &mut rt,
+        &GOVERNOR,
+        &SUBNET_TWO,
+        true,
+        ExitCode::USR_ILLEGAL_STATE,
+    )
+    .unwrap();
+
+    // permissioned mode
+    let mut rt = new_runtime();
+    let mut h = new_harness(ROOTNET_ID.clone());
+    h.registration_allowlist = Some(vec![*SUBNET_ONE]);
+    h.construct_and_verify(&mut rt);
+
+    // allowlisted subnet can register
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    // non-allowlisted subnet is rejected
+    h.register(&mut rt, &SUBNET_TWO, &value, ExitCode::USR_FORBIDDEN)
+        .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.total_subnets, 1);
+
+    // only the governor can update the allowlist
+    h.allow_registration(
+        &mut rt,
+        &SUBNET_TWO,
+        &SUBNET_TWO,
+        true,
+        ExitCode::USR_FORBIDDEN,
+    )
+    .unwrap();
+    h.allow_registration(&mut rt, &GOVERNOR, &SUBNET_TWO, true, ExitCode::OK)
+        .unwrap();
+    h.register(&mut rt, &SUBNET_TWO, &value, ExitCode::OK)
+        .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.total_subnets, 2);
+
+    // removed entries can't register anymore
+    h.allow_registration(&mut rt, &GOVERNOR, &SUBNET_THR, true, ExitCode::OK)
+        .unwrap();
+    h.allow_registration(&mut rt, &GOVERNOR, &SUBNET_THR, false, ExitCode::OK)
+        .unwrap();
+    h.register(&mut rt, &SUBNET_THR, &value, ExitCode::USR_FORBIDDEN)
+        .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(
+        st.registration_allowlist,
+        Some(vec![*SUBNET_ONE, *SUBNET_TWO])
+    );
+}
+
+#[test]
+fn bulk_register() {
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+
+    // register subnets sequentially
+    let (h, mut rt) = setup_root();
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    h.register(&mut rt, &SUBNET_TWO, &value, ExitCode::OK)
+        .unwrap();
+    let sequential: State = rt.get_state();
+
+    // and in bulk
+    let (h, mut rt) = setup_root();
+    let shid_one = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let shid_two = SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO);
+    let subnets = vec![
+        GenesisSubnet {
+            id: shid_one.clone(),
+            stake: value.clone(),
+        },
+        GenesisSubnet {
+            id: shid_two.clone(),
+            stake: value.clone(),
+        },
+    ];
+    rt.set_balance(value.clone() * 2);
+    h.bulk_register(&mut rt, subnets.clone(), ExitCode::OK)
+        .unwrap();
+    let bulk: State = rt.get_state();
+
+    assert_eq!(bulk.total_subnets, sequential.total_subnets);
+    assert_eq!(bulk.subnets.cid(), sequential.subnets.cid());
+    assert_eq!(h.get_subnet(&rt, &shid_one).unwrap().stake, value);
+
+    // registering already registered subnets fails
+    h.bulk_register(&mut rt, subnets, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    // duplicates are rejected and nothing is registered
+    let (h, mut rt) = setup_root();
+    rt.set_balance(value.clone() * 2);
+    let dup = GenesisSubnet {
+        id: shid_one.clone(),
+        stake: value.clone(),
+    };
+    h.bulk_register(
+        &mut rt,
+        vec![dup.clone(), dup],
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.total_subnets, 0);
+
+    // subnets need to be children of the current network
+    let grandchild = GenesisSubnet {
+        id: SubnetID::new_from_parent(&shid_one, *SUBNET_THR),
+        stake: value.clone(),
+    };
+    h.bulk_register(&mut rt, vec![grandchild], ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    // the gateway needs to hold the stake
+    rt.set_balance(TokenAmount::zero());
+    h.bulk_register(
+        &mut rt,
+        vec![GenesisSubnet {
+            id: shid_one,
+            stake: value,
+        }],
+        ExitCode::USR_ILLEGAL_STATE,
+    )
+    .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.total_subnets, 0);
+}
+
 #[test]
 fn add_stake() {
     let (h, mut rt) = setup_root();
@@ -206,6 +483,108 @@ fn release_stake() {
     .unwrap();
 }
 
+#[test]
+fn reactivate_subnet() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+
+    // reactivating an active subnet is a no-op
+    assert_eq!(
+        h.reactivate_subnet(&mut rt, &shid, ExitCode::OK),
+        Some(false)
+    );
+    assert_eq!(h.get_subnet(&rt, &shid).unwrap().status, Active);
+
+    // release stake below the minimum to inactivate the subnet
+    let half = TokenAmount::from_atto(5u64.pow(17));
+    rt.set_balance(value.clone());
+    h.release_stake(&mut rt, &shid, &half, ExitCode::OK)
+        .unwrap();
+    assert_eq!(h.get_subnet(&rt, &shid).unwrap().status, Inactive);
+
+    // can't be reactivated without enough stake
+    h.reactivate_subnet(&mut rt, &shid, ExitCode::USR_ILLEGAL_STATE);
+    assert_eq!(h.get_subnet(&rt, &shid).unwrap().status, Inactive);
+
+    // restoring the stake reactivates the subnet
+    h.add_stake(&mut rt, &shid, &half, ExitCode::OK).unwrap();
+    assert_eq!(h.get_subnet(&rt, &shid).unwrap().status, Active);
+
+    // after the transition reactivating is a no-op
+    assert_eq!(
+        h.reactivate_subnet(&mut rt, &shid, ExitCode::OK),
+        Some(false)
+    );
+    h.add_stake(&mut rt, &shid, &half, ExitCode::OK).unwrap();
+    assert_eq!(h.get_subnet(&rt, &shid).unwrap().status, Active);
+
+    // unregistered subnets can't be reactivated
+    h.reactivate_subnet(
+        &mut rt,
+        &SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    );
+}
+
+#[test]
+fn collateralization() {
+    let (h, mut rt) = setup_root();
+
+    let stake = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &stake, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+
+    // without circulating supply there is no ratio
+    let c = h.get_collateralization(&mut rt, &shid);
+    assert_eq!(c.stake, stake);
+    assert_eq!(c.circ_supply, TokenAmount::zero());
+    assert_eq!(c.ratio_bps, None);
+
+    // twice as much supply as stake
+    let funder = Address::new_id(1001);
+    let amount = stake.clone().mul(2);
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
+    let c = h.get_collateralization(&mut rt, &shid);
+    assert_eq!(c.stake, stake);
+    assert_eq!(c.circ_supply, amount);
+    assert_eq!(c.ratio_bps, Some(5_000));
+
+    // releasing all the stake leaves the supply uncollateralized
+    rt.set_balance(&amount + &stake);
+    h.release_stake(&mut rt, &shid, &stake, ExitCode::OK)
+        .unwrap();
+    let c = h.get_collateralization(&mut rt, &shid);
+    assert_eq!(c.stake, TokenAmount::zero());
+    assert_eq!(c.ratio_bps, Some(0));
+
+    // unregistered subnets are rejected
+    let unknown = SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO);
+    rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, Address::new_id(1001));
+    rt.expect_validate_caller_any();
+    expect_abort(
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        rt.call::<Actor>(
+            Method::GetCollateralization as MethodNum,
+            IpldBlock::serialize_cbor(&unknown).unwrap(),
+        ),
+    );
+    rt.verify();
+}
+
 #[test]
 fn test_kill() {
     let (h, mut rt) = setup_root();
@@ -233,160 +612,157 @@ fn test_kill() {
 }
 
 #[test]
-fn checkpoint_commit() {
+fn test_reregister_killed_subnet() {
     let (h, mut rt) = setup_root();
-
-    // Register a subnet with 1FIL collateral
     let value = TokenAmount::from_atto(10_u64.pow(18));
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
     h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
         .unwrap();
-
+    rt.set_epoch(5);
+    h.kill(&mut rt, &shid, &value, ExitCode::OK).unwrap();
     let st: State = rt.get_state();
-    assert_eq!(st.total_subnets, 1);
-    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
-    let subnet = h.get_subnet(&rt, &shid).unwrap();
-    assert_eq!(subnet.id, shid);
-    assert_eq!(subnet.stake, value);
-    assert_eq!(subnet.circ_supply, TokenAmount::zero());
-    assert_eq!(subnet.status, Active);
-    h.check_state();
-
-    // Commit first checkpoint for first window in first subnet
-    let epoch: ChainEpoch = 10;
-    rt.set_epoch(epoch);
-    let ch = Checkpoint::new(shid.clone(), epoch + 9);
+    assert_eq!(st.get_killed_subnet(rt.store(), &shid).unwrap(), Some(5));
 
-    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+    // killed subnets can't register again by default
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::USR_ILLEGAL_ARGUMENT)
         .unwrap();
-    let st: State = rt.get_state();
-    let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
-    assert_eq!(commit.epoch(), DEFAULT_CHECKPOINT_PERIOD);
-    let child_check = has_childcheck_source(&commit.data.children, &shid).unwrap();
-    assert_eq!(&child_check.checks.len(), &1);
-    assert_eq!(has_cid(&child_check.checks, &ch.cid()), true);
+    assert!(h.get_subnet(&rt, &shid).is_none());
 
-    // Commit a checkpoint for subnet twice
-    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::USR_ILLEGAL_ARGUMENT)
+    // unless the governor allows it
+    h.set_allow_reregister(&mut rt, &SUBNET_ONE, true, ExitCode::USR_FORBIDDEN)
         .unwrap();
-    let prev_cid = ch.cid();
-
-    // Append a new checkpoint for the same subnet
-    let mut ch = Checkpoint::new(shid.clone(), epoch + 11);
-    ch.data.prev_check = TCid::from(prev_cid);
-    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+    h.set_allow_reregister(&mut rt, &GOVERNOR, true, ExitCode::OK)
+        .unwrap();
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
         .unwrap();
+    assert_eq!(h.get_subnet(&rt, &shid).unwrap().stake, value);
     let st: State = rt.get_state();
-    let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
-    assert_eq!(commit.epoch(), DEFAULT_CHECKPOINT_PERIOD);
-    let child_check = has_childcheck_source(&commit.data.children, &shid).unwrap();
-    assert_eq!(&child_check.checks.len(), &2);
-    assert_eq!(has_cid(&child_check.checks, &ch.cid()), true);
+    assert_eq!(st.get_killed_subnet(rt.store(), &shid).unwrap(), None);
 
-    // Register second subnet
+    // other subnets are not affected
+    h.set_allow_reregister(&mut rt, &GOVERNOR, false, ExitCode::OK)
+        .unwrap();
     h.register(&mut rt, &SUBNET_TWO, &value, ExitCode::OK)
         .unwrap();
+}
 
-    let st: State = rt.get_state();
-    assert_eq!(st.total_subnets, 2);
-    let shid_two = SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO);
-    let subnet = h.get_subnet(&rt, &shid_two).unwrap();
-    assert_eq!(subnet.id, shid_two);
-    h.check_state();
+#[test]
+fn test_gov_actions() {
+    let (h, mut rt) = setup_root();
+    let governors: Vec<Address> = (901..904).map(Address::new_id).collect();
+    let method = Method::SetSafeMode as MethodNum;
+    let params = RawBytes::serialize(true).unwrap();
 
-    // Trying to commit from the wrong subnet
-    let ch = Checkpoint::new(shid.clone(), epoch + 9);
-    h.commit_child_check(&mut rt, &shid_two, &ch, ExitCode::USR_ILLEGAL_ARGUMENT)
+    // governance actions are disabled until governors are set
+    h.propose_gov_action(
+        &mut rt,
+        &governors[0],
+        vec![],
+        method,
+        params.clone(),
+        false,
+        ExitCode::USR_FORBIDDEN,
+    )
+    .unwrap();
+    h.set_governors(
+        &mut rt,
+        &GOVERNOR,
+        governors.clone(),
+        4,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+    h.set_governors(&mut rt, &GOVERNOR, governors.clone(), 2, ExitCode::OK)
         .unwrap();
 
-    // Commit first checkpoint for first window in second subnet
-    let epoch: ChainEpoch = 10;
-    rt.set_epoch(epoch);
-    let ch = Checkpoint::new(shid_two.clone(), epoch + 9);
+    // the single governor can't call privileged methods anymore
+    rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *GOVERNOR);
+    rt.expect_validate_caller_addr(vec![*ACTOR]);
+    expect_abort(
+        ExitCode::USR_FORBIDDEN,
+        rt.call::<Actor>(method, IpldBlock::serialize_cbor(&true).unwrap()),
+    );
+    rt.verify();
 
-    h.commit_child_check(&mut rt, &shid_two, &ch, ExitCode::OK)
+    h.propose_gov_action(
+        &mut rt,
+        &GOVERNOR,
+        governors.clone(),
+        method,
+        params.clone(),
+        false,
+        ExitCode::USR_FORBIDDEN,
+    )
+    .unwrap();
+    let id = h
+        .propose_gov_action(
+            &mut rt,
+            &governors[0],
+            governors.clone(),
+            method,
+            params.clone(),
+            false,
+            ExitCode::OK,
+        )
         .unwrap();
     let st: State = rt.get_state();
-    let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
-    assert_eq!(commit.epoch(), DEFAULT_CHECKPOINT_PERIOD);
-    let child_check = has_childcheck_source(&commit.data.children, &shid_two).unwrap();
-    assert_eq!(&child_check.checks.len(), &1);
-    assert_eq!(has_cid(&child_check.checks, &ch.cid()), true);
-}
+    let proposal = st.get_gov_proposal(rt.store(), id).unwrap().unwrap();
+    assert_eq!(proposal.approvals, vec![governors[0]]);
 
-#[test]
-fn checkpoint_crossmsgs() {
-    let (h, mut rt) = setup_root();
+    // below the threshold approvals of the same governor don't count
+    h.approve_gov_action(
+        &mut rt,
+        &governors[0],
+        governors.clone(),
+        id,
+        None,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
 
-    // Register a subnet with 1FIL collateral
-    let value = TokenAmount::from_atto(10_u64.pow(18));
-    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+    // the action is executed at the threshold
+    let executed = h
+        .approve_gov_action(
+            &mut rt,
+            &governors[1],
+            governors.clone(),
+            id,
+            Some((method, params)),
+            ExitCode::OK,
+        )
         .unwrap();
-
+    assert!(executed);
     let st: State = rt.get_state();
-    assert_eq!(st.total_subnets, 1);
-    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
-    let subnet = h.get_subnet(&rt, &shid).unwrap();
-    assert_eq!(subnet.id, shid);
-    assert_eq!(subnet.stake, value);
-    assert_eq!(subnet.circ_supply, TokenAmount::zero());
-    assert_eq!(subnet.status, Active);
-    h.check_state();
-
-    // Commit first checkpoint for first window in first subnet
-    let epoch: ChainEpoch = 10;
-    rt.set_epoch(epoch);
-    let mut ch = Checkpoint::new(shid.clone(), epoch + 9);
-    // and include some fees in msgmeta.
-    let fee = TokenAmount::from_atto(5);
-    set_msg_meta(
-        &mut ch,
-        "rand1".as_bytes().to_vec(),
-        TokenAmount::zero(),
-        fee.clone(),
-    );
-
-    rt.expect_send(
-        shid.subnet_actor(),
-        SUBNET_ACTOR_REWARD_METHOD,
-        None,
-        fee,
+    assert!(st.get_gov_proposal(rt.store(), id).unwrap().is_none());
+    h.approve_gov_action(
+        &mut rt,
+        &governors[2],
+        governors.clone(),
+        id,
         None,
-        ExitCode::OK,
-    );
-    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+
+    // the gateway calling itself is accepted as the governor
+    rt.set_caller(*INIT_ACTOR_CODE_ID, *ACTOR);
+    rt.expect_validate_caller_addr(vec![*ACTOR]);
+    rt.call::<Actor>(method, IpldBlock::serialize_cbor(&true).unwrap())
         .unwrap();
+    rt.verify();
     let st: State = rt.get_state();
-    let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
-    assert_eq!(commit.epoch(), DEFAULT_CHECKPOINT_PERIOD);
-    let child_check = has_childcheck_source(&commit.data.children, &shid).unwrap();
-    assert_eq!(&child_check.checks.len(), &1);
-    let prev_cid = ch.cid();
-    assert_eq!(has_cid(&child_check.checks, &prev_cid), true);
-
-    // TODO: More extensive tests?
+    assert!(st.safe_mode);
 }
 
 #[test]
-fn test_fund() {
+fn test_adjust_circ_supply() {
     let (h, mut rt) = setup_root();
-
-    // Register a subnet with 1FIL collateral
     let value = TokenAmount::from_atto(10_u64.pow(18));
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
     h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
         .unwrap();
-
-    let st: State = rt.get_state();
-    assert_eq!(st.total_subnets, 1);
-    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
-    let subnet = h.get_subnet(&rt, &shid).unwrap();
-    assert_eq!(subnet.id, shid);
-    assert_eq!(subnet.stake, value);
-    assert_eq!(subnet.circ_supply, TokenAmount::zero());
-    assert_eq!(subnet.status, Active);
-    h.check_state();
-
     let funder = Address::new_id(1001);
-    let amount = TokenAmount::from_atto(10_u64.pow(18));
+    let amount = TokenAmount::from_atto(10_u64.pow(17));
     h.fund(
         &mut rt,
         &funder,
@@ -397,207 +773,3602 @@ fn test_fund() {
         &amount,
     )
     .unwrap();
-    let funder = Address::new_id(1002);
-    let mut exp_cs = amount.clone() * 2;
-    h.fund(
+    let drift = TokenAmount::from_atto(10_u64.pow(16));
+
+    // corrections are only allowed to the governor in safe mode
+    h.adjust_circ_supply(
         &mut rt,
-        &funder,
+        &GOVERNOR,
         &shid,
-        ExitCode::OK,
-        amount.clone(),
-        2,
-        &exp_cs,
+        -drift.clone(),
+        ExitCode::USR_ILLEGAL_STATE,
     )
     .unwrap();
-    exp_cs += amount.clone();
-    h.fund(
+    h.set_safe_mode(&mut rt, &GOVERNOR, true, ExitCode::OK)
+        .unwrap();
+    h.adjust_circ_supply(
         &mut rt,
         &funder,
         &shid,
-        ExitCode::OK,
-        amount.clone(),
-        3,
-        &exp_cs,
+        -drift.clone(),
+        ExitCode::USR_FORBIDDEN,
     )
     .unwrap();
-    // No funds sent
-    h.fund(
+
+    // the supply can be corrected in both directions
+    h.adjust_circ_supply(&mut rt, &GOVERNOR, &shid, -drift.clone(), ExitCode::OK)
+        .unwrap();
+    assert_eq!(
+        h.get_subnet(&rt, &shid).unwrap().circ_supply,
+        &amount - &drift
+    );
+    h.adjust_circ_supply(&mut rt, &GOVERNOR, &shid, drift.clone(), ExitCode::OK)
+        .unwrap();
+    assert_eq!(h.get_subnet(&rt, &shid).unwrap().circ_supply, amount);
+
+    // but it can't become negative
+    h.adjust_circ_supply(
         &mut rt,
-        &funder,
+        &GOVERNOR,
         &shid,
+        -(&amount + &drift),
         ExitCode::USR_ILLEGAL_ARGUMENT,
-        TokenAmount::zero(),
-        3,
-        &exp_cs,
     )
     .unwrap();
-
-    // Subnet doesn't exist
-    h.fund(
+    h.adjust_circ_supply(
         &mut rt,
-        &funder,
+        &GOVERNOR,
         &SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO),
+        drift,
         ExitCode::USR_ILLEGAL_ARGUMENT,
-        TokenAmount::zero(),
-        3,
-        &exp_cs,
     )
     .unwrap();
-}
-
-#[test]
-fn test_release() {
-    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
-    let (h, mut rt) = setup(shid.clone());
+    assert_eq!(h.get_subnet(&rt, &shid).unwrap().circ_supply, amount);
 
-    let releaser = Address::new_id(1001);
-    // Release funds
-    let r_amount = TokenAmount::from_atto(5_u64.pow(18));
-    rt.set_balance(2 * r_amount.clone());
-    let prev_cid = h
-        .release(
-            &mut rt,
-            &releaser,
-            ExitCode::OK,
-            r_amount.clone(),
-            0,
-            &Cid::default(),
-            CROSS_MSG_FEE.clone(),
-        )
+    // once the supply is cleared the subnet can be killed, distributing the
+    // fee held for the message it didn't apply
+    h.adjust_circ_supply(&mut rt, &GOVERNOR, &shid, -amount, ExitCode::OK)
         .unwrap();
-    h.release(
-        &mut rt,
-        &releaser,
-        ExitCode::OK,
-        r_amount,
-        1,
-        &prev_cid,
-        2 * CROSS_MSG_FEE.clone(),
-    )
-    .unwrap();
+    rt.set_balance(&value + &h.cross_msg_fees.fund);
+    h.kill(&mut rt, &shid, &value, ExitCode::OK).unwrap();
 }
 
 #[test]
-fn test_send_cross() {
-    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
-    let (h, mut rt) = setup(shid.clone());
-
-    let from = Address::new_id(1001);
-    let to = Address::new_id(1002);
+fn test_force_kill() {
+    let (h, mut rt) = setup_root();
 
     let value = TokenAmount::from_atto(10_u64.pow(18));
-
-    // register subnet
-    let reg_value = TokenAmount::from_atto(10_u64.pow(18));
-    h.register(&mut rt, &SUBNET_ONE, &reg_value, ExitCode::OK)
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
         .unwrap();
-
-    // top-down
-    let sub = SubnetID::from_str("/root/f0101/f0101").unwrap();
-    h.send_cross(
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(17));
+    h.fund(
         &mut rt,
-        &from,
+        &funder,
         &shid,
-        &to,
-        sub,
         ExitCode::OK,
-        value.clone(),
+        amount.clone(),
         1,
-        &value,
-    )
-    .unwrap();
-    let sub = SubnetID::from_str("/root/f0101/f0101").unwrap();
-    let circ_sup = 2 * &value;
-    h.send_cross(
-        &mut rt,
-        &from,
-        &shid,
-        &to,
-        sub,
-        ExitCode::OK,
-        value.clone(),
-        2,
-        &circ_sup,
+        &amount,
     )
     .unwrap();
-    let sub = SubnetID::from_str("/root/f0101/f0101/f01002").unwrap();
-    let circ_sup = circ_sup.clone() + &value;
-    h.send_cross(
+
+    // kill is still rejected while there is circulating supply
+    h.kill(&mut rt, &shid, &value, ExitCode::USR_ILLEGAL_STATE)
+        .unwrap();
+
+    let recovery_addr = Address::new_id(2000);
+    let params = |subnet: &SubnetID| ForceKillParams {
+        subnet: subnet.clone(),
+        recovery_addr,
+    };
+    // only the governor can force the kill
+    h.force_kill(&mut rt, &funder, params(&shid), ExitCode::USR_FORBIDDEN)
+        .unwrap();
+    h.force_kill(
         &mut rt,
-        &from,
-        &shid,
-        &to,
-        sub,
-        ExitCode::OK,
-        value.clone(),
-        3,
-        &circ_sup,
+        &GOVERNOR,
+        params(&SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO)),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
     )
     .unwrap();
 
-    // bottom-up
-    rt.set_balance(3 * &value);
-    let sub = SubnetID::from_str("/root/f0102/f0101").unwrap();
-    let zero = TokenAmount::zero();
-    h.send_cross(
-        &mut rt,
-        &from,
-        &shid,
-        &to,
-        sub,
+    // the residual supply is swept to the recovery address, and the fee held
+    // for the unapplied message is distributed to the subnet actor
+    let fee = h.cross_msg_fees.fund.clone();
+    rt.set_balance(&value + &amount + &fee);
+    rt.expect_send(
+        recovery_addr,
+        METHOD_SEND,
+        None,
+        amount.clone(),
+        None,
         ExitCode::OK,
+    );
+    rt.expect_send(
+        shid.subnet_actor(),
+        METHOD_SEND,
+        None,
         value.clone(),
-        0,
-        &zero,
-    )
-    .unwrap();
-    let sub = SubnetID::from_str("/root/f0102/f0101").unwrap();
-    h.send_cross(
-        &mut rt,
-        &from,
-        &shid,
-        &to,
-        sub,
+        None,
         ExitCode::OK,
-        value.clone(),
-        1,
-        &zero,
-    )
-    .unwrap();
-    let sub = SubnetID::from_str("/root").unwrap();
-    h.send_cross(
-        &mut rt,
-        &from,
-        &shid,
-        &to,
-        sub,
+    );
+    rt.expect_send(
+        shid.subnet_actor(),
+        SUBNET_ACTOR_REWARD_METHOD,
+        None,
+        fee,
+        None,
         ExitCode::OK,
-        value.clone(),
-        2,
-        &zero,
-    )
-    .unwrap();
+    );
+    h.force_kill(&mut rt, &GOVERNOR, params(&shid), ExitCode::OK)
+        .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.total_subnets, 0);
+    assert!(h.get_subnet(&rt, &shid).is_none());
 }
 
-/// This test covers the case where a bottom up cross_msg's target subnet is the SAME as that of
-/// the gateway. It should directly commit the message and will not save in postbox.
 #[test]
-fn test_apply_msg_bu_target_subnet() {
-    // ============== Register subnet ==============
-    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
-    let (h, mut rt) = setup(ROOTNET_ID.clone());
-
-    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
-    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+fn checkpoint_commit() {
+    let (h, mut rt) = setup_root();
 
-    let sub = shid.clone();
+    // Register a subnet with 1FIL collateral
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
 
-    // ================ Setup ===============
-    let value = TokenAmount::from_atto(10_u64.pow(17));
+    let st: State = rt.get_state();
+    assert_eq!(st.total_subnets, 1);
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let subnet = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(subnet.id, shid);
+    assert_eq!(subnet.stake, value);
+    assert_eq!(subnet.circ_supply, TokenAmount::zero());
+    assert_eq!(subnet.status, Active);
+    h.check_state();
+
+    // Commit first checkpoint for first window in first subnet
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let ch = Checkpoint::new(shid.clone(), epoch + 9);
+
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+    let st: State = rt.get_state();
+    let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
+    assert_eq!(commit.epoch(), DEFAULT_CHECKPOINT_PERIOD);
+    let child_check = has_childcheck_source(&commit.data.children, &shid).unwrap();
+    assert_eq!(&child_check.checks.len(), &1);
+    assert_eq!(has_cid(&child_check.checks, &ch.cid()), true);
+
+    // Commit a checkpoint for subnet twice
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+    let prev_cid = ch.cid();
+
+    // Append a new checkpoint for the same subnet
+    let mut ch = Checkpoint::new(shid.clone(), epoch + 11);
+    ch.data.prev_check = TCid::from(prev_cid);
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+    let st: State = rt.get_state();
+    let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
+    assert_eq!(commit.epoch(), DEFAULT_CHECKPOINT_PERIOD);
+    let child_check = has_childcheck_source(&commit.data.children, &shid).unwrap();
+    assert_eq!(&child_check.checks.len(), &2);
+    assert_eq!(has_cid(&child_check.checks, &ch.cid()), true);
+
+    // Register second subnet
+    h.register(&mut rt, &SUBNET_TWO, &value, ExitCode::OK)
+        .unwrap();
+
+    let st: State = rt.get_state();
+    assert_eq!(st.total_subnets, 2);
+    let shid_two = SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO);
+    let subnet = h.get_subnet(&rt, &shid_two).unwrap();
+    assert_eq!(subnet.id, shid_two);
+    h.check_state();
+
+    // Trying to commit from the wrong subnet
+    let ch = Checkpoint::new(shid.clone(), epoch + 9);
+    h.commit_child_check(&mut rt, &shid_two, &ch, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    // Commit first checkpoint for first window in second subnet
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let ch = Checkpoint::new(shid_two.clone(), epoch + 9);
+
+    h.commit_child_check(&mut rt, &shid_two, &ch, ExitCode::OK)
+        .unwrap();
+    let st: State = rt.get_state();
+    let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
+    assert_eq!(commit.epoch(), DEFAULT_CHECKPOINT_PERIOD);
+    let child_check = has_childcheck_source(&commit.data.children, &shid_two).unwrap();
+    assert_eq!(&child_check.checks.len(), &1);
+    assert_eq!(has_cid(&child_check.checks, &ch.cid()), true);
+}
+
+#[test]
+fn get_prev_checkpoint() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+
+    // no checkpoint committed yet
+    let prev = h.get_prev_checkpoint(&mut rt, &shid, ExitCode::OK).unwrap();
+    assert!(prev.is_none());
+    assert_eq!(h.get_last_committed_epoch(&mut rt, &shid), None);
+
+    // unregistered subnet
+    h.get_prev_checkpoint(
+        &mut rt,
+        &SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let ch = Checkpoint::new(shid.clone(), epoch + 9);
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+    let prev = h
+        .get_prev_checkpoint(&mut rt, &shid, ExitCode::OK)
+        .unwrap()
+        .unwrap();
+    assert_eq!(prev.cid(), ch.cid());
+    assert_eq!(h.get_last_committed_epoch(&mut rt, &shid), Some(ch.epoch()));
+
+    // a checkpoint built from the returned one is accepted
+    let mut next = Checkpoint::new(shid.clone(), epoch + 11);
+    next.data.prev_check = TCid::from(prev.cid());
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert!(sub.verify_prev_checkpoint(&next).is_ok());
+    h.commit_child_check(&mut rt, &shid, &next, ExitCode::OK)
+        .unwrap();
+    let prev = h
+        .get_prev_checkpoint(&mut rt, &shid, ExitCode::OK)
+        .unwrap()
+        .unwrap();
+    assert_eq!(prev.cid(), next.cid());
+
+    // a checkpoint with an inconsistent prev_check is rejected
+    let mut wrong = Checkpoint::new(shid.clone(), epoch + 12);
+    wrong.data.prev_check = TCid::from(ch.cid());
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert!(sub.verify_prev_checkpoint(&wrong).is_err());
+    h.commit_child_check(&mut rt, &shid, &wrong, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+}
+
+#[test]
+fn checkpoint_pruning() {
+    let mut h = new_harness(SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_THR));
+    h.checkpoint_retention = 2;
+    let mut rt = new_runtime();
+    h.construct_and_verify(&mut rt);
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    h.register(&mut rt, &SUBNET_TWO, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let shid_two = SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO);
+
+    // the second subnet only commits a checkpoint in the first window
+    rt.set_epoch(10);
+    let ch = Checkpoint::new(shid_two.clone(), 19);
+    h.commit_child_check(&mut rt, &shid_two, &ch, ExitCode::OK)
+        .unwrap();
+
+    // the first subnet commits a checkpoint in every window
+    let mut prev_cid = None;
+    for epoch in [10, 20, 30, 40] {
+        rt.set_epoch(epoch);
+        let mut ch = Checkpoint::new(shid.clone(), epoch + 9);
+        if let Some(prev) = prev_cid {
+            ch.data.prev_check = TCid::from(prev);
+        }
+        h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+            .unwrap();
+        prev_cid = Some(ch.cid());
+    }
+
+    // the window of epoch 20 also includes a released message
+    rt.set_epoch(20);
+    let amount = TokenAmount::from_atto(10_u64.pow(17));
+    rt.set_balance(amount.clone());
+    let released_cid = h
+        .release(
+            &mut rt,
+            &Address::new_id(1001),
+            ExitCode::OK,
+            amount,
+            0,
+            &Cid::default(),
+            CROSS_MSG_FEE.clone(),
+        )
+        .unwrap();
+
+    // two bottom-up metas were committed by children, and the first one was applied
+    let mut st: State = rt.get_state();
+    st.bottomup_msg_meta
+        .update(rt.store(), |metas| {
+            for nonce in 0..2 {
+                let meta = CrossMsgMeta {
+                    nonce,
+                    ..Default::default()
+                };
+                metas.set(nonce, meta).unwrap();
+            }
+            Ok(())
+        })
+        .unwrap();
+    st.applied_bottomup_nonce = 1;
+    rt.replace_state(&st);
+
+    let children = |rt: &MockRuntime, epoch| {
+        let st: State = rt.get_state();
+        st.get_window_checkpoint(rt.store(), epoch)
+            .unwrap()
+            .data
+            .children
+            .len()
+    };
+
+    // nothing to prune while all windows are within the retention
+    rt.set_epoch(40);
+    assert_eq!(h.prune_checkpoints(&mut rt), 0);
+
+    // the window of epoch 20 falls out of the retention. The one of epoch 10 is
+    // kept because it includes the latest checkpoint of the second subnet.
+    rt.set_epoch(50);
+    assert_eq!(h.prune_checkpoints(&mut rt), 1);
+    assert_eq!(children(&rt, 10), 2);
+    assert_eq!(children(&rt, 20), 0);
+    assert_eq!(children(&rt, 30), 1);
+    assert_eq!(children(&rt, 40), 1);
+    assert_eq!(h.prune_checkpoints(&mut rt), 0);
+
+    // the messages of the pruned window are removed from the registry, and the
+    // index only keeps the metas that haven't been applied
+    let st: State = rt.get_state();
+    let registry = st.check_msg_registry.load(rt.store()).unwrap();
+    assert!(registry.get(&released_cid.to_bytes()).unwrap().is_none());
+    let metas = st.bottomup_msg_meta.load(rt.store()).unwrap();
+    assert!(metas.get(0).unwrap().is_none());
+    assert!(metas.get(1).unwrap().is_some());
+}
+
+#[test]
+fn checkpoint_crossmsgs() {
+    let (h, mut rt) = setup_root();
+
+    // Register a subnet with 1FIL collateral
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+
+    let st: State = rt.get_state();
+    assert_eq!(st.total_subnets, 1);
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let subnet = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(subnet.id, shid);
+    assert_eq!(subnet.stake, value);
+    assert_eq!(subnet.circ_supply, TokenAmount::zero());
+    assert_eq!(subnet.status, Active);
+    h.check_state();
+
+    // fund the subnet so the fees of its checkpoint can be released
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(17));
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
+
+    // Commit first checkpoint for first window in first subnet
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let mut ch = Checkpoint::new(shid.clone(), epoch + 9);
+    // and include some fees in msgmeta.
+    let fee = TokenAmount::from_atto(5);
+    set_msg_meta(
+        rt.store(),
+        &mut ch,
+        "rand1".as_bytes().to_vec(),
+        fee.clone(),
+        fee.clone(),
+    );
+
+    rt.expect_send(
+        shid.subnet_actor(),
+        SUBNET_ACTOR_REWARD_METHOD,
+        None,
+        fee,
+        None,
+        ExitCode::OK,
+    );
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+    let st: State = rt.get_state();
+    let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
+    assert_eq!(commit.epoch(), DEFAULT_CHECKPOINT_PERIOD);
+    let child_check = has_childcheck_source(&commit.data.children, &shid).unwrap();
+    assert_eq!(&child_check.checks.len(), &1);
+    let prev_cid = ch.cid();
+    assert_eq!(has_cid(&child_check.checks, &prev_cid), true);
+
+    // TODO: More extensive tests?
+}
+
+#[test]
+fn test_bottomup_fee_policy() {
+    let parent = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let cases = [
+        // the child subnet committing the checkpoint is paid all the fees
+        (BottomUpFeePolicy::Committer, parent.clone(), 10, 10, 0),
+        // half of the fees are kept for the validators of the parent
+        (BottomUpFeePolicy::Split, parent.clone(), 10, 5, 5),
+        // the remainder of an uneven split goes to the child
+        (BottomUpFeePolicy::Split, parent, 11, 6, 5),
+        // the root network has no parent to forward fees to
+        (BottomUpFeePolicy::Split, ROOTNET_ID.clone(), 10, 10, 0),
+    ];
+    for (policy, net_name, fee, child_fee, forwarded) in cases {
+        let mut h = new_harness(net_name);
+        h.bottomup_fee_policy = policy;
+        let mut rt = new_runtime();
+        h.construct(&mut rt);
+
+        let value = TokenAmount::from_atto(10_u64.pow(18));
+        h.register(&mut rt, &SUBNET_TWO, &value, ExitCode::OK)
+            .unwrap();
+        let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO);
+        let funder = Address::new_id(1001);
+        let amount = TokenAmount::from_atto(10_u64.pow(17));
+        h.fund(
+            &mut rt,
+            &funder,
+            &shid,
+            ExitCode::OK,
+            amount.clone(),
+            1,
+            &amount,
+        )
+        .unwrap();
+
+        // the checkpoint of the child carries the fees of bottom-up messages
+        // that may come from further down the hierarchy.
+        let epoch: ChainEpoch = 10;
+        rt.set_epoch(epoch);
+        let mut ch = Checkpoint::new(shid.clone(), epoch + 9);
+        set_msg_meta(
+            rt.store(),
+            &mut ch,
+            "rand1".as_bytes().to_vec(),
+            TokenAmount::from_atto(fee),
+            TokenAmount::from_atto(fee),
+        );
+        rt.expect_send(
+            shid.subnet_actor(),
+            SUBNET_ACTOR_REWARD_METHOD,
+            None,
+            TokenAmount::from_atto(child_fee),
+            None,
+            ExitCode::OK,
+        );
+        h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+            .unwrap();
+
+        // the rest of the fees are paid in the checkpoint of this subnet
+        let st: State = rt.get_state();
+        let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
+        let fee = commit
+            .cross_msgs()
+            .map(|meta| meta.fee.clone())
+            .unwrap_or_default();
+        assert_eq!(fee, TokenAmount::from_atto(forwarded));
+        assert_eq!(st.pending_fee_distribution, TokenAmount::zero());
+    }
+}
+
+#[test]
+fn checkpoint_commit_atomic() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(17));
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
+
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let fee = TokenAmount::from_atto(5);
+    // rewards exceeding the fees are only detected after the supply to
+    // release has been computed.
+    let mut excess_rewards = Checkpoint::new(shid.clone(), epoch + 9);
+    set_msg_meta(
+        rt.store(),
+        &mut excess_rewards,
+        "rand1".as_bytes().to_vec(),
+        amount.clone(),
+        fee.clone(),
+    );
+    excess_rewards.set_rewards(vec![RewardEntry {
+        addr: Address::new_id(1001),
+        amount: TokenAmount::from_atto(6),
+    }]);
+    // releasing more than the circulating supply fails after the
+    // cross-msgs of the checkpoint have been validated.
+    let mut excess_value = Checkpoint::new(shid.clone(), epoch + 9);
+    set_msg_meta(
+        rt.store(),
+        &mut excess_value,
+        "rand2".as_bytes().to_vec(),
+        amount.clone() * 2,
+        fee,
+    );
+
+    for (ch, code) in [
+        (excess_rewards, ExitCode::USR_ILLEGAL_ARGUMENT),
+        (excess_value, ExitCode::USR_ILLEGAL_STATE),
+    ] {
+        h.commit_child_check(&mut rt, &shid, &ch, code).unwrap();
+
+        // nothing of the commitment is persisted
+        let st: State = rt.get_state();
+        let sub = h.get_subnet(&rt, &shid).unwrap();
+        assert_eq!(sub.circ_supply, amount);
+        assert_eq!(sub.prev_checkpoint, None);
+        let commit = st.get_window_checkpoint(rt.store(), epoch).unwrap();
+        assert!(commit.data.children.is_empty());
+        assert_eq!(commit.cross_msgs(), None);
+        assert_eq!(st.bottomup_nonce, 0);
+        assert_eq!(st.pending_fee_distribution, TokenAmount::zero());
+    }
+}
+
+#[test]
+fn checkpoint_too_many_crossmsgs() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let st: State = rt.get_state();
+
+    let checkpoint = |num_msgs: u64| {
+        let crossmsgs = CrossMsgs {
+            msgs: (0..num_msgs)
+                .map(|nonce| CrossMsg {
+                    msg: StorableMsg {
+                        from: IPCAddress::new(&shid, &TEST_BLS).unwrap(),
+                        to: IPCAddress::new(&ROOTNET_ID, &TEST_BLS).unwrap(),
+                        method: METHOD_SEND,
+                        params: RawBytes::default(),
+                        value: TokenAmount::zero(),
+                        nonce,
+                        memo: Vec::new(),
+                        valid_until_epoch: None,
+                        correlation_id: None,
+                    },
+                    wrapped: false,
+                })
+                .collect(),
+        };
+        let mut ch = Checkpoint::new(shid.clone(), epoch + 9);
+        ch.set_cross_msgs(CrossMsgMeta {
+            msgs_cid: TCid::from(
+                rt.store()
+                    .put_cbor(&crossmsgs, cid::multihash::Code::Blake2b256)
+                    .unwrap(),
+            ),
+            nonce: 0,
+            value: TokenAmount::zero(),
+            fee: TokenAmount::zero(),
+            msgs_value: TokenAmount::zero(),
+            num_msgs,
+        });
+        ch
+    };
+
+    let too_many = checkpoint(st.max_msgs_per_checkpoint + 1);
+    let max = checkpoint(st.max_msgs_per_checkpoint);
+    let mut undeclared = checkpoint(1);
+    undeclared.cross_msgs_mut().unwrap().num_msgs = 0;
+
+    // checkpoint exceeding the maximum number of messages is rejected
+    h.commit_child_check(&mut rt, &shid, &too_many, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.bottomup_nonce, 0);
+
+    // as is one including messages without declaring them
+    h.commit_child_check(&mut rt, &shid, &undeclared, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.bottomup_nonce, 0);
+
+    // a checkpoint with exactly the maximum is accepted
+    h.commit_child_check(&mut rt, &shid, &max, ExitCode::OK)
+        .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.bottomup_nonce, 1);
+}
+
+#[test]
+fn checkpoint_fee_mismatch() {
+    let (h, mut rt) = setup_root();
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(17));
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
+
+    // a bottom-up message releasing part of the supply, paying the rest as fees
+    let fee = TokenAmount::from_atto(5);
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let checkpoint = |meta: CrossMsgMeta| {
+        let mut ch = Checkpoint::new(shid.clone(), epoch + 9);
+        ch.set_cross_msgs(meta);
+        ch
+    };
+    let mut ch = Checkpoint::new(shid.clone(), epoch + 9);
+    set_msg_meta(
+        rt.store(),
+        &mut ch,
+        "rand1".as_bytes().to_vec(),
+        amount.clone(),
+        fee.clone(),
+    );
+    let meta = ch.cross_msgs().unwrap().clone();
+
+    // the declared fee exceeds the fees paid by the messages
+    let overstated = CrossMsgMeta {
+        fee: fee.clone() * 2,
+        ..meta.clone()
+    };
+    h.commit_child_check(
+        &mut rt,
+        &shid,
+        &checkpoint(overstated),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+
+    // the value of the messages is understated to inflate the fee
+    let understated = CrossMsgMeta {
+        msgs_value: &meta.msgs_value - TokenAmount::from_atto(1),
+        fee: &fee + TokenAmount::from_atto(1),
+        ..meta.clone()
+    };
+    h.commit_child_check(
+        &mut rt,
+        &shid,
+        &checkpoint(understated),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+
+    // the number of messages declared doesn't match the ones included
+    let miscounted = CrossMsgMeta {
+        num_msgs: 2,
+        ..meta.clone()
+    };
+    h.commit_child_check(
+        &mut rt,
+        &shid,
+        &checkpoint(miscounted),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+
+    // the messages of the meta need to be resolvable to verify the fee
+    let unresolved = CrossMsgMeta {
+        msgs_cid: TCid::from(Checkpoint::new(shid.clone(), 0).cid()),
+        ..meta.clone()
+    };
+    h.commit_child_check(
+        &mut rt,
+        &shid,
+        &checkpoint(unresolved),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+
+    // the messages can't carry more than the value of the meta
+    let excess = CrossMsgMeta {
+        msgs_value: &amount + TokenAmount::from_atto(1),
+        ..meta.clone()
+    };
+    h.commit_child_check(
+        &mut rt,
+        &shid,
+        &checkpoint(excess),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+
+    // a meta without messages can only carry fees
+    let fee_only = CrossMsgMeta {
+        msgs_cid: TCid::default(),
+        num_msgs: 0,
+        ..meta.clone()
+    };
+    h.commit_child_check(
+        &mut rt,
+        &shid,
+        &checkpoint(fee_only),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+
+    rt.expect_send(
+        shid.subnet_actor(),
+        SUBNET_ACTOR_REWARD_METHOD,
+        None,
+        fee,
+        None,
+        ExitCode::OK,
+    );
+    h.commit_child_check(&mut rt, &shid, &checkpoint(meta), ExitCode::OK)
+        .unwrap();
+    assert_eq!(
+        h.get_subnet(&rt, &shid).unwrap().circ_supply,
+        TokenAmount::zero()
+    );
+}
+
+#[test]
+fn test_fund() {
+    let (h, mut rt) = setup_root();
+
+    // Register a subnet with 1FIL collateral
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+
+    let st: State = rt.get_state();
+    assert_eq!(st.total_subnets, 1);
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let subnet = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(subnet.id, shid);
+    assert_eq!(subnet.stake, value);
+    assert_eq!(subnet.circ_supply, TokenAmount::zero());
+    assert_eq!(subnet.status, Active);
+    h.check_state();
+
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(18));
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
+    let funder = Address::new_id(1002);
+    let mut exp_cs = amount.clone() * 2;
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        2,
+        &exp_cs,
+    )
+    .unwrap();
+    exp_cs += amount.clone();
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        3,
+        &exp_cs,
+    )
+    .unwrap();
+    // No funds sent
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        TokenAmount::zero(),
+        3,
+        &exp_cs,
+    )
+    .unwrap();
+
+    // Subnet doesn't exist
+    h.fund(
+        &mut rt,
+        &funder,
+        &SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        TokenAmount::zero(),
+        3,
+        &exp_cs,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_safe_mode() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let child = SubnetID::new_from_parent(&shid, *SUBNET_ONE);
+    let from = Address::new_id(1001);
+    let to = Address::new_id(1002);
+
+    // only the governor can toggle the safe mode
+    h.set_safe_mode(&mut rt, &from, true, ExitCode::USR_FORBIDDEN)
+        .unwrap();
+    h.set_safe_mode(&mut rt, &GOVERNOR, true, ExitCode::OK)
+        .unwrap();
+    let st: State = rt.get_state();
+    assert!(st.safe_mode);
+
+    // value transfers are rejected
+    h.fund(
+        &mut rt,
+        &from,
+        &child,
+        ExitCode::USR_ILLEGAL_STATE,
+        value.clone(),
+        1,
+        &value,
+    )
+    .unwrap();
+    h.release(
+        &mut rt,
+        &from,
+        ExitCode::USR_ILLEGAL_STATE,
+        value.clone(),
+        0,
+        &Cid::default(),
+        CROSS_MSG_FEE.clone(),
+    )
+    .unwrap();
+    h.send_cross(
+        &mut rt,
+        &from,
+        &shid,
+        &to,
+        child.clone(),
+        ExitCode::USR_ILLEGAL_STATE,
+        value.clone(),
+        1,
+        &value,
+    )
+    .unwrap();
+
+    // while zero-value messages still flow
+    h.send_cross(
+        &mut rt,
+        &from,
+        &shid,
+        &to,
+        child.clone(),
+        ExitCode::OK,
+        TokenAmount::zero(),
+        1,
+        &TokenAmount::zero(),
+    )
+    .unwrap();
+
+    // value transfers are restored once the safe mode is disabled
+    h.set_safe_mode(&mut rt, &GOVERNOR, false, ExitCode::OK)
+        .unwrap();
+    h.send_cross(
+        &mut rt,
+        &from,
+        &shid,
+        &to,
+        child,
+        ExitCode::OK,
+        value.clone(),
+        2,
+        &value,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_get_state_root() {
+    let (h, mut rt) = setup_root();
+
+    // read-only calls don't change the root
+    let root = h.get_state_root(&mut rt);
+    assert_eq!(root, rt.get_state_root().unwrap());
+    h.get_total_fees_collected(&mut rt);
+    assert_eq!(h.get_state_root(&mut rt), root);
+
+    // while state changes do
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let new_root = h.get_state_root(&mut rt);
+    assert_ne!(new_root, root);
+    assert_eq!(new_root, rt.get_state_root().unwrap());
+}
+
+#[test]
+fn test_total_fees_collected() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let child = SubnetID::new_from_parent(&shid, *SUBNET_ONE);
+    let from = Address::new_id(1001);
+    let to = Address::new_id(1002);
+    assert_eq!(h.get_total_fees_collected(&mut rt), TokenAmount::zero());
+
+    // every fee-bearing operation adds its fee to the total
+    h.fund(
+        &mut rt,
+        &from,
+        &child,
+        ExitCode::OK,
+        value.clone(),
+        1,
+        &value,
+    )
+    .unwrap();
+    assert_eq!(h.get_total_fees_collected(&mut rt), CROSS_MSG_FEE.clone());
+    h.send_cross(
+        &mut rt,
+        &from,
+        &shid,
+        &to,
+        child,
+        ExitCode::OK,
+        value.clone(),
+        2,
+        &(2 * &value),
+    )
+    .unwrap();
+    assert_eq!(h.get_total_fees_collected(&mut rt), 2 * &*CROSS_MSG_FEE);
+    rt.set_balance(value.clone());
+    h.release(
+        &mut rt,
+        &from,
+        ExitCode::OK,
+        value.clone(),
+        0,
+        &Cid::default(),
+        CROSS_MSG_FEE.clone(),
+    )
+    .unwrap();
+    assert_eq!(h.get_total_fees_collected(&mut rt), 3 * &*CROSS_MSG_FEE);
+
+    // while failed operations don't
+    h.release(
+        &mut rt,
+        &from,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        TokenAmount::zero(),
+        1,
+        &Cid::default(),
+        CROSS_MSG_FEE.clone(),
+    )
+    .unwrap();
+    assert_eq!(h.get_total_fees_collected(&mut rt), 3 * &*CROSS_MSG_FEE);
+}
+
+#[test]
+fn test_cross_msg_fees_per_kind() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let fees = CrossMsgFees {
+        fund: TokenAmount::from_nano(100),
+        release: TokenAmount::from_nano(200),
+        send_cross: TokenAmount::from_nano(300),
+    };
+    let mut h = new_harness(shid.clone());
+    h.cross_msg_fees = fees.clone();
+    let mut rt = new_runtime();
+    h.construct_and_verify(&mut rt);
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let child = SubnetID::new_from_parent(&shid, *SUBNET_ONE);
+    let from = Address::new_id(1001);
+    let to = Address::new_id(1002);
+
+    // each kind of cross-message is charged its own fee
+    h.fund(
+        &mut rt,
+        &from,
+        &child,
+        ExitCode::OK,
+        value.clone(),
+        1,
+        &value,
+    )
+    .unwrap();
+    assert_eq!(h.get_total_fees_collected(&mut rt), fees.fund);
+    h.send_cross(
+        &mut rt,
+        &from,
+        &shid,
+        &to,
+        child,
+        ExitCode::OK,
+        value.clone(),
+        2,
+        &(2 * &value),
+    )
+    .unwrap();
+    assert_eq!(
+        h.get_total_fees_collected(&mut rt),
+        &fees.fund + &fees.send_cross
+    );
+    rt.set_balance(value.clone());
+    h.release(
+        &mut rt,
+        &from,
+        ExitCode::OK,
+        value.clone(),
+        0,
+        &Cid::default(),
+        fees.release.clone(),
+    )
+    .unwrap();
+    assert_eq!(
+        h.get_total_fees_collected(&mut rt),
+        &fees.fund + &fees.send_cross + &fees.release
+    );
+
+    // and the schedule reports them
+    rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+    rt.expect_validate_caller_any();
+    let ret = rt
+        .call::<Actor>(Method::GetFeeSchedule as MethodNum, None)
+        .unwrap();
+    rt.verify();
+    let schedule: FeeSchedule = deserialize_block(ret).unwrap();
+    assert_eq!(schedule.base_fee, fees.send_cross);
+    assert_eq!(schedule.min_fee, fees.fund);
+    assert_eq!(schedule.max_fee, fees.send_cross);
+    assert_eq!(schedule.msg_fees, fees);
+}
+
+#[test]
+fn test_min_cross_value() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let min = TokenAmount::from_atto(10_u64.pow(17));
+    let mut h = new_harness(shid.clone());
+    h.min_cross_value = min.clone();
+    let mut rt = new_runtime();
+    h.construct_and_verify(&mut rt);
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let child = SubnetID::new_from_parent(&shid, *SUBNET_ONE);
+    let from = Address::new_id(1001);
+    let to = Address::new_id(1002);
+    let dust = &min - TokenAmount::from_atto(1);
+
+    // dust transfers are rejected once the fee is deducted
+    h.fund(
+        &mut rt,
+        &from,
+        &child,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        dust.clone(),
+        1,
+        &dust,
+    )
+    .unwrap();
+    h.release(
+        &mut rt,
+        &from,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        dust.clone(),
+        0,
+        &Cid::default(),
+        CROSS_MSG_FEE.clone(),
+    )
+    .unwrap();
+    h.send_cross(
+        &mut rt,
+        &from,
+        &shid,
+        &to,
+        child.clone(),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        dust.clone(),
+        1,
+        &dust,
+    )
+    .unwrap();
+
+    // while transfers of the minimum value pass
+    h.fund(&mut rt, &from, &child, ExitCode::OK, min.clone(), 1, &min)
+        .unwrap();
+    h.send_cross(
+        &mut rt,
+        &from,
+        &shid,
+        &to,
+        child,
+        ExitCode::OK,
+        min.clone(),
+        2,
+        &(min.clone() * 2),
+    )
+    .unwrap();
+    rt.set_balance(min.clone());
+    h.release(
+        &mut rt,
+        &from,
+        ExitCode::OK,
+        min.clone(),
+        0,
+        &Cid::default(),
+        CROSS_MSG_FEE.clone(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_fee_exemption() {
+    let (h, mut rt) = setup_root();
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(18));
+
+    // only the governor can exempt addresses from fees
+    h.set_fee_exemption(&mut rt, &funder, &funder, true, ExitCode::USR_FORBIDDEN)
+        .unwrap();
+    h.set_fee_exemption(&mut rt, &GOVERNOR, &funder, true, ExitCode::OK)
+        .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.fee_exempt, vec![funder]);
+
+    // the exempt funder doesn't pay the fee, so no reward is distributed
+    rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, funder);
+    rt.set_value(amount.clone());
+    rt.expect_validate_caller_type(SIG_TYPES.clone());
+    rt.expect_send(
+        funder,
+        ext::account::PUBKEY_ADDRESS_METHOD,
+        None,
+        TokenAmount::zero(),
+        IpldBlock::serialize_cbor(&*TEST_BLS).unwrap(),
+        ExitCode::OK,
+    );
+    rt.call::<Actor>(
+        Method::Fund as MethodNum,
+        IpldBlock::serialize_cbor(&shid).unwrap(),
+    )
+    .unwrap();
+    rt.verify();
+    let subnet = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(subnet.circ_supply, amount);
+    assert_eq!(h.get_pending_fee_distribution(&mut rt), TokenAmount::zero());
+
+    // other callers keep paying it
+    let exp_cs = amount.clone() * 2;
+    h.fund(
+        &mut rt,
+        &Address::new_id(1002),
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        2,
+        &exp_cs,
+    )
+    .unwrap();
+
+    // and so does the funder once the exemption is removed
+    h.set_fee_exemption(&mut rt, &GOVERNOR, &funder, false, ExitCode::OK)
+        .unwrap();
+    let st: State = rt.get_state();
+    assert!(st.fee_exempt.is_empty());
+    let exp_cs = amount.clone() * 3;
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        3,
+        &exp_cs,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_pending_fee_distribution() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    assert_eq!(h.get_pending_fee_distribution(&mut rt), TokenAmount::zero());
+
+    // top-down fees are held until the subnet applies the messages
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(18));
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
+    let topdown_fee = h.cross_msg_fees.fund.clone();
+    assert_eq!(h.get_pending_fee_distribution(&mut rt), TokenAmount::zero());
+    assert_eq!(get_held_topdown_fee(&rt, &shid), topdown_fee);
+
+    // and distributed with the fees of the child checkpoint reporting them
+    // applied
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let mut ch = Checkpoint::new(shid.clone(), epoch + 9);
+    ch.data.applied_topdown_nonce = 1;
+    let fee = TokenAmount::from_atto(5);
+    set_msg_meta(
+        rt.store(),
+        &mut ch,
+        "rand1".as_bytes().to_vec(),
+        fee.clone(),
+        fee.clone(),
+    );
+    rt.expect_send(
+        shid.subnet_actor(),
+        SUBNET_ACTOR_REWARD_METHOD,
+        None,
+        fee + topdown_fee,
+        None,
+        ExitCode::OK,
+    );
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+    assert_eq!(h.get_pending_fee_distribution(&mut rt), TokenAmount::zero());
+    assert_eq!(get_held_topdown_fee(&rt, &shid), TokenAmount::zero());
+    let st: State = rt.get_state();
+    st.topdown_fees
+        .load(rt.store())
+        .unwrap()
+        .for_each(|_, _| panic!("expected no fees held"))
+        .unwrap();
+}
+
+#[test]
+fn checkpoint_reward_manifest() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    // fund the subnet so the fees of its checkpoint can be released
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(17));
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
+
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let fee = TokenAmount::from_atto(10);
+    let rewards = vec![
+        RewardEntry {
+            addr: Address::new_id(1001),
+            amount: TokenAmount::from_atto(4),
+        },
+        RewardEntry {
+            addr: Address::new_id(1002),
+            amount: TokenAmount::from_atto(3),
+        },
+    ];
+
+    // manifest exceeding the fees accrued in the checkpoint is rejected
+    let mut ch = Checkpoint::new(shid.clone(), epoch + 9);
+    set_msg_meta(
+        rt.store(),
+        &mut ch,
+        "rand1".as_bytes().to_vec(),
+        TokenAmount::from_atto(6),
+        TokenAmount::from_atto(6),
+    );
+    ch.set_rewards(rewards.clone());
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    // the manifest is forwarded to the subnet actor and the remainder
+    // distributed through the default reward method.
+    let mut ch = Checkpoint::new(shid.clone(), epoch + 9);
+    set_msg_meta(
+        rt.store(),
+        &mut ch,
+        "rand1".as_bytes().to_vec(),
+        fee.clone(),
+        fee.clone(),
+    );
+    ch.set_rewards(rewards.clone());
+    rt.expect_send(
+        shid.subnet_actor(),
+        SUBNET_ACTOR_DISTRIBUTE_REWARDS_METHOD,
+        IpldBlock::serialize_cbor(&DistributeRewardsParams { rewards }).unwrap(),
+        TokenAmount::from_atto(7),
+        None,
+        ExitCode::OK,
+    );
+    rt.expect_send(
+        shid.subnet_actor(),
+        SUBNET_ACTOR_REWARD_METHOD,
+        None,
+        TokenAmount::from_atto(3),
+        None,
+        ExitCode::OK,
+    );
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+    assert_eq!(h.get_pending_fee_distribution(&mut rt), TokenAmount::zero());
+}
+
+#[test]
+fn test_fund_inactive_subnet() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(17));
+
+    // unregistered subnets can't be funded
+    let unknown = SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO);
+    h.fund(
+        &mut rt,
+        &funder,
+        &unknown,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        amount.clone(),
+        0,
+        &TokenAmount::zero(),
+    )
+    .unwrap();
+
+    // nor subnets inactive for lack of stake
+    rt.set_balance(value.clone());
+    h.release_stake(&mut rt, &shid, &TokenAmount::from_atto(1), ExitCode::OK)
+        .unwrap();
+    assert_eq!(h.get_subnet(&rt, &shid).unwrap().status, Inactive);
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        amount.clone(),
+        0,
+        &TokenAmount::zero(),
+    )
+    .unwrap();
+    assert_eq!(h.get_subnet(&rt, &shid).unwrap().nonce, 0);
+
+    // once active again funds go through
+    h.add_stake(&mut rt, &shid, &TokenAmount::from_atto(1), ExitCode::OK)
+        .unwrap();
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
+
+    // killed subnets are no longer registered
+    h.register(&mut rt, &SUBNET_TWO, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO);
+    rt.set_balance(value.clone().mul(2) + &amount);
+    h.kill(&mut rt, &shid, &value, ExitCode::OK).unwrap();
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        amount,
+        0,
+        &TokenAmount::zero(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_fund_multi() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    h.register(&mut rt, &SUBNET_TWO, &value, ExitCode::OK)
+        .unwrap();
+    let sub_one = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let sub_two = SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO);
+    let sub_thr = SubnetID::new_from_parent(&h.net_name, *SUBNET_THR);
+
+    let funder = Address::new_id(1001);
+    let amount_one = TokenAmount::from_atto(10_u64.pow(18));
+    let amount_two = TokenAmount::from_atto(2 * 10_u64.pow(18));
+    let funds = vec![
+        (sub_one.clone(), amount_one.clone()),
+        (sub_two.clone(), amount_two.clone()),
+    ];
+    let total = &amount_one + &amount_two + CROSS_MSG_FEE.clone() * 2;
+
+    // the value must cover the funds and the fee of every destination
+    h.fund_multi(
+        &mut rt,
+        &funder,
+        &funds,
+        &amount_one + &amount_two,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+
+    h.fund_multi(&mut rt, &funder, &funds, total, ExitCode::OK)
+        .unwrap();
+    let subnet = h.get_subnet(&rt, &sub_one).unwrap();
+    assert_eq!(subnet.nonce, 1);
+    assert_eq!(subnet.circ_supply, amount_one);
+    let subnet = h.get_subnet(&rt, &sub_two).unwrap();
+    assert_eq!(subnet.nonce, 1);
+    assert_eq!(subnet.circ_supply, amount_two);
+    let st: State = rt.get_state();
+    assert_eq!(st.pending_fee_distribution, TokenAmount::zero());
+
+    // a single invalid destination rolls back the whole batch
+    let funds = vec![
+        (sub_one.clone(), amount_one.clone()),
+        (sub_thr, amount_two.clone()),
+    ];
+    let total = &amount_one + &amount_two + CROSS_MSG_FEE.clone() * 2;
+    h.fund_multi(
+        &mut rt,
+        &funder,
+        &funds,
+        total,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+    let subnet = h.get_subnet(&rt, &sub_one).unwrap();
+    assert_eq!(subnet.nonce, 1);
+    assert_eq!(subnet.circ_supply, amount_one);
+    let st: State = rt.get_state();
+    assert_eq!(st.pending_fee_distribution, TokenAmount::zero());
+}
+
+#[test]
+fn test_fund_with_target() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let funder = Address::new_id(1001);
+    let target = TokenAmount::from_atto(10_u64.pow(17));
+
+    // underpaying the target and the fee is rejected
+    h.fund_with_target(
+        &mut rt,
+        &funder,
+        &shid,
+        target.clone(),
+        target.clone(),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+
+    // paying exactly the target and the fee
+    h.fund_with_target(
+        &mut rt,
+        &funder,
+        &shid,
+        &target + &*CROSS_MSG_FEE,
+        target.clone(),
+        ExitCode::OK,
+    )
+    .unwrap();
+    let subnet = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(subnet.nonce, 1);
+    assert_eq!(subnet.circ_supply, target);
+
+    // overpaying refunds the surplus, so only the target is funded
+    h.fund_with_target(
+        &mut rt,
+        &funder,
+        &shid,
+        target.clone() * 3 + &*CROSS_MSG_FEE,
+        target.clone(),
+        ExitCode::OK,
+    )
+    .unwrap();
+    let subnet = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(subnet.nonce, 2);
+    assert_eq!(subnet.circ_supply, target.clone() * 2);
+    let crossmsgs = subnet.top_down_msgs.load(rt.store()).unwrap();
+    let msg = get_topdown_msg(&crossmsgs, 1).unwrap().unwrap();
+    assert_eq!(msg.value, target);
+}
+
+#[test]
+fn test_fund_on_behalf() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let funder = Address::new_id(1001);
+    let beneficiary = Address::new_bls(&[5; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let amount = TokenAmount::from_atto(10_u64.pow(17));
+
+    // ID addresses can't be used as beneficiaries
+    h.fund_on_behalf(
+        &mut rt,
+        &funder,
+        &shid,
+        &Address::new_id(1002),
+        amount.clone(),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+
+    h.fund_on_behalf(
+        &mut rt,
+        &funder,
+        &shid,
+        &beneficiary,
+        amount.clone(),
+        ExitCode::OK,
+    )
+    .unwrap();
+
+    // the message is wrapped, sent by the funder and credits the beneficiary
+    let subnet = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(subnet.nonce, 1);
+    assert_eq!(subnet.circ_supply, amount);
+    let crossmsgs = subnet.top_down_msgs.load(rt.store()).unwrap();
+    let cross_msg = crossmsgs.get(0).unwrap().unwrap().clone();
+    assert!(cross_msg.wrapped);
+    assert_eq!(
+        cross_msg.msg.from,
+        IPCAddress::new(&h.net_name, &TEST_BLS).unwrap()
+    );
+    assert_eq!(
+        cross_msg.msg.to,
+        IPCAddress::new(&shid, &beneficiary).unwrap()
+    );
+    assert_eq!(cross_msg.msg.value, amount);
+
+    // when applied in the subnet the funds go to the beneficiary, along with
+    // the original message.
+    let (_, mut rt) = setup(shid.clone());
+    rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
+    expect_validate_apply_caller(&mut rt);
+    rt.set_balance(amount.clone());
+    rt.expect_send(
+        REWARD_ACTOR_ADDR,
+        ext::reward::EXTERNAL_FUNDING_METHOD,
+        IpldBlock::serialize_cbor(&ext::reward::FundingParams {
+            addr: *ACTOR,
+            value: amount.clone(),
+        })
+        .unwrap(),
+        TokenAmount::zero(),
+        None,
+        ExitCode::OK,
+    );
+    rt.expect_send(
+        beneficiary,
+        METHOD_SEND,
+        IpldBlock::serialize_cbor(&ApplyMsgParams {
+            cross_msg: cross_msg.clone(),
+        })
+        .unwrap(),
+        amount.clone(),
+        None,
+        ExitCode::OK,
+    );
+    rt.call::<Actor>(
+        Method::ApplyMessage as MethodNum,
+        IpldBlock::serialize_cbor(&ApplyMsgParams { cross_msg }).unwrap(),
+    )
+    .unwrap();
+    rt.verify();
+}
+
+#[test]
+fn test_cancel_fund() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(18));
+    let fee = h.cross_msg_fees.fund.clone();
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        2,
+        &(amount.clone() * 2),
+    )
+    .unwrap();
+
+    // a checkpoint can't report more messages applied than committed
+    let mut ch = Checkpoint::new(shid.clone(), 10);
+    ch.data.applied_topdown_nonce = 3;
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    // cancel a message that doesn't exist
+    h.cancel_fund(&mut rt, &funder, &shid, 5, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    // the cancellation is only requested, the message stays in the queue
+    h.cancel_fund(&mut rt, &funder, &shid, 1, ExitCode::OK)
+        .unwrap();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.cancel_requests, vec![1]);
+    assert_eq!(sub.circ_supply, amount.clone() * 2);
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    assert!(get_topdown_msg(&crossmsgs, 1).unwrap().is_some());
+
+    // and it can't be requested twice
+    h.cancel_fund(&mut rt, &funder, &shid, 1, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    // the subnet can only acknowledge the cancellation of requested messages
+    let mut ch = Checkpoint::new(shid.clone(), 10);
+    ch.data.applied_topdown_nonce = 2;
+    ch.data.cancelled_topdown_msgs = vec![0];
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+    // that it went past without applying them
+    ch.data.applied_topdown_nonce = 1;
+    ch.data.cancelled_topdown_msgs = vec![1];
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    // once acknowledged, the fee of the applied message is distributed and the
+    // value and fee of the skipped one are refunded to its sender
+    ch.data.applied_topdown_nonce = 2;
+    rt.set_balance(&value + (&amount + &fee) * 2);
+    rt.expect_send(
+        shid.subnet_actor(),
+        SUBNET_ACTOR_REWARD_METHOD,
+        None,
+        fee.clone(),
+        None,
+        ExitCode::OK,
+    );
+    rt.expect_send(
+        *TEST_BLS,
+        METHOD_SEND,
+        None,
+        &amount + &fee,
+        None,
+        ExitCode::OK,
+    );
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.circ_supply, amount);
+    assert_eq!(sub.applied_topdown_nonce, 2);
+    assert!(sub.cancel_requests.is_empty());
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    assert!(get_topdown_msg(&crossmsgs, 1).unwrap().is_none());
+    assert_eq!(h.get_pending_fee_distribution(&mut rt), TokenAmount::zero());
+
+    // messages reported as applied can't be cancelled
+    h.cancel_fund(&mut rt, &funder, &shid, 0, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+    h.cancel_fund(&mut rt, &funder, &shid, 1, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+}
+
+#[test]
+fn test_skip_topdown_msg() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+    let ff = IPCAddress::new(&ROOTNET_ID, &TEST_BLS).unwrap();
+    let tt = IPCAddress::new(&shid, &to).unwrap();
+    let msg = |nonce| StorableMsg {
+        to: tt.clone(),
+        from: ff.clone(),
+        method: METHOD_SEND,
+        value: value.clone(),
+        params: RawBytes::default(),
+        nonce,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
+    };
+
+    // only the next top-down message can be skipped
+    h.skip_topdown_msg(&mut rt, msg(1), ExitCode::USR_ILLEGAL_STATE)
+        .unwrap();
+    // and only top-down messages
+    let bottomup = StorableMsg {
+        from: IPCAddress::new(&SubnetID::new_from_parent(&shid, *SUBNET_ONE), &to).unwrap(),
+        ..msg(0)
+    };
+    h.skip_topdown_msg(&mut rt, bottomup, ExitCode::USR_ILLEGAL_ARGUMENT)
+        .unwrap();
+
+    // the skipped message is reported in the checkpoint of the window
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    h.skip_topdown_msg(&mut rt, msg(0), ExitCode::OK).unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 1);
+    let ch = st.get_window_checkpoint(rt.store(), epoch).unwrap();
+    assert_eq!(ch.data.cancelled_topdown_msgs, vec![0]);
+
+    // messages already attempted had their funds minted, so they can't be
+    // skipped anymore
+    let v = value.clone();
+    let results = h
+        .apply_cross_msgs(
+            &mut rt,
+            value.clone(),
+            vec![msg(1)],
+            Some(Box::new(move |rt| {
+                rt.expect_send(
+                    REWARD_ACTOR_ADDR,
+                    ext::reward::EXTERNAL_FUNDING_METHOD,
+                    IpldBlock::serialize_cbor(&ext::reward::FundingParams {
+                        addr: *ACTOR,
+                        value: v.clone(),
+                    })
+                    .unwrap(),
+                    TokenAmount::zero(),
+                    None,
+                    ExitCode::OK,
+                );
+                rt.expect_send(
+                    to,
+                    METHOD_SEND,
+                    None,
+                    v.clone(),
+                    None,
+                    ExitCode::USR_UNHANDLED_MESSAGE,
+                );
+            })),
+        )
+        .unwrap();
+    assert_eq!(results[0].exit_code, ExitCode::OK);
+    h.skip_topdown_msg(&mut rt, msg(1), ExitCode::USR_ILLEGAL_STATE)
+        .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 1);
+}
+
+#[test]
+fn test_set_applied_topdown_nonce() {
+    let (_, mut rt) = setup_root();
+
+    let set_nonce = |rt: &mut MockRuntime, caller: &Address, nonce: u64, code: ExitCode| {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+        let ret = rt.call::<Actor>(
+            Method::SetAppliedTopDownNonce as MethodNum,
+            IpldBlock::serialize_cbor(&nonce).unwrap(),
+        );
+        if code == ExitCode::OK {
+            ret.unwrap();
+        } else {
+            expect_abort(code, ret);
+        }
+        rt.verify();
+    };
+
+    // only the governor can move the nonce
+    set_nonce(&mut rt, &Address::new_id(1001), 5, ExitCode::USR_FORBIDDEN);
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 0);
+
+    // forward adjustment works
+    set_nonce(&mut rt, &GOVERNOR, 5, ExitCode::OK);
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 5);
+
+    // backward or no-op adjustments are rejected
+    set_nonce(&mut rt, &GOVERNOR, 3, ExitCode::USR_ILLEGAL_ARGUMENT);
+    set_nonce(&mut rt, &GOVERNOR, 5, ExitCode::USR_ILLEGAL_ARGUMENT);
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 5);
+}
+
+#[test]
+fn test_cross_msg_status_topdown() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    assert_eq!(
+        h.get_cross_msg_status(&mut rt, &Cid::default(), Some((shid.clone(), 0)), None),
+        CrossMsgStatus::NotFound
+    );
+
+    // the fund message is committed for the child subnet
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(18));
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    let cid = crossmsgs.get(0).unwrap().unwrap().cid().unwrap();
+    assert_eq!(
+        h.get_cross_msg_status(&mut rt, &cid, Some((shid.clone(), 0)), None),
+        CrossMsgStatus::TopDownCommitted {
+            subnet: shid.clone(),
+            nonce: 0
+        }
+    );
+    // the message is only looked up where the caller says it was committed
+    assert_eq!(
+        h.get_cross_msg_status(&mut rt, &cid, Some((shid.clone(), 1)), None),
+        CrossMsgStatus::NotFound
+    );
+
+    // the gateway's own watermark tracks messages applied from its parent,
+    // so it doesn't mark messages to the child as applied
+    rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *GOVERNOR);
+    rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+    rt.call::<Actor>(
+        Method::SetAppliedTopDownNonce as MethodNum,
+        IpldBlock::serialize_cbor(&1_u64).unwrap(),
+    )
+    .unwrap();
+    rt.verify();
+    assert_eq!(
+        h.get_cross_msg_status(&mut rt, &cid, Some((shid.clone(), 0)), None),
+        CrossMsgStatus::TopDownCommitted {
+            subnet: shid.clone(),
+            nonce: 0
+        }
+    );
+
+    // but it is applied once the child reports it in a checkpoint, which
+    // releases the fee held for it
+    let mut ch = Checkpoint::new(shid.clone(), 10);
+    ch.data.applied_topdown_nonce = 1;
+    rt.expect_send(
+        shid.subnet_actor(),
+        SUBNET_ACTOR_REWARD_METHOD,
+        None,
+        h.cross_msg_fees.fund.clone(),
+        None,
+        ExitCode::OK,
+    );
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+    assert_eq!(
+        h.get_cross_msg_status(&mut rt, &cid, Some((shid.clone(), 0)), None),
+        CrossMsgStatus::TopDownApplied {
+            subnet: shid,
+            nonce: 0
+        }
+    );
+}
+
+#[test]
+fn test_release() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+
+    let releaser = Address::new_id(1001);
+    // Release funds
+    let r_amount = TokenAmount::from_atto(5_u64.pow(18));
+    rt.set_balance(2 * r_amount.clone());
+    assert_eq!(h.get_total_burned(&mut rt), TokenAmount::zero());
+    let prev_cid = h
+        .release(
+            &mut rt,
+            &releaser,
+            ExitCode::OK,
+            r_amount.clone(),
+            0,
+            &Cid::default(),
+            CROSS_MSG_FEE.clone(),
+        )
+        .unwrap();
+    // the funds released are burnt
+    assert_eq!(h.get_total_burned(&mut rt), r_amount);
+    h.release(
+        &mut rt,
+        &releaser,
+        ExitCode::OK,
+        r_amount.clone(),
+        1,
+        &prev_cid,
+        2 * CROSS_MSG_FEE.clone(),
+    )
+    .unwrap();
+    assert_eq!(h.get_total_burned(&mut rt), 2 * r_amount);
+}
+
+#[test]
+fn test_get_bottomup_msg() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid);
+
+    let releaser = Address::new_id(1001);
+    let r_amount = TokenAmount::from_atto(5_u64.pow(18));
+    rt.set_balance(3 * r_amount.clone());
+    let prev_cid = h
+        .release(
+            &mut rt,
+            &releaser,
+            ExitCode::OK,
+            r_amount.clone(),
+            0,
+            &Cid::default(),
+            CROSS_MSG_FEE.clone(),
+        )
+        .unwrap();
+    h.release(
+        &mut rt,
+        &releaser,
+        ExitCode::OK,
+        2 * r_amount.clone(),
+        1,
+        &prev_cid,
+        2 * CROSS_MSG_FEE.clone(),
+    )
+    .unwrap();
+
+    // messages are found by any epoch of the window they were committed in
+    let msg = h.get_bottomup_msg(&mut rt, 0, 0).unwrap();
+    assert_eq!(msg.msg.nonce, 0);
+    assert_eq!(msg.msg.value, r_amount);
+    let window = h
+        .get_bottomup_msg(&mut rt, DEFAULT_CHECKPOINT_PERIOD - 1, 1)
+        .unwrap();
+    assert_eq!(window.msg.nonce, 1);
+    assert_eq!(window.msg.value, 2 * r_amount);
+
+    assert!(h.get_bottomup_msg(&mut rt, 0, 2).is_none());
+    assert!(h
+        .get_bottomup_msg(&mut rt, DEFAULT_CHECKPOINT_PERIOD, 0)
+        .is_none());
+}
+
+#[test]
+fn test_get_actor_info() {
+    let mut h = new_harness(ROOTNET_ID.clone());
+    h.checkpoint_retention = 10;
+    let mut rt = new_runtime();
+    h.construct_and_verify(&mut rt);
+
+    let info: ActorInfo = h.get_actor_info(&mut rt);
+    assert_eq!(info.state_version, STATE_VERSION);
+    assert_eq!(info.methods, SUPPORTED_METHODS.to_vec());
+    assert_eq!(info.features, vec![String::from("checkpoint-pruning")]);
+
+    // every reported method is routed by the dispatch table. Malformed params
+    // make the call fail before reaching the method implementation.
+    let malformed = IpldBlock {
+        codec: fvm_ipld_encoding::DAG_CBOR,
+        data: vec![0xff],
+    };
+    for method in info.methods.iter() {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        let err = rt
+            .call::<Actor>(*method, Some(malformed.clone()))
+            .unwrap_err();
+        assert_ne!(err.exit_code(), ExitCode::USR_UNHANDLED_MESSAGE);
+    }
+
+    // methods not reported are not handled
+    let unknown = frc42_dispatch::method_hash!("UnknownMethod");
+    assert!(!info.methods.contains(&unknown));
+    rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+    let err = rt.call::<Actor>(unknown, Some(malformed)).unwrap_err();
+    assert_eq!(err.exit_code(), ExitCode::USR_UNHANDLED_MESSAGE);
+}
+
+/// Lists the number of every `Method`. It fails to compile if a method is
+/// missing from the list.
+macro_rules! method_numbers {
+    ($($method:ident),* $(,)?) => {{
+        fn _exhaustive(m: Method) {
+            match m {
+                $(Method::$method)|* => {}
+            }
+        }
+        vec![$(Method::$method as MethodNum),*]
+    }};
+}
+
+#[test]
+fn test_method_numbers() {
+    // every supported method is reported once and maps to a `Method`
+    let mut methods = SUPPORTED_METHODS.to_vec();
+    methods.sort_unstable();
+    methods.dedup();
+    assert_eq!(methods.len(), SUPPORTED_METHODS.len());
+    for method in SUPPORTED_METHODS {
+        assert!(Method::from_u64(*method).is_some(), "method {}", method);
+    }
+
+    // and every `Method` is supported
+    let mut all = method_numbers!(
+        Constructor,
+        Register,
+        AddStake,
+        ReleaseStake,
+        Kill,
+        CommitChildCheckpoint,
+        Fund,
+        FundMulti,
+        Release,
+        ReleaseToSubnet,
+        SendCross,
+        ApplyMessage,
+        Propagate,
+        WhiteListPropagator,
+        ReplacePostboxOwners,
+        GetPrevCheckpoint,
+        GetLastCommittedEpoch,
+        CancelFund,
+        BulkRegister,
+        GetPendingFeeDistribution,
+        AllowRegistration,
+        DisallowRegistration,
+        SetAppliedTopDownNonce,
+        ListDeadLetters,
+        RetryDeadLetter,
+        RefundDeadLetter,
+        MigrateState,
+        GetFeeSchedule,
+        FreezeSubnet,
+        UnfreezeSubnet,
+        DryRunCrossMsg,
+        GetTotalBurned,
+        GetQueueDepths,
+        PruneCheckpoints,
+        GetActorInfo,
+        GetCrossMsgStatus,
+        GetCollateralization,
+        FundWithMemo,
+        AddFeeExemption,
+        RemoveFeeExemption,
+        PropagateBatch,
+        ReactivateSubnet,
+        SetSafeMode,
+        ComputeSubnetID,
+        ForceKill,
+        GetGatewayStats,
+        FundWithTarget,
+        ApplyMessages,
+        GetAncestry,
+        GetTotalFeesCollected,
+        GetStateRoot,
+        SetAllowReregister,
+        AdjustCircSupply,
+        SetGovernors,
+        ProposeGovAction,
+        ApproveGovAction,
+        GetBottomUpMsg,
+        SetMaxTopDownValue,
+        FundOnBehalf,
+        SkipTopDownMsg,
+    );
+    all.sort_unstable();
+    assert_eq!(methods, all);
+
+    // exported methods are in the FRC-42 range, so they can't collide with the
+    // reserved method numbers.
+    for method in SUPPORTED_METHODS {
+        if *method != Method::Constructor as MethodNum {
+            assert!(*method >= 1 << 24, "method {}", method);
+        }
+    }
+}
+
+#[test]
+fn test_queue_depths() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+    let depths = |topdown, bottomup| QueueDepths { topdown, bottomup };
+    assert_eq!(h.get_queue_depths(&mut rt), depths(0, 0));
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_TWO, &value, ExitCode::OK)
+        .unwrap();
+    let child = SubnetID::new_from_parent(&shid, *SUBNET_TWO);
+
+    // fund commits top-down messages for the child
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(18));
+    h.fund(
+        &mut rt,
+        &funder,
+        &child,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
+    h.fund(
+        &mut rt,
+        &funder,
+        &child,
+        ExitCode::OK,
+        amount.clone(),
+        2,
+        &(2 * amount.clone()),
+    )
+    .unwrap();
+    assert_eq!(h.get_queue_depths(&mut rt), depths(2, 0));
+
+    // release commits a bottom-up message in the current window
+    rt.set_balance(amount.clone());
+    h.release(
+        &mut rt,
+        &funder,
+        ExitCode::OK,
+        amount.clone(),
+        0,
+        &Cid::default(),
+        CROSS_MSG_FEE.clone(),
+    )
+    .unwrap();
+    assert_eq!(h.get_queue_depths(&mut rt), depths(2, 1));
+
+    // applying a top-down message from the parent doesn't affect the
+    // messages pending for the child
+    let from = IPCAddress::new(&ROOTNET_ID, &TEST_BLS).unwrap();
+    let to = IPCAddress::new(&shid, &TEST_BLS).unwrap();
+    h.apply_cross_msg(&mut rt, &from, &to, amount, 0, 0, ExitCode::OK)
+        .unwrap();
+    assert_eq!(h.get_queue_depths(&mut rt), depths(2, 1));
+
+    // the child reports the messages it applied in its checkpoints
+    let mut ch = Checkpoint::new(child.clone(), 10);
+    ch.data.applied_topdown_nonce = 1;
+    rt.set_balance(h.cross_msg_fees.fund.clone());
+    rt.expect_send(
+        child.subnet_actor(),
+        SUBNET_ACTOR_REWARD_METHOD,
+        None,
+        h.cross_msg_fees.fund.clone(),
+        None,
+        ExitCode::OK,
+    );
+    h.commit_child_check(&mut rt, &child, &ch, ExitCode::OK)
+        .unwrap();
+    assert_eq!(h.get_queue_depths(&mut rt), depths(1, 1));
+}
+
+#[test]
+fn test_gateway_stats() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+    let stats = h.get_gateway_stats(&mut rt);
+    assert_eq!(stats.total_subnets, 0);
+    assert_eq!(stats.total_stake, TokenAmount::zero());
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_TWO, &value, ExitCode::OK)
+        .unwrap();
+    h.register(&mut rt, &SUBNET_THR, &(2 * value.clone()), ExitCode::OK)
+        .unwrap();
+    let child = SubnetID::new_from_parent(&shid, *SUBNET_TWO);
+
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(17));
+    h.fund(
+        &mut rt,
+        &funder,
+        &child,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
+    rt.set_balance(amount.clone());
+    h.release(
+        &mut rt,
+        &funder,
+        ExitCode::OK,
+        amount.clone(),
+        0,
+        &Cid::default(),
+        CROSS_MSG_FEE.clone(),
+    )
+    .unwrap();
+
+    let st: State = rt.get_state();
+    let stats = h.get_gateway_stats(&mut rt);
+    assert_eq!(
+        stats,
+        GatewayStats {
+            total_subnets: 2,
+            total_stake: 3 * value,
+            total_circ_supply: amount.clone(),
+            queue_depths: QueueDepths {
+                topdown: 1,
+                bottomup: 1
+            },
+            total_burned: amount,
+            applied_topdown_nonce: st.applied_topdown_nonce,
+            applied_bottomup_nonce: st.applied_bottomup_nonce,
+        }
+    );
+    assert_eq!(stats.total_subnets, st.total_subnets);
+    assert_eq!(stats.total_burned, st.total_burned);
+    assert_eq!(stats.queue_depths, h.get_queue_depths(&mut rt));
+
+    // top-down messages are no longer pending once the child reports them
+    let mut ch = Checkpoint::new(child.clone(), 10);
+    ch.data.applied_topdown_nonce = 1;
+    rt.set_balance(h.cross_msg_fees.fund.clone());
+    rt.expect_send(
+        child.subnet_actor(),
+        SUBNET_ACTOR_REWARD_METHOD,
+        None,
+        h.cross_msg_fees.fund.clone(),
+        None,
+        ExitCode::OK,
+    );
+    h.commit_child_check(&mut rt, &child, &ch, ExitCode::OK)
+        .unwrap();
+    let stats = h.get_gateway_stats(&mut rt);
+    assert_eq!(stats.queue_depths.topdown, 0);
+    assert_eq!(stats.queue_depths, h.get_queue_depths(&mut rt));
+}
+
+#[test]
+fn test_release_to_subnet() {
+    let parent = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let shid = SubnetID::new_from_parent(&parent, *SUBNET_TWO);
+    let (h, mut rt) = setup(shid.clone());
+
+    let releaser = Address::new_id(1001);
+    let r_amount = TokenAmount::from_atto(5_u64.pow(18));
+    rt.set_balance(2 * r_amount.clone());
+
+    // the target needs to be a strict ancestor
+    let sibling = SubnetID::new_from_parent(&parent, *SUBNET_THR);
+    for target in [&shid, &sibling] {
+        h.release_to_subnet(
+            &mut rt,
+            &releaser,
+            target,
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            r_amount.clone(),
+        )
+        .unwrap();
+    }
+
+    // release two levels up, to the root
+    h.release_to_subnet(
+        &mut rt,
+        &releaser,
+        &ROOTNET_ID,
+        ExitCode::OK,
+        r_amount.clone(),
+    )
+    .unwrap();
+
+    let st: State = rt.get_state();
+    let ch = st.get_window_checkpoint(rt.store(), 0).unwrap();
+    let chmeta = ch.cross_msgs().unwrap();
+    assert_eq!(chmeta.num_msgs, 1);
+    let cross_reg = st.check_msg_registry.load(rt.store()).unwrap();
+    let meta = get_cross_msgs(&cross_reg, &chmeta.msgs_cid.cid())
+        .unwrap()
+        .unwrap();
+    let msg = &meta.msgs[0].msg;
+    assert_eq!(
+        msg.from,
+        IPCAddress::new(&shid, &BURNT_FUNDS_ACTOR_ADDR).unwrap()
+    );
+    assert_eq!(msg.to, IPCAddress::new(&ROOTNET_ID, &TEST_BLS).unwrap());
+    assert_eq!(msg.value, r_amount);
+    // the immediate parent forwards the message further up
+    assert_eq!(
+        route_cross_msg(
+            &parent,
+            &msg.from.subnet().unwrap(),
+            &msg.to.subnet().unwrap()
+        ),
+        RouteDecision::CommitBottomUp
+    );
+}
+
+#[test]
+fn test_send_cross() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+
+    let from = Address::new_id(1001);
+    let to = Address::new_id(1002);
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+
+    // register subnet
+    let reg_value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &reg_value, ExitCode::OK)
+        .unwrap();
+
+    // top-down
+    let sub = SubnetID::from_str("/root/f0101/f0101").unwrap();
+    h.send_cross(
+        &mut rt,
+        &from,
+        &shid,
+        &to,
+        sub,
+        ExitCode::OK,
+        value.clone(),
+        1,
+        &value,
+    )
+    .unwrap();
+    let sub = SubnetID::from_str("/root/f0101/f0101").unwrap();
+    let circ_sup = 2 * &value;
+    h.send_cross(
+        &mut rt,
+        &from,
+        &shid,
+        &to,
+        sub,
+        ExitCode::OK,
+        value.clone(),
+        2,
+        &circ_sup,
+    )
+    .unwrap();
+    let sub = SubnetID::from_str("/root/f0101/f0101/f01002").unwrap();
+    let circ_sup = circ_sup.clone() + &value;
+    h.send_cross(
+        &mut rt,
+        &from,
+        &shid,
+        &to,
+        sub,
+        ExitCode::OK,
+        value.clone(),
+        3,
+        &circ_sup,
+    )
+    .unwrap();
+
+    // bottom-up
+    rt.set_balance(3 * &value);
+    let sub = SubnetID::from_str("/root/f0102/f0101").unwrap();
+    let zero = TokenAmount::zero();
+    h.send_cross(
+        &mut rt,
+        &from,
+        &shid,
+        &to,
+        sub,
+        ExitCode::OK,
+        value.clone(),
+        0,
+        &zero,
+    )
+    .unwrap();
+    let sub = SubnetID::from_str("/root/f0102/f0101").unwrap();
+    h.send_cross(
+        &mut rt,
+        &from,
+        &shid,
+        &to,
+        sub,
+        ExitCode::OK,
+        value.clone(),
+        1,
+        &zero,
+    )
+    .unwrap();
+    let sub = SubnetID::from_str("/root").unwrap();
+    h.send_cross(
+        &mut rt,
+        &from,
+        &shid,
+        &to,
+        sub,
+        ExitCode::OK,
+        value.clone(),
+        2,
+        &zero,
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_correlation_id_identical_sends() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+
+    let from = Address::new_id(1001);
+    let to = Address::new_id(1002);
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+
+    // the same message is sent twice
+    let sub = SubnetID::from_str("/root/f0101/f0101").unwrap();
+    for nonce in 1..=2 {
+        h.send_cross(
+            &mut rt,
+            &from,
+            &shid,
+            &to,
+            sub.clone(),
+            ExitCode::OK,
+            value.clone(),
+            nonce,
+            &TokenAmount::from_atto(nonce * 10_u64.pow(18)),
+        )
+        .unwrap();
+    }
+
+    let sub = h.get_subnet(&rt, &sub).unwrap();
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    let first = get_topdown_msg(&crossmsgs, 0).unwrap().unwrap();
+    let second = get_topdown_msg(&crossmsgs, 1).unwrap().unwrap();
+    assert_eq!(first.from, second.from);
+    assert_eq!(first.to, second.to);
+    assert_eq!(first.value, second.value);
+
+    // but each of them gets its own id in the origin
+    let id = |msg: &StorableMsg| {
+        CrossMsg {
+            msg: msg.clone(),
+            wrapped: false,
+        }
+        .correlation_id()
+        .unwrap()
+    };
+    assert!(first.correlation_id.is_some());
+    assert!(second.correlation_id.is_some());
+    assert_ne!(id(first), id(second));
+}
+
+#[test]
+fn test_send_cross_require_actor_destination() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let from = Address::new_id(1001);
+    let actor = Address::new_id(1002);
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    let zero = TokenAmount::zero();
+
+    for require_actor_destination in [false, true] {
+        let mut h = new_harness(shid.clone());
+        h.require_actor_destination = require_actor_destination;
+        let mut rt = new_runtime();
+        h.construct_and_verify(&mut rt);
+        rt.set_balance(2 * &value);
+
+        // actor destinations are always accepted
+        h.send_cross(
+            &mut rt,
+            &from,
+            &shid,
+            &actor,
+            ROOTNET_ID.clone(),
+            ExitCode::OK,
+            value.clone(),
+            0,
+            &zero,
+        )
+        .unwrap();
+
+        // account destinations only if the gateway doesn't require an actor
+        if require_actor_destination {
+            h.send_cross(
+                &mut rt,
+                &from,
+                &shid,
+                &TEST_BLS,
+                ROOTNET_ID.clone(),
+                ExitCode::USR_ILLEGAL_ARGUMENT,
+                value.clone(),
+                1,
+                &zero,
+            )
+            .unwrap();
+        } else {
+            h.send_cross(
+                &mut rt,
+                &from,
+                &shid,
+                &TEST_BLS,
+                ROOTNET_ID.clone(),
+                ExitCode::OK,
+                value.clone(),
+                1,
+                &zero,
+            )
+            .unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_send_cross_local() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let to = Address::new_id(1002);
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+
+    for allow_local_cross in [false, true] {
+        let mut h = new_harness(shid.clone());
+        h.allow_local_cross = allow_local_cross;
+        let mut rt = new_runtime();
+        h.construct_and_verify(&mut rt);
+        rt.set_balance(value.clone());
+
+        rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
+        rt.expect_validate_caller_not_type(SIG_TYPES.clone());
+        rt.set_value(value.clone());
+        let params = CrossMsgParams {
+            destination: shid.clone(),
+            cross_msg: CrossMsg {
+                msg: StorableMsg {
+                    from: IPCAddress::new(&shid, &SYSTEM_ACTOR_ADDR).unwrap(),
+                    to: IPCAddress::new(&shid, &to).unwrap(),
+                    method: METHOD_SEND,
+                    params: RawBytes::default(),
+                    value: value.clone(),
+                    nonce: 0,
+                    memo: Vec::new(),
+                    valid_until_epoch: None,
+                    correlation_id: None,
+                },
+                wrapped: false,
+            },
+        };
+        let params = IpldBlock::serialize_cbor(&params).unwrap();
+
+        if !allow_local_cross {
+            // same-subnet cross-messages are rejected by default
+            expect_abort(
+                ExitCode::USR_ILLEGAL_ARGUMENT,
+                rt.call::<Actor>(Method::SendCross as MethodNum, params),
+            );
+            rt.verify();
+            continue;
+        }
+
+        // the message is delivered locally without charging any fee
+        rt.expect_send(to, METHOD_SEND, None, value.clone(), None, ExitCode::OK);
+        rt.call::<Actor>(Method::SendCross as MethodNum, params)
+            .unwrap();
+        rt.verify();
+        let st: State = rt.get_state();
+        assert_eq!(st.nonce, 0);
+        assert_eq!(st.bottomup_nonce, 0);
+        assert_eq!(st.pending_fee_distribution, TokenAmount::zero());
+    }
+}
+
+#[test]
+fn test_send_cross_to_self() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let mut h = new_harness(shid.clone());
+    h.allow_local_cross = true;
+    let mut rt = new_runtime();
+    h.construct_and_verify(&mut rt);
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    rt.set_balance(value.clone());
+
+    // a cross-message whose resolved from and to are the same is rejected
+    rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
+    rt.expect_validate_caller_not_type(SIG_TYPES.clone());
+    rt.set_value(value.clone());
+    let params = CrossMsgParams {
+        destination: shid.clone(),
+        cross_msg: CrossMsg {
+            msg: StorableMsg {
+                from: IPCAddress::new(&shid, &SYSTEM_ACTOR_ADDR).unwrap(),
+                to: IPCAddress::new(&shid, &SYSTEM_ACTOR_ADDR).unwrap(),
+                method: METHOD_SEND,
+                params: RawBytes::default(),
+                value: value.clone(),
+                nonce: 0,
+                memo: Vec::new(),
+                valid_until_epoch: None,
+                correlation_id: None,
+            },
+            wrapped: false,
+        },
+    };
+    expect_abort(
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        rt.call::<Actor>(
+            Method::SendCross as MethodNum,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        ),
+    );
+    rt.verify();
+}
+
+#[test]
+fn test_send_cross_surplus_policy() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let mut h = new_harness(shid.clone());
+    h.send_cross_fee_policy = SendCrossFeePolicy::Surplus;
+    let mut rt = new_runtime();
+    h.construct_and_verify(&mut rt);
+
+    let to = Address::new_id(1002);
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    let dest = SubnetID::from_str("/root/f0102/f0101").unwrap();
+    rt.set_balance(10 * &value);
+
+    let mut send_cross = |received: TokenAmount, code: ExitCode, refund: TokenAmount| {
+        rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
+        rt.expect_validate_caller_not_type(SIG_TYPES.clone());
+        rt.set_value(received);
+        let params = CrossMsgParams {
+            destination: dest.clone(),
+            cross_msg: CrossMsg {
+                msg: StorableMsg {
+                    from: IPCAddress::new(&shid, &SYSTEM_ACTOR_ADDR).unwrap(),
+                    to: IPCAddress::new(&dest, &to).unwrap(),
+                    method: METHOD_SEND,
+                    params: RawBytes::default(),
+                    value: value.clone(),
+                    nonce: 0,
+                    memo: Vec::new(),
+                    valid_until_epoch: None,
+                    correlation_id: None,
+                },
+                wrapped: false,
+            },
+        };
+        let params = IpldBlock::serialize_cbor(&params).unwrap();
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::SendCross as MethodNum, params),
+            );
+            rt.verify();
+            return;
+        }
+        // the value of the message is burnt in full, the fee is paid on top
+        rt.expect_send(
+            BURNT_FUNDS_ACTOR_ADDR,
+            METHOD_SEND,
+            None,
+            value.clone(),
+            None,
+            ExitCode::OK,
+        );
+        if !refund.is_zero() {
+            rt.expect_send(
+                SYSTEM_ACTOR_ADDR,
+                METHOD_SEND,
+                None,
+                refund,
+                None,
+                ExitCode::OK,
+            );
+        }
+        rt.call::<Actor>(Method::SendCross as MethodNum, params)
+            .unwrap();
+        rt.verify();
+    };
+
+    let fee = CROSS_MSG_FEE.clone();
+    let surplus = TokenAmount::from_atto(1000);
+    // exact payment
+    send_cross(&value + &fee, ExitCode::OK, TokenAmount::zero());
+    // overpayment is refunded
+    send_cross(&value + &fee + &surplus, ExitCode::OK, surplus);
+    // underpayment is rejected
+    send_cross(
+        &value + &fee - TokenAmount::from_atto(1),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        TokenAmount::zero(),
+    );
+
+    let st: State = rt.get_state();
+    let ch = st.get_window_checkpoint(rt.store(), 0).unwrap();
+    let chmeta = ch.cross_msgs().unwrap();
+    assert_eq!(chmeta.num_msgs, 2);
+    assert_eq!(chmeta.fee, 2 * fee);
+    assert_eq!(chmeta.msgs_value, 2 * value);
+}
+
+/// This test covers the case where a bottom up cross_msg's target subnet is the SAME as that of
+/// the gateway. It should directly commit the message and will not save in postbox.
+#[test]
+fn test_apply_msg_bu_target_subnet() {
+    // ============== Register subnet ==============
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(ROOTNET_ID.clone());
+
+    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+
+    let sub = shid.clone();
+
+    // ================ Setup ===============
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+
+    // ================= Bottom-Up ===============
+    let ff = IPCAddress::new(&sub, &to).unwrap();
+    let tt = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
+    let msg_nonce = 0;
+
+    // Only system code is allowed to this method
+    let params = StorableMsg {
+        to: tt.clone(),
+        from: ff.clone(),
+        method: METHOD_SEND,
+        value: value.clone(),
+        params: RawBytes::default(),
+        nonce: msg_nonce,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
+    };
+    let sto = tt.raw_addr().unwrap();
+
+    let cid = h
+        .apply_cross_execute_only(
+            &mut rt,
+            value.clone(),
+            params,
+            Some(Box::new(move |rt| {
+                rt.expect_send(
+                    sto.clone(),
+                    METHOD_SEND,
+                    None,
+                    value.clone(),
+                    None,
+                    ExitCode::OK,
+                );
+            })),
+        )
+        .unwrap();
+    assert_eq!(cid.is_none(), true);
+}
+
+fn bu_msg_to_root(value: &TokenAmount) -> (StorableMsg, Address) {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let msg = StorableMsg {
+        to: IPCAddress::new(&ROOTNET_ID, &to).unwrap(),
+        from: IPCAddress::new(&shid, &from).unwrap(),
+        method: METHOD_SEND,
+        value: value.clone(),
+        params: RawBytes::default(),
+        nonce: 0,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
+    };
+    (msg, to)
+}
+
+/// Production builds only accept the system actor as the caller of `ApplyMessage`.
+#[cfg(not(feature = "apply-simulation"))]
+#[test]
+fn test_apply_msg_strict_caller() {
+    let (_, mut rt) = setup(ROOTNET_ID.clone());
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+    let (msg, _) = bu_msg_to_root(&value);
+
+    rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+    rt.expect_validate_caller_addr(vec![fil_actors_runtime::SYSTEM_ACTOR_ADDR]);
+    expect_abort(
+        ExitCode::USR_FORBIDDEN,
+        rt.call::<Actor>(
+            Method::ApplyMessage as MethodNum,
+            IpldBlock::serialize_cbor(&ApplyMsgParams {
+                cross_msg: CrossMsg {
+                    msg,
+                    wrapped: false,
+                },
+            })
+            .unwrap(),
+        ),
+    );
+    rt.verify();
+}
+
+/// With the `apply-simulation` feature any caller can apply cross-messages.
+#[cfg(feature = "apply-simulation")]
+#[test]
+fn test_apply_msg_simulation_caller() {
+    let (_, mut rt) = setup(ROOTNET_ID.clone());
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+    let (msg, to) = bu_msg_to_root(&value);
+
+    rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+    rt.expect_validate_caller_any();
+    rt.set_balance(value.clone());
+    rt.expect_send(to, METHOD_SEND, None, value, None, ExitCode::OK);
+    rt.call::<Actor>(
+        Method::ApplyMessage as MethodNum,
+        IpldBlock::serialize_cbor(&ApplyMsgParams {
+            cross_msg: CrossMsg {
+                msg,
+                wrapped: false,
+            },
+        })
+        .unwrap(),
+    )
+    .unwrap();
+    rt.verify();
+
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_bottomup_nonce, 0);
+}
+
+/// This test covers bottom-up messages that fail to execute in the target subnet. After the
+/// maximum number of attempts they are moved to the dead-letter queue, from where they can
+/// be retried or refunded.
+#[test]
+fn test_apply_msg_dead_letter() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(ROOTNET_ID.clone());
+
+    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+
+    let ff = IPCAddress::new(&shid, &from).unwrap();
+    let tt = IPCAddress::new(&ROOTNET_ID, &to).unwrap();
+    let msg = |nonce| StorableMsg {
+        to: tt.clone(),
+        from: ff.clone(),
+        method: METHOD_SEND,
+        value: value.clone(),
+        params: RawBytes::default(),
+        nonce,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
+    };
+    let fail_send = |value: TokenAmount| -> Option<Box<dyn Fn(&mut MockRuntime)>> {
+        Some(Box::new(move |rt| {
+            rt.expect_send(
+                to,
+                METHOD_SEND,
+                None,
+                value.clone(),
+                None,
+                ExitCode::USR_UNHANDLED_MESSAGE,
+            );
+        }))
+    };
+
+    // the message is retried until the maximum number of attempts is reached
+    for nonce in 0..2 {
+        for attempt in 1..=DEFAULT_MAX_APPLY_ATTEMPTS {
+            h.apply_cross_execute_only(
+                &mut rt,
+                value.clone(),
+                msg(nonce),
+                fail_send(value.clone()),
+            )
+            .unwrap();
+            let st: State = rt.get_state();
+            if attempt < DEFAULT_MAX_APPLY_ATTEMPTS {
+                assert_eq!(
+                    st.applied_bottomup_nonce,
+                    if nonce == 0 { MAX_NONCE } else { nonce - 1 }
+                );
+            } else {
+                assert_eq!(st.applied_bottomup_nonce, nonce);
+            }
+        }
+    }
+
+    let dead_letters = h.list_dead_letters(&mut rt);
+    assert_eq!(dead_letters.len(), 2);
+    let cid_of = |nonce| {
+        CrossMsg {
+            msg: msg(nonce),
+            wrapped: false,
+        }
+        .cid()
+        .unwrap()
+    };
+    assert_eq!(
+        h.get_cross_msg_status(&mut rt, &cid_of(1), None, None),
+        CrossMsgStatus::Failed
+    );
+
+    // only the governor or the sender can handle dead letters
+    h.handle_dead_letter(
+        &mut rt,
+        &to,
+        Method::RefundDeadLetter,
+        &cid_of(0),
+        ExitCode::USR_FORBIDDEN,
+    )
+    .unwrap();
+
+    // the funds of the message are sent back to the sender in its subnet
+    // through the postbox
+    let ret = h
+        .handle_dead_letter(
+            &mut rt,
+            &from,
+            Method::RefundDeadLetter,
+            &cid_of(0),
+            ExitCode::OK,
+        )
+        .unwrap();
+    let postbox_cid = Cid::try_from(ret.to_vec().as_slice()).unwrap();
+    let st: State = rt.get_state();
+    let item = st.load_from_postbox(rt.store(), postbox_cid).unwrap();
+    assert_eq!(item.owners, None);
+    let refund = item.cross_msg.msg;
+    assert_eq!(
+        refund.from,
+        IPCAddress::new(&ROOTNET_ID, &BURNT_FUNDS_ACTOR_ADDR).unwrap()
+    );
+    assert_eq!(refund.to, ff);
+    assert_eq!(refund.value, value);
+    h.handle_dead_letter(
+        &mut rt,
+        &from,
+        Method::RefundDeadLetter,
+        &cid_of(0),
+        ExitCode::USR_NOT_FOUND,
+    )
+    .unwrap();
+
+    // once governors are set the single governor can't handle dead letters anymore
+    let governors: Vec<Address> = (901..903).map(Address::new_id).collect();
+    h.set_governors(&mut rt, &GOVERNOR, governors, 2, ExitCode::OK)
+        .unwrap();
+    h.handle_dead_letter(
+        &mut rt,
+        &GOVERNOR,
+        Method::RetryDeadLetter,
+        &cid_of(1),
+        ExitCode::USR_FORBIDDEN,
+    )
+    .unwrap();
+
+    // an approved governance action can retry the execution of the message
+    rt.expect_send(to, METHOD_SEND, None, value.clone(), None, ExitCode::OK);
+    h.handle_dead_letter(
+        &mut rt,
+        &ACTOR,
+        Method::RetryDeadLetter,
+        &cid_of(1),
+        ExitCode::OK,
+    )
+    .unwrap();
+    assert!(h.list_dead_letters(&mut rt).is_empty());
+    assert_eq!(
+        h.get_cross_msg_status(&mut rt, &cid_of(1), None, None),
+        CrossMsgStatus::NotFound
+    );
+}
+
+/// This test covers the case where a bottom up cross_msg's target subnet is NOT the same as that of
+/// the gateway. It will save it in the postbox.
+#[test]
+fn test_apply_msg_bu_not_target_subnet() {
+    // ============== Register subnet ==============
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+
+    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+
+    let sub = shid.clone();
+
+    // ================ Setup ===============
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+
+    // ================= Bottom-Up ===============
+    let ff = IPCAddress::new(&sub, &to).unwrap();
+    let tt = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
+    let msg_nonce = 0;
+
+    // Only system code is allowed to this method
+    let params = StorableMsg {
+        to: tt.clone(),
+        from: ff.clone(),
+        method: METHOD_SEND,
+        value: value.clone(),
+        params: RawBytes::default(),
+        nonce: msg_nonce,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
+    };
+    let cid = h
+        .apply_cross_execute_only(&mut rt, value.clone(), params.clone(), None)
+        .unwrap()
+        .unwrap();
+
+    // Part 1: test the message is stored in postbox
+    let st: State = rt.get_state();
+    assert_ne!(tt.subnet().unwrap(), st.network_name);
+
+    // Check 1: `tt` is in `sub1`, which is not in that of `runtime` of gateway, will store in postbox
+    let item = st.load_from_postbox(rt.store(), cid.clone()).unwrap();
+    assert_eq!(item.owners, Some(vec![ff.clone().raw_addr().unwrap()]));
+    let msg = item.cross_msg.msg;
+    assert_eq!(msg.to, tt);
+    // the nonce should not have changed at all
+    assert_eq!(msg.nonce, msg_nonce);
+    assert_eq!(msg.value, value);
+    // the message is tracked by the cid of its postbox item
+    assert_eq!(
+        h.get_cross_msg_status(&mut rt, &cid, None, None),
+        CrossMsgStatus::InPostbox
+    );
+
+    // Part 2: Now we propagate from postbox
+    // get the original subnet nonce first
+    let caller = ff.clone().raw_addr().unwrap();
+    let old_state: State = rt.get_state();
+    // propagating a bottom-up message triggers the
+    // funds included in the message to be burnt.
+    rt.expect_send(
+        BURNT_FUNDS_ACTOR_ADDR,
+        METHOD_SEND,
+        None,
+        params.clone().value,
+        None,
+        ExitCode::OK,
+    );
+    h.propagate(
+        &mut rt,
+        caller,
+        cid.clone(),
+        &params.value,
+        TokenAmount::zero(),
+    )
+    .unwrap();
+
+    // state should be updated, load again
+    let new_state: State = rt.get_state();
+
+    // cid should be removed from postbox
+    let r = new_state.load_from_postbox(rt.store(), cid.clone());
+    assert_eq!(r.is_err(), true);
+    let err = r.unwrap_err();
+    assert_eq!(err.to_string(), "cid not found in postbox");
+    assert_eq!(new_state.nonce, old_state.nonce + 1);
+
+    // and committed in the checkpoint of the current window
+    let ch = new_state
+        .get_window_checkpoint(rt.store(), rt.curr_epoch())
+        .unwrap();
+    let cross_reg = new_state.check_msg_registry.load(rt.store()).unwrap();
+    let meta = get_cross_msgs(&cross_reg, &ch.cross_msgs().unwrap().msgs_cid.cid())
+        .unwrap()
+        .unwrap();
+    let committed_cid = meta.msgs[0].cid().unwrap();
+    assert_eq!(
+        h.get_cross_msg_status(&mut rt, &committed_cid, None, Some(rt.curr_epoch())),
+        CrossMsgStatus::BottomUpCommitted { epoch: ch.epoch() }
+    );
+    assert_eq!(
+        h.get_cross_msg_status(&mut rt, &committed_cid, None, None),
+        CrossMsgStatus::NotFound
+    );
+    assert_eq!(
+        h.get_cross_msg_status(&mut rt, &cid, None, Some(rt.curr_epoch())),
+        CrossMsgStatus::NotFound
+    );
+}
+
+/// This test covers the case where the amount send in the propagate
+/// message exceeds the required fee and the remainder is returned
+/// to the caller.
+#[test]
+fn test_propagate_with_remainder() {
+    // ============== Register subnet ==============
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+
+    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+
+    let sub = shid.clone();
+
+    // ================ Setup ===============
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+
+    // ================= Bottom-Up ===============
+    let ff = IPCAddress::new(&sub, &to).unwrap();
+    let tt = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
+    let msg_nonce = 0;
+
+    // Only system code is allowed to this method
+    let params = StorableMsg {
+        to: tt.clone(),
+        from: ff.clone(),
+        method: METHOD_SEND,
+        value: value.clone(),
+        params: RawBytes::default(),
+        nonce: msg_nonce,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
+    };
+    let cid = h
+        .apply_cross_execute_only(&mut rt, value.clone(), params.clone(), None)
+        .unwrap()
+        .unwrap();
+
+    // Part 1: test the message is stored in postbox
+    let st: State = rt.get_state();
+    assert_ne!(tt.subnet().unwrap(), st.network_name);
+
+    // Check 1: `tt` is in `sub1`, which is not in that of `runtime` of gateway, will store in postbox
+    let item = st.load_from_postbox(rt.store(), cid.clone()).unwrap();
+    assert_eq!(item.owners, Some(vec![ff.clone().raw_addr().unwrap()]));
+    let msg = item.cross_msg.msg;
+    assert_eq!(msg.to, tt);
+    // the nonce should not have changed at all
+    assert_eq!(msg.nonce, msg_nonce);
+    assert_eq!(msg.value, value);
+
+    // Part 2: Now we propagate from postbox
+    // get the original subnet nonce first with an
+    // excess to check that there is a remainder
+    // to be returned
+    let caller = ff.clone().raw_addr().unwrap();
+    let old_state: State = rt.get_state();
+    // propagating a bottom-up message triggers the
+    // funds included in the message to be burnt.
+    rt.expect_send(
+        BURNT_FUNDS_ACTOR_ADDR,
+        METHOD_SEND,
+        None,
+        params.clone().value,
+        None,
+        ExitCode::OK,
+    );
+    h.propagate(&mut rt, caller, cid.clone(), &params.value, value.clone())
+        .unwrap();
+
+    // state should be updated, load again
+    let new_state: State = rt.get_state();
+
+    // cid should be removed from postbox
+    let r = new_state.load_from_postbox(rt.store(), cid.clone());
+    assert_eq!(r.is_err(), true);
+    let err = r.unwrap_err();
+    assert_eq!(err.to_string(), "cid not found in postbox");
+    assert_eq!(new_state.nonce, old_state.nonce + 1);
+}
+
+#[test]
+fn test_propagate_batch() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let owner = Address::new_id(1001);
+    let other = Address::new_id(1002);
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+
+    // store two bottom-up messages with different owners in the postbox
+    let tt = IPCAddress::new(&ROOTNET_ID, &to).unwrap();
+    let mut cids = Vec::new();
+    for (nonce, from) in [owner, other].into_iter().enumerate() {
+        let params = StorableMsg {
+            to: tt.clone(),
+            from: IPCAddress::new(&shid, &from).unwrap(),
+            method: METHOD_SEND,
+            value: value.clone(),
+            params: RawBytes::default(),
+            nonce: nonce as u64,
+            memo: Vec::new(),
+            valid_until_epoch: None,
+            correlation_id: None,
+        };
+        let cid = h
+            .apply_cross_execute_only(&mut rt, value.clone(), params, None)
+            .unwrap()
+            .unwrap();
+        cids.push(cid);
+    }
+
+    // an empty batch is rejected
+    rt.set_caller(Default::default(), owner);
+    rt.expect_validate_caller_any();
+    expect_abort(
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        rt.call::<Actor>(
+            Method::PropagateBatch as MethodNum,
+            IpldBlock::serialize_cbor(&PropagateBatchParams {
+                postbox_cids: vec![],
+            })
+            .unwrap(),
+        ),
+    );
+    rt.verify();
+
+    // only the item owned by the caller is propagated, the fee for the
+    // other one is returned to the caller.
+    let old_state: State = rt.get_state();
+    rt.set_balance(value.clone() * 2 + CROSS_MSG_FEE.clone() * 2);
+    rt.expect_send(
+        BURNT_FUNDS_ACTOR_ADDR,
+        METHOD_SEND,
+        None,
+        value.clone(),
+        None,
+        ExitCode::OK,
+    );
+    rt.expect_send(
+        owner,
+        METHOD_SEND,
+        None,
+        CROSS_MSG_FEE.clone(),
+        None,
+        ExitCode::OK,
+    );
+    let ret = h
+        .propagate_batch(&mut rt, owner, cids.clone(), CROSS_MSG_FEE.clone() * 2)
+        .unwrap();
+    assert_eq!(ret.propagated, vec![cids[0]]);
+    assert_eq!(ret.pending, vec![cids[1]]);
+
+    let st: State = rt.get_state();
+    assert!(st.load_from_postbox(rt.store(), cids[0]).is_err());
+    assert!(st.load_from_postbox(rt.store(), cids[1]).is_ok());
+    assert_eq!(st.nonce, old_state.nonce + 1);
+
+    // the pending item can be propagated later by its owner
+    rt.set_balance(value.clone() + CROSS_MSG_FEE.clone());
+    rt.expect_send(
+        BURNT_FUNDS_ACTOR_ADDR,
+        METHOD_SEND,
+        None,
+        value.clone(),
+        None,
+        ExitCode::OK,
+    );
+    let ret = h
+        .propagate_batch(&mut rt, other, vec![cids[1]], CROSS_MSG_FEE.clone())
+        .unwrap();
+    assert_eq!(ret.propagated, vec![cids[1]]);
+    assert!(ret.pending.is_empty());
+    let st: State = rt.get_state();
+    assert!(st.load_from_postbox(rt.store(), cids[1]).is_err());
+}
+
+/// This test covers the case where a bottom up cross_msg's target subnet is NOT the same as that of
+#[test]
+fn test_replace_postbox_owners() {
+    let parent_sub = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(parent_sub.clone());
+
+    let old_owner = Address::new_id(1001);
+    let new_owners = vec![Address::new_id(1002), Address::new_id(1003)];
+    let params = StorableMsg {
+        to: IPCAddress::new(
+            &SubnetID::new_from_parent(&parent_sub, *SUBNET_THR),
+            &TEST_BLS,
+        )
+        .unwrap(),
+        from: IPCAddress::new(
+            &SubnetID::new_from_parent(&parent_sub, *SUBNET_TWO),
+            &TEST_BLS,
+        )
+        .unwrap(),
+        method: METHOD_SEND,
+        value: TokenAmount::from_atto(10_u64.pow(17)),
+        params: RawBytes::default(),
+        nonce: 0,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
+    };
+    let cid = rt
+        .transaction(|st: &mut State, r| {
+            Ok(st
+                .insert_postbox(
+                    r.store(),
+                    Some(vec![old_owner]),
+                    CrossMsg {
+                        wrapped: false,
+                        msg: params.clone(),
+                    },
+                    r.curr_epoch(),
+                )
+                .unwrap())
+        })
+        .unwrap();
+
+    // an empty set of owners is rejected
+    let err = h
+        .replace_postbox_owners(&mut rt, old_owner, cid, vec![])
+        .unwrap_err();
+    assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_ARGUMENT);
+
+    // only owners can replace the owners
+    let err = h
+        .replace_postbox_owners(&mut rt, new_owners[0], cid, new_owners.clone())
+        .unwrap_err();
+    assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_STATE);
+
+    h.replace_postbox_owners(&mut rt, old_owner, cid, new_owners.clone())
+        .unwrap();
+    let st: State = rt.get_state();
+    let item = st.load_from_postbox(rt.store(), cid).unwrap();
+    assert_eq!(item.owners, Some(new_owners.clone()));
+
+    // the old owner lost access and the new ones gained it
+    let err = h
+        .replace_postbox_owners(&mut rt, old_owner, cid, vec![old_owner])
+        .unwrap_err();
+    assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_STATE);
+    h.replace_postbox_owners(&mut rt, new_owners[1], cid, vec![new_owners[1]])
+        .unwrap();
+    let st: State = rt.get_state();
+    let item = st.load_from_postbox(rt.store(), cid).unwrap();
+    assert_eq!(item.owners, Some(vec![new_owners[1]]));
+}
+
+/// the gateway. It would save in postbox. Also, the gateway is the nearest parent, a switch to
+/// top down cross msg should occur.
+#[test]
+fn test_apply_msg_bu_switch_td() {
+    // ============== Register subnet ==============
+    let parent_sub = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(parent_sub.clone());
+
+    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+
+    // ================ Setup ===============
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+
+    // ================= Bottom-Up ===============
+    let reg_value = TokenAmount::from_atto(10_u64.pow(18));
+    // ff: /root/f101/f102
+    // to: /root/f101/f103
+    // we are executing the message from, harness or the gateway is at: /root/f101
+    let ff_sub = SubnetID::new_from_parent(&parent_sub, *SUBNET_TWO);
+    let tt_sub = SubnetID::new_from_parent(&parent_sub, *SUBNET_THR);
+    h.register(&mut rt, &SUBNET_TWO, &reg_value, ExitCode::OK)
+        .unwrap();
+    h.register(&mut rt, &SUBNET_THR, &reg_value, ExitCode::OK)
+        .unwrap();
+
+    let ff = IPCAddress::new(&ff_sub, &to).unwrap();
+    let tt = IPCAddress::new(&tt_sub, &from).unwrap();
+    let msg_nonce = 0;
+
+    // Only system code is allowed to this method
+    let params = StorableMsg {
+        to: tt.clone(),
+        from: ff.clone(),
+        method: METHOD_SEND,
+        value: value.clone(),
+        params: RawBytes::default(),
+        nonce: msg_nonce,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
+    };
+
+    let caller = ff.clone().raw_addr().unwrap();
+
+    // we directly insert message into postbox as we dont really care how it's got stored in queue
+    let cid = rt
+        .transaction(|st: &mut State, r| {
+            Ok(st
+                .insert_postbox(
+                    r.store(),
+                    Some(vec![caller.clone()]),
+                    CrossMsg {
+                        wrapped: false,
+                        msg: params.clone(),
+                    },
+                    r.curr_epoch(),
+                )
+                .unwrap())
+        })
+        .unwrap();
+
+    let starting_nonce = get_subnet(&rt, &tt.subnet().unwrap().down(&h.net_name).unwrap())
+        .unwrap()
+        .nonce;
+
+    // now we propagate
+    h.propagate(
+        &mut rt,
+        caller,
+        cid.clone(),
+        &params.value,
+        TokenAmount::zero(),
+    )
+    .unwrap();
+
+    // state should be updated, load again to perform the checks!
+    let st: State = rt.get_state();
+
+    // cid should be removed from postbox
+    let r = st.load_from_postbox(rt.store(), cid.clone());
+    assert_eq!(r.is_err(), true);
+    let err = r.unwrap_err();
+    assert_eq!(err.to_string(), "cid not found in postbox");
+
+    // the cross msg should have been committed to the next subnet, check this!
+    let sub = get_subnet(&rt, &tt.subnet().unwrap().down(&h.net_name).unwrap()).unwrap();
+    assert_eq!(sub.nonce, starting_nonce + 1);
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    let msg = get_topdown_msg(&crossmsgs, starting_nonce).unwrap();
+    assert_eq!(msg.is_some(), true);
+    let msg = msg.unwrap();
+    assert_eq!(msg.to, tt);
+    // the nonce should not have changed at all
+    assert_eq!(msg.nonce, starting_nonce);
+    assert_eq!(msg.value, value);
+}
+
+/// This test covers the case where the cross_msg's target subnet is the SAME as that of
+/// the gateway. It would directly commit the message and will not save in postbox.
+/// Applying a top-down message when the applied nonce is already at `u64::MAX` fails
+/// instead of wrapping the nonce around.
+#[test]
+fn test_apply_msg_nonce_overflow() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+
+    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
 
-    // ================= Bottom-Up ===============
-    let ff = IPCAddress::new(&sub, &to).unwrap();
-    let tt = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
+    let mut st: State = rt.get_state();
+    st.applied_topdown_nonce = u64::MAX;
+    rt.replace_state(&st);
+
+    let ff = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
+    let tt = IPCAddress::new(&shid, &to).unwrap();
+    h.apply_cross_msg(
+        &mut rt,
+        &ff,
+        &tt,
+        TokenAmount::zero(),
+        u64::MAX,
+        u64::MAX,
+        ExitCode::USR_ILLEGAL_STATE,
+    )
+    .unwrap();
+
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, u64::MAX);
+}
+
+#[test]
+fn test_apply_msg_tp_target_subnet() {
+    // ============== Register subnet ==============
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+
+    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+
+    let sub = shid.clone();
+
+    // ================ Setup ===============
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+
+    // ================= Top-Down ===============
+    let ff = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
+    let tt = IPCAddress::new(&sub, &to).unwrap();
     let msg_nonce = 0;
 
     // Only system code is allowed to this method
@@ -608,20 +4379,36 @@ fn test_apply_msg_bu_target_subnet() {
         value: value.clone(),
         params: RawBytes::default(),
         nonce: msg_nonce,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
     };
     let sto = tt.raw_addr().unwrap();
-
+    let v = value.clone();
     let cid = h
         .apply_cross_execute_only(
             &mut rt,
             value.clone(),
             params,
             Some(Box::new(move |rt| {
+                // expect to send reward message
+                rt.expect_send(
+                    REWARD_ACTOR_ADDR,
+                    ext::reward::EXTERNAL_FUNDING_METHOD,
+                    IpldBlock::serialize_cbor(&ext::reward::FundingParams {
+                        addr: *ACTOR,
+                        value: v.clone(),
+                    })
+                    .unwrap(),
+                    TokenAmount::zero(),
+                    None,
+                    ExitCode::OK,
+                );
                 rt.expect_send(
                     sto.clone(),
                     METHOD_SEND,
                     None,
-                    value.clone(),
+                    v.clone(),
                     None,
                     ExitCode::OK,
                 );
@@ -631,25 +4418,499 @@ fn test_apply_msg_bu_target_subnet() {
     assert_eq!(cid.is_none(), true);
 }
 
-/// This test covers the case where a bottom up cross_msg's target subnet is NOT the same as that of
-/// the gateway. It will save it in the postbox.
 #[test]
-fn test_apply_msg_bu_not_target_subnet() {
-    // ============== Register subnet ==============
+fn test_apply_msg_tp_expired() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+
+    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+    let ff = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
+    let tt = IPCAddress::new(&shid, &to).unwrap();
+
+    let params = StorableMsg {
+        to: tt.clone(),
+        from: ff.clone(),
+        method: METHOD_SEND,
+        value: value.clone(),
+        params: RawBytes::default(),
+        nonce: 0,
+        memo: Vec::new(),
+        valid_until_epoch: Some(10),
+        correlation_id: None,
+    };
+
+    // the message is past its validity window, so the funds are minted but
+    // the message is not executed.
+    rt.set_epoch(11);
+    let v = value.clone();
+    let cid = h
+        .apply_cross_execute_only(
+            &mut rt,
+            value.clone(),
+            params,
+            Some(Box::new(move |rt| {
+                rt.expect_send(
+                    REWARD_ACTOR_ADDR,
+                    ext::reward::EXTERNAL_FUNDING_METHOD,
+                    IpldBlock::serialize_cbor(&ext::reward::FundingParams {
+                        addr: *ACTOR,
+                        value: v.clone(),
+                    })
+                    .unwrap(),
+                    TokenAmount::zero(),
+                    None,
+                    ExitCode::OK,
+                );
+            })),
+        )
+        .unwrap()
+        .unwrap();
+
+    // the nonce is consumed and the value is refunded through the postbox
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 1);
+    let item = st.load_from_postbox(rt.store(), cid).unwrap();
+    assert_eq!(item.owners, None);
+    let refund = item.cross_msg.msg;
+    assert_eq!(
+        refund.from,
+        IPCAddress::new(&shid, &BURNT_FUNDS_ACTOR_ADDR).unwrap()
+    );
+    assert_eq!(refund.to, ff);
+    assert_eq!(refund.value, value);
+    assert_eq!(refund.valid_until_epoch, None);
+
+    // the next message is still applied within its window
+    let params = StorableMsg {
+        to: tt.clone(),
+        from: ff.clone(),
+        method: METHOD_SEND,
+        value: value.clone(),
+        params: RawBytes::default(),
+        nonce: 1,
+        memo: Vec::new(),
+        valid_until_epoch: Some(11),
+        correlation_id: None,
+    };
+    let sto = tt.raw_addr().unwrap();
+    let v = value.clone();
+    let cid = h
+        .apply_cross_execute_only(
+            &mut rt,
+            value.clone(),
+            params,
+            Some(Box::new(move |rt| {
+                rt.expect_send(
+                    REWARD_ACTOR_ADDR,
+                    ext::reward::EXTERNAL_FUNDING_METHOD,
+                    IpldBlock::serialize_cbor(&ext::reward::FundingParams {
+                        addr: *ACTOR,
+                        value: v.clone(),
+                    })
+                    .unwrap(),
+                    TokenAmount::zero(),
+                    None,
+                    ExitCode::OK,
+                );
+                rt.expect_send(sto, METHOD_SEND, None, v.clone(), None, ExitCode::OK);
+            })),
+        )
+        .unwrap();
+    assert!(cid.is_none());
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 2);
+}
+
+#[test]
+fn test_apply_msgs() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (h, mut rt) = setup(shid.clone());
+
+    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+    let ff = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
+    let tt = IPCAddress::new(&shid, &to).unwrap();
+    let msg = |nonce| StorableMsg {
+        to: tt.clone(),
+        from: ff.clone(),
+        method: METHOD_SEND,
+        value: value.clone(),
+        params: RawBytes::default(),
+        nonce,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
+    };
+
+    // empty batches are rejected
+    expect_abort(
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        h.apply_cross_msgs(&mut rt, value.clone(), Vec::new(), None),
+    );
+
+    // the first message is executed, the second doesn't hold the subsequent
+    // nonce and the execution of the third one fails.
+    let v = value.clone();
+    let results = h
+        .apply_cross_msgs(
+            &mut rt,
+            value.clone().mul(2),
+            vec![msg(0), msg(5), msg(1)],
+            Some(Box::new(move |rt| {
+                let mint = |rt: &mut MockRuntime| {
+                    rt.expect_send(
+                        REWARD_ACTOR_ADDR,
+                        ext::reward::EXTERNAL_FUNDING_METHOD,
+                        IpldBlock::serialize_cbor(&ext::reward::FundingParams {
+                            addr: *ACTOR,
+                            value: v.clone(),
+                        })
+                        .unwrap(),
+                        TokenAmount::zero(),
+                        None,
+                        ExitCode::OK,
+                    );
+                };
+                mint(rt);
+                rt.expect_send(to, METHOD_SEND, None, v.clone(), None, ExitCode::OK);
+                mint(rt);
+                rt.expect_send(
+                    to,
+                    METHOD_SEND,
+                    None,
+                    v.clone(),
+                    None,
+                    ExitCode::USR_UNHANDLED_MESSAGE,
+                );
+            })),
+        )
+        .unwrap();
+    assert_eq!(
+        results
+            .iter()
+            .map(|r| r.exit_code)
+            .collect::<Vec<ExitCode>>(),
+        vec![ExitCode::OK, ExitCode::USR_ILLEGAL_STATE, ExitCode::OK]
+    );
+
+    // the failed execution is recorded to be retried with the same nonce
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 1);
+    let cid = CrossMsg {
+        msg: msg(1),
+        wrapped: false,
+    }
+    .cid()
+    .unwrap();
+    assert_eq!(st.get_apply_attempts(rt.store(), &cid).unwrap(), 1);
+}
+
+#[test]
+fn test_apply_msgs_no_mint_on_failure() {
+    // gateway: /root/sub1, messages are stored for /root/sub1/sub1
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let sub = SubnetID::new_from_parent(&shid, *SUBNET_ONE);
+    let ff = IPCAddress::new(&ROOTNET_ID, &Address::new_id(1001)).unwrap();
+    let tt = IPCAddress::new(&sub, &Address::new_id(1002)).unwrap();
+    let value = TokenAmount::from_atto(10_u64.pow(17));
+    let msg = |nonce| StorableMsg {
+        to: tt.clone(),
+        from: ff.clone(),
+        method: METHOD_SEND,
+        value: value.clone(),
+        params: RawBytes::default(),
+        nonce,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
+    };
+
+    let mut h = new_harness(shid);
+    h.max_postbox_items = 1;
+    h.postbox_overflow_policy = PostboxOverflowPolicy::Reject;
+    let mut rt = new_runtime();
+    h.construct_and_verify(&mut rt);
+
+    // the funds of the first message are minted once it's in the postbox, the
+    // second one doesn't fit so nothing is minted for it.
+    let v = value.clone();
+    let results = h
+        .apply_cross_msgs(
+            &mut rt,
+            TokenAmount::zero(),
+            vec![msg(0), msg(1)],
+            Some(Box::new(move |rt| {
+                rt.expect_send(
+                    REWARD_ACTOR_ADDR,
+                    ext::reward::EXTERNAL_FUNDING_METHOD,
+                    IpldBlock::serialize_cbor(&ext::reward::FundingParams {
+                        addr: *ACTOR,
+                        value: v.clone(),
+                    })
+                    .unwrap(),
+                    TokenAmount::zero(),
+                    None,
+                    ExitCode::OK,
+                );
+            })),
+        )
+        .unwrap();
+    assert_eq!(
+        results
+            .iter()
+            .map(|r| r.exit_code)
+            .collect::<Vec<ExitCode>>(),
+        vec![ExitCode::OK, ExitCode::USR_ILLEGAL_STATE]
+    );
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 1);
+
+    // retrying the message doesn't mint its funds either while it can't be applied
+    let results = h
+        .apply_cross_msgs(&mut rt, TokenAmount::zero(), vec![msg(1)], None)
+        .unwrap();
+    assert_eq!(results[0].exit_code, ExitCode::USR_ILLEGAL_STATE);
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 1);
+}
+
+#[test]
+fn test_postbox_cap() {
+    // gateway: /root/sub1, messages are stored for /root/sub1/sub1
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let sub = SubnetID::new_from_parent(&shid, *SUBNET_ONE);
+    let ff = IPCAddress::new(&ROOTNET_ID, &Address::new_id(1001)).unwrap();
+    let tt = IPCAddress::new(&sub, &Address::new_id(1002)).unwrap();
+    let msg = |nonce, valid_until_epoch| StorableMsg {
+        to: tt.clone(),
+        from: ff.clone(),
+        method: METHOD_SEND,
+        value: TokenAmount::zero(),
+        params: RawBytes::default(),
+        nonce,
+        memo: Vec::new(),
+        valid_until_epoch,
+        correlation_id: None,
+    };
+
+    let mut h = new_harness(shid.clone());
+    h.max_postbox_items = 2;
+    let mut rt = new_runtime();
+    h.construct_and_verify(&mut rt);
+
+    let expired = h
+        .apply_cross_execute_only(&mut rt, TokenAmount::zero(), msg(0, Some(5)), None)
+        .unwrap()
+        .unwrap();
+    let kept = h
+        .apply_cross_execute_only(&mut rt, TokenAmount::zero(), msg(1, None), None)
+        .unwrap()
+        .unwrap();
+
+    // the postbox is full, so the expired item is evicted to the dead-letter queue
+    rt.set_epoch(10);
+    let added = h
+        .apply_cross_execute_only(&mut rt, TokenAmount::zero(), msg(2, None), None)
+        .unwrap()
+        .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.postbox_len, 2);
+    assert!(st.load_from_postbox(rt.store(), expired).is_err());
+    assert!(st.load_from_postbox(rt.store(), kept).is_ok());
+    assert!(st.load_from_postbox(rt.store(), added).is_ok());
+    let dead_letters = h.list_dead_letters(&mut rt);
+    assert_eq!(dead_letters.len(), 1);
+    assert_eq!(dead_letters[0].cross_msg.msg, msg(0, Some(5)));
+
+    // with no expired items left the message is rejected
+    let err = h
+        .apply_cross_execute_only(&mut rt, TokenAmount::zero(), msg(3, None), None)
+        .unwrap_err();
+    assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_STATE);
+
+    // under the reject policy expired items are not evicted
+    let mut h = new_harness(shid);
+    h.max_postbox_items = 1;
+    h.postbox_overflow_policy = PostboxOverflowPolicy::Reject;
+    let mut rt = new_runtime();
+    h.construct_and_verify(&mut rt);
+    h.apply_cross_execute_only(&mut rt, TokenAmount::zero(), msg(0, Some(5)), None)
+        .unwrap();
+    rt.set_epoch(10);
+    let err = h
+        .apply_cross_execute_only(&mut rt, TokenAmount::zero(), msg(1, None), None)
+        .unwrap_err();
+    assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_STATE);
+    assert!(h.list_dead_letters(&mut rt).is_empty());
+}
+
+#[test]
+fn test_cross_msg_memo() {
+    let (h, mut rt) = setup_root();
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let memo = b"intent-42".to_vec();
+    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    let amount = TokenAmount::from_atto(10_u64.pow(17));
+
+    // the memo is kept in the top-down message committed by `SendCross`
+    let params = |memo: Vec<u8>| {
+        CrossMsgParams::builder(shid.clone())
+            .from(IPCAddress::new(&h.net_name, &SYSTEM_ACTOR_ADDR).unwrap())
+            .to(to)
+            .value(amount.clone() + &*CROSS_MSG_FEE)
+            .memo(memo)
+            .wrapped(true)
+            .build()
+            .unwrap()
+    };
+    rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
+    rt.set_value(amount.clone() + &*CROSS_MSG_FEE);
+    rt.expect_validate_caller_not_type(SIG_TYPES.clone());
+    rt.call::<Actor>(
+        Method::SendCross as MethodNum,
+        IpldBlock::serialize_cbor(&params(memo.clone())).unwrap(),
+    )
+    .unwrap();
+    rt.verify();
+
+    // and in the one committed by `FundWithMemo`
+    let funder = Address::new_id(1001);
+    let fund_params = |memo: Vec<u8>| {
+        IpldBlock::serialize_cbor(&FundWithMemoParams {
+            subnet: shid.clone(),
+            memo,
+        })
+        .unwrap()
+    };
+    rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, funder);
+    rt.set_value(amount.clone() + &*CROSS_MSG_FEE);
+    rt.expect_validate_caller_type(SIG_TYPES.clone());
+    rt.expect_send(
+        funder,
+        ext::account::PUBKEY_ADDRESS_METHOD,
+        None,
+        TokenAmount::zero(),
+        IpldBlock::serialize_cbor(&*TEST_BLS).unwrap(),
+        ExitCode::OK,
+    );
+    rt.call::<Actor>(Method::FundWithMemo as MethodNum, fund_params(memo.clone()))
+        .unwrap();
+    rt.verify();
+
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    let committed = crossmsgs.get(0).unwrap().unwrap().clone();
+    assert_eq!(committed.msg.memo, memo);
+    assert_eq!(committed.msg.value, amount);
+    let funded = crossmsgs.get(1).unwrap().unwrap();
+    assert_eq!(funded.msg.memo, memo);
+    assert_eq!(funded.msg.value, amount);
+
+    // memos over the maximum length are rejected
+    let long_memo = vec![0; MAX_MEMO_LENGTH + 1];
+    rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
+    rt.set_value(amount.clone() + &*CROSS_MSG_FEE);
+    rt.expect_validate_caller_not_type(SIG_TYPES.clone());
+    let mut invalid = params(Vec::new());
+    invalid.cross_msg.msg.memo = long_memo.clone();
+    expect_abort(
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        rt.call::<Actor>(
+            Method::SendCross as MethodNum,
+            IpldBlock::serialize_cbor(&invalid).unwrap(),
+        ),
+    );
+    rt.verify();
+    rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, funder);
+    rt.expect_validate_caller_type(SIG_TYPES.clone());
+    expect_abort(
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+        rt.call::<Actor>(Method::FundWithMemo as MethodNum, fund_params(long_memo)),
+    );
+    rt.verify();
+
+    // the message applied in the child delivers the memo intact
+    let (_, mut rt) = setup(shid);
+    rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
+    expect_validate_apply_caller(&mut rt);
+    rt.set_balance(amount.clone());
+    rt.expect_send(
+        REWARD_ACTOR_ADDR,
+        ext::reward::EXTERNAL_FUNDING_METHOD,
+        IpldBlock::serialize_cbor(&ext::reward::FundingParams {
+            addr: *ACTOR,
+            value: amount.clone(),
+        })
+        .unwrap(),
+        TokenAmount::zero(),
+        None,
+        ExitCode::OK,
+    );
+    rt.expect_send(
+        to,
+        METHOD_SEND,
+        IpldBlock::serialize_cbor(&ApplyMsgParams {
+            cross_msg: committed.clone(),
+        })
+        .unwrap(),
+        amount,
+        None,
+        ExitCode::OK,
+    );
+    rt.call::<Actor>(
+        Method::ApplyMessage as MethodNum,
+        IpldBlock::serialize_cbor(&ApplyMsgParams {
+            cross_msg: committed,
+        })
+        .unwrap(),
+    )
+    .unwrap();
+    rt.verify();
+}
+
+/// This test covers the case where the cross_msg's target subnet is not the same as that of
+/// the gateway.
+#[test]
+fn test_apply_msg_tp_not_target_subnet() {
+    // ============== Define Parameters ==============
+    // gateway: /root/sub1
     let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
-    let (h, mut rt) = setup(shid.clone());
 
     let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
     let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
 
-    let sub = shid.clone();
+    // /root/sub1/sub1
+    let sub = SubnetID::new_from_parent(&shid, *SUBNET_ONE);
 
     // ================ Setup ===============
+    let reg_value = TokenAmount::from_atto(10_u64.pow(18));
+    let (h, mut rt) = setup(shid.clone());
+    h.register(&mut rt, &SUBNET_ONE, &reg_value, ExitCode::OK)
+        .unwrap();
+    // add some circulating supply to subnets
+    let funder = Address::new_id(1002);
+    h.fund(
+        &mut rt,
+        &funder,
+        &sub,
+        ExitCode::OK,
+        reg_value.clone(),
+        1,
+        &reg_value,
+    )
+    .unwrap();
+
     let value = TokenAmount::from_atto(10_u64.pow(17));
 
-    // ================= Bottom-Up ===============
-    let ff = IPCAddress::new(&sub, &to).unwrap();
-    let tt = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
+    // ================= Top-Down ===============
+    let ff = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
+    let tt = IPCAddress::new(&sub, &to).unwrap();
     let msg_nonce = 0;
 
     // Only system code is allowed to this method
@@ -660,9 +4921,33 @@ fn test_apply_msg_bu_not_target_subnet() {
         value: value.clone(),
         params: RawBytes::default(),
         nonce: msg_nonce,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
     };
+    let v = value.clone();
+    // cid is expected, should not be None
     let cid = h
-        .apply_cross_execute_only(&mut rt, value.clone(), params.clone(), None)
+        .apply_cross_execute_only(
+            &mut rt,
+            value.clone(),
+            params.clone(),
+            Some(Box::new(move |rt| {
+                // expect to send reward message
+                rt.expect_send(
+                    REWARD_ACTOR_ADDR,
+                    ext::reward::EXTERNAL_FUNDING_METHOD,
+                    IpldBlock::serialize_cbor(&ext::reward::FundingParams {
+                        addr: *ACTOR,
+                        value: v.clone(),
+                    })
+                    .unwrap(),
+                    TokenAmount::zero(),
+                    None,
+                    ExitCode::OK,
+                );
+            })),
+        )
         .unwrap()
         .unwrap();
 
@@ -681,18 +4966,11 @@ fn test_apply_msg_bu_not_target_subnet() {
 
     // Part 2: Now we propagate from postbox
     // get the original subnet nonce first
+    let starting_nonce = get_subnet(&rt, &tt.subnet().unwrap().down(&h.net_name).unwrap())
+        .unwrap()
+        .nonce;
     let caller = ff.clone().raw_addr().unwrap();
-    let old_state: State = rt.get_state();
-    // propagating a bottom-up message triggers the
-    // funds included in the message to be burnt.
-    rt.expect_send(
-        BURNT_FUNDS_ACTOR_ADDR,
-        METHOD_SEND,
-        None,
-        params.clone().value,
-        None,
-        ExitCode::OK,
-    );
+
     h.propagate(
         &mut rt,
         caller,
@@ -703,429 +4981,745 @@ fn test_apply_msg_bu_not_target_subnet() {
     .unwrap();
 
     // state should be updated, load again
-    let new_state: State = rt.get_state();
+    let st: State = rt.get_state();
 
     // cid should be removed from postbox
-    let r = new_state.load_from_postbox(rt.store(), cid.clone());
+    let r = st.load_from_postbox(rt.store(), cid.clone());
     assert_eq!(r.is_err(), true);
     let err = r.unwrap_err();
     assert_eq!(err.to_string(), "cid not found in postbox");
-    assert_eq!(new_state.nonce, old_state.nonce + 1);
+
+    // the cross msg should have been committed to the next subnet, check this!
+    let sub = get_subnet(&rt, &tt.subnet().unwrap().down(&h.net_name).unwrap()).unwrap();
+    assert_eq!(sub.nonce, starting_nonce + 1);
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    let msg = get_topdown_msg(&crossmsgs, starting_nonce).unwrap();
+    assert_eq!(msg.is_some(), true);
+    let msg = msg.unwrap();
+    assert_eq!(msg.to, tt);
+    // the nonce should not have changed at all
+    assert_eq!(msg.nonce, starting_nonce);
+    assert_eq!(msg.value, value);
 }
 
-/// This test covers the case where the amount send in the propagate
-/// message exceeds the required fee and the remainder is returned
-/// to the caller.
 #[test]
-fn test_propagate_with_remainder() {
-    // ============== Register subnet ==============
-    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
-    let (h, mut rt) = setup(shid.clone());
+fn test_apply_msg_tp_multi_hop() {
+    // a message from /root is routed down to /root/sub1/sub2/sub3 through the
+    // gateways of /root/sub1 and /root/sub1/sub2.
+    let a = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let b = SubnetID::new_from_parent(&a, *SUBNET_TWO);
+    let c = SubnetID::new_from_parent(&b, *SUBNET_THR);
 
     let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
     let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
-
-    let sub = shid.clone();
-
-    // ================ Setup ===============
+    let reg_value = TokenAmount::from_atto(10_u64.pow(18));
     let value = TokenAmount::from_atto(10_u64.pow(17));
-
-    // ================= Bottom-Up ===============
-    let ff = IPCAddress::new(&sub, &to).unwrap();
-    let tt = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
-    let msg_nonce = 0;
-
-    // Only system code is allowed to this method
-    let params = StorableMsg {
+    let ff = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
+    let tt = IPCAddress::new(&c, &to).unwrap();
+    let mint = |value: &TokenAmount| -> Option<Box<dyn Fn(&mut MockRuntime)>> {
+        let value = value.clone();
+        Some(Box::new(move |rt| {
+            rt.expect_send(
+                REWARD_ACTOR_ADDR,
+                ext::reward::EXTERNAL_FUNDING_METHOD,
+                IpldBlock::serialize_cbor(&ext::reward::FundingParams {
+                    addr: *ACTOR,
+                    value: value.clone(),
+                })
+                .unwrap(),
+                TokenAmount::zero(),
+                None,
+                ExitCode::OK,
+            );
+        }))
+    };
+    let msg = StorableMsg {
         to: tt.clone(),
-        from: ff.clone(),
+        from: ff,
         method: METHOD_SEND,
         value: value.clone(),
         params: RawBytes::default(),
-        nonce: msg_nonce,
+        nonce: 0,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
     };
+
+    // ================ /root/sub1 ===============
+    let (h, mut rt) = setup(a);
+    h.register(&mut rt, &SUBNET_TWO, &reg_value, ExitCode::OK)
+        .unwrap();
+    // a fund takes the first nonce of /root/sub1/sub2
+    h.fund(
+        &mut rt,
+        &Address::new_id(1001),
+        &b,
+        ExitCode::OK,
+        value.clone(),
+        1,
+        &value,
+    )
+    .unwrap();
+
     let cid = h
-        .apply_cross_execute_only(&mut rt, value.clone(), params.clone(), None)
+        .apply_cross_execute_only(&mut rt, value.clone(), msg.clone(), mint(&value))
         .unwrap()
         .unwrap();
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 1);
 
-    // Part 1: test the message is stored in postbox
+    // the nonce is consumed, so the message can't be applied again
+    let err = h
+        .apply_cross_execute_only(&mut rt, value.clone(), msg, None)
+        .unwrap_err();
+    assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_STATE);
+
+    h.propagate(&mut rt, from, cid, &value, TokenAmount::zero())
+        .unwrap();
+    let sub = h.get_subnet(&rt, &b).unwrap();
+    assert_eq!(sub.nonce, 2);
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    let msg = get_topdown_msg(&crossmsgs, 1).unwrap().unwrap();
+    assert_eq!(msg.to, tt);
+    assert_eq!(msg.nonce, 1);
+
+    // ================ /root/sub1/sub2 ===============
+    let (h, mut rt) = setup(b);
+    h.register(&mut rt, &SUBNET_THR, &reg_value, ExitCode::OK)
+        .unwrap();
+    // the fund with the previous nonce was already applied
+    let mut st: State = rt.get_state();
+    st.applied_topdown_nonce = 1;
+    rt.replace_state(&st);
+
+    let cid = h
+        .apply_cross_execute_only(&mut rt, value.clone(), msg, mint(&value))
+        .unwrap()
+        .unwrap();
     let st: State = rt.get_state();
-    assert_ne!(tt.subnet().unwrap(), st.network_name);
+    assert_eq!(st.applied_topdown_nonce, 2);
 
-    // Check 1: `tt` is in `sub1`, which is not in that of `runtime` of gateway, will store in postbox
-    let item = st.load_from_postbox(rt.store(), cid.clone()).unwrap();
-    assert_eq!(item.owners, Some(vec![ff.clone().raw_addr().unwrap()]));
-    let msg = item.cross_msg.msg;
+    h.propagate(&mut rt, from, cid, &value, TokenAmount::zero())
+        .unwrap();
+    let sub = h.get_subnet(&rt, &c).unwrap();
+    assert_eq!(sub.nonce, 1);
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    let msg = get_topdown_msg(&crossmsgs, 0).unwrap().unwrap();
     assert_eq!(msg.to, tt);
-    // the nonce should not have changed at all
-    assert_eq!(msg.nonce, msg_nonce);
-    assert_eq!(msg.value, value);
+    assert_eq!(msg.nonce, 0);
 
-    // Part 2: Now we propagate from postbox
-    // get the original subnet nonce first with an
-    // excess to check that there is a remainder
-    // to be returned
-    let caller = ff.clone().raw_addr().unwrap();
-    let old_state: State = rt.get_state();
-    // propagating a bottom-up message triggers the
-    // funds included in the message to be burnt.
-    rt.expect_send(
-        BURNT_FUNDS_ACTOR_ADDR,
-        METHOD_SEND,
-        None,
-        params.clone().value,
-        None,
-        ExitCode::OK,
-    );
-    h.propagate(&mut rt, caller, cid.clone(), &params.value, value.clone())
+    // ================ /root/sub1/sub2/sub3 ===============
+    let (h, mut rt) = setup(c);
+    let v = value.clone();
+    let ret = h
+        .apply_cross_execute_only(
+            &mut rt,
+            value.clone(),
+            msg,
+            Some(Box::new(move |rt| {
+                mint(&v).unwrap()(rt);
+                rt.expect_send(to, METHOD_SEND, None, v.clone(), None, ExitCode::OK);
+            })),
+        )
+        .unwrap();
+    assert!(ret.is_none());
+    let st: State = rt.get_state();
+    assert_eq!(st.applied_topdown_nonce, 1);
+}
+
+#[test]
+fn test_apply_msg_match_target_subnet() {
+    let (h, mut rt) = setup_root();
+
+    // Register a subnet with 1FIL collateral
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
         .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+
+    // inject some funds
+    let funder_id = Address::new_id(1001);
+    let funder = IPCAddress::new(
+        &shid.parent().unwrap(),
+        &Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap(),
+    )
+    .unwrap();
+    let amount = TokenAmount::from_atto(10_u64.pow(18));
+    h.fund(
+        &mut rt,
+        &funder_id,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
+
+    // Apply fund messages
+    for i in 0..5 {
+        h.apply_cross_msg(&mut rt, &funder, &funder, value.clone(), i, i, ExitCode::OK)
+            .unwrap();
+    }
+    // Apply release messages
+    let from = IPCAddress::new(&shid, &BURNT_FUNDS_ACTOR_ADDR).unwrap();
+    // with the same nonce
+    for _ in 0..5 {
+        h.apply_cross_msg(&mut rt, &from, &funder, value.clone(), 0, 0, ExitCode::OK)
+            .unwrap();
+    }
+    // with increasing nonce
+    for i in 0..5 {
+        h.apply_cross_msg(&mut rt, &from, &funder, value.clone(), i, i, ExitCode::OK)
+            .unwrap();
+    }
+
+    // trying to apply non-subsequent nonce.
+    h.apply_cross_msg(
+        &mut rt,
+        &from,
+        &funder,
+        value.clone(),
+        10,
+        0,
+        ExitCode::USR_ILLEGAL_STATE,
+    )
+    .unwrap();
+    // trying already applied nonce
+    h.apply_cross_msg(
+        &mut rt,
+        &from,
+        &funder,
+        value.clone(),
+        0,
+        0,
+        ExitCode::USR_ILLEGAL_STATE,
+    )
+    .unwrap();
 
-    // state should be updated, load again
-    let new_state: State = rt.get_state();
+    // TODO: Trying to release over circulating supply
+}
 
-    // cid should be removed from postbox
-    let r = new_state.load_from_postbox(rt.store(), cid.clone());
-    assert_eq!(r.is_err(), true);
-    let err = r.unwrap_err();
-    assert_eq!(err.to_string(), "cid not found in postbox");
-    assert_eq!(new_state.nonce, old_state.nonce + 1);
+/// Layouts of the subnets and checkpoints stored by unversioned gateways.
+#[derive(Serialize)]
+struct SubnetV1 {
+    id: SubnetID,
+    stake: TokenAmount,
+    top_down_msgs: TCid<TAmt<CrossMsg, CROSSMSG_AMT_BITWIDTH>>,
+    nonce: u64,
+    circ_supply: TokenAmount,
+    status: Status,
+    prev_checkpoint: Option<CheckpointV1>,
+}
+
+#[derive(Serialize)]
+struct CheckpointV1 {
+    data: CheckDataV1,
+    #[serde(with = "serde_bytes")]
+    sig: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct CheckDataV1 {
+    source: SubnetID,
+    #[serde(with = "serde_bytes")]
+    tip_set: Vec<u8>,
+    epoch: ChainEpoch,
+    prev_check: TCid<TLink<Checkpoint>>,
+    children: Vec<ChildCheck>,
+    cross_msgs: Option<CrossMsgMetaV1>,
+}
+
+#[derive(Serialize)]
+struct CrossMsgMetaV1 {
+    msgs_cid: TCid<TLink<CrossMsgs>>,
+    nonce: u64,
+    value: TokenAmount,
+    fee: TokenAmount,
 }
 
-/// This test covers the case where a bottom up cross_msg's target subnet is NOT the same as that of
-/// the gateway. It would save in postbox. Also, the gateway is the nearest parent, a switch to
-/// top down cross msg should occur.
 #[test]
-fn test_apply_msg_bu_switch_td() {
-    // ============== Register subnet ==============
-    let parent_sub = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
-    let (h, mut rt) = setup(parent_sub.clone());
+fn test_migrate_state() {
+    let (h, mut rt) = setup_root();
+    let st: State = rt.get_state();
+    assert_eq!(st.version, STATE_VERSION);
+
+    let migrate = |rt: &mut MockRuntime, code: ExitCode| {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let res = rt.call::<Actor>(Method::MigrateState as MethodNum, None);
+        if code == ExitCode::OK {
+            res.unwrap();
+        } else {
+            expect_abort(code, res);
+        }
+        rt.verify();
+    };
 
-    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
-    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
+    // migrating an up to date state is a no-op
+    let root = rt.state.unwrap();
+    migrate(&mut rt, ExitCode::OK);
+    assert_eq!(rt.state.unwrap(), root);
 
-    // ================ Setup ===============
-    let value = TokenAmount::from_atto(10_u64.pow(17));
+    // a subnet registered before versioning, with a committed checkpoint
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let fee = TokenAmount::from_atto(5);
+    let child_check = CheckpointV1 {
+        data: CheckDataV1 {
+            source: shid.clone(),
+            tip_set: Vec::new(),
+            epoch: 10,
+            prev_check: TCid::default(),
+            children: Vec::new(),
+            cross_msgs: Some(CrossMsgMetaV1 {
+                msgs_cid: TCid::default(),
+                nonce: 0,
+                value: fee.clone(),
+                fee: fee.clone(),
+            }),
+        },
+        sig: Vec::new(),
+    };
+    let child_cid = Cid::new_v1(
+        fvm_ipld_encoding::DAG_CBOR,
+        cid::multihash::Code::Blake2b256.digest(&to_vec(&child_check.data).unwrap()),
+    );
+    let mut subnets = make_empty_map::<_, SubnetV1>(rt.store(), HAMT_BIT_WIDTH);
+    subnets
+        .set(
+            shid.to_bytes().into(),
+            SubnetV1 {
+                id: shid.clone(),
+                stake: st.min_stake.clone(),
+                top_down_msgs: TCid::new_amt(rt.store()).unwrap(),
+                nonce: 2,
+                circ_supply: TokenAmount::from_atto(100),
+                status: Active,
+                prev_checkpoint: Some(child_check),
+            },
+        )
+        .unwrap();
 
-    // ================= Bottom-Up ===============
-    let reg_value = TokenAmount::from_atto(10_u64.pow(18));
-    // ff: /root/f101/f102
-    // to: /root/f101/f103
-    // we are executing the message from, harness or the gateway is at: /root/f101
-    let ff_sub = SubnetID::new_from_parent(&parent_sub, *SUBNET_TWO);
-    let tt_sub = SubnetID::new_from_parent(&parent_sub, *SUBNET_THR);
-    h.register(&mut rt, &SUBNET_TWO, &reg_value, ExitCode::OK)
+    // and a checkpoint of the gateway including a bottom-up message
+    let crossmsgs = CrossMsgs {
+        msgs: vec![CrossMsg {
+            msg: StorableMsg::new_release_msg(&ROOTNET_ID, &TEST_BLS, TokenAmount::from_atto(7), 0)
+                .unwrap(),
+            wrapped: false,
+        }],
+    };
+    let msgs_cid = rt
+        .store()
+        .put_cbor(&crossmsgs, cid::multihash::Code::Blake2b256)
         .unwrap();
-    h.register(&mut rt, &SUBNET_THR, &reg_value, ExitCode::OK)
+    let mut registry = make_empty_map::<_, CrossMsgs>(rt.store(), HAMT_BIT_WIDTH);
+    registry.set(msgs_cid.to_bytes().into(), crossmsgs).unwrap();
+    let mut checkpoints = make_empty_map::<_, CheckpointV1>(rt.store(), HAMT_BIT_WIDTH);
+    let epoch: ChainEpoch = 20;
+    checkpoints
+        .set(
+            epoch.to_ne_bytes().to_vec().into(),
+            CheckpointV1 {
+                data: CheckDataV1 {
+                    source: ROOTNET_ID.clone(),
+                    tip_set: Vec::new(),
+                    epoch,
+                    prev_check: TCid::default(),
+                    children: Vec::new(),
+                    cross_msgs: Some(CrossMsgMetaV1 {
+                        msgs_cid: TCid::from(msgs_cid),
+                        nonce: 0,
+                        value: TokenAmount::from_atto(7) + &fee,
+                        fee: fee.clone(),
+                    }),
+                },
+                sig: Vec::new(),
+            },
+        )
         .unwrap();
 
-    let ff = IPCAddress::new(&ff_sub, &to).unwrap();
-    let tt = IPCAddress::new(&tt_sub, &from).unwrap();
-    let msg_nonce = 0;
+    // unversioned states are migrated to the current layout
+    let v1 = StateV1 {
+        network_name: ROOTNET_ID.clone(),
+        total_subnets: 1,
+        min_stake: st.min_stake.clone(),
+        subnets: TCid::from(subnets.flush().unwrap()),
+        check_period: 20,
+        checkpoints: TCid::from(checkpoints.flush().unwrap()),
+        check_msg_registry: TCid::from(registry.flush().unwrap()),
+        postbox: st.postbox.clone(),
+        nonce: 3,
+        bottomup_nonce: 4,
+        bottomup_msg_meta: st.bottomup_msg_meta.clone(),
+        applied_bottomup_nonce: 5,
+        applied_topdown_nonce: 6,
+    };
+    rt.state = Some(
+        rt.store()
+            .put_cbor(&v1, cid::multihash::Code::Blake2b256)
+            .unwrap(),
+    );
+    migrate(&mut rt, ExitCode::OK);
 
-    // Only system code is allowed to this method
-    let params = StorableMsg {
-        to: tt.clone(),
-        from: ff.clone(),
-        method: METHOD_SEND,
-        value: value.clone(),
-        params: RawBytes::default(),
-        nonce: msg_nonce,
+    let st: State = rt.get_state();
+    assert_eq!(st.version, STATE_VERSION);
+    assert_eq!(st.network_name, v1.network_name);
+    assert_eq!(st.total_subnets, 1);
+    assert_eq!(st.check_period, 20);
+    assert_eq!(st.nonce, 3);
+    assert_eq!(st.bottomup_nonce, 4);
+    assert_eq!(st.applied_bottomup_nonce, 5);
+    assert_eq!(st.applied_topdown_nonce, 6);
+    assert_eq!(st.max_msgs_per_checkpoint, DEFAULT_MAX_MSGS_PER_CHECKPOINT);
+    assert_eq!(st.max_apply_attempts, DEFAULT_MAX_APPLY_ATTEMPTS);
+    assert_eq!(st.pending_fee_distribution, TokenAmount::zero());
+    assert_eq!(st.governor, fil_actors_runtime::SYSTEM_ACTOR_ADDR);
+    assert_eq!(st.registration_allowlist, None);
+
+    // the registered subnet is loaded with the defaults of the new fields, and
+    // its committed checkpoint keeps its cid.
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert_eq!(sub.nonce, 2);
+    assert_eq!(sub.circ_supply, TokenAmount::from_atto(100));
+    assert!(!sub.frozen);
+    assert_eq!(sub.max_topdown_value, None);
+    assert_eq!(sub.applied_topdown_nonce, 0);
+    let prev = sub.prev_checkpoint.unwrap();
+    assert_eq!(prev.cid(), child_cid);
+    assert!(prev.rewards().is_empty());
+    prev.cross_msgs().unwrap().verify_fee(rt.store()).unwrap();
+
+    // the cross-msgs of the checkpoints of the gateway declare their messages
+    let ch = st.get_window_checkpoint(rt.store(), epoch).unwrap();
+    let meta = ch.cross_msgs().unwrap();
+    assert_eq!(meta.num_msgs, 1);
+    assert_eq!(meta.msgs_value, TokenAmount::from_atto(7));
+    meta.verify_fee(rt.store()).unwrap();
+
+    // states in version 2 are migrated keeping the fields they already had
+    let v2 = StateV2 {
+        version: 2,
+        network_name: ROOTNET_ID.clone(),
+        total_subnets: 1,
+        min_stake: st.min_stake.clone(),
+        subnets: v1.subnets.clone(),
+        check_period: 20,
+        max_msgs_per_checkpoint: 10,
+        checkpoints: v1.checkpoints.clone(),
+        check_msg_registry: v1.check_msg_registry.clone(),
+        postbox: st.postbox.clone(),
+        nonce: 3,
+        bottomup_nonce: 4,
+        bottomup_msg_meta: st.bottomup_msg_meta.clone(),
+        applied_bottomup_nonce: 5,
+        applied_topdown_nonce: 6,
+        pending_fee_distribution: fee.clone(),
+        governor: *GOVERNOR,
+        registration_allowlist: Some(vec![*SUBNET_ONE]),
+        max_apply_attempts: 2,
+        apply_attempts: st.apply_attempts.clone(),
+        dead_letters: st.dead_letters.clone(),
     };
+    rt.state = Some(
+        rt.store()
+            .put_cbor(&v2, cid::multihash::Code::Blake2b256)
+            .unwrap(),
+    );
+    migrate(&mut rt, ExitCode::OK);
+
+    let mut st: State = rt.get_state();
+    assert_eq!(st.version, STATE_VERSION);
+    assert_eq!(st.max_msgs_per_checkpoint, 10);
+    assert_eq!(st.pending_fee_distribution, fee);
+    assert_eq!(st.governor, *GOVERNOR);
+    assert_eq!(st.registration_allowlist, Some(vec![*SUBNET_ONE]));
+    assert_eq!(st.max_apply_attempts, 2);
+    assert!(st.governors.is_empty());
+    assert_eq!(st.total_burned, TokenAmount::zero());
+    let ch = st.get_window_checkpoint(rt.store(), epoch).unwrap();
+    assert_eq!(ch.cross_msgs().unwrap().num_msgs, 1);
+
+    // unknown future versions are rejected
+    st.version = STATE_VERSION + 1;
+    rt.replace_state(&st);
+    migrate(&mut rt, ExitCode::USR_ILLEGAL_STATE);
+}
 
-    let caller = ff.clone().raw_addr().unwrap();
+#[test]
+fn test_get_fee_schedule() {
+    let (_, mut rt) = setup_root();
 
-    // we directly insert message into postbox as we dont really care how it's got stored in queue
-    let cid = rt
-        .transaction(|st: &mut State, r| {
-            Ok(st
-                .insert_postbox(
-                    r.store(),
-                    Some(vec![caller.clone()]),
-                    CrossMsg {
-                        wrapped: false,
-                        msg: params.clone(),
-                    },
-                )
-                .unwrap())
-        })
+    rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+    rt.expect_validate_caller_any();
+    let ret = rt
+        .call::<Actor>(Method::GetFeeSchedule as MethodNum, None)
         .unwrap();
+    rt.verify();
+
+    let schedule: FeeSchedule = deserialize_block(ret).unwrap();
+    assert_eq!(
+        schedule,
+        FeeSchedule {
+            base_fee: CROSS_MSG_FEE.clone(),
+            min_fee: CROSS_MSG_FEE.clone(),
+            max_fee: CROSS_MSG_FEE.clone(),
+            msg_fees: CrossMsgFees::default(),
+        }
+    );
+}
 
-    let starting_nonce = get_subnet(&rt, &tt.subnet().unwrap().down(&h.net_name).unwrap())
-        .unwrap()
-        .nonce;
+#[test]
+fn test_freeze_subnet() {
+    let (h, mut rt) = setup_root();
+
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+
+    // only the governor can freeze subnets
+    h.freeze_subnet(&mut rt, &TEST_BLS, &shid, true, ExitCode::USR_FORBIDDEN)
+        .unwrap();
+    let unknown = SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO);
+    h.freeze_subnet(
+        &mut rt,
+        &GOVERNOR,
+        &unknown,
+        true,
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+    h.freeze_subnet(&mut rt, &GOVERNOR, &shid, true, ExitCode::OK)
+        .unwrap();
+    let sub = h.get_subnet(&rt, &shid).unwrap();
+    assert!(sub.frozen);
 
-    // propagated as top-down, so it should distribute a fee in this subnet
+    // funding a frozen subnet is rejected
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(18));
     rt.expect_send(
-        tt.subnet()
-            .unwrap()
-            .down(&h.net_name)
-            .unwrap()
-            .subnet_actor(),
-        SUBNET_ACTOR_REWARD_METHOD,
-        None,
-        CROSS_MSG_FEE.clone(),
+        funder,
+        ext::account::PUBKEY_ADDRESS_METHOD,
         None,
+        TokenAmount::zero(),
+        IpldBlock::serialize_cbor(&*TEST_BLS).unwrap(),
         ExitCode::OK,
     );
-
-    // now we propagate
-    h.propagate(
+    h.fund(
         &mut rt,
-        caller,
-        cid.clone(),
-        &params.value,
-        TokenAmount::zero(),
+        &funder,
+        &shid,
+        ExitCode::USR_ILLEGAL_STATE,
+        amount.clone(),
+        0,
+        &TokenAmount::zero(),
     )
     .unwrap();
 
-    // state should be updated, load again to perform the checks!
-    let st: State = rt.get_state();
-
-    // cid should be removed from postbox
-    let r = st.load_from_postbox(rt.store(), cid.clone());
-    assert_eq!(r.is_err(), true);
-    let err = r.unwrap_err();
-    assert_eq!(err.to_string(), "cid not found in postbox");
-
-    // the cross msg should have been committed to the next subnet, check this!
-    let sub = get_subnet(&rt, &tt.subnet().unwrap().down(&h.net_name).unwrap()).unwrap();
-    assert_eq!(sub.nonce, starting_nonce + 1);
-    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
-    let msg = get_topdown_msg(&crossmsgs, starting_nonce).unwrap();
-    assert_eq!(msg.is_some(), true);
-    let msg = msg.unwrap();
-    assert_eq!(msg.to, tt);
-    // the nonce should not have changed at all
-    assert_eq!(msg.nonce, starting_nonce);
-    assert_eq!(msg.value, value);
-}
-
-/// This test covers the case where the cross_msg's target subnet is the SAME as that of
-/// the gateway. It would directly commit the message and will not save in postbox.
-#[test]
-fn test_apply_msg_tp_target_subnet() {
-    // ============== Register subnet ==============
-    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
-    let (h, mut rt) = setup(shid.clone());
-
-    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
-    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
-
-    let sub = shid.clone();
-
-    // ================ Setup ===============
-    let value = TokenAmount::from_atto(10_u64.pow(17));
+    // bottom-up messages from a frozen subnet are rejected
+    let from = IPCAddress::new(&shid, &funder).unwrap();
+    let to = IPCAddress::new(&h.net_name, &funder).unwrap();
+    h.apply_cross_msg(
+        &mut rt,
+        &from,
+        &to,
+        amount.clone(),
+        0,
+        0,
+        ExitCode::USR_FORBIDDEN,
+    )
+    .unwrap();
 
-    // ================= Top-Down ===============
-    let ff = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
-    let tt = IPCAddress::new(&sub, &to).unwrap();
-    let msg_nonce = 0;
+    // validators can still release their stake
+    rt.set_balance(value.clone());
+    h.release_stake(&mut rt, &shid, &TokenAmount::from_atto(1), ExitCode::OK)
+        .unwrap();
+    h.add_stake(&mut rt, &shid, &TokenAmount::from_atto(1), ExitCode::OK)
+        .unwrap();
 
-    // Only system code is allowed to this method
-    let params = StorableMsg {
-        to: tt.clone(),
-        from: ff.clone(),
-        method: METHOD_SEND,
-        value: value.clone(),
-        params: RawBytes::default(),
-        nonce: msg_nonce,
-    };
-    let sto = tt.raw_addr().unwrap();
-    let v = value.clone();
-    let cid = h
-        .apply_cross_execute_only(
-            &mut rt,
-            value.clone(),
-            params,
-            Some(Box::new(move |rt| {
-                // expect to send reward message
-                rt.expect_send(
-                    REWARD_ACTOR_ADDR,
-                    ext::reward::EXTERNAL_FUNDING_METHOD,
-                    IpldBlock::serialize_cbor(&ext::reward::FundingParams {
-                        addr: *ACTOR,
-                        value: v.clone(),
-                    })
-                    .unwrap(),
-                    TokenAmount::zero(),
-                    None,
-                    ExitCode::OK,
-                );
-                rt.expect_send(
-                    sto.clone(),
-                    METHOD_SEND,
-                    None,
-                    v.clone(),
-                    None,
-                    ExitCode::OK,
-                );
-            })),
-        )
+    // unfreezing restores the flow of funds
+    h.freeze_subnet(&mut rt, &GOVERNOR, &shid, false, ExitCode::OK)
         .unwrap();
-    assert_eq!(cid.is_none(), true);
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
+    )
+    .unwrap();
 }
 
-/// This test covers the case where the cross_msg's target subnet is not the same as that of
-/// the gateway.
 #[test]
-fn test_apply_msg_tp_not_target_subnet() {
-    // ============== Define Parameters ==============
-    // gateway: /root/sub1
-    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
-
-    let from = Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap();
-    let to = Address::new_bls(&[4; fvm_shared::address::BLS_PUB_LEN]).unwrap();
-
-    // /root/sub1/sub1
-    let sub = SubnetID::new_from_parent(&shid, *SUBNET_ONE);
-
-    // ================ Setup ===============
-    let reg_value = TokenAmount::from_atto(10_u64.pow(18));
-    let (h, mut rt) = setup(shid.clone());
-    h.register(&mut rt, &SUBNET_ONE, &reg_value, ExitCode::OK)
+fn test_max_topdown_value() {
+    let (h, mut rt) = setup_root();
+    let value = TokenAmount::from_atto(10_u64.pow(18));
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    let limit = TokenAmount::from_atto(10_u64.pow(17));
+
+    // only the governor can limit the value of top-down messages
+    h.set_max_topdown_value(
+        &mut rt,
+        &TEST_BLS,
+        &shid,
+        Some(limit.clone()),
+        ExitCode::USR_FORBIDDEN,
+    )
+    .unwrap();
+    h.set_max_topdown_value(
+        &mut rt,
+        &GOVERNOR,
+        &shid,
+        Some(TokenAmount::from_atto(-1)),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+    let unknown = SubnetID::new_from_parent(&h.net_name, *SUBNET_TWO);
+    h.set_max_topdown_value(
+        &mut rt,
+        &GOVERNOR,
+        &unknown,
+        Some(limit.clone()),
+        ExitCode::USR_ILLEGAL_ARGUMENT,
+    )
+    .unwrap();
+    h.set_max_topdown_value(&mut rt, &GOVERNOR, &shid, Some(limit.clone()), ExitCode::OK)
         .unwrap();
-    // add some circulating supply to subnets
-    let funder = Address::new_id(1002);
+    assert_eq!(
+        h.get_subnet(&rt, &shid).unwrap().max_topdown_value,
+        Some(limit.clone())
+    );
+
+    // funding up to the limit is accepted
+    let funder = Address::new_id(1001);
     h.fund(
         &mut rt,
         &funder,
-        &sub,
+        &shid,
         ExitCode::OK,
-        reg_value.clone(),
+        limit.clone(),
         1,
-        &reg_value,
+        &limit,
     )
     .unwrap();
 
-    let value = TokenAmount::from_atto(10_u64.pow(17));
-
-    // ================= Top-Down ===============
-    let ff = IPCAddress::new(&ROOTNET_ID, &from).unwrap();
-    let tt = IPCAddress::new(&sub, &to).unwrap();
-    let msg_nonce = 0;
-
-    // Only system code is allowed to this method
-    let params = StorableMsg {
-        to: tt.clone(),
-        from: ff.clone(),
-        method: METHOD_SEND,
-        value: value.clone(),
-        params: RawBytes::default(),
-        nonce: msg_nonce,
-    };
-    let v = value.clone();
-    // cid is expected, should not be None
-    let cid = h
-        .apply_cross_execute_only(
-            &mut rt,
-            value.clone(),
-            params.clone(),
-            Some(Box::new(move |rt| {
-                // expect to send reward message
-                rt.expect_send(
-                    REWARD_ACTOR_ADDR,
-                    ext::reward::EXTERNAL_FUNDING_METHOD,
-                    IpldBlock::serialize_cbor(&ext::reward::FundingParams {
-                        addr: *ACTOR,
-                        value: v.clone(),
-                    })
-                    .unwrap(),
-                    TokenAmount::zero(),
-                    None,
-                    ExitCode::OK,
-                );
-            })),
-        )
-        .unwrap()
-        .unwrap();
-
-    // Part 1: test the message is stored in postbox
-    let st: State = rt.get_state();
-    assert_ne!(tt.subnet().unwrap(), st.network_name);
-
-    // Check 1: `tt` is in `sub1`, which is not in that of `runtime` of gateway, will store in postbox
-    let item = st.load_from_postbox(rt.store(), cid.clone()).unwrap();
-    assert_eq!(item.owners, Some(vec![ff.clone().raw_addr().unwrap()]));
-    let msg = item.cross_msg.msg;
-    assert_eq!(msg.to, tt);
-    // the nonce should not have changed at all
-    assert_eq!(msg.nonce, msg_nonce);
-    assert_eq!(msg.value, value);
-
-    // Part 2: Now we propagate from postbox
-    // get the original subnet nonce first
-    let starting_nonce = get_subnet(&rt, &tt.subnet().unwrap().down(&h.net_name).unwrap())
-        .unwrap()
-        .nonce;
-    let caller = ff.clone().raw_addr().unwrap();
-
-    // propagated as top-down, so it should distribute a fee in this subnet
+    // but not above it
+    let over = &limit + TokenAmount::from_atto(1);
     rt.expect_send(
-        tt.subnet()
-            .unwrap()
-            .down(&h.net_name)
-            .unwrap()
-            .subnet_actor(),
-        SUBNET_ACTOR_REWARD_METHOD,
-        None,
-        CROSS_MSG_FEE.clone(),
+        funder,
+        ext::account::PUBKEY_ADDRESS_METHOD,
         None,
+        TokenAmount::zero(),
+        IpldBlock::serialize_cbor(&*TEST_BLS).unwrap(),
         ExitCode::OK,
     );
-
-    h.propagate(
+    h.fund(
         &mut rt,
-        caller,
-        cid.clone(),
-        &params.value,
-        TokenAmount::zero(),
+        &funder,
+        &shid,
+        ExitCode::USR_ILLEGAL_STATE,
+        over.clone(),
+        1,
+        &limit,
     )
     .unwrap();
+    assert_eq!(h.get_subnet(&rt, &shid).unwrap().circ_supply, limit);
 
-    // state should be updated, load again
-    let st: State = rt.get_state();
-
-    // cid should be removed from postbox
-    let r = st.load_from_postbox(rt.store(), cid.clone());
-    assert_eq!(r.is_err(), true);
-    let err = r.unwrap_err();
-    assert_eq!(err.to_string(), "cid not found in postbox");
+    // once the limit is removed the value is unlimited
+    h.set_max_topdown_value(&mut rt, &GOVERNOR, &shid, None, ExitCode::OK)
+        .unwrap();
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        over.clone(),
+        2,
+        &(&limit + &over),
+    )
+    .unwrap();
+}
 
-    // the cross msg should have been committed to the next subnet, check this!
-    let sub = get_subnet(&rt, &tt.subnet().unwrap().down(&h.net_name).unwrap()).unwrap();
-    assert_eq!(sub.nonce, starting_nonce + 1);
-    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
-    let msg = get_topdown_msg(&crossmsgs, starting_nonce).unwrap();
-    assert_eq!(msg.is_some(), true);
-    let msg = msg.unwrap();
-    assert_eq!(msg.to, tt);
-    // the nonce should not have changed at all
-    assert_eq!(msg.nonce, starting_nonce);
-    assert_eq!(msg.value, value);
+#[test]
+fn test_dry_run_cross_msg() {
+    let shid = SubnetID::new_from_parent(&ROOTNET_ID, *SUBNET_ONE);
+    let (_, mut rt) = setup(shid.clone());
+
+    let addr = Address::new_id(1001);
+    let cases = vec![
+        (shid.clone(), RouteDecision::Local),
+        (ROOTNET_ID.clone(), RouteDecision::CommitBottomUp),
+        (
+            SubnetID::new_from_parent(&shid, *SUBNET_TWO),
+            RouteDecision::CommitTopDown,
+        ),
+    ];
+    for (to, expected) in cases {
+        let msg = StorableMsg {
+            from: IPCAddress::new(&shid, &addr).unwrap(),
+            to: IPCAddress::new(&to, &addr).unwrap(),
+            method: METHOD_SEND,
+            params: RawBytes::default(),
+            value: TokenAmount::zero(),
+            nonce: 0,
+            memo: Vec::new(),
+            valid_until_epoch: None,
+            correlation_id: None,
+        };
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(
+                Method::DryRunCrossMsg as MethodNum,
+                IpldBlock::serialize_cbor(&msg).unwrap(),
+            )
+            .unwrap();
+        rt.verify();
+        let route: RouteDecision = deserialize_block(ret).unwrap();
+        assert_eq!(route, expected);
+    }
 }
 
+/// Every mutating method keeps the balance of the gateway accounted for by its
+/// state. Run with the `value-accounting` feature.
+#[cfg(feature = "value-accounting")]
 #[test]
-fn test_apply_msg_match_target_subnet() {
+fn test_value_conservation() {
+    use ipc_gateway::accounting;
+
+    let assert_value_conserved =
+        |rt: &MockRuntime| accounting::assert_value_conserved(rt, &rt.get_balance());
+
+    // the mock runtime doesn't credit the value received by the gateway, or the
+    // funds minted for it, so they are credited before every call.
+    let credit = |rt: &mut MockRuntime, value: TokenAmount| {
+        let balance = rt.get_balance();
+        rt.set_balance(balance + value);
+    };
+
+    // ============== Parent of the subnet ==============
     let (h, mut rt) = setup_root();
+    let fees = h.cross_msg_fees.clone();
 
-    // Register a subnet with 1FIL collateral
     let value = TokenAmount::from_atto(10_u64.pow(18));
     h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
         .unwrap();
+    assert_value_conserved(&rt);
     let shid = SubnetID::new_from_parent(&h.net_name, *SUBNET_ONE);
+    credit(&mut rt, value.clone());
+    h.add_stake(&mut rt, &shid, &value, ExitCode::OK).unwrap();
+    assert_value_conserved(&rt);
 
-    // inject some funds
-    let funder_id = Address::new_id(1001);
-    let funder = IPCAddress::new(
-        &shid.parent().unwrap(),
-        &Address::new_bls(&[3; fvm_shared::address::BLS_PUB_LEN]).unwrap(),
-    )
-    .unwrap();
-    let amount = TokenAmount::from_atto(10_u64.pow(18));
+    let funder = Address::new_id(1001);
+    let amount = TokenAmount::from_atto(10_u64.pow(17));
+    credit(&mut rt, &amount + &fees.fund);
     h.fund(
         &mut rt,
-        &funder_id,
+        &funder,
         &shid,
         ExitCode::OK,
         amount.clone(),
@@ -1133,47 +5727,176 @@ fn test_apply_msg_match_target_subnet() {
         &amount,
     )
     .unwrap();
+    assert_value_conserved(&rt);
+    credit(&mut rt, &amount + &fees.fund);
+    h.fund(
+        &mut rt,
+        &funder,
+        &shid,
+        ExitCode::OK,
+        amount.clone(),
+        2,
+        &(amount.clone() * 2),
+    )
+    .unwrap();
+    assert_value_conserved(&rt);
+    h.cancel_fund(&mut rt, &funder, &shid, 0, ExitCode::OK)
+        .unwrap();
+    assert_value_conserved(&rt);
 
-    // Apply fund messages
-    for i in 0..5 {
-        h.apply_cross_msg(&mut rt, &funder, &funder, value.clone(), i, i, ExitCode::OK)
-            .unwrap();
-    }
-    // Apply release messages
-    let from = IPCAddress::new(&shid, &BURNT_FUNDS_ACTOR_ADDR).unwrap();
-    // with the same nonce
-    for _ in 0..5 {
-        h.apply_cross_msg(&mut rt, &from, &funder, value.clone(), 0, 0, ExitCode::OK)
-            .unwrap();
-    }
-    // with increasing nonce
-    for i in 0..5 {
-        h.apply_cross_msg(&mut rt, &from, &funder, value.clone(), i, i, ExitCode::OK)
-            .unwrap();
-    }
+    // the checkpoint releases the circulating supply and pays fees, along with
+    // the fees held for the top-down messages applied, and refunds the ones
+    // skipped. The value of its messages is held until they are applied.
+    let epoch: ChainEpoch = 10;
+    rt.set_epoch(epoch);
+    let mut ch = Checkpoint::new(shid.clone(), epoch + 9);
+    ch.data.applied_topdown_nonce = 2;
+    ch.data.cancelled_topdown_msgs = vec![0];
+    let fee = TokenAmount::from_atto(5);
+    set_msg_meta(
+        rt.store(),
+        &mut ch,
+        "rand1".as_bytes().to_vec(),
+        amount.clone(),
+        fee.clone(),
+    );
+    rt.expect_send(
+        shid.subnet_actor(),
+        SUBNET_ACTOR_REWARD_METHOD,
+        None,
+        &fee + &fees.fund,
+        None,
+        ExitCode::OK,
+    );
+    rt.expect_send(
+        *TEST_BLS,
+        METHOD_SEND,
+        None,
+        &amount + &fees.fund,
+        None,
+        ExitCode::OK,
+    );
+    h.commit_child_check(&mut rt, &shid, &ch, ExitCode::OK)
+        .unwrap();
+    assert_value_conserved(&rt);
 
-    // trying to apply non-subsequent nonce.
-    h.apply_cross_msg(
+    // applying the bottom-up message sends the value released
+    let released = &amount - &fee;
+    let msg = StorableMsg {
+        from: IPCAddress::new(&shid, &TEST_BLS).unwrap(),
+        to: IPCAddress::new(&ROOTNET_ID, &TEST_BLS).unwrap(),
+        method: METHOD_SEND,
+        params: RawBytes::default(),
+        value: released.clone(),
+        nonce: 0,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
+    };
+    let balance = rt.get_balance();
+    h.apply_cross_execute_only(
+        &mut rt,
+        balance,
+        msg,
+        Some(Box::new(move |rt| {
+            rt.expect_send(
+                *TEST_BLS,
+                METHOD_SEND,
+                None,
+                released.clone(),
+                None,
+                ExitCode::OK,
+            );
+        })),
+    )
+    .unwrap();
+    assert_value_conserved(&rt);
+
+    h.release_stake(&mut rt, &shid, &value, ExitCode::OK)
+        .unwrap();
+    assert_value_conserved(&rt);
+    h.kill(&mut rt, &shid, &value, ExitCode::OK).unwrap();
+    assert_value_conserved(&rt);
+
+    // ============== Child subnet ==============
+    let (h, mut rt) = setup(shid.clone());
+
+    h.register(&mut rt, &SUBNET_ONE, &value, ExitCode::OK)
+        .unwrap();
+    assert_value_conserved(&rt);
+
+    // the value released is burnt, and its fee is kept in the checkpoint
+    credit(&mut rt, &amount + &fees.release);
+    h.release(
         &mut rt,
-        &from,
         &funder,
-        value.clone(),
-        10,
+        ExitCode::OK,
+        amount.clone(),
         0,
-        ExitCode::USR_ILLEGAL_STATE,
+        &Cid::default(),
+        fees.release.clone(),
     )
     .unwrap();
-    // trying already applied nonce
-    h.apply_cross_msg(
+    assert_value_conserved(&rt);
+
+    let from = Address::new_id(1001);
+    let to = Address::new_id(1002);
+    let sub = SubnetID::new_from_parent(&shid, *SUBNET_ONE);
+    credit(&mut rt, &amount + &fees.send_cross);
+    h.send_cross(
         &mut rt,
         &from,
-        &funder,
-        value.clone(),
-        0,
-        0,
-        ExitCode::USR_ILLEGAL_STATE,
+        &shid,
+        &to,
+        sub,
+        ExitCode::OK,
+        amount.clone(),
+        1,
+        &amount,
     )
     .unwrap();
+    assert_value_conserved(&rt);
 
-    // TODO: Trying to release over circulating supply
+    // applying a top-down message mints and sends its value
+    let msg = StorableMsg {
+        from: IPCAddress::new(&ROOTNET_ID, &TEST_BLS).unwrap(),
+        to: IPCAddress::new(&shid, &TEST_BLS).unwrap(),
+        method: METHOD_SEND,
+        params: RawBytes::default(),
+        value: amount.clone(),
+        nonce: 0,
+        memo: Vec::new(),
+        valid_until_epoch: None,
+        correlation_id: None,
+    };
+    let balance = rt.get_balance() + &amount;
+    h.apply_cross_execute_only(
+        &mut rt,
+        balance,
+        msg,
+        Some(Box::new(move |rt| {
+            rt.expect_send(
+                REWARD_ACTOR_ADDR,
+                ext::reward::EXTERNAL_FUNDING_METHOD,
+                IpldBlock::serialize_cbor(&ext::reward::FundingParams {
+                    addr: *ACTOR,
+                    value: amount.clone(),
+                })
+                .unwrap(),
+                TokenAmount::zero(),
+                None,
+                ExitCode::OK,
+            );
+            rt.expect_send(
+                *TEST_BLS,
+                METHOD_SEND,
+                None,
+                amount.clone(),
+                None,
+                ExitCode::OK,
+            );
+        })),
+    )
+    .unwrap();
+    assert_value_conserved(&rt);
 }
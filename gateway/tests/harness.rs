@@ -1,6 +1,5 @@
 use anyhow::anyhow;
 use cid::multihash::Code;
-use cid::multihash::MultihashDigest;
 use cid::Cid;
 use fil_actors_runtime::builtin::HAMT_BIT_WIDTH;
 use fil_actors_runtime::deserialize_block;
@@ -17,10 +16,11 @@ use fil_actors_runtime::{
 use fil_actors_runtime::{Array, INIT_ACTOR_ADDR};
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::ipld_block::IpldBlock;
-use fvm_ipld_encoding::RawBytes;
+use fvm_ipld_encoding::{CborStore, RawBytes};
 use fvm_shared::address::Address;
 use fvm_shared::bigint::bigint_ser::BigIntDe;
 use fvm_shared::bigint::Zero;
+use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
 use fvm_shared::MethodNum;
@@ -28,10 +28,16 @@ use fvm_shared::METHOD_SEND;
 use ipc_gateway::checkpoint::ChildCheck;
 use ipc_gateway::SUBNET_ACTOR_REWARD_METHOD;
 use ipc_gateway::{
-    ext, get_topdown_msg, is_bottomup, Actor, ApplyMsgParams, Checkpoint, ConstructorParams,
-    CrossMsg, CrossMsgMeta, CrossMsgParams, CrossMsgs, FundParams, IPCAddress, IPCMsgType, Method,
-    PropagateParams, State, StorableMsg, Subnet, SubnetID, CROSSMSG_AMT_BITWIDTH, CROSS_MSG_FEE,
-    DEFAULT_CHECKPOINT_PERIOD, MAX_NONCE, MIN_COLLATERAL_AMOUNT,
+    ext, get_topdown_msg, is_bottomup, Actor, ActorInfo, AdjustCircSupplyParams, ApplyMsgParams,
+    ApplyMsgResult, ApplyMsgsParams, BottomUpFeePolicy, BulkRegisterParams, CancelFundParams,
+    Checkpoint, Collateralization, ConstructorParams, CrossMsg, CrossMsgFees, CrossMsgMeta,
+    CrossMsgParams, CrossMsgStatus, CrossMsgs, DeadLetter, ForceKillParams, FundOnBehalfParams,
+    FundParams, FundWithTargetParams, GatewayStats, GenesisSubnet, GetBottomUpMsgParams,
+    GetCrossMsgStatusParams, IPCAddress, IPCMsgType, Method, PostboxOverflowPolicy,
+    PropagateBatchParams, PropagateBatchReturn, PropagateParams, ProposeGovActionParams,
+    QueueDepths, ReplacePostboxOwnersParams, SendCrossFeePolicy, SetGovernorsParams,
+    SetMaxTopDownValueParams, State, StorableMsg, Subnet, SubnetID, CROSSMSG_AMT_BITWIDTH,
+    DEFAULT_CHECKPOINT_PERIOD, DEFAULT_MAX_MSGS_PER_CHECKPOINT, MAX_NONCE, MIN_COLLATERAL_AMOUNT,
 };
 use lazy_static::lazy_static;
 use primitives::{TCid, TCidContent};
@@ -46,6 +52,7 @@ lazy_static! {
     pub static ref TEST_BLS: Address =
         Address::new_bls(&[1; fvm_shared::address::BLS_PUB_LEN]).unwrap();
     pub static ref ACTOR: Address = Address::new_actor("actor".as_bytes());
+    pub static ref GOVERNOR: Address = Address::new_id(900);
     pub static ref SIG_TYPES: Vec<Cid> = vec![*ACCOUNT_ACTOR_CODE_ID, *MULTISIG_ACTOR_CODE_ID];
 }
 
@@ -59,7 +66,20 @@ pub fn new_runtime() -> MockRuntime {
 }
 
 pub fn new_harness(id: SubnetID) -> Harness {
-    Harness { net_name: id }
+    Harness {
+        net_name: id,
+        registration_allowlist: None,
+        send_cross_fee_policy: SendCrossFeePolicy::Deduct,
+        checkpoint_retention: 0,
+        allow_local_cross: false,
+        bottomup_fee_policy: BottomUpFeePolicy::Committer,
+        fee_exempt: Vec::new(),
+        max_postbox_items: 0,
+        postbox_overflow_policy: PostboxOverflowPolicy::EvictExpired,
+        min_cross_value: TokenAmount::zero(),
+        cross_msg_fees: CrossMsgFees::default(),
+        require_actor_destination: false,
+    }
 }
 
 pub fn setup_root() -> (Harness, MockRuntime) {
@@ -76,6 +96,17 @@ pub fn setup(id: SubnetID) -> (Harness, MockRuntime) {
 #[allow(dead_code)]
 pub struct Harness {
     pub net_name: SubnetID,
+    pub registration_allowlist: Option<Vec<Address>>,
+    pub send_cross_fee_policy: SendCrossFeePolicy,
+    pub checkpoint_retention: u64,
+    pub allow_local_cross: bool,
+    pub bottomup_fee_policy: BottomUpFeePolicy,
+    pub fee_exempt: Vec<Address>,
+    pub max_postbox_items: u64,
+    pub postbox_overflow_policy: PostboxOverflowPolicy,
+    pub min_cross_value: TokenAmount,
+    pub cross_msg_fees: CrossMsgFees,
+    pub require_actor_destination: bool,
 }
 
 impl Harness {
@@ -84,6 +115,20 @@ impl Harness {
         let params = ConstructorParams {
             network_name: self.net_name.to_string(),
             checkpoint_period: 10,
+            max_msgs_per_checkpoint: 0,
+            max_apply_attempts: 0,
+            governor: *GOVERNOR,
+            registration_allowlist: self.registration_allowlist.clone(),
+            send_cross_fee_policy: self.send_cross_fee_policy,
+            checkpoint_retention: self.checkpoint_retention,
+            allow_local_cross: self.allow_local_cross,
+            bottomup_fee_policy: self.bottomup_fee_policy,
+            fee_exempt: self.fee_exempt.clone(),
+            max_postbox_items: self.max_postbox_items,
+            postbox_overflow_policy: self.postbox_overflow_policy,
+            min_cross_value: self.min_cross_value.clone(),
+            cross_msg_fees: self.cross_msg_fees.clone(),
+            require_actor_destination: self.require_actor_destination,
         };
         rt.set_caller(*INIT_ACTOR_CODE_ID, INIT_ACTOR_ADDR);
         rt.call::<Actor>(
@@ -105,6 +150,10 @@ impl Harness {
         assert_eq!(st.network_name, self.net_name);
         assert_eq!(st.min_stake, TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
         assert_eq!(st.check_period, DEFAULT_CHECKPOINT_PERIOD);
+        assert_eq!(st.max_msgs_per_checkpoint, DEFAULT_MAX_MSGS_PER_CHECKPOINT);
+        assert_eq!(st.governor, *GOVERNOR);
+        assert_eq!(st.registration_allowlist, self.registration_allowlist);
+        assert_eq!(st.send_cross_fee_policy, self.send_cross_fee_policy);
         assert_eq!(st.applied_bottomup_nonce, MAX_NONCE);
         assert_eq!(st.bottomup_msg_meta.cid(), empty_bottomup_array);
         verify_empty_map(rt, st.subnets.cid());
@@ -140,6 +189,346 @@ impl Harness {
         Ok(())
     }
 
+    pub fn bulk_register(
+        &self,
+        rt: &mut MockRuntime,
+        subnets: Vec<GenesisSubnet>,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
+        rt.set_value(TokenAmount::zero());
+        rt.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR, INIT_ACTOR_ADDR]);
+
+        let params = BulkRegisterParams { subnets };
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::BulkRegister as MethodNum,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(
+            Method::BulkRegister as MethodNum,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        )?;
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn allow_registration(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        subnet_actor: &Address,
+        allow: bool,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+
+        let method = if allow {
+            Method::AllowRegistration
+        } else {
+            Method::DisallowRegistration
+        };
+        let params = IpldBlock::serialize_cbor(subnet_actor).unwrap();
+        if code != ExitCode::OK {
+            expect_abort(code, rt.call::<Actor>(method as MethodNum, params));
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(method as MethodNum, params)?;
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn set_safe_mode(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        enabled: bool,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+
+        let params = IpldBlock::serialize_cbor(&enabled).unwrap();
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::SetSafeMode as MethodNum, params),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(Method::SetSafeMode as MethodNum, params)?;
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn set_allow_reregister(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        allow: bool,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+
+        let params = IpldBlock::serialize_cbor(&allow).unwrap();
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::SetAllowReregister as MethodNum, params),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(Method::SetAllowReregister as MethodNum, params)?;
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn set_governors(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        governors: Vec<Address>,
+        threshold: u64,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+
+        let params = IpldBlock::serialize_cbor(&SetGovernorsParams {
+            governors,
+            threshold,
+        })
+        .unwrap();
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::SetGovernors as MethodNum, params),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(Method::SetGovernors as MethodNum, params)?;
+        rt.verify();
+        Ok(())
+    }
+
+    /// Proposes a governance action. If `execute` is set the proposal is
+    /// expected to reach the threshold and be sent to the gateway itself.
+    #[allow(clippy::too_many_arguments)]
+    pub fn propose_gov_action(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        governors: Vec<Address>,
+        method: MethodNum,
+        params: RawBytes,
+        execute: bool,
+        code: ExitCode,
+    ) -> Result<u64, ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(governors);
+
+        let call_params = IpldBlock::serialize_cbor(&ProposeGovActionParams {
+            method,
+            params: params.clone(),
+        })
+        .unwrap();
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::ProposeGovAction as MethodNum, call_params),
+            );
+            rt.verify();
+            return Ok(0);
+        }
+
+        if execute {
+            rt.expect_send(
+                *ACTOR,
+                method,
+                params.into(),
+                TokenAmount::zero(),
+                None,
+                ExitCode::OK,
+            );
+        }
+        let ret = rt.call::<Actor>(Method::ProposeGovAction as MethodNum, call_params)?;
+        rt.verify();
+        Ok(deserialize_block(ret).unwrap())
+    }
+
+    /// Approves a governance action. If `execute` is set the approval is
+    /// expected to reach the threshold and send `method` to the gateway itself.
+    #[allow(clippy::too_many_arguments)]
+    pub fn approve_gov_action(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        governors: Vec<Address>,
+        id: u64,
+        execute: Option<(MethodNum, RawBytes)>,
+        code: ExitCode,
+    ) -> Result<bool, ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(governors);
+
+        let params = IpldBlock::serialize_cbor(&id).unwrap();
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::ApproveGovAction as MethodNum, params),
+            );
+            rt.verify();
+            return Ok(false);
+        }
+
+        if let Some((method, params)) = execute {
+            rt.expect_send(
+                *ACTOR,
+                method,
+                params.into(),
+                TokenAmount::zero(),
+                None,
+                ExitCode::OK,
+            );
+        }
+        let ret = rt.call::<Actor>(Method::ApproveGovAction as MethodNum, params)?;
+        rt.verify();
+        Ok(deserialize_block(ret).unwrap())
+    }
+
+    pub fn adjust_circ_supply(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        subnet: &SubnetID,
+        delta: TokenAmount,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+
+        let params = IpldBlock::serialize_cbor(&AdjustCircSupplyParams {
+            subnet: subnet.clone(),
+            delta,
+        })
+        .unwrap();
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::AdjustCircSupply as MethodNum, params),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(Method::AdjustCircSupply as MethodNum, params)?;
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn set_fee_exemption(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        addr: &Address,
+        exempt: bool,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+
+        let method = if exempt {
+            Method::AddFeeExemption
+        } else {
+            Method::RemoveFeeExemption
+        };
+        let params = IpldBlock::serialize_cbor(addr).unwrap();
+        if code != ExitCode::OK {
+            expect_abort(code, rt.call::<Actor>(method as MethodNum, params));
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(method as MethodNum, params)?;
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn freeze_subnet(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        id: &SubnetID,
+        freeze: bool,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+
+        let method = if freeze {
+            Method::FreezeSubnet
+        } else {
+            Method::UnfreezeSubnet
+        };
+        let params = IpldBlock::serialize_cbor(id).unwrap();
+        if code != ExitCode::OK {
+            expect_abort(code, rt.call::<Actor>(method as MethodNum, params));
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(method as MethodNum, params)?;
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn set_max_topdown_value(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        id: &SubnetID,
+        max: Option<TokenAmount>,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+
+        let params = IpldBlock::serialize_cbor(&SetMaxTopDownValueParams {
+            subnet: id.clone(),
+            max,
+        })
+        .unwrap();
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::SetMaxTopDownValue as MethodNum, params),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(Method::SetMaxTopDownValue as MethodNum, params)?;
+        rt.verify();
+        Ok(())
+    }
+
     pub fn add_stake(
         &self,
         rt: &mut MockRuntime,
@@ -164,25 +553,521 @@ impl Harness {
         Ok(())
     }
 
-    pub fn release_stake(
+    pub fn reactivate_subnet(
+        &self,
+        rt: &mut MockRuntime,
+        id: &SubnetID,
+        code: ExitCode,
+    ) -> Option<bool> {
+        rt.set_caller(*SUBNET_ACTOR_CODE_ID, id.subnet_actor());
+        rt.expect_validate_caller_any();
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::ReactivateSubnet as MethodNum, None),
+            );
+            rt.verify();
+            return None;
+        }
+
+        let ret = rt
+            .call::<Actor>(Method::ReactivateSubnet as MethodNum, None)
+            .unwrap();
+        rt.verify();
+        Some(deserialize_block(ret).unwrap())
+    }
+
+    pub fn release_stake(
+        &self,
+        rt: &mut MockRuntime,
+        id: &SubnetID,
+        value: &TokenAmount,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*SUBNET_ACTOR_CODE_ID, id.subnet_actor());
+        rt.expect_validate_caller_any();
+
+        let params = FundParams {
+            value: value.clone(),
+        };
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::ReleaseStake as MethodNum,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.expect_send(
+            id.subnet_actor(),
+            METHOD_SEND,
+            None,
+            value.clone(),
+            None,
+            ExitCode::OK,
+        );
+        rt.call::<Actor>(
+            Method::ReleaseStake as MethodNum,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        )
+        .unwrap();
+        rt.verify();
+
+        Ok(())
+    }
+
+    pub fn kill(
+        &self,
+        rt: &mut MockRuntime,
+        id: &SubnetID,
+        release_value: &TokenAmount,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*SUBNET_ACTOR_CODE_ID, id.subnet_actor());
+        rt.expect_validate_caller_any();
+
+        if code != ExitCode::OK {
+            expect_abort(code, rt.call::<Actor>(Method::Kill as MethodNum, None));
+            rt.verify();
+            return Ok(());
+        }
+
+        let held_fee = get_held_topdown_fee(rt, id);
+        rt.expect_send(
+            id.subnet_actor(),
+            METHOD_SEND,
+            None,
+            release_value.clone(),
+            None,
+            ExitCode::OK,
+        );
+        // the fees held for the messages it didn't apply are distributed
+        if !held_fee.is_zero() {
+            rt.expect_send(
+                id.subnet_actor(),
+                SUBNET_ACTOR_REWARD_METHOD,
+                None,
+                held_fee,
+                None,
+                ExitCode::OK,
+            );
+        }
+        rt.call::<Actor>(Method::Kill as MethodNum, None).unwrap();
+        rt.verify();
+
+        Ok(())
+    }
+
+    pub fn force_kill(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        params: ForceKillParams,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_addr(vec![*GOVERNOR]);
+
+        let params = IpldBlock::serialize_cbor(&params).unwrap();
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::ForceKill as MethodNum, params),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(Method::ForceKill as MethodNum, params)?;
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn commit_child_check(
+        &self,
+        rt: &mut MockRuntime,
+        id: &SubnetID,
+        ch: &Checkpoint,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*SUBNET_ACTOR_CODE_ID, id.subnet_actor());
+        rt.expect_validate_caller_any();
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::CommitChildCheckpoint as MethodNum,
+                    IpldBlock::serialize_cbor(&ch).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+        rt.call::<Actor>(
+            Method::CommitChildCheckpoint as MethodNum,
+            IpldBlock::serialize_cbor(&ch).unwrap(),
+        )
+        .unwrap();
+        rt.verify();
+
+        Ok(())
+    }
+
+    pub fn get_prev_checkpoint(
+        &self,
+        rt: &mut MockRuntime,
+        id: &SubnetID,
+        code: ExitCode,
+    ) -> Result<Option<Checkpoint>, ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::GetPrevCheckpoint as MethodNum,
+                    IpldBlock::serialize_cbor(&id).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(None);
+        }
+
+        let ret = rt.call::<Actor>(
+            Method::GetPrevCheckpoint as MethodNum,
+            IpldBlock::serialize_cbor(&id).unwrap(),
+        )?;
+        rt.verify();
+        Ok(deserialize_block(ret).unwrap())
+    }
+
+    pub fn get_last_committed_epoch(
+        &self,
+        rt: &mut MockRuntime,
+        id: &SubnetID,
+    ) -> Option<ChainEpoch> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(
+                Method::GetLastCommittedEpoch as MethodNum,
+                IpldBlock::serialize_cbor(&id).unwrap(),
+            )
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn get_pending_fee_distribution(&self, rt: &mut MockRuntime) -> TokenAmount {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(Method::GetPendingFeeDistribution as MethodNum, None)
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn prune_checkpoints(&self, rt: &mut MockRuntime) -> u64 {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(Method::PruneCheckpoints as MethodNum, None)
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn get_queue_depths(&self, rt: &mut MockRuntime) -> QueueDepths {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(Method::GetQueueDepths as MethodNum, None)
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn get_gateway_stats(&self, rt: &mut MockRuntime) -> GatewayStats {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(Method::GetGatewayStats as MethodNum, None)
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn get_actor_info(&self, rt: &mut MockRuntime) -> ActorInfo {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(Method::GetActorInfo as MethodNum, None)
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn get_cross_msg_status(
+        &self,
+        rt: &mut MockRuntime,
+        cid: &Cid,
+        topdown: Option<(SubnetID, u64)>,
+        epoch: Option<ChainEpoch>,
+    ) -> CrossMsgStatus {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let params = GetCrossMsgStatusParams {
+            cid: *cid,
+            topdown,
+            epoch,
+        };
+        let ret = rt
+            .call::<Actor>(
+                Method::GetCrossMsgStatus as MethodNum,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            )
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn get_bottomup_msg(
+        &self,
+        rt: &mut MockRuntime,
+        epoch: ChainEpoch,
+        nonce: u64,
+    ) -> Option<CrossMsg> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(
+                Method::GetBottomUpMsg as MethodNum,
+                IpldBlock::serialize_cbor(&GetBottomUpMsgParams { epoch, nonce }).unwrap(),
+            )
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn get_collateralization(&self, rt: &mut MockRuntime, id: &SubnetID) -> Collateralization {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(
+                Method::GetCollateralization as MethodNum,
+                IpldBlock::serialize_cbor(id).unwrap(),
+            )
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn compute_subnet_id(&self, rt: &mut MockRuntime, addr: &Address) -> SubnetID {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(
+                Method::ComputeSubnetID as MethodNum,
+                IpldBlock::serialize_cbor(addr).unwrap(),
+            )
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn get_ancestry(
+        &self,
+        rt: &mut MockRuntime,
+        id: &SubnetID,
+        code: ExitCode,
+    ) -> Result<Vec<SubnetID>, ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::GetAncestry as MethodNum,
+                    IpldBlock::serialize_cbor(id).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(vec![]);
+        }
+
+        let ret = rt.call::<Actor>(
+            Method::GetAncestry as MethodNum,
+            IpldBlock::serialize_cbor(id).unwrap(),
+        )?;
+        rt.verify();
+        Ok(deserialize_block(ret).unwrap())
+    }
+
+    pub fn get_total_burned(&self, rt: &mut MockRuntime) -> TokenAmount {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(Method::GetTotalBurned as MethodNum, None)
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn get_state_root(&self, rt: &mut MockRuntime) -> Cid {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(Method::GetStateRoot as MethodNum, None)
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn get_total_fees_collected(&self, rt: &mut MockRuntime) -> TokenAmount {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(Method::GetTotalFeesCollected as MethodNum, None)
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    pub fn list_dead_letters(&self, rt: &mut MockRuntime) -> Vec<DeadLetter> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *TEST_BLS);
+        rt.expect_validate_caller_any();
+        let ret = rt
+            .call::<Actor>(Method::ListDeadLetters as MethodNum, None)
+            .unwrap();
+        rt.verify();
+        deserialize_block(ret).unwrap()
+    }
+
+    /// Calls `RetryDeadLetter` or `RefundDeadLetter` for the dead letter with the given cid.
+    pub fn handle_dead_letter(
+        &self,
+        rt: &mut MockRuntime,
+        caller: &Address,
+        method: Method,
+        cid: &Cid,
+        code: ExitCode,
+    ) -> Result<RawBytes, ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *caller);
+        rt.expect_validate_caller_any();
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    method as MethodNum,
+                    IpldBlock::serialize_cbor(&cid).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(RawBytes::default());
+        }
+
+        let ret = rt.call::<Actor>(
+            method as MethodNum,
+            IpldBlock::serialize_cbor(&cid).unwrap(),
+        )?;
+        rt.verify();
+        Ok(deserialize_block(ret).unwrap())
+    }
+
+    pub fn fund(
+        &self,
+        rt: &mut MockRuntime,
+        funder: &Address,
+        id: &SubnetID,
+        code: ExitCode,
+        value: TokenAmount,
+        expected_nonce: u64,
+        expected_circ_sup: &TokenAmount,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *funder);
+        rt.expect_validate_caller_type(SIG_TYPES.clone());
+
+        // set value and include the cross_msg_fee
+        set_rt_value_with_cross_fee(rt, &value, &self.cross_msg_fees.fund);
+
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::Fund as MethodNum,
+                    IpldBlock::serialize_cbor(&id).unwrap(),
+                ),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.expect_send(
+            *funder,
+            ext::account::PUBKEY_ADDRESS_METHOD,
+            None,
+            TokenAmount::zero(),
+            IpldBlock::serialize_cbor(&*TEST_BLS).unwrap(),
+            ExitCode::OK,
+        );
+        rt.call::<Actor>(
+            Method::Fund as MethodNum,
+            IpldBlock::serialize_cbor(&id).unwrap(),
+        )
+        .unwrap();
+        rt.verify();
+
+        let sub = self.get_subnet(rt, id).unwrap();
+        let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+        let msg = get_topdown_msg(&crossmsgs, expected_nonce - 1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(&sub.circ_supply, expected_circ_sup);
+        assert_eq!(sub.nonce, expected_nonce);
+        let from = IPCAddress::new(&self.net_name, &*TEST_BLS).unwrap();
+        let to = IPCAddress::new(&id, &TEST_BLS).unwrap();
+        assert_eq!(msg.from, from);
+        assert_eq!(msg.to, to);
+        assert_eq!(msg.nonce, expected_nonce - 1);
+        assert_eq!(msg.value, value);
+
+        Ok(())
+    }
+
+    pub fn fund_with_target(
         &self,
         rt: &mut MockRuntime,
+        funder: &Address,
         id: &SubnetID,
-        value: &TokenAmount,
+        value: TokenAmount,
+        target_amount: TokenAmount,
         code: ExitCode,
     ) -> Result<(), ActorError> {
-        rt.set_caller(*SUBNET_ACTOR_CODE_ID, id.subnet_actor());
-        rt.expect_validate_caller_any();
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *funder);
+        rt.expect_validate_caller_type(SIG_TYPES.clone());
+        rt.set_value(value.clone());
+        rt.set_balance(value.clone());
 
-        let params = FundParams {
-            value: value.clone(),
+        let params = FundWithTargetParams {
+            subnet: id.clone(),
+            target_amount: target_amount.clone(),
         };
-
         if code != ExitCode::OK {
             expect_abort(
                 code,
                 rt.call::<Actor>(
-                    Method::ReleaseStake as MethodNum,
+                    Method::FundWithTarget as MethodNum,
                     IpldBlock::serialize_cbor(&params).unwrap(),
                 ),
             );
@@ -191,149 +1076,158 @@ impl Harness {
         }
 
         rt.expect_send(
-            id.subnet_actor(),
-            METHOD_SEND,
-            None,
-            value.clone(),
+            *funder,
+            ext::account::PUBKEY_ADDRESS_METHOD,
             None,
+            TokenAmount::zero(),
+            IpldBlock::serialize_cbor(&*TEST_BLS).unwrap(),
             ExitCode::OK,
         );
+        let surplus = value - target_amount - self.cross_msg_fees.fund.clone();
+        if surplus > TokenAmount::zero() {
+            rt.expect_send(*funder, METHOD_SEND, None, surplus, None, ExitCode::OK);
+        }
         rt.call::<Actor>(
-            Method::ReleaseStake as MethodNum,
+            Method::FundWithTarget as MethodNum,
             IpldBlock::serialize_cbor(&params).unwrap(),
-        )
-        .unwrap();
+        )?;
         rt.verify();
 
         Ok(())
     }
 
-    pub fn kill(
+    pub fn fund_on_behalf(
         &self,
         rt: &mut MockRuntime,
+        funder: &Address,
         id: &SubnetID,
-        release_value: &TokenAmount,
+        beneficiary: &Address,
+        value: TokenAmount,
         code: ExitCode,
     ) -> Result<(), ActorError> {
-        rt.set_caller(*SUBNET_ACTOR_CODE_ID, id.subnet_actor());
-        rt.expect_validate_caller_any();
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *funder);
+        rt.expect_validate_caller_type(SIG_TYPES.clone());
+        set_rt_value_with_cross_fee(rt, &value, &self.cross_msg_fees.fund);
 
+        let params = FundOnBehalfParams {
+            subnet: id.clone(),
+            beneficiary: *beneficiary,
+        };
         if code != ExitCode::OK {
-            expect_abort(code, rt.call::<Actor>(Method::Kill as MethodNum, None));
+            expect_abort(
+                code,
+                rt.call::<Actor>(
+                    Method::FundOnBehalf as MethodNum,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                ),
+            );
             rt.verify();
             return Ok(());
         }
 
         rt.expect_send(
-            id.subnet_actor(),
-            METHOD_SEND,
-            None,
-            release_value.clone(),
+            *funder,
+            ext::account::PUBKEY_ADDRESS_METHOD,
             None,
+            TokenAmount::zero(),
+            IpldBlock::serialize_cbor(&*TEST_BLS).unwrap(),
             ExitCode::OK,
         );
-        rt.call::<Actor>(Method::Kill as MethodNum, None).unwrap();
+        rt.call::<Actor>(
+            Method::FundOnBehalf as MethodNum,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        )?;
         rt.verify();
 
         Ok(())
     }
 
-    pub fn commit_child_check(
+    pub fn fund_multi(
         &self,
         rt: &mut MockRuntime,
-        id: &SubnetID,
-        ch: &Checkpoint,
+        funder: &Address,
+        funds: &[(SubnetID, TokenAmount)],
+        value: TokenAmount,
         code: ExitCode,
     ) -> Result<(), ActorError> {
-        rt.set_caller(*SUBNET_ACTOR_CODE_ID, id.subnet_actor());
-        rt.expect_validate_caller_any();
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *funder);
+        rt.expect_validate_caller_type(SIG_TYPES.clone());
+        rt.set_value(value);
+
+        // the sender is only resolved once the params have been validated.
+        if code != ExitCode::USR_ILLEGAL_ARGUMENT {
+            rt.expect_send(
+                *funder,
+                ext::account::PUBKEY_ADDRESS_METHOD,
+                None,
+                TokenAmount::zero(),
+                IpldBlock::serialize_cbor(&*TEST_BLS).unwrap(),
+                ExitCode::OK,
+            );
+        }
 
         if code != ExitCode::OK {
             expect_abort(
                 code,
                 rt.call::<Actor>(
-                    Method::CommitChildCheckpoint as MethodNum,
-                    IpldBlock::serialize_cbor(&ch).unwrap(),
+                    Method::FundMulti as MethodNum,
+                    IpldBlock::serialize_cbor(&funds).unwrap(),
                 ),
             );
             rt.verify();
             return Ok(());
         }
+
         rt.call::<Actor>(
-            Method::CommitChildCheckpoint as MethodNum,
-            IpldBlock::serialize_cbor(&ch).unwrap(),
-        )
-        .unwrap();
+            Method::FundMulti as MethodNum,
+            IpldBlock::serialize_cbor(&funds).unwrap(),
+        )?;
         rt.verify();
-
         Ok(())
     }
 
-    pub fn fund(
+    pub fn cancel_fund(
         &self,
         rt: &mut MockRuntime,
         funder: &Address,
         id: &SubnetID,
+        nonce: u64,
         code: ExitCode,
-        value: TokenAmount,
-        expected_nonce: u64,
-        expected_circ_sup: &TokenAmount,
     ) -> Result<(), ActorError> {
         rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *funder);
+        rt.set_value(TokenAmount::zero());
         rt.expect_validate_caller_type(SIG_TYPES.clone());
+        rt.expect_send(
+            *funder,
+            ext::account::PUBKEY_ADDRESS_METHOD,
+            None,
+            TokenAmount::zero(),
+            IpldBlock::serialize_cbor(&*TEST_BLS).unwrap(),
+            ExitCode::OK,
+        );
 
-        // set value and include the cross_msg_fee
-        set_rt_value_with_cross_fee(rt, &value);
-
+        let params = CancelFundParams {
+            subnet: id.clone(),
+            nonce,
+        };
         if code != ExitCode::OK {
             expect_abort(
                 code,
                 rt.call::<Actor>(
-                    Method::Fund as MethodNum,
-                    IpldBlock::serialize_cbor(&id).unwrap(),
+                    Method::CancelFund as MethodNum,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
                 ),
             );
             rt.verify();
             return Ok(());
         }
 
-        rt.expect_send(
-            *funder,
-            ext::account::PUBKEY_ADDRESS_METHOD,
-            None,
-            TokenAmount::zero(),
-            IpldBlock::serialize_cbor(&*TEST_BLS).unwrap(),
-            ExitCode::OK,
-        );
-        rt.expect_send(
-            id.subnet_actor(),
-            SUBNET_ACTOR_REWARD_METHOD,
-            None,
-            CROSS_MSG_FEE.clone(),
-            None,
-            ExitCode::OK,
-        );
         rt.call::<Actor>(
-            Method::Fund as MethodNum,
-            IpldBlock::serialize_cbor(&id).unwrap(),
-        )
-        .unwrap();
+            Method::CancelFund as MethodNum,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        )?;
         rt.verify();
 
-        let sub = self.get_subnet(rt, id).unwrap();
-        let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
-        let msg = get_topdown_msg(&crossmsgs, expected_nonce - 1)
-            .unwrap()
-            .unwrap();
-        assert_eq!(&sub.circ_supply, expected_circ_sup);
-        assert_eq!(sub.nonce, expected_nonce);
-        let from = IPCAddress::new(&self.net_name, &*TEST_BLS).unwrap();
-        let to = IPCAddress::new(&id, &TEST_BLS).unwrap();
-        assert_eq!(msg.from, from);
-        assert_eq!(msg.to, to);
-        assert_eq!(msg.nonce, expected_nonce - 1);
-        assert_eq!(msg.value, value);
-
         Ok(())
     }
 
@@ -350,7 +1244,7 @@ impl Harness {
         rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *releaser);
         rt.expect_validate_caller_type(SIG_TYPES.clone());
         // set value and include the cross_msg_fee
-        set_rt_value_with_cross_fee(rt, &value);
+        set_rt_value_with_cross_fee(rt, &value, &self.cross_msg_fees.release);
 
         if code != ExitCode::OK {
             expect_abort(code, rt.call::<Actor>(Method::Release as MethodNum, None));
@@ -383,8 +1277,9 @@ impl Harness {
         let parent = &self.net_name.parent().unwrap();
         let from = IPCAddress::new(&self.net_name, &BURNT_FUNDS_ACTOR_ADDR).unwrap();
         let to = IPCAddress::new(&parent, &TEST_BLS).unwrap();
-        rt.set_epoch(0);
-        let ch = st.get_window_checkpoint(rt.store(), 0).unwrap();
+        let ch = st
+            .get_window_checkpoint(rt.store(), rt.curr_epoch())
+            .unwrap();
         let chmeta = ch.cross_msgs().unwrap();
         // check that fees are collected
         assert_eq!(chmeta.fee, expected_fee);
@@ -396,6 +1291,7 @@ impl Harness {
         let msg = meta.msgs[expected_nonce as usize].clone();
 
         assert_eq!(meta.msgs.len(), (expected_nonce + 1) as usize);
+        assert_eq!(chmeta.num_msgs, expected_nonce + 1);
         assert_eq!(msg.msg.from, from);
         assert_eq!(msg.msg.to, to);
         assert_eq!(msg.msg.nonce, expected_nonce);
@@ -411,6 +1307,50 @@ impl Harness {
         Ok(chmeta.msgs_cid.cid())
     }
 
+    pub fn release_to_subnet(
+        &self,
+        rt: &mut MockRuntime,
+        releaser: &Address,
+        target: &SubnetID,
+        code: ExitCode,
+        value: TokenAmount,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*ACCOUNT_ACTOR_CODE_ID, *releaser);
+        rt.expect_validate_caller_type(SIG_TYPES.clone());
+        // set value and include the cross_msg_fee
+        set_rt_value_with_cross_fee(rt, &value, &self.cross_msg_fees.release);
+
+        rt.expect_send(
+            *releaser,
+            ext::account::PUBKEY_ADDRESS_METHOD,
+            None,
+            TokenAmount::zero(),
+            IpldBlock::serialize_cbor(&*TEST_BLS).unwrap(),
+            ExitCode::OK,
+        );
+        let params = IpldBlock::serialize_cbor(target).unwrap();
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::ReleaseToSubnet as MethodNum, params),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.expect_send(
+            BURNT_FUNDS_ACTOR_ADDR,
+            METHOD_SEND,
+            None,
+            value,
+            None,
+            ExitCode::OK,
+        );
+        rt.call::<Actor>(Method::ReleaseToSubnet as MethodNum, params)?;
+        rt.verify();
+        Ok(())
+    }
+
     pub fn send_cross(
         &self,
         rt: &mut MockRuntime,
@@ -427,7 +1367,7 @@ impl Harness {
         rt.expect_validate_caller_not_type(SIG_TYPES.clone());
 
         // set value and include the cross_msg_fee
-        set_rt_value_with_cross_fee(rt, &value);
+        set_rt_value_with_cross_fee(rt, &value, &self.cross_msg_fees.send_cross);
 
         let msg = StorableMsg {
             from: IPCAddress::new(source_sub, from).unwrap(),
@@ -435,7 +1375,10 @@ impl Harness {
             nonce,
             method: METHOD_SEND,
             params: RawBytes::default(),
-            value: value.clone() + &*CROSS_MSG_FEE,
+            value: value.clone() + &self.cross_msg_fees.send_cross,
+            memo: Vec::new(),
+            valid_until_epoch: None,
+            correlation_id: None,
         };
         let dest = sub.clone();
         let cross = CrossMsg {
@@ -458,8 +1401,8 @@ impl Harness {
             return Ok(());
         }
 
-        let is_bu = is_bottomup(&self.net_name, &dest);
-        if is_bu {
+        // if top-down, the fee is held until the message is applied
+        if is_bottomup(&self.net_name, &dest) {
             rt.expect_send(
                 BURNT_FUNDS_ACTOR_ADDR,
                 METHOD_SEND,
@@ -468,16 +1411,6 @@ impl Harness {
                 None,
                 ExitCode::OK,
             );
-        } else {
-            // if top-down, reward is distributed
-            rt.expect_send(
-                dest.down(&self.net_name).unwrap().subnet_actor(),
-                SUBNET_ACTOR_REWARD_METHOD,
-                None,
-                CROSS_MSG_FEE.clone(),
-                None,
-                ExitCode::OK,
-            );
         }
         rt.call::<Actor>(
             Method::SendCross as MethodNum,
@@ -533,7 +1466,7 @@ impl Harness {
         append_expected_send: Option<Box<dyn Fn(&mut MockRuntime)>>,
     ) -> Result<Option<Cid>, ActorError> {
         rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
-        rt.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR.clone()]);
+        expect_validate_apply_caller(rt);
         rt.set_balance(balance);
 
         if let Some(f) = append_expected_send {
@@ -558,6 +1491,88 @@ impl Harness {
         }
     }
 
+    pub fn skip_topdown_msg(
+        &self,
+        rt: &mut MockRuntime,
+        params: StorableMsg,
+        code: ExitCode,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
+        expect_validate_apply_caller(rt);
+
+        let params = IpldBlock::serialize_cbor(&ApplyMsgParams {
+            cross_msg: CrossMsg {
+                msg: params,
+                wrapped: false,
+            },
+        })
+        .unwrap();
+        if code != ExitCode::OK {
+            expect_abort(
+                code,
+                rt.call::<Actor>(Method::SkipTopDownMsg as MethodNum, params),
+            );
+            rt.verify();
+            return Ok(());
+        }
+
+        rt.call::<Actor>(Method::SkipTopDownMsg as MethodNum, params)?;
+        rt.verify();
+        Ok(())
+    }
+
+    pub fn apply_cross_msgs(
+        &self,
+        rt: &mut MockRuntime,
+        balance: TokenAmount,
+        msgs: Vec<StorableMsg>,
+        append_expected_send: Option<Box<dyn Fn(&mut MockRuntime)>>,
+    ) -> Result<Vec<ApplyMsgResult>, ActorError> {
+        rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
+        expect_validate_apply_caller(rt);
+        rt.set_balance(balance);
+
+        if let Some(f) = append_expected_send {
+            f(rt)
+        }
+        let params = ApplyMsgsParams {
+            cross_msgs: msgs
+                .into_iter()
+                .map(|msg| CrossMsg {
+                    msg,
+                    wrapped: false,
+                })
+                .collect(),
+        };
+        let ret = rt.call::<Actor>(
+            Method::ApplyMessages as MethodNum,
+            IpldBlock::serialize_cbor(&params)?,
+        );
+        rt.verify();
+        Ok(deserialize_block(ret?).unwrap())
+    }
+
+    pub fn replace_postbox_owners(
+        &self,
+        rt: &mut MockRuntime,
+        caller: Address,
+        cid: Cid,
+        new_owners: Vec<Address>,
+    ) -> Result<(), ActorError> {
+        rt.set_caller(Default::default(), caller);
+        rt.expect_validate_caller_any();
+        let params = ReplacePostboxOwnersParams {
+            postbox_cid: cid,
+            new_owners,
+        };
+        let ret = rt.call::<Actor>(
+            Method::ReplacePostboxOwners as MethodNum,
+            IpldBlock::serialize_cbor(&params).unwrap(),
+        );
+        rt.verify();
+        ret.map(|_| ())
+    }
+
     pub fn propagate(
         &self,
         rt: &mut MockRuntime,
@@ -568,8 +1583,8 @@ impl Harness {
     ) -> Result<(), ActorError> {
         rt.set_caller(Default::default(), owner);
         rt.expect_validate_caller_any();
-        rt.set_balance(msg_value.clone() + CROSS_MSG_FEE.clone() + excess.clone());
-        rt.set_received(CROSS_MSG_FEE.clone() + excess.clone());
+        rt.set_balance(msg_value.clone() + self.cross_msg_fees.send_cross.clone() + excess.clone());
+        rt.set_received(self.cross_msg_fees.send_cross.clone() + excess.clone());
 
         if excess > TokenAmount::zero() {
             rt.expect_send(owner, METHOD_SEND, None, excess.clone(), None, ExitCode::OK);
@@ -584,6 +1599,26 @@ impl Harness {
         Ok(())
     }
 
+    pub fn propagate_batch(
+        &self,
+        rt: &mut MockRuntime,
+        owner: Address,
+        cids: Vec<Cid>,
+        value: TokenAmount,
+    ) -> Result<PropagateBatchReturn, ActorError> {
+        rt.set_caller(Default::default(), owner);
+        rt.expect_validate_caller_any();
+        rt.set_received(value);
+
+        let ret = rt.call::<Actor>(
+            Method::PropagateBatch as MethodNum,
+            IpldBlock::serialize_cbor(&PropagateBatchParams { postbox_cids: cids })?,
+        )?;
+        rt.verify();
+
+        Ok(deserialize_block(ret).unwrap())
+    }
+
     pub fn apply_cross_msg(
         &self,
         rt: &mut MockRuntime,
@@ -595,7 +1630,7 @@ impl Harness {
         code: ExitCode,
     ) -> Result<(), ActorError> {
         rt.set_caller(*SYSTEM_ACTOR_CODE_ID, SYSTEM_ACTOR_ADDR);
-        rt.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR.clone()]);
+        expect_validate_apply_caller(rt);
 
         rt.set_balance(value.clone());
         let params = StorableMsg {
@@ -605,6 +1640,9 @@ impl Harness {
             value: value.clone(),
             params: RawBytes::default(),
             nonce: msg_nonce,
+            memo: Vec::new(),
+            valid_until_epoch: None,
+            correlation_id: None,
         };
 
         let st: State = rt.get_state();
@@ -732,6 +1770,28 @@ pub fn get_subnet(rt: &MockRuntime, id: &SubnetID) -> Option<Subnet> {
     subnets.get(&id.to_bytes()).unwrap().cloned()
 }
 
+/// Returns the fees held for the top-down messages of a subnet that it hasn't
+/// applied yet.
+pub fn get_held_topdown_fee(rt: &MockRuntime, id: &SubnetID) -> TokenAmount {
+    let st: State = rt.get_state();
+    let sub = match get_subnet(rt, id) {
+        Some(sub) => sub,
+        None => return TokenAmount::zero(),
+    };
+    let crossmsgs = sub.top_down_msgs.load(rt.store()).unwrap();
+    let fees = st.topdown_fees.load(rt.store()).unwrap();
+    let mut held = TokenAmount::zero();
+    for nonce in sub.applied_topdown_nonce..sub.nonce {
+        if let Some(cross_msg) = crossmsgs.get(nonce).unwrap() {
+            let cid = cross_msg.cid().unwrap();
+            if let Some(fee) = fees.get(&cid.to_bytes()).unwrap() {
+                held += fee;
+            }
+        }
+    }
+    held
+}
+
 pub fn verify_empty_map(rt: &MockRuntime, key: Cid) {
     let map =
         make_map_with_root_and_bitwidth::<_, BigIntDe>(&key, &rt.store, HAMT_BIT_WIDTH).unwrap();
@@ -759,25 +1819,58 @@ pub fn get_cross_msgs<'m, BS: Blockstore>(
         .map_err(|e| anyhow!("error getting fross messages: {:?}", e))
 }
 
-fn set_rt_value_with_cross_fee(rt: &mut MockRuntime, value: &TokenAmount) {
+fn set_rt_value_with_cross_fee(rt: &mut MockRuntime, value: &TokenAmount, fee: &TokenAmount) {
     rt.set_value(if value.clone() != TokenAmount::zero() {
-        value.clone() + &*CROSS_MSG_FEE
+        value.clone() + fee
     } else {
         value.clone()
     });
 }
 
-pub fn set_msg_meta(ch: &mut Checkpoint, rand: Vec<u8>, value: TokenAmount, fee: TokenAmount) {
-    let mh_code = Code::Blake2b256;
-    let c = TCid::from(Cid::new_v1(
-        fvm_ipld_encoding::DAG_CBOR,
-        mh_code.digest(&rand),
-    ));
+/// Sets the cross-msgs of the checkpoint to a single bottom-up message, put in
+/// the store, carrying `value` minus the `fee` paid. The message memo is set to
+/// `rand` so different metas can be created with the same value.
+pub fn set_msg_meta<BS: Blockstore>(
+    store: &BS,
+    ch: &mut Checkpoint,
+    rand: Vec<u8>,
+    value: TokenAmount,
+    fee: TokenAmount,
+) {
+    let crossmsgs = CrossMsgs {
+        msgs: vec![CrossMsg {
+            msg: StorableMsg {
+                from: IPCAddress::new(ch.source(), &TEST_BLS).unwrap(),
+                to: IPCAddress::new(&ch.source().parent().unwrap(), &TEST_BLS).unwrap(),
+                method: METHOD_SEND,
+                params: RawBytes::default(),
+                value: &value - &fee,
+                nonce: 0,
+                memo: rand,
+                valid_until_epoch: None,
+                correlation_id: None,
+            },
+            wrapped: false,
+        }],
+    };
+    let c = TCid::from(store.put_cbor(&crossmsgs, Code::Blake2b256).unwrap());
     let meta = CrossMsgMeta {
         msgs_cid: c,
         nonce: 0,
+        msgs_value: &value - &fee,
         value,
         fee,
+        num_msgs: 1,
     };
     ch.set_cross_msgs(meta);
 }
+
+/// Sets the caller validation expected by `ApplyMessage`, which depends on
+/// whether the `apply-simulation` feature is enabled.
+pub fn expect_validate_apply_caller(rt: &mut MockRuntime) {
+    if cfg!(feature = "apply-simulation") {
+        rt.expect_validate_caller_any();
+    } else {
+        rt.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+    }
+}
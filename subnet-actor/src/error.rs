@@ -0,0 +1,32 @@
+use cid::Cid;
+use fvm_shared::clock::ChainEpoch;
+use ipc_gateway::SubnetID;
+use thiserror::Error;
+
+/// Reasons a checkpoint submitted to the subnet can be rejected.
+#[derive(Debug, Error)]
+pub enum CheckpointError {
+    #[error("submitting checkpoints is not allowed while subnet is not active")]
+    NotActive,
+    #[error("a checkpoint for epoch {0} was already committed")]
+    AlreadyCommitted(ChainEpoch),
+    #[error(
+        "epoch {epoch} in checkpoint doesn't correspond with a signing window of period {period}"
+    )]
+    BadEpoch {
+        epoch: ChainEpoch,
+        period: ChainEpoch,
+    },
+    // thiserror reserves `source` for the underlying error, so the checkpoint
+    // source is named `found`.
+    #[error("checkpoint source {found} doesn't match subnet {expected}")]
+    BadSource { found: SubnetID, expected: SubnetID },
+    #[error("previous checkpoint {found} not consistent with previously committed {expected}")]
+    BadPrevCid { found: Cid, expected: Cid },
+    #[error("invalid cross-msg meta: {0}")]
+    BadCrossMsgMeta(String),
+    #[error("invalid signature: {0}")]
+    BadSignature(String),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
@@ -1,19 +1,25 @@
 #![feature(is_some_and)]
 
+pub mod error;
+pub mod migration;
 pub mod state;
 pub mod types;
 
 use fil_actors_runtime::runtime::{ActorCode, Runtime};
 use fil_actors_runtime::{
-    actor_dispatch, actor_error, restrict_internal_api, ActorDowncast, ActorError, INIT_ACTOR_ADDR,
+    actor_dispatch, actor_error, restrict_internal_api, ActorDowncast, ActorError,
+    BURNT_FUNDS_ACTOR_ADDR, INIT_ACTOR_ADDR, SYSTEM_ACTOR_ADDR,
 };
 use fvm_ipld_encoding::ipld_block::IpldBlock;
 use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
 use fvm_shared::{MethodNum, METHOD_CONSTRUCTOR, METHOD_SEND};
-use ipc_gateway::{Checkpoint, FundParams, MIN_COLLATERAL_AMOUNT};
-use num::BigInt;
+use ipc_gateway::{
+    Checkpoint, DistributeRewardsParams, FundParams, SubnetID, MIN_COLLATERAL_AMOUNT,
+};
 use num_derive::FromPrimitive;
 use num_traits::{FromPrimitive, Zero};
 
@@ -31,8 +37,38 @@ pub enum Method {
     Join = frc42_dispatch::method_hash!("Join"),
     Leave = frc42_dispatch::method_hash!("Leave"),
     Kill = frc42_dispatch::method_hash!("Kill"),
+    FinalizeKill = frc42_dispatch::method_hash!("FinalizeKill"),
+    JailInactiveValidators = frc42_dispatch::method_hash!("JailInactiveValidators"),
+    Unjail = frc42_dispatch::method_hash!("Unjail"),
     SubmitCheckpoint = frc42_dispatch::method_hash!("SubmitCheckpoint"),
     Reward = frc42_dispatch::method_hash!("Reward"),
+    DistributeRewards = frc42_dispatch::method_hash!("DistributeRewards"),
+    IsValidator = frc42_dispatch::method_hash!("IsValidator"),
+    MigrateState = frc42_dispatch::method_hash!("MigrateState"),
+    GetCheckpoints = frc42_dispatch::method_hash!("GetCheckpoints"),
+    RecomputeTotalStake = frc42_dispatch::method_hash!("RecomputeTotalStake"),
+    GetCheckpointCertificate = frc42_dispatch::method_hash!("GetCheckpointCertificate"),
+    ClaimReward = frc42_dispatch::method_hash!("ClaimReward"),
+    GetPendingReward = frc42_dispatch::method_hash!("GetPendingReward"),
+    GetFeesReceived = frc42_dispatch::method_hash!("GetFeesReceived"),
+    EstimateCheckpointSize = frc42_dispatch::method_hash!("EstimateCheckpointSize"),
+    SetRewardAddr = frc42_dispatch::method_hash!("SetRewardAddr"),
+    RecoverFunds = frc42_dispatch::method_hash!("RecoverFunds"),
+    GetEligibleVoters = frc42_dispatch::method_hash!("GetEligibleVoters"),
+    JoinAndVote = frc42_dispatch::method_hash!("JoinAndVote"),
+    GetRewardSchedule = frc42_dispatch::method_hash!("GetRewardSchedule"),
+    SetRewardInterval = frc42_dispatch::method_hash!("SetRewardInterval"),
+    GetActivationRequirements = frc42_dispatch::method_hash!("GetActivationRequirements"),
+    SetCheckpointDelegate = frc42_dispatch::method_hash!("SetCheckpointDelegate"),
+    ClearCheckpointDelegate = frc42_dispatch::method_hash!("ClearCheckpointDelegate"),
+    ClearAllVotes = frc42_dispatch::method_hash!("ClearAllVotes"),
+    IsCheckpointCommitted = frc42_dispatch::method_hash!("IsCheckpointCommitted"),
+    HasStake = frc42_dispatch::method_hash!("HasStake"),
+    VerifyCheckpoint = frc42_dispatch::method_hash!("VerifyCheckpoint"),
+    ApproveValidator = frc42_dispatch::method_hash!("ApproveValidator"),
+    RevokeValidator = frc42_dispatch::method_hash!("RevokeValidator"),
+    CancelKill = frc42_dispatch::method_hash!("CancelKill"),
+    Activate = frc42_dispatch::method_hash!("Activate"),
 }
 
 /// SubnetActor trait. Custom subnet actors need to implement this trait
@@ -51,7 +87,9 @@ pub trait SubnetActor {
     /// Called by peers to leave a subnet.
     fn leave(rt: &mut impl Runtime) -> Result<Option<RawBytes>, ActorError>;
 
-    /// Sends a kill signal for the subnet to the gateway.
+    /// Sends a kill signal for the subnet to the gateway, or starts the
+    /// kill grace period if the subnet has one configured. Only validators
+    /// can start the grace period.
     fn kill(rt: &mut impl Runtime) -> Result<Option<RawBytes>, ActorError>;
 
     /// Submits a new checkpoint for the subnet.
@@ -62,6 +100,12 @@ pub trait SubnetActor {
 
     /// Distributes the rewards for the subnet to validators.
     fn reward(rt: &mut impl Runtime) -> Result<Option<RawBytes>, ActorError>;
+
+    /// Distributes the rewards of a checkpoint manifest to validators.
+    fn distribute_rewards(
+        rt: &mut impl Runtime,
+        params: DistributeRewardsParams,
+    ) -> Result<Option<RawBytes>, ActorError>;
 }
 
 /// SubnetActor trait. Custom subnet actors need to implement this trait
@@ -78,6 +122,8 @@ impl SubnetActor for Actor {
     fn constructor(rt: &mut impl Runtime, params: ConstructParams) -> Result<(), ActorError> {
         rt.validate_immediate_caller_is(std::iter::once(&INIT_ACTOR_ADDR))?;
 
+        params.validate()?;
+
         let st = State::new(rt.store(), params).map_err(|e| {
             e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "Failed to create actor state")
         })?;
@@ -92,54 +138,7 @@ impl SubnetActor for Actor {
     /// It implements the basic logic to onboard new peers to the subnet.
     fn join(rt: &mut impl Runtime, params: JoinParams) -> Result<Option<RawBytes>, ActorError> {
         rt.validate_immediate_caller_accept_any()?;
-
-        let caller = rt.message().caller();
-        let amount = rt.message().value_received();
-        if amount == TokenAmount::zero() {
-            return Err(actor_error!(
-                illegal_argument,
-                "a minimum collateral is required to join the subnet"
-            ));
-        }
-
-        let mut msg = None;
-        rt.transaction(|st: &mut State, rt| {
-            // increase collateral
-            st.add_stake(rt.store(), &caller, &params.validator_net_addr, &amount)
-                .map_err(|e| {
-                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
-                })?;
-
-            let total_stake = st.total_stake.clone();
-
-            if st.status == Status::Instantiated {
-                if total_stake >= TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) {
-                    msg = Some(CrossActorPayload::new(
-                        st.ipc_gateway_addr,
-                        ipc_gateway::Method::Register as u64,
-                        None,
-                        total_stake,
-                    ));
-                }
-            } else {
-                msg = Some(CrossActorPayload::new(
-                    st.ipc_gateway_addr,
-                    ipc_gateway::Method::AddStake as u64,
-                    None,
-                    amount,
-                ));
-            }
-
-            st.mutate_state();
-
-            Ok(())
-        })?;
-
-        if let Some(p) = msg {
-            rt.send(&p.to, p.method, p.params, p.value)?;
-        }
-
-        Ok(None)
+        Self::join_subnet(rt, params)
     }
 
     /// Called by peers looking to leave a subnet.
@@ -148,7 +147,7 @@ impl SubnetActor for Actor {
 
         let caller = rt.message().caller();
         let mut msg = None;
-        rt.transaction(|st: &mut State, rt| {
+        let stake = rt.transaction(|st: &mut State, rt| {
             let stake = st.get_stake(rt.store(), &caller).map_err(|e| {
                 e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load stake")
             })?;
@@ -158,7 +157,8 @@ impl SubnetActor for Actor {
             }
 
             let stake = stake.unwrap();
-            if st.status != Status::Terminating {
+            // during the kill grace period the stake can still be released
+            if st.status != Status::Terminating || st.kill_finalize_epoch.is_some() {
                 msg = Some(CrossActorPayload::new(
                     st.ipc_gateway_addr,
                     ipc_gateway::Method::ReleaseStake as u64,
@@ -173,66 +173,56 @@ impl SubnetActor for Actor {
             st.rm_stake(&rt.store(), &caller, &stake).map_err(|e| {
                 e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "cannot remove stake")
             })?;
+            st.record_leave(rt.store(), &caller, rt.curr_epoch())
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to record leave")
+                })?;
 
-            st.mutate_state();
+            st.mutate_state(rt.curr_epoch());
 
-            Ok(())
+            Ok(stake)
         })?;
 
         if let Some(p) = msg {
             rt.send(&p.to, p.method, p.params, p.value)?;
         }
 
+        log::info!("StakeRemoved: validator={} amount={}", caller, stake);
         Ok(None)
     }
 
     fn kill(rt: &mut impl Runtime) -> Result<Option<RawBytes>, ActorError> {
         rt.validate_immediate_caller_accept_any()?;
 
-        // prevent a subnet from being killed until all its locked balance has been withdrawn
-        if rt.current_balance() != TokenAmount::zero() {
-            return Err(actor_error!(
-                illegal_state,
-                format!("the subnet has non-zero balance: {:}", rt.current_balance())
-            ));
+        let st: State = rt.state()?;
+        if st.kill_grace_epochs == 0 {
+            return Self::terminate(rt, false);
         }
 
-        let mut msg = None;
-        rt.transaction(|st: &mut State, _| {
-            if st.status == Status::Terminating || st.status == Status::Killed {
+        // the subnet stays in terminating state during the grace period so
+        // validators can still leave and recover their stake. As it halts the
+        // subnet, only validators can start it.
+        let caller = rt.message().caller();
+        rt.transaction(|st: &mut State, rt| {
+            if !st.is_validator(&caller) {
                 return Err(actor_error!(
-                    illegal_state,
-                    "the subnet is already in a killed or terminating state"
+                    forbidden,
+                    "only validators can start the kill grace period"
                 ));
             }
-
-            if !st.validator_set.is_empty() || st.total_stake != TokenAmount::zero() {
+            if st.status == Status::Terminating || st.status == Status::Killed {
                 return Err(actor_error!(
                     illegal_state,
-                    "this subnet can only be killed when all validators have left"
+                    "the subnet is already in a killed or terminating state"
                 ));
             }
 
-            // move to terminating state
+            st.status_before_kill = Some(st.status);
             st.status = Status::Terminating;
-
-            st.mutate_state();
-
-            msg = Some(CrossActorPayload::new(
-                st.ipc_gateway_addr,
-                ipc_gateway::Method::Kill as u64,
-                None,
-                TokenAmount::zero(),
-            ));
-
+            st.kill_finalize_epoch = Some(rt.curr_epoch() + st.kill_grace_epochs);
             Ok(())
         })?;
 
-        // unregister subnet
-        if let Some(p) = msg {
-            rt.send(&p.to, p.method, p.params, p.value)?;
-        }
-
         Ok(None)
     }
 
@@ -241,119 +231,1194 @@ impl SubnetActor for Actor {
     /// This functions verifies that the checkpoint is valid before
     /// propagating it for commitment to the IPC gateway. It expects at least
     /// votes from 2/3 of miners with collateral.
+    ///
+    /// If the subnet requires a checkpoint deposit, it needs to be sent with
+    /// the submission and is held until a checkpoint is committed for the epoch
+    /// or the votes of the checkpoint submitted are cleared.
     fn submit_checkpoint(
         rt: &mut impl Runtime,
         ch: Checkpoint,
     ) -> Result<Option<RawBytes>, ActorError> {
         rt.validate_immediate_caller_accept_any()?;
 
-        let state: State = rt.state()?;
-        let caller = rt.message().caller();
-
-        if !state.is_validator(&caller) {
-            return Err(actor_error!(illegal_state, "not validator"));
-        }
-
-        state
-            .verify_checkpoint(rt, &ch)
-            .map_err(|_| actor_error!(illegal_state, "checkpoint failed"))?;
-
-        let mut msg = None;
-
-        rt.transaction(|st: &mut State, rt| {
-            let ch_cid = ch.cid();
-
-            let mut found = false;
-            let mut votes = match st.get_votes(rt.store(), &ch_cid)? {
-                Some(v) => {
-                    found = true;
-                    v
-                }
-                None => Votes {
-                    validators: Vec::new(),
-                },
-            };
-
-            if votes.validators.iter().any(|x| x == &caller) {
+        let st: State = rt.state()?;
+        if !st.checkpoint_deposit.is_zero() {
+            let amount = rt.message().value_received();
+            if amount != st.checkpoint_deposit {
                 return Err(actor_error!(
-                    illegal_state,
-                    "miner has already voted the checkpoint"
+                    illegal_argument,
+                    "checkpoint submissions require a deposit of {}, {} sent",
+                    st.checkpoint_deposit,
+                    amount
                 ));
             }
+            Self::hold_checkpoint_deposit(rt, &ch, amount)?;
+        }
+        Self::vote_checkpoint(rt, ch)
+    }
 
-            // add miner vote
-            votes.validators.push(caller);
-
-            // if has majority
-            if st.has_majority_vote(rt.store(), &votes)? {
-                // commit checkpoint
-                st.flush_checkpoint(rt.store(), &ch)
-                    .map_err(|_| actor_error!(illegal_state, "cannot flush checkpoint"))?;
+    /// Distributes the rewards for the subnet to validators.
+    fn reward(rt: &mut impl Runtime) -> Result<Option<RawBytes>, ActorError> {
+        let st: State = rt.state()?;
+        // the ipc-gateway must trigger the reward distribution
+        rt.validate_immediate_caller_is(vec![&st.ipc_gateway_addr])?;
 
-                // prepare the message
-                msg = Some(CrossActorPayload::new(
-                    st.ipc_gateway_addr,
-                    ipc_gateway::Method::CommitChildCheckpoint as u64,
-                    IpldBlock::serialize_cbor(&ch)?,
-                    TokenAmount::zero(),
-                ));
+        let amount = rt.message().value_received();
+        if amount == TokenAmount::zero() {
+            return Err(actor_error!(
+                illegal_argument,
+                "no rewards sent for distribution"
+            ));
+        };
 
-                // remove votes used for commitment
-                if found {
-                    st.remove_votes(rt.store(), &ch_cid)?;
-                }
-            } else {
-                // if no majority store vote and return
-                st.set_votes(rt.store(), &ch_cid, votes)?;
+        // rewards are accumulated until the reward interval has elapsed, or
+        // until there are validators to receive them if the subnet is configured so.
+        let amount = rt.transaction(|st: &mut State, rt| {
+            st.accumulated_rewards += &amount;
+            if rt.curr_epoch() < st.next_reward_epoch() {
+                return Ok(None);
             }
-
-            Ok(())
+            if st.validator_set.is_empty() && st.accumulate_unassigned_rewards {
+                log::info!(
+                    "UnassignedRewardsAccumulated: amount={} accumulated={}",
+                    amount,
+                    st.accumulated_rewards
+                );
+                return Ok(None);
+            }
+            st.last_reward_epoch = Some(rt.curr_epoch());
+            Ok(Some(std::mem::take(&mut st.accumulated_rewards)))
         })?;
+        let amount = match amount {
+            Some(amount) => amount,
+            None => return Ok(None),
+        };
 
-        // propagate to sca
-        if let Some(p) = msg {
-            rt.send(&p.to, p.method, p.params, p.value)?;
+        // rewards are split following the reward policy of the subnet.
+        let st: State = rt.state()?;
+        if st.validator_set.len() == 0 {
+            return Err(actor_error!(illegal_state, "no validators in subnet"));
+        };
+        // jailed validators don't earn rewards
+        let validators: Vec<Validator> = st
+            .validator_set
+            .iter()
+            .filter(|v| !st.is_jailed(&v.addr))
+            .cloned()
+            .collect();
+        if validators.is_empty() {
+            return Err(actor_error!(illegal_state, "all validators are jailed"));
+        };
+        let mut stakes = Vec::with_capacity(validators.len());
+        for v in validators.iter() {
+            let stake = st
+                .get_stake(rt.store(), &v.addr)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load stake")
+                })?
+                .unwrap_or_default();
+            stakes.push(stake);
         }
-
+        let rewards = st.reward_policy.split(&amount, &stakes);
+        Self::pay_rewards(
+            rt,
+            validators
+                .into_iter()
+                .map(|v| v.addr)
+                .zip(rewards)
+                .collect(),
+        )?;
         Ok(None)
     }
 
-    /// Distributes the rewards for the subnet to validators.
-    fn reward(rt: &mut impl Runtime) -> Result<Option<RawBytes>, ActorError> {
+    /// Distributes the rewards of a checkpoint manifest to validators.
+    ///
+    /// The amount received must match the total of rewards in the manifest,
+    /// and every beneficiary must be part of the validator set. As in `Reward`,
+    /// jailed validators don't earn rewards: their share is accumulated for the
+    /// next distribution.
+    fn distribute_rewards(
+        rt: &mut impl Runtime,
+        params: DistributeRewardsParams,
+    ) -> Result<Option<RawBytes>, ActorError> {
         let st: State = rt.state()?;
         // the ipc-gateway must trigger the reward distribution
         rt.validate_immediate_caller_is(vec![&st.ipc_gateway_addr])?;
 
         let amount = rt.message().value_received();
-        if amount == TokenAmount::zero() {
+        let total = params
+            .rewards
+            .iter()
+            .fold(TokenAmount::zero(), |acc, r| acc + &r.amount);
+        if amount != total {
             return Err(actor_error!(
                 illegal_argument,
-                "no rewards sent for distribution"
+                "rewards received ({}) don't match the manifest ({})",
+                amount,
+                total
             ));
-        };
+        }
 
-        // even distribution of rewards. Each subnet may choose more
-        // complex and fair policies to incentivize certain behaviors.
-        // we may even have a default one for IPC.
-        let div = {
-            if st.validator_set.len() == 0 {
-                return Err(actor_error!(illegal_state, "no validators in subnet"));
-            };
-            match BigInt::from_usize(st.validator_set.len()) {
-                None => {
-                    return Err(actor_error!(illegal_state, "couldn't convert into BigInt"));
-                }
-                Some(val) => val,
+        for r in params.rewards.iter() {
+            if !st.is_validator(&r.addr) {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "reward beneficiary {} is not a validator",
+                    r.addr
+                ));
             }
-        };
-        let rew_amount = amount.div_floor(div);
-        for v in st.validator_set.into_iter() {
-            rt.send(&v.addr, METHOD_SEND, None, rew_amount.clone())?;
         }
+
+        let (jailed, rewards): (Vec<_>, Vec<_>) = params
+            .rewards
+            .into_iter()
+            .partition(|r| st.is_jailed(&r.addr));
+        let withheld = jailed
+            .iter()
+            .fold(TokenAmount::zero(), |acc, r| acc + &r.amount);
+        if !withheld.is_zero() {
+            rt.transaction(|st: &mut State, _| {
+                st.accumulated_rewards += &withheld;
+                Ok(())
+            })?;
+            log::info!("JailedRewardsAccumulated: amount={}", withheld);
+        }
+
+        Self::pay_rewards(
+            rt,
+            rewards.into_iter().map(|r| (r.addr, r.amount)).collect(),
+        )?;
         Ok(None)
     }
 }
 
+impl Actor {
+    /// Returns true if the address is part of the current validator set
+    /// of the subnet.
+    fn is_validator(rt: &mut impl Runtime, params: Address) -> Result<bool, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        Ok(st.is_validator(&params))
+    }
+
+    /// Returns true if the address holds stake in the subnet, whether or not it
+    /// is part of the validator set.
+    fn has_stake(rt: &mut impl Runtime, params: Address) -> Result<bool, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let stake = st
+            .get_stake(rt.store(), &params)
+            .map_err(|e| e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load stake"))?;
+        Ok(stake.map_or(false, |s| !s.is_zero()))
+    }
+
+    /// Returns the checkpoints committed in the inclusive range of epochs, in
+    /// order. Only the first `MAX_CHECKPOINTS_PER_QUERY` signing windows of the
+    /// range are visited, so callers syncing a longer range need to continue
+    /// from the end of the last window visited.
+    fn get_checkpoints(
+        rt: &mut impl Runtime,
+        params: CheckpointRangeParams,
+    ) -> Result<Vec<Checkpoint>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        if params.from_epoch > params.to_epoch {
+            return Err(actor_error!(
+                illegal_argument,
+                "invalid checkpoint range: {} > {}",
+                params.from_epoch,
+                params.to_epoch
+            ));
+        }
+
+        let st: State = rt.state()?;
+        st.get_checkpoints_in_range(
+            rt.store(),
+            params.from_epoch,
+            params.to_epoch,
+            MAX_CHECKPOINTS_PER_QUERY,
+        )
+        .map_err(|e| e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load checkpoints"))
+    }
+
+    /// Checks if a checkpoint has already been committed, so validators can
+    /// avoid voting it. Only the checkpoint committed for the epoch given is
+    /// looked up.
+    fn is_checkpoint_committed(
+        rt: &mut impl Runtime,
+        params: IsCheckpointCommittedParams,
+    ) -> Result<bool, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        st.is_checkpoint_committed(rt.store(), params.epoch, &params.cid)
+            .map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load checkpoints")
+            })
+    }
+
+    /// Returns the checkpoint committed in an epoch with the validators, and their
+    /// stake, whose votes committed it. `None` if no checkpoint was committed.
+    fn get_checkpoint_certificate(
+        rt: &mut impl Runtime,
+        epoch: ChainEpoch,
+    ) -> Result<Option<CheckpointCertificate>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        st.get_checkpoint_certificate(rt.store(), epoch)
+            .map_err(|e| {
+                e.downcast_default(
+                    ExitCode::USR_ILLEGAL_STATE,
+                    "failed to load checkpoint certificate",
+                )
+            })
+    }
+
+    /// Returns the validators eligible to vote the checkpoint of the window in an
+    /// epoch with their stake, the weight of their votes. `None` if the epoch isn't
+    /// a signing window or no snapshot was taken for it.
+    fn get_eligible_voters(
+        rt: &mut impl Runtime,
+        epoch: ChainEpoch,
+    ) -> Result<Option<Vec<CheckpointVoter>>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        st.get_eligible_voters(rt.store(), epoch).map_err(|e| {
+            e.downcast_default(
+                ExitCode::USR_ILLEGAL_STATE,
+                "failed to load eligible voters",
+            )
+        })
+    }
+
+    /// Sends the rewards to the reward address of their beneficiaries, or
+    /// accrues them to be claimed later if the subnet uses pull-based rewards.
+    /// Either way the rewards are added to the fees received by each beneficiary.
+    fn pay_rewards(
+        rt: &mut impl Runtime,
+        rewards: Vec<(Address, TokenAmount)>,
+    ) -> Result<(), ActorError> {
+        let (pull, payouts) = rt.transaction(|st: &mut State, rt| {
+            let mut payouts = Vec::with_capacity(rewards.len());
+            for (addr, amount) in rewards.iter() {
+                if amount.is_zero() {
+                    continue;
+                }
+                st.record_fees_received(rt.store(), addr, amount)
+                    .map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            "failed to record fees received",
+                        )
+                    })?;
+                if st.pull_rewards {
+                    st.accrue_reward(rt.store(), addr, amount).map_err(|e| {
+                        e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to accrue reward")
+                    })?;
+                } else {
+                    let reward_addr = st.get_reward_addr(rt.store(), addr).map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            "failed to load reward addr",
+                        )
+                    })?;
+                    payouts.push((reward_addr, amount.clone()));
+                }
+            }
+            Ok((st.pull_rewards, payouts))
+        })?;
+        if pull {
+            return Ok(());
+        }
+        for (addr, amount) in payouts.into_iter() {
+            rt.send(&addr, METHOD_SEND, None, amount)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the address the rewards of the caller are paid to. The stake
+    /// remains tied to the caller.
+    fn set_reward_addr(rt: &mut impl Runtime, params: Address) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        rt.transaction(|st: &mut State, rt| {
+            let stake = st.get_stake(rt.store(), &caller).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load stake")
+            })?;
+            if stake.map_or(true, |s| s.is_zero()) {
+                return Err(actor_error!(illegal_state, "caller has no stake in subnet"));
+            }
+            st.set_reward_addr(rt.store(), &caller, &params)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to set reward addr")
+                })
+        })
+    }
+
+    /// Sends the rewards accrued by the caller and not claimed yet to its reward address.
+    fn claim_reward(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        let (amount, reward_addr) = rt.transaction(|st: &mut State, rt| {
+            let amount = st.take_pending_reward(rt.store(), &caller).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to take pending reward")
+            })?;
+            let reward_addr = st.get_reward_addr(rt.store(), &caller).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load reward addr")
+            })?;
+            Ok((amount, reward_addr))
+        })?;
+        if amount.is_zero() {
+            return Err(actor_error!(
+                illegal_argument,
+                "no pending rewards for {}",
+                caller
+            ));
+        }
+        rt.send(&reward_addr, METHOD_SEND, None, amount.clone())?;
+        log::info!("RewardClaimed: validator={} amount={}", caller, amount);
+        Ok(())
+    }
+
+    /// Returns the rewards accrued by an address that haven't been claimed yet.
+    fn get_pending_reward(
+        rt: &mut impl Runtime,
+        params: Address,
+    ) -> Result<TokenAmount, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        st.get_pending_reward(rt.store(), &params).map_err(|e| {
+            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load pending reward")
+        })
+    }
+
+    /// Returns the size of a checkpoint once serialized and the number of
+    /// cross-messages it includes, so relayers can split checkpoints that
+    /// would exceed the message size limits before submitting them.
+    fn estimate_checkpoint_size(
+        rt: &mut impl Runtime,
+        params: Checkpoint,
+    ) -> Result<CheckpointSize, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        CheckpointSize::new(&params)
+            .map_err(|e| actor_error!(illegal_argument, "failed to serialize checkpoint: {}", e))
+    }
+
+    /// Returns the total of rewards distributed to an address.
+    fn get_fees_received(
+        rt: &mut impl Runtime,
+        params: Address,
+    ) -> Result<TokenAmount, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        st.get_fees_received(rt.store(), &params).map_err(|e| {
+            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load fees received")
+        })
+    }
+
+    /// Jails the validators that missed too many committed checkpoints in a
+    /// row, excluding them from rewards. Returns the validators jailed.
+    fn jail_inactive_validators(rt: &mut impl Runtime) -> Result<Vec<Address>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let jailed = rt.transaction(|st: &mut State, rt| {
+            st.jail_inactive_validators(rt.store()).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to jail validators")
+            })
+        })?;
+        for v in jailed.iter() {
+            log::info!("ValidatorJailed: validator={}", v);
+        }
+        Ok(jailed)
+    }
+
+    /// Releases the caller from jail once it has voted a committed checkpoint again.
+    fn unjail(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        rt.transaction(|st: &mut State, rt| {
+            st.unjail(rt.store(), &caller)
+                .map_err(|e| e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "cannot unjail"))
+        })
+    }
+
+    /// Completes the kill of a subnet once its grace period is over.
+    fn finalize_kill(rt: &mut impl Runtime) -> Result<Option<RawBytes>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        Self::terminate(rt, true)
+    }
+
+    /// Cancels a kill in its grace period, restoring the status the subnet had
+    /// when it started. It can only be called by a validator.
+    fn cancel_kill(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        rt.transaction(|st: &mut State, rt| {
+            if st.kill_finalize_epoch.is_none() {
+                return Err(actor_error!(illegal_state, "no pending kill to cancel"));
+            }
+            if !st.is_validator(&caller) {
+                return Err(actor_error!(
+                    forbidden,
+                    "only validators can cancel a pending kill"
+                ));
+            }
+
+            st.kill_finalize_epoch = None;
+            st.status = st.status_before_kill.take().unwrap_or(Status::Active);
+            // a subnet that was never registered is only activated by a join
+            // or `Activate`
+            if st.status != Status::Instantiated {
+                st.mutate_state(rt.curr_epoch());
+            }
+            Ok(())
+        })?;
+
+        log::info!("KillCancelled: validator={}", caller);
+        Ok(())
+    }
+
+    /// Registers the subnet in the gateway with the stake accumulated once it
+    /// can be activated, so subnets with an activation epoch don't need a new
+    /// join after it is reached. It can be triggered by anyone.
+    fn activate(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let msg = rt.transaction(|st: &mut State, rt| {
+            if st.status != Status::Instantiated {
+                return Err(actor_error!(
+                    illegal_state,
+                    "only instantiated subnets can be activated"
+                ));
+            }
+            if !st.can_activate(rt.curr_epoch()) {
+                return Err(actor_error!(
+                    illegal_state,
+                    "subnet doesn't meet the activation requirements"
+                ));
+            }
+            st.mutate_state(rt.curr_epoch());
+            Ok(CrossActorPayload::new(
+                st.ipc_gateway_addr,
+                ipc_gateway::Method::Register as u64,
+                None,
+                st.total_stake.clone(),
+            ))
+        })?;
+        rt.send(&msg.to, msg.method, msg.params, msg.value.clone())?;
+
+        log::info!("SubnetActivated: stake={}", msg.value);
+        Ok(())
+    }
+
+    /// Stakes the funds received by the caller to join the subnet, registering
+    /// the subnet in the gateway once it has enough collateral.
+    fn join_subnet(
+        rt: &mut impl Runtime,
+        params: JoinParams,
+    ) -> Result<Option<RawBytes>, ActorError> {
+        let amount = rt.message().value_received();
+        Self::stake_collateral(rt, params, amount)
+    }
+
+    /// Stakes `amount` of the funds received by the caller as its collateral.
+    fn stake_collateral(
+        rt: &mut impl Runtime,
+        params: JoinParams,
+        amount: TokenAmount,
+    ) -> Result<Option<RawBytes>, ActorError> {
+        let caller = rt.message().caller();
+        if amount == TokenAmount::zero() {
+            return Err(actor_error!(
+                illegal_argument,
+                "a minimum collateral is required to join the subnet"
+            ));
+        }
+
+        let mut msg = None;
+        rt.transaction(|st: &mut State, rt| {
+            if !st.can_join(&caller) {
+                return Err(actor_error!(
+                    forbidden,
+                    "caller {} is not approved to join the permissioned subnet",
+                    caller
+                ));
+            }
+            st.net_addr_validation
+                .validate(&params.validator_net_addr)?;
+
+            let can_rejoin = st
+                .can_rejoin(rt.store(), &caller, rt.curr_epoch())
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load last leave")
+                })?;
+            if !can_rejoin {
+                return Err(actor_error!(
+                    forbidden,
+                    "caller left the subnet within the rejoin cooldown"
+                ));
+            }
+
+            // increase collateral
+            st.add_stake(rt.store(), &caller, &params.validator_net_addr, &amount)
+                .map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to load subnet")
+                })?;
+            if let Some(reward_addr) = params.reward_addr {
+                st.set_reward_addr(rt.store(), &caller, &reward_addr)
+                    .map_err(|e| {
+                        e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to set reward addr")
+                    })?;
+            }
+
+            let total_stake = st.total_stake.clone();
+
+            if st.status == Status::Instantiated {
+                if st.can_activate(rt.curr_epoch()) {
+                    msg = Some(CrossActorPayload::new(
+                        st.ipc_gateway_addr,
+                        ipc_gateway::Method::Register as u64,
+                        None,
+                        total_stake,
+                    ));
+                }
+            } else {
+                msg = Some(CrossActorPayload::new(
+                    st.ipc_gateway_addr,
+                    ipc_gateway::Method::AddStake as u64,
+                    None,
+                    amount.clone(),
+                ));
+            }
+
+            st.mutate_state(rt.curr_epoch());
+
+            Ok(())
+        })?;
+
+        if let Some(p) = msg {
+            rt.send(&p.to, p.method, p.params, p.value)?;
+        }
+
+        log::info!("StakeAdded: validator={} amount={}", caller, amount);
+        Ok(None)
+    }
+
+    /// Records the vote of the caller for a checkpoint, committing it in the
+    /// gateway once it gathers the majority of the stake.
+    fn vote_checkpoint(
+        rt: &mut impl Runtime,
+        ch: Checkpoint,
+    ) -> Result<Option<RawBytes>, ActorError> {
+        let state: State = rt.state()?;
+        let caller = Self::validate_vote(rt, &state, &ch)?;
+
+        let mut msg = None;
+        let mut deposits = None;
+        let mut released = Vec::new();
+
+        rt.transaction(|st: &mut State, rt| {
+            let ch_cid = ch.cid();
+
+            // clear the votes of past windows that were never committed
+            let (removed, stale_deposits) =
+                st.remove_stale_votes(rt.store(), ch.epoch()).map_err(|e| {
+                    e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to remove stale votes")
+                })?;
+            released = stale_deposits;
+            if removed > 0 {
+                log::info!("StaleVotesRemoved: epoch={} count={}", ch.epoch(), removed);
+            }
+
+            let mut found = false;
+            let mut votes = match st.get_votes(rt.store(), &ch_cid)? {
+                Some(v) => {
+                    found = true;
+                    v
+                }
+                None => {
+                    // a new checkpoint is being voted
+                    st.pending_window_start = Some(rt.curr_epoch());
+                    st.snapshot_eligible_voters(rt.store(), ch.epoch())
+                        .map_err(|e| {
+                            e.downcast_default(
+                                ExitCode::USR_ILLEGAL_STATE,
+                                "failed to snapshot eligible voters",
+                            )
+                        })?;
+                    Votes {
+                        validators: Vec::new(),
+                    }
+                }
+            };
+
+            // add miner vote
+            votes.validators.push(caller);
+
+            // if has majority
+            if st.has_majority_vote(rt.store(), &votes, ch.epoch())? {
+                // commit checkpoint
+                st.flush_checkpoint(rt.store(), &ch)
+                    .map_err(|_| actor_error!(illegal_state, "cannot flush checkpoint"))?;
+                st.record_checkpoint_voters(rt.store(), ch.epoch(), &votes)
+                    .map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            "failed to record checkpoint voters",
+                        )
+                    })?;
+                st.record_validator_diff(rt.store(), ch.epoch())
+                    .map_err(|e| {
+                        e.downcast_default(
+                            ExitCode::USR_ILLEGAL_STATE,
+                            "failed to record validator set diff",
+                        )
+                    })?;
+                st.pending_window_start = None;
+                deposits = Some(
+                    st.settle_checkpoint_deposits(rt.store(), &ch)
+                        .map_err(|e| {
+                            e.downcast_default(
+                                ExitCode::USR_ILLEGAL_STATE,
+                                "failed to settle checkpoint deposits",
+                            )
+                        })?,
+                );
+                st.record_participation(rt.store(), &votes).map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_STATE,
+                        "failed to record checkpoint participation",
+                    )
+                })?;
+
+                // prepare the message
+                msg = Some(CrossActorPayload::new(
+                    st.ipc_gateway_addr,
+                    ipc_gateway::Method::CommitChildCheckpoint as u64,
+                    IpldBlock::serialize_cbor(&ch)?,
+                    TokenAmount::zero(),
+                ));
+
+                // remove votes used for commitment
+                if found {
+                    st.remove_votes(rt.store(), &ch_cid)?;
+                }
+            } else {
+                // if no majority store vote and return
+                st.set_votes(rt.store(), &ch_cid, votes)?;
+                if !found {
+                    st.tag_votes(rt.store(), &ch_cid, ch.epoch()).map_err(|e| {
+                        e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to tag votes")
+                    })?;
+                }
+            }
+
+            Ok(())
+        })?;
+
+        // propagate to sca
+        if let Some(p) = msg {
+            rt.send(&p.to, p.method, p.params, p.value)?;
+        }
+
+        // the deposits of the votes cleared are refunded
+        Self::refund_checkpoint_deposits(rt, &released)?;
+
+        // refund the deposits for the epoch committed and burn those of the
+        // validators that voted conflicting checkpoints for it
+        if let Some((refunded, forfeited)) = deposits {
+            Self::refund_checkpoint_deposits(rt, &refunded)?;
+            if !forfeited.is_zero() {
+                rt.send(
+                    &BURNT_FUNDS_ACTOR_ADDR,
+                    METHOD_SEND,
+                    None,
+                    forfeited.clone(),
+                )?;
+                log::info!(
+                    "CheckpointDepositsForfeited: epoch={} amount={}",
+                    ch.epoch(),
+                    forfeited
+                );
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Holds the deposit sent by the caller with its submission of `ch`.
+    fn hold_checkpoint_deposit(
+        rt: &mut impl Runtime,
+        ch: &Checkpoint,
+        amount: TokenAmount,
+    ) -> Result<(), ActorError> {
+        let deposit = CheckpointDeposit {
+            cid: ch.cid(),
+            submitter: rt.message().caller(),
+            amount,
+        };
+        rt.transaction(|st: &mut State, rt| {
+            st.add_checkpoint_deposit(rt.store(), ch.epoch(), deposit)
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_STATE,
+                        "failed to add checkpoint deposit",
+                    )
+                })
+        })
+    }
+
+    /// Returns the deposits held for checkpoint submissions to their submitters.
+    fn refund_checkpoint_deposits(
+        rt: &mut impl Runtime,
+        deposits: &[CheckpointDeposit],
+    ) -> Result<(), ActorError> {
+        for d in deposits.iter() {
+            rt.send(&d.submitter, METHOD_SEND, None, d.amount.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Runs the checks a checkpoint vote from the caller needs to pass before
+    /// being recorded, returning the validator the vote is recorded under.
+    ///
+    /// Do not call this function in transaction.
+    fn validate_vote(
+        rt: &mut impl Runtime,
+        st: &State,
+        ch: &Checkpoint,
+    ) -> Result<Address, ActorError> {
+        // votes submitted by a delegate are recorded under its validator
+        let caller = Self::checkpoint_voter(rt, st)?;
+
+        // fail fast on checkpoints for other subnets, the gateway would
+        // reject them anyway when committed.
+        let subnet_id = SubnetID::new_from_parent(&st.parent_id, rt.message().receiver());
+        if *ch.source() != subnet_id {
+            return Err(actor_error!(
+                illegal_argument,
+                "checkpoint source {} doesn't match subnet {}",
+                ch.source(),
+                subnet_id
+            ));
+        }
+
+        st.verify_checkpoint(rt, ch)
+            .map_err(|e| actor_error!(illegal_state, "checkpoint failed: {}", e))?;
+        st.check_vote(rt.store(), &ch.cid(), &caller, rt.curr_epoch())?;
+        Ok(caller)
+    }
+
+    /// VerifyCheckpoint runs the checks a submission of the checkpoint by the
+    /// caller would go through without recording its vote, so relayers can
+    /// verify a checkpoint before paying to submit it.
+    fn verify_checkpoint(
+        rt: &mut impl Runtime,
+        ch: Checkpoint,
+    ) -> Result<CheckpointVerification, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        Ok(match Self::validate_vote(rt, &st, &ch) {
+            Ok(_) => CheckpointVerification {
+                valid: true,
+                reason: None,
+            },
+            Err(e) => CheckpointVerification {
+                valid: false,
+                reason: Some(e.msg().to_string()),
+            },
+        })
+    }
+
+    /// Approves an address to join the subnet while it is permissioned. It can
+    /// only be called by the owner of the subnet.
+    fn approve_validator(rt: &mut impl Runtime, params: Address) -> Result<(), ActorError> {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is(st.owner.iter())?;
+
+        rt.transaction(|st: &mut State, _| {
+            if st.approved_validators.contains(&params) {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "validator {} is already approved",
+                    params
+                ));
+            }
+            st.approved_validators.push(params);
+            Ok(())
+        })?;
+
+        log::info!("ValidatorApproved: validator={}", params);
+        Ok(())
+    }
+
+    /// Revokes the approval of an address to join the subnet. Validators that
+    /// already joined keep their stake until they leave. It can only be called
+    /// by the owner of the subnet.
+    fn revoke_validator(rt: &mut impl Runtime, params: Address) -> Result<(), ActorError> {
+        let st: State = rt.state()?;
+        rt.validate_immediate_caller_is(st.owner.iter())?;
+
+        rt.transaction(|st: &mut State, _| {
+            let len = st.approved_validators.len();
+            st.approved_validators.retain(|v| *v != params);
+            if st.approved_validators.len() == len {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "validator {} is not approved",
+                    params
+                ));
+            }
+            Ok(())
+        })?;
+
+        log::info!("ValidatorRevoked: validator={}", params);
+        Ok(())
+    }
+
+    /// Returns the validator a checkpoint vote from the caller is recorded under,
+    /// either the caller itself or the validator that made it its delegate.
+    fn checkpoint_voter(rt: &impl Runtime, st: &State) -> Result<Address, ActorError> {
+        let caller = rt.message().caller();
+        if st.is_validator(&caller) {
+            return Ok(caller);
+        }
+        let validator = st
+            .get_checkpoint_delegator(rt.store(), &caller)
+            .map_err(|e| {
+                e.downcast_default(
+                    ExitCode::USR_ILLEGAL_STATE,
+                    "failed to load checkpoint delegates",
+                )
+            })?;
+        match validator {
+            Some(validator) if st.is_validator(&validator) => Ok(validator),
+            _ => Err(actor_error!(illegal_state, "not validator")),
+        }
+    }
+
+    /// SetCheckpointDelegate authorizes an address to submit checkpoint votes on
+    /// behalf of the calling validator, so a relayer can vote without holding
+    /// its keys. It replaces any previous delegate of the validator.
+    fn set_checkpoint_delegate(
+        rt: &mut impl Runtime,
+        params: Address,
+    ) -> Result<Option<RawBytes>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        rt.transaction(|st: &mut State, rt| {
+            if !st.is_validator(&caller) {
+                return Err(actor_error!(illegal_state, "not validator"));
+            }
+            if params == caller || st.is_validator(&params) {
+                return Err(actor_error!(
+                    illegal_argument,
+                    "validators can't be checkpoint delegates"
+                ));
+            }
+            st.set_checkpoint_delegate(rt.store(), &caller, &params)
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_ARGUMENT,
+                        "failed to set checkpoint delegate",
+                    )
+                })
+        })?;
+
+        log::info!(
+            "CheckpointDelegateSet: validator={} delegate={}",
+            caller,
+            params
+        );
+        Ok(None)
+    }
+
+    /// ClearCheckpointDelegate revokes the checkpoint delegate of the caller.
+    fn clear_checkpoint_delegate(rt: &mut impl Runtime) -> Result<Option<RawBytes>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let caller = rt.message().caller();
+        let cleared = rt.transaction(|st: &mut State, rt| {
+            st.clear_checkpoint_delegate(rt.store(), &caller)
+                .map_err(|e| {
+                    e.downcast_default(
+                        ExitCode::USR_ILLEGAL_STATE,
+                        "failed to clear checkpoint delegate",
+                    )
+                })
+        })?;
+        if !cleared {
+            return Err(actor_error!(
+                illegal_argument,
+                "caller has no checkpoint delegate"
+            ));
+        }
+
+        log::info!("CheckpointDelegateCleared: validator={}", caller);
+        Ok(None)
+    }
+
+    /// JoinAndVote joins the subnet and votes a checkpoint in the same message,
+    /// so validators bootstrapping a subnet can take part in its first checkpoint
+    /// right away. If any of the steps fails none of them is applied.
+    ///
+    /// The value sent needs to cover the checkpoint deposit of the vote, held as
+    /// in `SubmitCheckpoint`, and the rest of it is staked.
+    fn join_and_vote(
+        rt: &mut impl Runtime,
+        params: JoinAndVoteParams,
+    ) -> Result<Option<RawBytes>, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let amount = rt.message().value_received();
+        if !st.checkpoint_deposit.is_zero() && amount <= st.checkpoint_deposit {
+            return Err(actor_error!(
+                illegal_argument,
+                "joining and voting requires the stake and a checkpoint deposit of {}, {} sent",
+                st.checkpoint_deposit,
+                amount
+            ));
+        }
+        let stake = &amount - &st.checkpoint_deposit;
+
+        Self::stake_collateral(rt, params.join, stake)?;
+        if !st.checkpoint_deposit.is_zero() {
+            Self::hold_checkpoint_deposit(rt, &params.checkpoint, st.checkpoint_deposit)?;
+        }
+        Self::vote_checkpoint(rt, params.checkpoint)
+    }
+
+    /// Returns the interval between reward distributions, the epoch of the next
+    /// one and the rewards accumulated for it.
+    fn get_reward_schedule(rt: &mut impl Runtime) -> Result<RewardSchedule, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        Ok(RewardSchedule {
+            interval_epochs: st.reward_interval_epochs,
+            next_distribution_epoch: st.next_reward_epoch(),
+            accumulated: st.accumulated_rewards,
+        })
+    }
+
+    /// Sets the minimum number of epochs between reward distributions. As with
+    /// `RecoverFunds`, it can only be triggered by the system actor.
+    fn set_reward_interval(rt: &mut impl Runtime, params: ChainEpoch) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_is(std::iter::once(&SYSTEM_ACTOR_ADDR))?;
+
+        if params < 0 {
+            return Err(actor_error!(
+                illegal_argument,
+                "reward interval epochs can't be negative"
+            ));
+        }
+        rt.transaction(|st: &mut State, _| {
+            st.reward_interval_epochs = params;
+            Ok(())
+        })
+    }
+
+    /// Returns the collateral required to activate the subnet, the stake already
+    /// committed and what is still missing to reach it.
+    fn get_activation_requirements(
+        rt: &mut impl Runtime,
+    ) -> Result<ActivationRequirements, ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let st: State = rt.state()?;
+        let min_collateral = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let remaining = if st.total_stake < min_collateral {
+            &min_collateral - &st.total_stake
+        } else {
+            TokenAmount::zero()
+        };
+        Ok(ActivationRequirements {
+            min_collateral,
+            total_stake: st.total_stake,
+            remaining,
+            activation_epoch: st.activation_epoch,
+        })
+    }
+
+    /// Moves the subnet to its final state and unregisters it from the gateway.
+    /// If `pending` is set, it finalizes a kill in grace period.
+    fn terminate(rt: &mut impl Runtime, pending: bool) -> Result<Option<RawBytes>, ActorError> {
+        // prevent a subnet from being killed until all its locked balance has been withdrawn
+        if rt.current_balance() != TokenAmount::zero() {
+            return Err(actor_error!(
+                illegal_state,
+                format!("the subnet has non-zero balance: {:}", rt.current_balance())
+            ));
+        }
+
+        let mut msg = None;
+        rt.transaction(|st: &mut State, rt| {
+            if pending {
+                match st.kill_finalize_epoch {
+                    None => {
+                        return Err(actor_error!(illegal_state, "no pending kill to finalize"));
+                    }
+                    Some(epoch) if rt.curr_epoch() < epoch => {
+                        return Err(actor_error!(
+                            illegal_state,
+                            "the kill grace period ends at epoch {}",
+                            epoch
+                        ));
+                    }
+                    _ => {}
+                }
+            } else if st.status == Status::Terminating || st.status == Status::Killed {
+                return Err(actor_error!(
+                    illegal_state,
+                    "the subnet is already in a killed or terminating state"
+                ));
+            }
+
+            if !st.validator_set.is_empty() || st.total_stake != TokenAmount::zero() {
+                return Err(actor_error!(
+                    illegal_state,
+                    "this subnet can only be killed when all validators have left"
+                ));
+            }
+
+            // move to terminating state
+            st.status = Status::Terminating;
+            st.kill_finalize_epoch = None;
+            st.status_before_kill = None;
+
+            st.mutate_state(rt.curr_epoch());
+
+            msg = Some(CrossActorPayload::new(
+                st.ipc_gateway_addr,
+                ipc_gateway::Method::Kill as u64,
+                None,
+                TokenAmount::zero(),
+            ));
+
+            Ok(())
+        })?;
+
+        // unregister subnet
+        if let Some(p) = msg {
+            rt.send(&p.to, p.method, p.params, p.value)?;
+        }
+
+        Ok(None)
+    }
+
+    /// Recomputes `total_stake` from the balances of the stake table, to recover
+    /// from an inconsistent state. The subnet actor has no governor, so this can
+    /// only be triggered by the system actor through a network upgrade.
+    fn recompute_total_stake(rt: &mut impl Runtime) -> Result<StakeCorrection, ActorError> {
+        rt.validate_immediate_caller_is(std::iter::once(&SYSTEM_ACTOR_ADDR))?;
+
+        let correction = rt.transaction(|st: &mut State, rt| {
+            st.recompute_total_stake(rt.store()).map_err(|e| {
+                e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to recompute stake")
+            })
+        })?;
+        if correction.old_total_stake != correction.new_total_stake {
+            log::info!(
+                "TotalStakeCorrected: old={} new={}",
+                correction.old_total_stake,
+                correction.new_total_stake
+            );
+        }
+        Ok(correction)
+    }
+
+    /// Withdraws funds sent to the actor outside of `join` and `reward`, which
+    /// would otherwise be stranded. Only the balance over the stake of the
+    /// subnet and the rewards pending to be claimed can be recovered. As with
+    /// `RecomputeTotalStake`, it can only be triggered by the system actor.
+    fn recover_funds(rt: &mut impl Runtime, params: RecoverFundsParams) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_is(std::iter::once(&SYSTEM_ACTOR_ADDR))?;
+
+        if params.amount <= TokenAmount::zero() {
+            return Err(actor_error!(illegal_argument, "no funds to recover"));
+        }
+
+        let st: State = rt.state()?;
+        let accounted = st.accounted_funds(rt.store()).map_err(|e| {
+            e.downcast_default(
+                ExitCode::USR_ILLEGAL_STATE,
+                "failed to load accounted funds",
+            )
+        })?;
+        let balance = rt.current_balance();
+        let unaccounted = if balance > accounted {
+            balance - accounted
+        } else {
+            TokenAmount::zero()
+        };
+        if params.amount > unaccounted {
+            return Err(actor_error!(
+                illegal_argument,
+                "cannot recover {}, only {} is not accounted for",
+                params.amount,
+                unaccounted
+            ));
+        }
+
+        rt.send(&params.to, METHOD_SEND, None, params.amount.clone())?;
+        log::info!("FundsRecovered: to={} amount={}", params.to, params.amount);
+        Ok(())
+    }
+
+    /// Removes the votes of all the checkpoints being voted, to clear transient
+    /// state before an upgrade. It is only allowed while the subnet is inactive
+    /// and, as with `RecomputeTotalStake`, can only be triggered by the system actor.
+    ///
+    /// The deposits of the submissions of the checkpoints cleared are refunded.
+    /// It returns the number of vote sets removed.
+    fn clear_all_votes(rt: &mut impl Runtime) -> Result<u64, ActorError> {
+        rt.validate_immediate_caller_is(std::iter::once(&SYSTEM_ACTOR_ADDR))?;
+
+        let (removed, released) = rt.transaction(|st: &mut State, rt| {
+            if st.status != Status::Inactive {
+                return Err(actor_error!(
+                    illegal_state,
+                    "votes can only be cleared while the subnet is inactive"
+                ));
+            }
+            st.clear_all_votes(rt.store())
+        })?;
+        Self::refund_checkpoint_deposits(rt, &released)?;
+        log::info!("AllVotesCleared: count={}", removed);
+        Ok(removed)
+    }
+
+    /// Upgrades the state of the subnet actor to the current layout.
+    /// It is a no-op if the state is already up to date.
+    fn migrate_state(rt: &mut impl Runtime) -> Result<(), ActorError> {
+        rt.validate_immediate_caller_accept_any()?;
+
+        let root = rt.get_state_root()?;
+        let migrated = migration::migrate_state(rt.store(), &root).map_err(|e| {
+            e.downcast_default(ExitCode::USR_ILLEGAL_STATE, "failed to migrate state")
+        })?;
+        if let Some(root) = migrated {
+            rt.set_state_root(&root)?;
+        }
+        Ok(())
+    }
+}
+
 impl ActorCode for Actor {
     type Methods = Method;
 
@@ -362,7 +1427,37 @@ impl ActorCode for Actor {
         Join => join,
         Leave => leave,
         Kill => kill,
+        FinalizeKill => finalize_kill,
+        JailInactiveValidators => jail_inactive_validators,
+        Unjail => unjail,
         SubmitCheckpoint => submit_checkpoint,
         Reward => reward,
+        DistributeRewards => distribute_rewards,
+        IsValidator => is_validator,
+        MigrateState => migrate_state,
+        GetCheckpoints => get_checkpoints,
+        RecomputeTotalStake => recompute_total_stake,
+        GetCheckpointCertificate => get_checkpoint_certificate,
+        ClaimReward => claim_reward,
+        GetPendingReward => get_pending_reward,
+        GetFeesReceived => get_fees_received,
+        EstimateCheckpointSize => estimate_checkpoint_size,
+        SetRewardAddr => set_reward_addr,
+        RecoverFunds => recover_funds,
+        GetEligibleVoters => get_eligible_voters,
+        JoinAndVote => join_and_vote,
+        GetRewardSchedule => get_reward_schedule,
+        SetRewardInterval => set_reward_interval,
+        GetActivationRequirements => get_activation_requirements,
+        SetCheckpointDelegate => set_checkpoint_delegate,
+        ClearCheckpointDelegate => clear_checkpoint_delegate,
+        ClearAllVotes => clear_all_votes,
+        IsCheckpointCommitted => is_checkpoint_committed,
+        HasStake => has_stake,
+        VerifyCheckpoint => verify_checkpoint,
+        ApproveValidator => approve_validator,
+        RevokeValidator => revoke_validator,
+        CancelKill => cancel_kill,
+        Activate => activate,
     }
 }
@@ -0,0 +1,187 @@
+use anyhow::anyhow;
+use cid::multihash::Code;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::CborStore;
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use ipc_gateway::{Checkpoint, SubnetID};
+use num_traits::Zero;
+use primitives::{TCid, THamt};
+use serde::{Deserialize, Serialize};
+
+use crate::state::State;
+use crate::types::*;
+
+/// Version of the layout of the subnet actor `State` written by this actor.
+pub const STATE_VERSION: u8 = 3;
+
+/// Original, unversioned, layout of the subnet actor state.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StateV1 {
+    pub name: String,
+    pub parent_id: SubnetID,
+    pub ipc_gateway_addr: Address,
+    pub consensus: ConsensusType,
+    pub min_validator_stake: TokenAmount,
+    pub total_stake: TokenAmount,
+    pub stake: TCid<THamt<Cid, TokenAmount>>,
+    pub status: Status,
+    pub genesis: Vec<u8>,
+    pub finality_threshold: ChainEpoch,
+    pub check_period: ChainEpoch,
+    pub checkpoints: TCid<THamt<ChainEpoch, Checkpoint>>,
+    pub window_checks: TCid<THamt<Cid, Votes>>,
+    pub validator_set: Vec<Validator>,
+    pub min_validators: u64,
+}
+
+/// Layout of the subnet actor state in version 2.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StateV2 {
+    pub version: u8,
+    pub name: String,
+    pub parent_id: SubnetID,
+    pub ipc_gateway_addr: Address,
+    pub consensus: ConsensusType,
+    pub min_validator_stake: TokenAmount,
+    pub total_stake: TokenAmount,
+    pub stake: TCid<THamt<Cid, TokenAmount>>,
+    pub status: Status,
+    pub genesis: Vec<u8>,
+    pub finality_threshold: ChainEpoch,
+    pub check_period: ChainEpoch,
+    pub min_checkpoint_gap: ChainEpoch,
+    pub pending_window_start: Option<ChainEpoch>,
+    pub net_addr_validation: NetAddrValidation,
+    pub checkpoints: TCid<THamt<ChainEpoch, Checkpoint>>,
+    pub window_checks: TCid<THamt<Cid, Votes>>,
+    pub validator_set: Vec<Validator>,
+    pub min_validators: u64,
+}
+
+/// Used to peek the version of a serialized state. States written before
+/// versioning was introduced don't include the field and are version 1.
+#[derive(Deserialize)]
+struct StateVersion {
+    #[serde(default = "legacy_version")]
+    version: u8,
+}
+
+fn legacy_version() -> u8 {
+    1
+}
+
+/// Upgrades the state stored in `root` to the current layout.
+///
+/// Returns the root of the migrated state, or `None` if the state is
+/// already up to date. Unknown future versions are rejected.
+pub fn migrate_state<BS: Blockstore>(store: &BS, root: &Cid) -> anyhow::Result<Option<Cid>> {
+    let StateVersion { version } = store
+        .get_cbor(root)?
+        .ok_or_else(|| anyhow!("state not found for root {}", root))?;
+
+    let st = match version {
+        STATE_VERSION => return Ok(None),
+        1 => {
+            let v1: StateV1 = store
+                .get_cbor(root)?
+                .ok_or_else(|| anyhow!("state not found for root {}", root))?;
+            migrate_v2(store, migrate_v1(v1))?
+        }
+        2 => {
+            let v2: StateV2 = store
+                .get_cbor(root)?
+                .ok_or_else(|| anyhow!("state not found for root {}", root))?;
+            migrate_v2(store, v2)?
+        }
+        v => return Err(anyhow!("unknown state version {}", v)),
+    };
+
+    Ok(Some(store.put_cbor(&st, Code::Blake2b256)?))
+}
+
+/// Fields introduced in version 2 are initialized so the subnet keeps
+/// behaving as it did before the upgrade.
+fn migrate_v1(v1: StateV1) -> StateV2 {
+    StateV2 {
+        version: 2,
+        name: v1.name,
+        parent_id: v1.parent_id,
+        ipc_gateway_addr: v1.ipc_gateway_addr,
+        consensus: v1.consensus,
+        min_validator_stake: v1.min_validator_stake,
+        total_stake: v1.total_stake,
+        stake: v1.stake,
+        status: v1.status,
+        genesis: v1.genesis,
+        finality_threshold: v1.finality_threshold,
+        check_period: v1.check_period,
+        min_checkpoint_gap: 0,
+        pending_window_start: None,
+        net_addr_validation: NetAddrValidation::Disabled,
+        checkpoints: v1.checkpoints,
+        window_checks: v1.window_checks,
+        validator_set: v1.validator_set,
+        min_validators: v1.min_validators,
+    }
+}
+
+/// Fields introduced in version 3 are initialized so the subnet keeps
+/// behaving as it did before the upgrade. The checkpoints already committed
+/// decode the fields added since with their defaults.
+fn migrate_v2<BS: Blockstore>(store: &BS, v2: StateV2) -> anyhow::Result<State> {
+    Ok(State {
+        version: STATE_VERSION,
+        name: v2.name,
+        parent_id: v2.parent_id,
+        ipc_gateway_addr: v2.ipc_gateway_addr,
+        consensus: v2.consensus,
+        min_validator_stake: v2.min_validator_stake,
+        total_stake: v2.total_stake,
+        stake: v2.stake,
+        status: v2.status,
+        genesis: v2.genesis,
+        finality_threshold: v2.finality_threshold,
+        check_period: v2.check_period,
+        min_checkpoint_gap: v2.min_checkpoint_gap,
+        pending_window_start: v2.pending_window_start,
+        net_addr_validation: v2.net_addr_validation,
+        kill_grace_epochs: 0,
+        kill_finalize_epoch: None,
+        status_before_kill: None,
+        jail_threshold: 0,
+        missed_checkpoints: TCid::new_hamt(store)?,
+        jailed: Vec::new(),
+        rejoin_cooldown_epochs: 0,
+        last_left: TCid::new_hamt(store)?,
+        reward_policy: RewardPolicy::Even,
+        pull_rewards: false,
+        pending_rewards: TCid::new_hamt(store)?,
+        fees_received: TCid::new_hamt(store)?,
+        reward_addrs: TCid::new_hamt(store)?,
+        checkpoint_delegates: TCid::new_hamt(store)?,
+        checkpoints: v2.checkpoints,
+        checkpoint_voters: TCid::new_hamt(store)?,
+        eligible_voters: TCid::new_hamt(store)?,
+        validator_diffs: TCid::new_hamt(store)?,
+        committed_validators: v2.validator_set.iter().map(|v| v.addr).collect(),
+        window_checks: v2.window_checks,
+        vote_retention_windows: 0,
+        quorum_policy: QuorumPolicy::Stake,
+        reward_interval_epochs: 0,
+        last_reward_epoch: None,
+        accumulated_rewards: TokenAmount::zero(),
+        accumulate_unassigned_rewards: false,
+        activation_epoch: None,
+        permissioned: false,
+        owner: None,
+        approved_validators: Vec::new(),
+        vote_epochs: TCid::new_hamt(store)?,
+        checkpoint_deposit: TokenAmount::zero(),
+        checkpoint_deposits: TCid::new_hamt(store)?,
+        validator_set: v2.validator_set,
+        min_validators: v2.min_validators,
+    })
+}
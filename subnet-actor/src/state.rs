@@ -17,6 +17,8 @@ use num::BigInt;
 use primitives::{TCid, THamt};
 use serde::{Deserialize, Serialize};
 
+use crate::error::CheckpointError;
+use crate::migration::STATE_VERSION;
 use crate::types::*;
 
 lazy_static! {
@@ -29,6 +31,8 @@ lazy_static! {
 /// The state object.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct State {
+    /// Version of the layout of the state, see `STATE_VERSION`.
+    pub version: u8,
     pub name: String,
     /// The parent id of the subnet actor, it should be the same as the
     /// actor's gateway's state.network_name
@@ -42,8 +46,70 @@ pub struct State {
     pub genesis: Vec<u8>,
     pub finality_threshold: ChainEpoch,
     pub check_period: ChainEpoch,
+    pub min_checkpoint_gap: ChainEpoch,
+    /// Epoch in which the voting for the current, still uncommitted,
+    /// checkpoint was initiated.
+    pub pending_window_start: Option<ChainEpoch>,
+    pub net_addr_validation: NetAddrValidation,
+    pub kill_grace_epochs: ChainEpoch,
+    /// Epoch from which a subnet in its kill grace period can be
+    /// finalized. `None` if no kill is pending.
+    pub kill_finalize_epoch: Option<ChainEpoch>,
+    /// Status of the subnet when its kill grace period started, restored if
+    /// the kill is cancelled.
+    pub status_before_kill: Option<Status>,
+    pub jail_threshold: u64,
+    /// Number of consecutive committed checkpoints missed by each validator.
+    pub missed_checkpoints: TCid<THamt<Cid, u64>>,
+    /// Validators excluded from rewards for not participating in checkpoints.
+    pub jailed: Vec<Address>,
+    pub rejoin_cooldown_epochs: ChainEpoch,
+    /// Epoch in which each address last left the subnet, tracked while the
+    /// rejoin cooldown is enabled.
+    pub last_left: TCid<THamt<Cid, ChainEpoch>>,
+    pub reward_policy: RewardPolicy,
+    pub pull_rewards: bool,
+    /// Rewards accrued by each validator and not claimed yet, tracked while
+    /// pull-based rewards are enabled.
+    pub pending_rewards: TCid<THamt<Cid, TokenAmount>>,
+    /// Total rewards distributed to each validator over the life of the subnet.
+    pub fees_received: TCid<THamt<Cid, TokenAmount>>,
+    /// Address rewards are paid to for the validators that set one different
+    /// from the address they stake from.
+    pub reward_addrs: TCid<THamt<Cid, Address>>,
+    /// Validator that authorized each address to submit checkpoint votes on
+    /// its behalf, indexed by the delegate.
+    pub checkpoint_delegates: TCid<THamt<Cid, Address>>,
     pub checkpoints: TCid<THamt<ChainEpoch, Checkpoint>>,
+    /// Validators that voted each committed checkpoint, indexed by epoch.
+    pub checkpoint_voters: TCid<THamt<ChainEpoch, Vec<CheckpointVoter>>>,
+    /// Validators, with their stake, eligible to vote the checkpoint of each
+    /// window, snapshotted when the voting of the window started.
+    pub eligible_voters: TCid<THamt<ChainEpoch, Vec<CheckpointVoter>>>,
+    /// Changes in the validator set recorded with each committed checkpoint.
+    pub validator_diffs: TCid<THamt<ChainEpoch, ValidatorSetDiff>>,
+    /// Validator set when the last checkpoint was committed.
+    pub committed_validators: Vec<Address>,
     pub window_checks: TCid<THamt<Cid, Votes>>,
+    pub vote_retention_windows: u64,
+    pub quorum_policy: QuorumPolicy,
+    pub reward_interval_epochs: ChainEpoch,
+    /// Epoch of the last reward distribution, `None` if there was none yet.
+    pub last_reward_epoch: Option<ChainEpoch>,
+    /// Rewards received since the last distribution.
+    pub accumulated_rewards: TokenAmount,
+    pub accumulate_unassigned_rewards: bool,
+    pub activation_epoch: Option<ChainEpoch>,
+    pub permissioned: bool,
+    pub owner: Option<Address>,
+    /// Addresses allowed to join the subnet while it is permissioned.
+    pub approved_validators: Vec<Address>,
+    /// Epoch of the checkpoint voted by each set of votes in `window_checks`,
+    /// tracked while the cleanup of stale votes is enabled.
+    pub vote_epochs: TCid<THamt<Cid, ChainEpoch>>,
+    pub checkpoint_deposit: TokenAmount,
+    /// Deposits of the checkpoint submissions not settled yet, indexed by epoch.
+    pub checkpoint_deposits: TCid<THamt<ChainEpoch, Vec<CheckpointDeposit>>>,
     pub validator_set: Vec<Validator>,
     pub min_validators: u64,
 }
@@ -56,6 +122,7 @@ impl State {
         let min_stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
 
         let state = State {
+            version: STATE_VERSION,
             name: params.name,
             parent_id: params.parent,
             ipc_gateway_addr: Address::new_id(params.ipc_gateway_addr),
@@ -73,11 +140,49 @@ impl State {
             } else {
                 params.check_period
             },
+            min_checkpoint_gap: if params.min_checkpoint_gap < 0 {
+                0
+            } else {
+                params.min_checkpoint_gap
+            },
+            pending_window_start: None,
+            net_addr_validation: params.net_addr_validation,
+            kill_grace_epochs: params.kill_grace_epochs,
+            kill_finalize_epoch: None,
+            status_before_kill: None,
+            jail_threshold: params.jail_threshold,
+            missed_checkpoints: TCid::new_hamt(store)?,
+            jailed: Vec::new(),
+            rejoin_cooldown_epochs: params.rejoin_cooldown_epochs,
+            last_left: TCid::new_hamt(store)?,
+            reward_policy: params.reward_policy,
+            pull_rewards: params.pull_rewards,
+            pending_rewards: TCid::new_hamt(store)?,
+            fees_received: TCid::new_hamt(store)?,
+            reward_addrs: TCid::new_hamt(store)?,
+            checkpoint_delegates: TCid::new_hamt(store)?,
             genesis: params.genesis,
             status: Status::Instantiated,
             checkpoints: TCid::new_hamt(store)?,
+            checkpoint_voters: TCid::new_hamt(store)?,
+            eligible_voters: TCid::new_hamt(store)?,
+            validator_diffs: TCid::new_hamt(store)?,
+            committed_validators: Vec::new(),
             stake: TCid::new_hamt(store)?,
             window_checks: TCid::new_hamt(store)?,
+            vote_retention_windows: params.vote_retention_windows,
+            quorum_policy: params.quorum_policy,
+            reward_interval_epochs: params.reward_interval_epochs,
+            last_reward_epoch: None,
+            accumulated_rewards: TokenAmount::zero(),
+            accumulate_unassigned_rewards: params.accumulate_unassigned_rewards,
+            activation_epoch: params.activation_epoch,
+            permissioned: params.permissioned,
+            owner: params.owner,
+            approved_validators: Vec::new(),
+            vote_epochs: TCid::new_hamt(store)?,
+            checkpoint_deposit: params.checkpoint_deposit,
+            checkpoint_deposits: TCid::new_hamt(store)?,
             validator_set: Vec::new(),
         };
 
@@ -111,6 +216,14 @@ impl State {
                 Ok(true)
             })
             .map_err(|_| actor_error!(illegal_state, "cannot modify window checks"))?;
+        if self.vote_retention_windows > 0 {
+            self.vote_epochs
+                .update(store, |hamt| {
+                    hamt.delete(&BytesKey::from(cid.to_bytes()))?;
+                    Ok(())
+                })
+                .map_err(|_| actor_error!(illegal_state, "cannot modify vote epochs"))?;
+        }
 
         Ok(())
     }
@@ -131,6 +244,122 @@ impl State {
         Ok(())
     }
 
+    /// Records the epoch of the checkpoint voted by the votes stored for `cid`.
+    pub(crate) fn tag_votes<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        cid: &Cid,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        if self.vote_retention_windows == 0 {
+            return Ok(());
+        }
+        self.vote_epochs.update(store, |hamt| {
+            hamt.set(BytesKey::from(cid.to_bytes()), epoch)?;
+            Ok(())
+        })
+    }
+
+    /// Removes the votes of checkpoints that are `vote_retention_windows` or
+    /// more windows older than the checkpoint of `epoch`, returning the number
+    /// of vote sets removed and the deposits of their submissions, to be refunded.
+    pub(crate) fn remove_stale_votes<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<(u64, Vec<CheckpointDeposit>)> {
+        if self.vote_retention_windows == 0 {
+            return Ok((0, Vec::new()));
+        }
+        let retention =
+            ChainEpoch::try_from(self.vote_retention_windows)?.saturating_mul(self.check_period);
+
+        let mut stale = Vec::new();
+        self.vote_epochs.load(store)?.for_each(|k, voted| {
+            if voted.saturating_add(retention) <= epoch {
+                stale.push((k.clone(), *voted));
+            }
+            Ok(())
+        })?;
+
+        let removed = stale.len() as u64;
+        let mut released = Vec::new();
+        if removed > 0 {
+            self.window_checks.update(store, |hamt| {
+                for (k, _) in stale.iter() {
+                    hamt.delete(k)?;
+                }
+                Ok(())
+            })?;
+            self.vote_epochs.update(store, |hamt| {
+                for (k, _) in stale.iter() {
+                    hamt.delete(k)?;
+                }
+                Ok(())
+            })?;
+            for (k, voted) in stale.iter() {
+                let cid = Cid::try_from(k.0.as_slice())?;
+                released.extend(self.take_checkpoint_deposits(store, *voted, &cid)?);
+            }
+        }
+        Ok((removed, released))
+    }
+
+    /// Removes all the stored vote sets, returning the number removed and the
+    /// deposits of their submissions, to be refunded. It fails if a set already
+    /// gathered a majority, as its checkpoint is being committed.
+    pub(crate) fn clear_all_votes<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+    ) -> Result<(u64, Vec<CheckpointDeposit>), ActorError> {
+        let mut all = Vec::new();
+        self.window_checks
+            .load(store)
+            .map_err(|_| actor_error!(illegal_state, "cannot load votes hamt"))?
+            .for_each(|k, votes| {
+                all.push((k.clone(), votes.clone()));
+                Ok(())
+            })
+            .map_err(|_| actor_error!(illegal_state, "cannot read votes"))?;
+
+        // vote sets don't record the window they were cast for, so they are
+        // weighted with the current validators
+        let eligible = self
+            .current_voters(store)
+            .map_err(|_| actor_error!(illegal_state, "cannot load validators stake"))?;
+        for (k, votes) in all.iter() {
+            if self.has_quorum(votes, &eligible) {
+                let cid = Cid::try_from(k.0.as_slice())
+                    .map_err(|_| actor_error!(illegal_state, "invalid votes key"))?;
+                return Err(actor_error!(
+                    illegal_state,
+                    "checkpoint {} has a majority of votes and is being committed",
+                    cid
+                ));
+            }
+        }
+
+        self.window_checks = TCid::new_hamt(store)
+            .map_err(|_| actor_error!(illegal_state, "cannot create votes hamt"))?;
+        self.vote_epochs = TCid::new_hamt(store)
+            .map_err(|_| actor_error!(illegal_state, "cannot create vote epochs hamt"))?;
+        self.pending_window_start = None;
+
+        // every deposit held belongs to a submission of a vote set removed
+        let mut released = Vec::new();
+        self.checkpoint_deposits
+            .load(store)
+            .map_err(|_| actor_error!(illegal_state, "cannot load checkpoint deposits"))?
+            .for_each(|_, deposits| {
+                released.extend(deposits.iter().cloned());
+                Ok(())
+            })
+            .map_err(|_| actor_error!(illegal_state, "cannot read checkpoint deposits"))?;
+        self.checkpoint_deposits = TCid::new_hamt(store)
+            .map_err(|_| actor_error!(illegal_state, "cannot create checkpoint deposits hamt"))?;
+        Ok((all.len() as u64, released))
+    }
+
     /// Get the stake of an address.
     pub fn get_stake<BS: Blockstore>(
         &self,
@@ -142,6 +371,25 @@ impl State {
         Ok(amount.cloned())
     }
 
+    /// Sets `total_stake` to the sum of the balances in the stake table.
+    pub(crate) fn recompute_total_stake<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+    ) -> anyhow::Result<StakeCorrection> {
+        let hamt = self.stake.load(store)?;
+        let mut total = TokenAmount::zero();
+        hamt.for_each(|_, amount| {
+            total += amount;
+            Ok(())
+        })?;
+
+        let old = std::mem::replace(&mut self.total_stake, total.clone());
+        Ok(StakeCorrection {
+            old_total_stake: old,
+            new_total_stake: total,
+        })
+    }
+
     /// Adds stake from a validator
     pub(crate) fn add_stake<BS: Blockstore>(
         &mut self,
@@ -218,26 +466,364 @@ impl State {
         Ok(())
     }
 
+    /// Updates the participation of validators after a checkpoint is committed.
+    /// Voters have their missed checkpoints reset, the rest increase them.
+    pub(crate) fn record_participation<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        votes: &Votes,
+    ) -> anyhow::Result<()> {
+        if self.jail_threshold == 0 {
+            return Ok(());
+        }
+        let validators = &self.validator_set;
+        self.missed_checkpoints.update(store, |hamt| {
+            for v in validators.iter() {
+                let key = BytesKey::from(v.addr.to_bytes());
+                if votes.validators.contains(&v.addr) {
+                    hamt.delete(&key)?;
+                } else {
+                    let missed = hamt.get(&key)?.cloned().unwrap_or_default();
+                    hamt.set(key, missed + 1)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Get the number of consecutive committed checkpoints missed by a validator.
+    pub fn get_missed_checkpoints<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<u64> {
+        let hamt = self.missed_checkpoints.load(store)?;
+        let missed = hamt.get(&BytesKey::from(addr.to_bytes()))?;
+        Ok(missed.cloned().unwrap_or_default())
+    }
+
+    /// Jails the validators that missed at least `jail_threshold` committed
+    /// checkpoints in a row, returning the ones newly jailed.
+    pub(crate) fn jail_inactive_validators<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+    ) -> anyhow::Result<Vec<Address>> {
+        if self.jail_threshold == 0 {
+            return Ok(Vec::new());
+        }
+        let mut jailed = Vec::new();
+        for v in self.validator_set.iter() {
+            if self.is_jailed(&v.addr) {
+                continue;
+            }
+            if self.get_missed_checkpoints(store, &v.addr)? >= self.jail_threshold {
+                jailed.push(v.addr);
+            }
+        }
+        self.jailed.extend(jailed.iter().cloned());
+        Ok(jailed)
+    }
+
+    /// Releases a validator from jail. The validator needs to have voted a
+    /// committed checkpoint since it was jailed.
+    pub(crate) fn unjail<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<()> {
+        if !self.is_jailed(addr) {
+            return Err(anyhow!("validator {} is not jailed", addr));
+        }
+        if self.get_missed_checkpoints(store, addr)? >= self.jail_threshold {
+            return Err(anyhow!(
+                "validator {} hasn't participated in a checkpoint since it was jailed",
+                addr
+            ));
+        }
+        self.jailed.retain(|x| x != addr);
+        Ok(())
+    }
+
+    pub fn is_jailed(&self, addr: &Address) -> bool {
+        self.jailed.contains(addr)
+    }
+
+    /// Records the epoch in which an address left the subnet.
+    pub(crate) fn record_leave<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        if self.rejoin_cooldown_epochs == 0 {
+            return Ok(());
+        }
+        self.last_left.update(store, |hamt| {
+            hamt.set(BytesKey::from(addr.to_bytes()), epoch)?;
+            Ok(())
+        })
+    }
+
+    /// Checks if an address is allowed to join the subnet in `curr_epoch`,
+    /// i.e. it hasn't left the subnet within the rejoin cooldown.
+    pub fn can_rejoin<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+        curr_epoch: ChainEpoch,
+    ) -> anyhow::Result<bool> {
+        if self.rejoin_cooldown_epochs == 0 {
+            return Ok(true);
+        }
+        let hamt = self.last_left.load(store)?;
+        match hamt.get(&BytesKey::from(addr.to_bytes()))? {
+            Some(left) => Ok(curr_epoch >= left + self.rejoin_cooldown_epochs),
+            None => Ok(true),
+        }
+    }
+
+    /// Adds `amount` to the rewards pending to be claimed by `addr`.
+    pub(crate) fn accrue_reward<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        amount: &TokenAmount,
+    ) -> anyhow::Result<()> {
+        let pending = self.get_pending_reward(store, addr)? + amount;
+        self.pending_rewards.update(store, |hamt| {
+            hamt.set(BytesKey::from(addr.to_bytes()), pending)?;
+            Ok(())
+        })
+    }
+
+    /// Returns the rewards accrued by `addr` that haven't been claimed yet.
+    pub fn get_pending_reward<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<TokenAmount> {
+        let hamt = self.pending_rewards.load(store)?;
+        let amount = hamt.get(&BytesKey::from(addr.to_bytes()))?;
+        Ok(amount.cloned().unwrap_or_default())
+    }
+
+    /// Returns the funds of the actor that are accounted for, i.e. the stake of
+    /// the subnet and the rewards pending to be claimed.
+    pub fn accounted_funds<BS: Blockstore>(&self, store: &BS) -> anyhow::Result<TokenAmount> {
+        let mut total = &self.total_stake + &self.accumulated_rewards;
+        self.pending_rewards.load(store)?.for_each(|_, amount| {
+            total += amount;
+            Ok(())
+        })?;
+        self.checkpoint_deposits
+            .load(store)?
+            .for_each(|_, deposits| {
+                for d in deposits {
+                    total += &d.amount;
+                }
+                Ok(())
+            })?;
+        Ok(total)
+    }
+
+    /// Returns the first epoch in which the rewards received can be distributed.
+    pub fn next_reward_epoch(&self) -> ChainEpoch {
+        match self.last_reward_epoch {
+            Some(last) => last + self.reward_interval_epochs,
+            None => 0,
+        }
+    }
+
+    /// Removes and returns the rewards pending to be claimed by `addr`.
+    pub(crate) fn take_pending_reward<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<TokenAmount> {
+        let amount = self.get_pending_reward(store, addr)?;
+        if !amount.is_zero() {
+            self.pending_rewards.update(store, |hamt| {
+                hamt.delete(&BytesKey::from(addr.to_bytes()))?;
+                Ok(())
+            })?;
+        }
+        Ok(amount)
+    }
+
+    /// Adds `amount` to the total of rewards received by `addr`.
+    pub(crate) fn record_fees_received<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        amount: &TokenAmount,
+    ) -> anyhow::Result<()> {
+        let received = self.get_fees_received(store, addr)? + amount;
+        self.fees_received.update(store, |hamt| {
+            hamt.set(BytesKey::from(addr.to_bytes()), received)?;
+            Ok(())
+        })
+    }
+
+    /// Returns the total of rewards distributed to `addr`.
+    pub fn get_fees_received<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<TokenAmount> {
+        let hamt = self.fees_received.load(store)?;
+        let amount = hamt.get(&BytesKey::from(addr.to_bytes()))?;
+        Ok(amount.cloned().unwrap_or_default())
+    }
+
+    /// Sets the address the rewards of `addr` are paid to. Setting it back to
+    /// `addr` itself removes the entry.
+    pub(crate) fn set_reward_addr<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        addr: &Address,
+        reward_addr: &Address,
+    ) -> anyhow::Result<()> {
+        self.reward_addrs.update(store, |hamt| {
+            let key = BytesKey::from(addr.to_bytes());
+            if addr == reward_addr {
+                hamt.delete(&key)?;
+            } else {
+                hamt.set(key, *reward_addr)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the address the rewards of `addr` are paid to, which is `addr`
+    /// itself unless a different reward address was set.
+    pub fn get_reward_addr<BS: Blockstore>(
+        &self,
+        store: &BS,
+        addr: &Address,
+    ) -> anyhow::Result<Address> {
+        let hamt = self.reward_addrs.load(store)?;
+        let reward_addr = hamt.get(&BytesKey::from(addr.to_bytes()))?;
+        Ok(reward_addr.copied().unwrap_or(*addr))
+    }
+
+    /// Authorizes `delegate` to submit checkpoint votes on behalf of `validator`,
+    /// replacing its previous delegate. An address can only be the delegate of
+    /// a single validator.
+    pub(crate) fn set_checkpoint_delegate<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        validator: &Address,
+        delegate: &Address,
+    ) -> anyhow::Result<()> {
+        if let Some(other) = self.get_checkpoint_delegator(store, delegate)? {
+            if other != *validator {
+                return Err(anyhow!("{} is already the delegate of {}", delegate, other));
+            }
+        }
+        self.clear_checkpoint_delegate(store, validator)?;
+        self.checkpoint_delegates.update(store, |hamt| {
+            hamt.set(BytesKey::from(delegate.to_bytes()), *validator)?;
+            Ok(())
+        })
+    }
+
+    /// Revokes the checkpoint delegate of `validator`, returning false if it
+    /// didn't have one.
+    pub(crate) fn clear_checkpoint_delegate<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        validator: &Address,
+    ) -> anyhow::Result<bool> {
+        let mut delegates = Vec::new();
+        self.checkpoint_delegates.load(store)?.for_each(|k, v| {
+            if v == validator {
+                delegates.push(k.clone());
+            }
+            Ok(())
+        })?;
+        if delegates.is_empty() {
+            return Ok(false);
+        }
+        self.checkpoint_delegates.update(store, |hamt| {
+            for k in delegates.iter() {
+                hamt.delete(k)?;
+            }
+            Ok(())
+        })?;
+        Ok(true)
+    }
+
+    /// Returns the validator that authorized `delegate` to submit checkpoint
+    /// votes on its behalf, if any.
+    pub fn get_checkpoint_delegator<BS: Blockstore>(
+        &self,
+        store: &BS,
+        delegate: &Address,
+    ) -> anyhow::Result<Option<Address>> {
+        let hamt = self.checkpoint_delegates.load(store)?;
+        let validator = hamt.get(&BytesKey::from(delegate.to_bytes()))?;
+        Ok(validator.copied())
+    }
+
+    /// Checks if `votes` reach the quorum of the validators eligible to vote the
+    /// checkpoint of the window in `epoch`, weighted with the stake snapshotted
+    /// when its voting started, as returned by `get_eligible_voters`.
     pub fn has_majority_vote<BS: Blockstore>(
         &self,
         store: &BS,
         votes: &Votes,
+        epoch: ChainEpoch,
     ) -> Result<bool, ActorError> {
+        let eligible = match self.get_eligible_voters(store, epoch) {
+            Ok(Some(eligible)) => eligible,
+            Ok(None) => self.current_voters(store),
+            Err(e) => Err(e),
+        }
+        .map_err(|_| actor_error!(illegal_state, "cannot load eligible voters"))?;
+        Ok(self.has_quorum(votes, &eligible))
+    }
+
+    /// Checks if `votes` reach the quorum of the `eligible` validators under the
+    /// quorum policy of the subnet. Votes of non-eligible validators don't count.
+    fn has_quorum(&self, votes: &Votes, eligible: &[CheckpointVoter]) -> bool {
+        let mut total = TokenAmount::zero();
         let mut sum = TokenAmount::zero();
-        for v in &votes.validators {
-            let stake = self
-                .get_stake(store, v)
-                .map_err(|_| actor_error!(illegal_state, "cannot load stake from hamt"))?;
-            sum += stake.unwrap_or_else(TokenAmount::zero);
+        let mut voters = 0;
+        for v in eligible.iter() {
+            total += &v.stake;
+            if votes.validators.contains(&v.addr) {
+                sum += &v.stake;
+                voters += 1;
+            }
+        }
+        if total.is_zero() {
+            return false;
+        }
+        let ftotal = Ratio::from_integer(total.atto().clone());
+        let stake_quorum = Ratio::from_integer(sum.atto().clone()) / ftotal >= *VOTING_THRESHOLD;
+        match self.quorum_policy {
+            QuorumPolicy::Stake => stake_quorum,
+            QuorumPolicy::StakeAndCount => stake_quorum && voters * 2 > eligible.len(),
         }
-        let ftotal = Ratio::from_integer(self.total_stake.atto().clone());
-        Ok(Ratio::from_integer(sum.atto().clone()) / ftotal >= *VOTING_THRESHOLD)
     }
 
-    pub fn mutate_state(&mut self) {
+    /// Returns true if the address can join the subnet, i.e. the subnet is
+    /// permissionless or the address was approved by the owner.
+    pub fn can_join(&self, addr: &Address) -> bool {
+        !self.permissioned || self.approved_validators.contains(addr)
+    }
+
+    /// Returns true if the subnet has enough collateral and has reached its
+    /// activation epoch, if any, so it can be registered in the gateway.
+    pub fn can_activate(&self, curr_epoch: ChainEpoch) -> bool {
+        self.total_stake >= TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT)
+            && self.activation_epoch.map_or(true, |e| curr_epoch >= e)
+    }
+
+    pub fn mutate_state(&mut self, curr_epoch: ChainEpoch) {
         match self.status {
             Status::Instantiated => {
-                if self.total_stake >= TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) {
+                if self.can_activate(curr_epoch) {
                     self.status = Status::Active
                 }
             }
@@ -254,7 +840,7 @@ impl State {
             // if no total_stake and current_balance left (except if we are testing where the funds
             // are never leaving the actor)
             Status::Terminating => {
-                if self.total_stake == TokenAmount::zero() {
+                if self.total_stake == TokenAmount::zero() && self.kill_finalize_epoch.is_none() {
                     self.status = Status::Killed
                 }
             }
@@ -278,52 +864,366 @@ impl State {
         Ok(checkpoint)
     }
 
+    /// Records the validators that voted the checkpoint committed in `epoch`,
+    /// along with their current stake.
+    pub(crate) fn record_checkpoint_voters<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+        votes: &Votes,
+    ) -> anyhow::Result<()> {
+        let mut voters = Vec::with_capacity(votes.validators.len());
+        for addr in votes.validators.iter() {
+            let stake = self.get_stake(store, addr)?.unwrap_or_default();
+            voters.push(CheckpointVoter { addr: *addr, stake });
+        }
+        self.checkpoint_voters.update(store, |hamt| {
+            hamt.set(BytesKey::from(epoch.to_ne_bytes().to_vec()), voters)?;
+            Ok(())
+        })
+    }
+
+    /// Holds the deposit of a submission of the checkpoint for `epoch`.
+    pub(crate) fn add_checkpoint_deposit<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+        deposit: CheckpointDeposit,
+    ) -> anyhow::Result<()> {
+        self.checkpoint_deposits.update(store, |hamt| {
+            let key = BytesKey::from(epoch.to_ne_bytes().to_vec());
+            let mut deposits = hamt.get(&key)?.cloned().unwrap_or_default();
+            deposits.push(deposit);
+            hamt.set(key, deposits)?;
+            Ok(())
+        })
+    }
+
+    /// Removes the deposits held for the submissions of checkpoint `cid`, voted
+    /// for `epoch`, and returns them.
+    pub(crate) fn take_checkpoint_deposits<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+        cid: &Cid,
+    ) -> anyhow::Result<Vec<CheckpointDeposit>> {
+        let key = BytesKey::from(epoch.to_ne_bytes().to_vec());
+        let deposits = self
+            .checkpoint_deposits
+            .load(store)?
+            .get(&key)?
+            .cloned()
+            .unwrap_or_default();
+        let (taken, kept): (Vec<_>, Vec<_>) = deposits.into_iter().partition(|d| d.cid == *cid);
+        if !taken.is_empty() {
+            self.checkpoint_deposits.update(store, |hamt| {
+                if kept.is_empty() {
+                    hamt.delete(&key)?;
+                } else {
+                    hamt.set(key.clone(), kept.clone())?;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(taken)
+    }
+
+    /// Settles the deposits of the submissions for the epoch of the committed
+    /// checkpoint `ch`.
+    ///
+    /// Only submitters that voted more than one checkpoint for the epoch can be
+    /// proven to misbehave, so the sum of their deposits is returned to be
+    /// forfeited. The rest are returned to be refunded, including the ones of
+    /// validators that voted a conflicting checkpoint only, as they may have
+    /// just been on a losing fork.
+    pub(crate) fn settle_checkpoint_deposits<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        ch: &Checkpoint,
+    ) -> anyhow::Result<(Vec<CheckpointDeposit>, TokenAmount)> {
+        let key = BytesKey::from(ch.epoch().to_ne_bytes().to_vec());
+        let deposits = self
+            .checkpoint_deposits
+            .load(store)?
+            .get(&key)?
+            .cloned()
+            .unwrap_or_default();
+        if !deposits.is_empty() {
+            self.checkpoint_deposits.update(store, |hamt| {
+                hamt.delete(&key)?;
+                Ok(())
+            })?;
+        }
+        let mut voted: Vec<(Address, Cid)> = Vec::new();
+        let mut equivocators = Vec::new();
+        for d in deposits.iter() {
+            match voted.iter().find(|(addr, _)| *addr == d.submitter) {
+                Some((_, cid)) if *cid != d.cid => equivocators.push(d.submitter),
+                Some(_) => {}
+                None => voted.push((d.submitter, d.cid)),
+            }
+        }
+        let (forfeited, refunded): (Vec<_>, Vec<_>) = deposits
+            .into_iter()
+            .partition(|d| equivocators.contains(&d.submitter));
+        let forfeited = forfeited
+            .iter()
+            .fold(TokenAmount::zero(), |acc, d| acc + &d.amount);
+        Ok((refunded, forfeited))
+    }
+
+    /// Snapshots the validators, with their current stake, eligible to vote the
+    /// checkpoint of the window in `epoch`. Only the first snapshot of a window
+    /// is kept, so validators joining or leaving while it is voted don't change it
+    /// nor the quorum needed to commit it.
+    pub(crate) fn snapshot_eligible_voters<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        let voters = self.current_voters(store)?;
+        self.eligible_voters.update(store, |hamt| {
+            let key = BytesKey::from(epoch.to_ne_bytes().to_vec());
+            if !hamt.contains_key(&key)? {
+                hamt.set(key, voters)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the validators, with their stake, eligible to vote the checkpoint
+    /// of the window in `epoch`. Windows whose voting hasn't started yet are
+    /// voted by the current validator set. It is `None` if `epoch` isn't a
+    /// signing window or its checkpoint was committed without a snapshot.
+    pub fn get_eligible_voters<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<Option<Vec<CheckpointVoter>>> {
+        if epoch % self.check_period != 0 {
+            return Ok(None);
+        }
+        let snapshot = self
+            .eligible_voters
+            .load(store)?
+            .get(&BytesKey::from(epoch.to_ne_bytes().to_vec()))?
+            .cloned();
+        if snapshot.is_some() {
+            return Ok(snapshot);
+        }
+        if self.get_checkpoint(store, &epoch)?.is_some() {
+            return Ok(None);
+        }
+        Ok(Some(self.current_voters(store)?))
+    }
+
+    fn current_voters<BS: Blockstore>(&self, store: &BS) -> anyhow::Result<Vec<CheckpointVoter>> {
+        let mut voters = Vec::with_capacity(self.validator_set.len());
+        for v in self.validator_set.iter() {
+            let stake = self.get_stake(store, &v.addr)?.unwrap_or_default();
+            voters.push(CheckpointVoter {
+                addr: v.addr,
+                stake,
+            });
+        }
+        Ok(voters)
+    }
+
+    /// Records the changes in the validator set since the previous committed
+    /// checkpoint along with the checkpoint committed in `epoch`.
+    pub(crate) fn record_validator_diff<BS: Blockstore>(
+        &mut self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<()> {
+        let validators: Vec<Address> = self.validator_set.iter().map(|v| v.addr).collect();
+        let diff = ValidatorSetDiff::new(&self.committed_validators, &validators);
+        self.validator_diffs.update(store, |hamt| {
+            hamt.set(BytesKey::from(epoch.to_ne_bytes().to_vec()), diff)?;
+            Ok(())
+        })?;
+        self.committed_validators = validators;
+        Ok(())
+    }
+
+    /// Returns the checkpoint committed in `epoch` with the validators that
+    /// voted it. Checkpoints committed before voters were recorded have none.
+    pub fn get_checkpoint_certificate<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<Option<CheckpointCertificate>> {
+        let checkpoint = match self.get_checkpoint(store, &epoch)? {
+            Some(ch) => ch,
+            None => return Ok(None),
+        };
+        let hamt = self.checkpoint_voters.load(store)?;
+        let voters = hamt
+            .get(&BytesKey::from(epoch.to_ne_bytes().to_vec()))?
+            .cloned()
+            .unwrap_or_default();
+        let validator_diff = self
+            .validator_diffs
+            .load(store)?
+            .get(&BytesKey::from(epoch.to_ne_bytes().to_vec()))?
+            .cloned();
+        Ok(Some(CheckpointCertificate {
+            checkpoint,
+            voters,
+            validator_diff,
+        }))
+    }
+
+    /// Checks if the checkpoint with the given cid has been committed for `epoch`.
+    pub fn is_checkpoint_committed<BS: Blockstore>(
+        &self,
+        store: &BS,
+        epoch: ChainEpoch,
+        cid: &Cid,
+    ) -> anyhow::Result<bool> {
+        Ok(self
+            .get_checkpoint(store, &epoch)?
+            .map_or(false, |ch| ch.cid() == *cid))
+    }
+
+    /// Returns the committed checkpoints with epoch in `[from, to]` in
+    /// ascending order. Only the first `max_windows` signing windows of the
+    /// range are visited, so at most that many checkpoints are returned.
+    pub fn get_checkpoints_in_range<BS: Blockstore>(
+        &self,
+        store: &BS,
+        from: ChainEpoch,
+        to: ChainEpoch,
+        max_windows: usize,
+    ) -> anyhow::Result<Vec<Checkpoint>> {
+        let mut out = Vec::new();
+        // checkpoints can only be committed at the end of a signing window
+        let mut epoch = from.max(0);
+        if epoch % self.check_period != 0 {
+            epoch = match epoch.checked_add(self.check_period - epoch % self.check_period) {
+                Some(epoch) => epoch,
+                None => return Ok(out),
+            };
+        }
+        for _ in 0..max_windows {
+            if epoch > to {
+                break;
+            }
+            if let Some(ch) = self.get_checkpoint(store, &epoch)? {
+                out.push(ch);
+            }
+            epoch = match epoch.checked_add(self.check_period) {
+                Some(epoch) => epoch,
+                None => break,
+            };
+        }
+        Ok(out)
+    }
+
+    /// Checks if a new checkpoint voting can be initiated in the current epoch.
+    ///
+    /// Validators can only start voting a new checkpoint if there is no other
+    /// checkpoint pending commitment or if at least `min_checkpoint_gap` epochs
+    /// have passed since the voting of the pending one started.
+    pub fn can_initiate_window(&self, curr_epoch: ChainEpoch) -> bool {
+        match self.pending_window_start {
+            Some(start) => curr_epoch - start >= self.min_checkpoint_gap,
+            None => true,
+        }
+    }
+
+    /// Checks that `voter` can vote the checkpoint with `ch_cid`: it hasn't voted
+    /// it yet and, if nobody voted it, a new window can be initiated.
+    pub fn check_vote<BS: Blockstore>(
+        &self,
+        store: &BS,
+        ch_cid: &Cid,
+        voter: &Address,
+        curr_epoch: ChainEpoch,
+    ) -> Result<(), ActorError> {
+        match self.get_votes(store, ch_cid)? {
+            Some(votes) => {
+                if votes.validators.iter().any(|x| x == voter) {
+                    return Err(actor_error!(
+                        illegal_state,
+                        "miner has already voted the checkpoint"
+                    ));
+                }
+            }
+            None => {
+                if !self.can_initiate_window(curr_epoch) {
+                    return Err(actor_error!(
+                        illegal_state,
+                        "checkpoint submitted before the minimum epoch gap since the pending one"
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn is_validator(&self, addr: &Address) -> bool {
         self.validator_set.iter().any(|x| x.addr == *addr)
     }
 
     /// Do not call this function in transaction
-    pub fn verify_checkpoint(&self, rt: &mut impl Runtime, ch: &Checkpoint) -> anyhow::Result<()> {
+    /// Checks that a checkpoint can be submitted to the subnet, returning the
+    /// reason it is rejected otherwise.
+    pub fn verify_checkpoint(
+        &self,
+        rt: &mut impl Runtime,
+        ch: &Checkpoint,
+    ) -> Result<(), CheckpointError> {
         // check that subnet is active
         if self.status != Status::Active {
-            return Err(anyhow!(
-                "submitting checkpoints is not allowed while subnet is not active"
-            ));
+            return Err(CheckpointError::NotActive);
         }
 
         // check that a checkpoint for the epoch doesn't exist already.
         if self.get_checkpoint(rt.store(), &ch.epoch())?.is_some() {
-            return Err(anyhow!("cannot submit checkpoint for epoch"));
+            return Err(CheckpointError::AlreadyCommitted(ch.epoch()));
         };
 
         // check that the epoch is correct
         if ch.epoch() % self.check_period != 0 {
-            return Err(anyhow!(
-                "epoch in checkpoint doesn't correspond with a signing window"
-            ));
+            return Err(CheckpointError::BadEpoch {
+                epoch: ch.epoch(),
+                period: self.check_period,
+            });
         }
 
         // check the source is correct
-        if *ch.source() != SubnetID::new_from_parent(&self.parent_id, rt.message().receiver()) {
-            return Err(anyhow!("submitting checkpoint with the wrong source"));
+        let expected = SubnetID::new_from_parent(&self.parent_id, rt.message().receiver());
+        if *ch.source() != expected {
+            return Err(CheckpointError::BadSource {
+                found: ch.source().clone(),
+                expected,
+            });
         }
 
         // check previous checkpoint
-        if self.prev_checkpoint_cid(rt.store(), &ch.epoch())? != ch.prev_check().cid() {
-            return Err(anyhow!(
-                "previous checkpoint not consistent with previously committed"
-            ));
+        let expected = self.prev_checkpoint_cid(rt.store(), &ch.epoch())?;
+        if expected != ch.prev_check().cid() {
+            return Err(CheckpointError::BadPrevCid {
+                found: ch.prev_check().cid(),
+                expected,
+            });
+        }
+
+        // check the fee of the cross-messages included
+        if let Some(meta) = ch.cross_msgs() {
+            meta.verify_fee(rt.store())
+                .map_err(|e| CheckpointError::BadCrossMsgMeta(e.to_string()))?;
         }
 
         // check signature
         let caller = rt.message().caller();
-        let pkey = resolve_secp_bls(rt, &caller)?;
-
-        rt.verify_signature(
-            &RawBytes::deserialize(&ch.signature().clone().into())?,
-            &pkey,
-            &ch.cid().to_bytes(),
-        )?;
+        let pkey = resolve_secp_bls(rt, &caller)
+            .map_err(|e| CheckpointError::BadSignature(e.msg().to_string()))?;
+        let sig = RawBytes::deserialize(&ch.signature().clone().into())
+            .map_err(|e| CheckpointError::BadSignature(e.to_string()))?;
+        rt.verify_signature(&sig, &pkey, &ch.cid().to_bytes())
+            .map_err(|e| CheckpointError::BadSignature(e.to_string()))?;
 
         Ok(())
     }
@@ -363,6 +1263,7 @@ impl State {
 impl Default for State {
     fn default() -> Self {
         Self {
+            version: STATE_VERSION,
             name: String::new(),
             parent_id: SubnetID::default(),
             ipc_gateway_addr: Address::new_id(0),
@@ -371,11 +1272,45 @@ impl Default for State {
             total_stake: TokenAmount::zero(),
             finality_threshold: 5,
             check_period: 10,
+            min_checkpoint_gap: 0,
+            pending_window_start: None,
+            net_addr_validation: NetAddrValidation::Disabled,
+            kill_grace_epochs: 0,
+            kill_finalize_epoch: None,
+            status_before_kill: None,
+            jail_threshold: 0,
+            missed_checkpoints: TCid::default(),
+            jailed: Vec::new(),
+            rejoin_cooldown_epochs: 0,
+            last_left: TCid::default(),
+            reward_policy: RewardPolicy::Even,
+            pull_rewards: false,
+            pending_rewards: TCid::default(),
+            fees_received: TCid::default(),
+            reward_addrs: TCid::default(),
+            checkpoint_delegates: TCid::default(),
             genesis: Vec::new(),
             status: Status::Instantiated,
             checkpoints: TCid::default(),
+            checkpoint_voters: TCid::default(),
+            eligible_voters: TCid::default(),
+            validator_diffs: TCid::default(),
+            committed_validators: Vec::new(),
             stake: TCid::default(),
             window_checks: TCid::default(),
+            vote_retention_windows: 0,
+            quorum_policy: QuorumPolicy::Stake,
+            reward_interval_epochs: 0,
+            last_reward_epoch: None,
+            accumulated_rewards: TokenAmount::zero(),
+            accumulate_unassigned_rewards: false,
+            activation_epoch: None,
+            permissioned: false,
+            owner: None,
+            approved_validators: Vec::new(),
+            vote_epochs: TCid::default(),
+            checkpoint_deposit: TokenAmount::zero(),
+            checkpoint_deposits: TCid::default(),
             validator_set: Vec::new(),
             min_validators: 0,
         }
@@ -1,11 +1,17 @@
+use cid::Cid;
+use fil_actors_runtime::{actor_error, ActorError};
 use fvm_ipld_encoding::ipld_block::IpldBlock;
 use fvm_ipld_encoding::repr::*;
+use fvm_ipld_encoding::to_vec;
 use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
 use fvm_shared::address::Address;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::MethodNum;
-use ipc_gateway::SubnetID;
+use ipc_gateway::{Checkpoint, SubnetID};
+use num::BigInt;
+use num_traits::{One, Zero};
+use serde::{Deserialize, Serialize};
 
 /// Optional leaving coefficient to penalize
 /// validators leaving the subnet.
@@ -14,6 +20,9 @@ use ipc_gateway::SubnetID;
 // This will change once we figure out the econ model.
 pub const LEAVING_COEFF: u64 = 1;
 pub const TESTING_ID: u64 = 339;
+/// Maximum number of signing windows visited, and thus of checkpoints returned,
+/// by a single `GetCheckpoints` query.
+pub const MAX_CHECKPOINTS_PER_QUERY: usize = 100;
 
 #[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
 pub struct Validator {
@@ -26,6 +35,52 @@ pub struct Votes {
     pub validators: Vec<Address>,
 }
 
+/// Validator that voted a committed checkpoint, with its stake at commit time.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckpointVoter {
+    pub addr: Address,
+    pub stake: TokenAmount,
+}
+
+/// Deposit attached to a checkpoint submission, held until a checkpoint
+/// is committed for its epoch.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckpointDeposit {
+    /// Cid of the checkpoint voted by the submission.
+    pub cid: Cid,
+    pub submitter: Address,
+    pub amount: TokenAmount,
+}
+
+/// Committed checkpoint together with the validators whose votes committed it,
+/// used as a proof of the commitment for external verification.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckpointCertificate {
+    pub checkpoint: Checkpoint,
+    pub voters: Vec<CheckpointVoter>,
+    /// Changes in the validator set since the previous committed checkpoint.
+    /// `None` for checkpoints committed before the changes were recorded.
+    pub validator_diff: Option<ValidatorSetDiff>,
+}
+
+/// Validators that joined and left the validator set between two consecutive
+/// committed checkpoints.
+#[derive(Clone, Debug, Default, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ValidatorSetDiff {
+    pub joined: Vec<Address>,
+    pub left: Vec<Address>,
+}
+
+impl ValidatorSetDiff {
+    /// Computes the changes from the `prev` validator set to `curr`.
+    pub fn new(prev: &[Address], curr: &[Address]) -> Self {
+        ValidatorSetDiff {
+            joined: curr.iter().filter(|v| !prev.contains(v)).cloned().collect(),
+            left: prev.iter().filter(|v| !curr.contains(v)).cloned().collect(),
+        }
+    }
+}
+
 /// Consensus types supported by hierarchical consensus
 #[derive(PartialEq, Eq, Clone, Copy, Debug, Deserialize_repr, Serialize_repr)]
 #[repr(u64)]
@@ -48,6 +103,130 @@ pub enum Status {
     Killed,
 }
 
+/// Policy used to validate the network address of validators
+/// joining the subnet.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub enum NetAddrValidation {
+    /// No validation is performed, intended for test networks.
+    Disabled,
+    /// The address must be a well-formed multiaddr.
+    Multiaddr,
+    /// The address must start with the given prefix.
+    Prefix(String),
+}
+
+impl NetAddrValidation {
+    pub fn validate(&self, net_addr: &str) -> Result<(), ActorError> {
+        match self {
+            NetAddrValidation::Disabled => Ok(()),
+            NetAddrValidation::Multiaddr => {
+                // a multiaddr is a sequence of /<protocol>[/<value>] components,
+                // we only check that it is well-formed, not the protocols used.
+                let components: Vec<&str> = match net_addr.strip_prefix('/') {
+                    Some(rest) => rest.split('/').collect(),
+                    None => Vec::new(),
+                };
+                if components.len() < 2 || components.iter().any(|c| c.is_empty()) {
+                    return Err(actor_error!(
+                        illegal_argument,
+                        "validator net address {} is not a valid multiaddr",
+                        net_addr
+                    ));
+                }
+                Ok(())
+            }
+            NetAddrValidation::Prefix(prefix) => {
+                if !net_addr.starts_with(prefix.as_str()) {
+                    return Err(actor_error!(
+                        illegal_argument,
+                        "validator net address {} doesn't start with {}",
+                        net_addr,
+                        prefix
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Rule used to decide if the votes received for a checkpoint commit it.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum QuorumPolicy {
+    /// Validators holding at least 2/3 of the stake need to vote.
+    Stake,
+    /// On top of the stake quorum, more than half of the validators need
+    /// to vote, so a single large validator can't commit checkpoints alone.
+    StakeAndCount,
+}
+
+/// Policy used to split the rewards of the subnet among its validators.
+///
+/// The remainder left by integer division is paid to the first validator,
+/// so the whole amount received is always distributed.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub enum RewardPolicy {
+    /// Every validator receives the same amount.
+    Even,
+    /// Rewards are proportional to the stake of each validator.
+    StakeWeighted,
+    /// Rewards are proportional to the square root of the stake of each
+    /// validator, dampening the advantage of large stakes.
+    Quadratic,
+    /// Every validator receives up to the given amount, and what is left is
+    /// split by stake. If the amount received doesn't cover the fixed reward
+    /// of every validator it is split evenly.
+    FixedPerValidator(TokenAmount),
+}
+
+impl RewardPolicy {
+    /// Splits `amount` among validators with the given stakes, returning the
+    /// reward of each of them in the same order.
+    pub fn split(&self, amount: &TokenAmount, stakes: &[TokenAmount]) -> Vec<TokenAmount> {
+        match self {
+            RewardPolicy::Even => split_weighted(amount, &vec![BigInt::one(); stakes.len()]),
+            RewardPolicy::StakeWeighted => split_weighted(
+                amount,
+                &stakes.iter().map(|s| s.atto().clone()).collect::<Vec<_>>(),
+            ),
+            RewardPolicy::Quadratic => split_weighted(
+                amount,
+                &stakes.iter().map(|s| s.atto().sqrt()).collect::<Vec<_>>(),
+            ),
+            RewardPolicy::FixedPerValidator(fixed) => {
+                let total_fixed = fixed * stakes.len() as u64;
+                if amount < &total_fixed {
+                    return RewardPolicy::Even.split(amount, stakes);
+                }
+                RewardPolicy::StakeWeighted
+                    .split(&(amount - &total_fixed), stakes)
+                    .into_iter()
+                    .map(|r| r + fixed)
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Splits `amount` proportionally to `weights`, falling back to an even split
+/// if all the weights are zero. The remainder goes to the first entry.
+fn split_weighted(amount: &TokenAmount, weights: &[BigInt]) -> Vec<TokenAmount> {
+    if weights.is_empty() {
+        return Vec::new();
+    }
+    let total: BigInt = weights.iter().sum();
+    if total.is_zero() {
+        return split_weighted(amount, &vec![BigInt::one(); weights.len()]);
+    }
+    let mut shares: Vec<TokenAmount> = weights
+        .iter()
+        .map(|w| TokenAmount::from_atto(amount.atto() * w / &total))
+        .collect();
+    let distributed = shares.iter().fold(TokenAmount::zero(), |acc, s| acc + s);
+    shares[0] += amount - &distributed;
+    shares
+}
+
 #[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
 pub struct ConstructParams {
     pub parent: SubnetID,
@@ -58,15 +237,217 @@ pub struct ConstructParams {
     pub min_validators: u64,
     pub finality_threshold: ChainEpoch,
     pub check_period: ChainEpoch,
+    /// Minimum number of epochs that need to pass before validators can
+    /// start voting a new checkpoint while a previous one is still pending.
+    pub min_checkpoint_gap: ChainEpoch,
+    pub net_addr_validation: NetAddrValidation,
+    /// Number of epochs the subnet stays in `Terminating` after `Kill`
+    /// before it can be finalized, letting validators withdraw their stake.
+    /// Zero kills the subnet immediately.
+    pub kill_grace_epochs: ChainEpoch,
+    /// Number of consecutive committed checkpoints a validator can miss
+    /// before it can be jailed. Zero disables jailing.
+    pub jail_threshold: u64,
+    /// Number of epochs an address needs to wait after leaving the subnet
+    /// before it can join again. Zero disables the cooldown.
+    pub rejoin_cooldown_epochs: ChainEpoch,
+    pub reward_policy: RewardPolicy,
+    /// Rewards are accrued for validators to claim with `ClaimReward`
+    /// instead of being sent to them when distributed.
+    pub pull_rewards: bool,
+    /// Number of checkpoint windows the votes of an uncommitted checkpoint
+    /// are kept before being cleared. Zero keeps them until committed.
+    pub vote_retention_windows: u64,
+    pub quorum_policy: QuorumPolicy,
+    /// Minimum number of epochs between reward distributions. The rewards
+    /// received in between are accumulated. Zero distributes them right away.
+    pub reward_interval_epochs: ChainEpoch,
+    /// Deposit required to submit a checkpoint vote. It is refunded once a
+    /// checkpoint is committed for the epoch or the votes of the checkpoint
+    /// are cleared, and forfeited if the submitter voted more than one
+    /// checkpoint for the committed epoch. Zero disables deposits.
+    pub checkpoint_deposit: TokenAmount,
+    /// Rewards received while the subnet has no validators are accumulated
+    /// for the next distribution instead of being rejected.
+    pub accumulate_unassigned_rewards: bool,
+    /// Epoch from which the subnet can be activated. The subnet is registered
+    /// in the gateway by the first join, or a call to `Activate`, once both the
+    /// collateral threshold is met and this epoch is reached. `None` activates
+    /// it on collateral alone.
+    pub activation_epoch: Option<ChainEpoch>,
+    /// Only validators approved by the owner with `ApproveValidator` can join.
+    pub permissioned: bool,
+    /// Address allowed to approve and revoke validators.
+    pub owner: Option<Address>,
     // genesis is no longer generated by the actor
     // on-the-fly, but it is accepted as a construct
     // param
     pub genesis: Vec<u8>,
 }
 
+impl ConstructParams {
+    /// Checks that the parameters used to deploy the subnet actor are sane,
+    /// returning an error pointing to the misconfigured field otherwise.
+    pub fn validate(&self) -> Result<(), ActorError> {
+        if self.parent == SubnetID::default() {
+            return Err(actor_error!(illegal_argument, "parent subnet id is empty"));
+        }
+        if self.name.is_empty() {
+            return Err(actor_error!(illegal_argument, "subnet name is empty"));
+        }
+        if self.ipc_gateway_addr == 0 {
+            return Err(actor_error!(
+                illegal_argument,
+                "ipc gateway address is not set"
+            ));
+        }
+        if self.min_validator_stake < TokenAmount::zero() {
+            return Err(actor_error!(
+                illegal_argument,
+                "min validator stake can't be negative"
+            ));
+        }
+        if self.finality_threshold < 0 {
+            return Err(actor_error!(
+                illegal_argument,
+                "finality threshold can't be negative"
+            ));
+        }
+        if self.check_period < 0 {
+            return Err(actor_error!(
+                illegal_argument,
+                "checkpoint period can't be negative"
+            ));
+        }
+        if self.min_checkpoint_gap < 0 {
+            return Err(actor_error!(
+                illegal_argument,
+                "min checkpoint gap can't be negative"
+            ));
+        }
+        if self.kill_grace_epochs < 0 {
+            return Err(actor_error!(
+                illegal_argument,
+                "kill grace epochs can't be negative"
+            ));
+        }
+        if self.rejoin_cooldown_epochs < 0 {
+            return Err(actor_error!(
+                illegal_argument,
+                "rejoin cooldown epochs can't be negative"
+            ));
+        }
+        if self.reward_interval_epochs < 0 {
+            return Err(actor_error!(
+                illegal_argument,
+                "reward interval epochs can't be negative"
+            ));
+        }
+        if self.checkpoint_deposit < TokenAmount::zero() {
+            return Err(actor_error!(
+                illegal_argument,
+                "checkpoint deposit can't be negative"
+            ));
+        }
+        if self.permissioned && self.owner.is_none() {
+            return Err(actor_error!(
+                illegal_argument,
+                "permissioned subnets need an owner to approve validators"
+            ));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
 pub struct JoinParams {
     pub validator_net_addr: String,
+    /// Address the rewards of the validator are paid to. If `None` they are
+    /// paid to the address joining the subnet.
+    pub reward_addr: Option<Address>,
+}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct JoinAndVoteParams {
+    pub join: JoinParams,
+    pub checkpoint: Checkpoint,
+}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckpointRangeParams {
+    pub from_epoch: ChainEpoch,
+    /// Inclusive end of the range.
+    pub to_epoch: ChainEpoch,
+}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct IsCheckpointCommittedParams {
+    /// Epoch of the checkpoint, only the one committed for it is checked.
+    pub epoch: ChainEpoch,
+    pub cid: Cid,
+}
+
+/// Value of `total_stake` before and after `RecomputeTotalStake`.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct StakeCorrection {
+    pub old_total_stake: TokenAmount,
+    pub new_total_stake: TokenAmount,
+}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct RecoverFundsParams {
+    /// Address the recovered funds are sent to.
+    pub to: Address,
+    pub amount: TokenAmount,
+}
+
+/// Cadence of the distribution of the rewards of the subnet.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct RewardSchedule {
+    pub interval_epochs: ChainEpoch,
+    /// First epoch in which the rewards received are distributed.
+    pub next_distribution_epoch: ChainEpoch,
+    /// Rewards received and waiting for the next distribution.
+    pub accumulated: TokenAmount,
+}
+
+/// Collateral required for the subnet to be activated in the gateway.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct ActivationRequirements {
+    /// Total stake required to activate the subnet.
+    pub min_collateral: TokenAmount,
+    pub total_stake: TokenAmount,
+    /// Stake still needed to reach `min_collateral`, zero once reached.
+    pub remaining: TokenAmount,
+    /// Epoch before which the subnet can't be activated, if any.
+    pub activation_epoch: Option<ChainEpoch>,
+}
+
+/// Result of verifying a checkpoint with `VerifyCheckpoint`.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckpointVerification {
+    /// Whether a submission of the checkpoint by the caller would be accepted.
+    pub valid: bool,
+    /// Reason the checkpoint would be rejected, `None` if it is valid.
+    pub reason: Option<String>,
+}
+
+/// Size of a checkpoint once serialized to be committed in the gateway.
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple, PartialEq, Eq)]
+pub struct CheckpointSize {
+    /// Length in bytes of the serialized checkpoint.
+    pub bytes: u64,
+    /// Number of cross-messages included in the checkpoint.
+    pub num_msgs: u64,
+}
+
+impl CheckpointSize {
+    pub fn new(ch: &Checkpoint) -> anyhow::Result<Self> {
+        Ok(CheckpointSize {
+            bytes: to_vec(ch)?.len() as u64,
+            num_msgs: ch.cross_msgs().map(|m| m.num_msgs).unwrap_or_default(),
+        })
+    }
 }
 
 pub(crate) struct CrossActorPayload {
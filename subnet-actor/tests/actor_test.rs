@@ -1,26 +1,43 @@
 #[cfg(test)]
 mod test {
+    use cid::multihash::{Code, MultihashDigest};
     use cid::Cid;
+    use fil_actors_runtime::builtin::HAMT_BIT_WIDTH;
     use fil_actors_runtime::runtime::Runtime;
     use fil_actors_runtime::test_utils::{
         expect_abort, ExpectedVerifySig, MockRuntime, INIT_ACTOR_CODE_ID,
     };
-    use fil_actors_runtime::{ActorError, INIT_ACTOR_ADDR};
+    use fil_actors_runtime::{
+        deserialize_block, make_empty_map, ActorError, BURNT_FUNDS_ACTOR_ADDR, INIT_ACTOR_ADDR,
+        SYSTEM_ACTOR_ADDR,
+    };
     use fvm_ipld_encoding::ipld_block::IpldBlock;
-    use fvm_ipld_encoding::RawBytes;
+    use fvm_ipld_encoding::{serde_bytes, to_vec, CborStore, RawBytes};
     use fvm_shared::address::Address;
+    use fvm_shared::clock::ChainEpoch;
     use fvm_shared::crypto::signature::Signature;
     use fvm_shared::econ::TokenAmount;
     use fvm_shared::error::ExitCode;
     use fvm_shared::METHOD_SEND;
-    use ipc_gateway::{Checkpoint, FundParams, SubnetID, MIN_COLLATERAL_AMOUNT};
+    use ipc_gateway::checkpoint::ChildCheck;
+    use ipc_gateway::{
+        Checkpoint, CrossMsgMeta, CrossMsgs, DistributeRewardsParams, FundParams, RewardEntry,
+        SubnetID, MIN_COLLATERAL_AMOUNT,
+    };
+    use ipc_subnet_actor::error::CheckpointError;
+    use ipc_subnet_actor::migration::{StateV1, StateV2, STATE_VERSION};
     use ipc_subnet_actor::{
-        Actor, ConsensusType, ConstructParams, JoinParams, Method, State, Status,
+        ActivationRequirements, Actor, CheckpointCertificate, CheckpointRangeParams,
+        CheckpointSize, CheckpointVerification, CheckpointVoter, ConsensusType, ConstructParams,
+        IsCheckpointCommittedParams, JoinAndVoteParams, JoinParams, Method, NetAddrValidation,
+        QuorumPolicy, RecoverFundsParams, RewardPolicy, RewardSchedule, StakeCorrection, State,
+        Status, ValidatorSetDiff, Votes, MAX_CHECKPOINTS_PER_QUERY,
     };
     use num::BigInt;
     use num_traits::FromPrimitive;
     use num_traits::Zero;
-    use primitives::TCid;
+    use primitives::{TCid, TLink};
+    use serde::Serialize;
     use std::str::FromStr;
 
     // just a test address
@@ -37,6 +54,21 @@ mod test {
             min_validators: 0,
             finality_threshold: 0,
             check_period: 0,
+            min_checkpoint_gap: 0,
+            net_addr_validation: NetAddrValidation::Disabled,
+            kill_grace_epochs: 0,
+            jail_threshold: 0,
+            rejoin_cooldown_epochs: 0,
+            reward_policy: RewardPolicy::Even,
+            pull_rewards: false,
+            vote_retention_windows: 0,
+            quorum_policy: QuorumPolicy::Stake,
+            reward_interval_epochs: 0,
+            checkpoint_deposit: TokenAmount::zero(),
+            accumulate_unassigned_rewards: false,
+            activation_epoch: None,
+            permissioned: false,
+            owner: None,
             genesis: vec![],
         }
     }
@@ -51,11 +83,13 @@ mod test {
     }
 
     fn construct_runtime_with_receiver(receiver: Address) -> MockRuntime {
+        construct_runtime_with_params(receiver, std_construct_param())
+    }
+
+    fn construct_runtime_with_params(receiver: Address, params: ConstructParams) -> MockRuntime {
         let mut runtime = new_runtime(receiver);
         runtime.set_caller(*INIT_ACTOR_CODE_ID, INIT_ACTOR_ADDR);
 
-        let params = std_construct_param();
-
         runtime.expect_validate_caller_addr(vec![INIT_ACTOR_ADDR]);
 
         runtime
@@ -85,6 +119,135 @@ mod test {
         assert_eq!(state.validator_set.is_empty(), true);
     }
 
+    /// Lists the number of every `Method`. It fails to compile if a method is
+    /// missing from the list.
+    macro_rules! method_numbers {
+        ($($method:ident),* $(,)?) => {{
+            fn _exhaustive(m: Method) {
+                match m {
+                    $(Method::$method)|* => {}
+                }
+            }
+            vec![$(Method::$method as u64),*]
+        }};
+    }
+
+    #[test]
+    fn test_method_numbers() {
+        let methods = method_numbers!(
+            Constructor,
+            Join,
+            Leave,
+            Kill,
+            FinalizeKill,
+            JailInactiveValidators,
+            Unjail,
+            SubmitCheckpoint,
+            Reward,
+            DistributeRewards,
+            IsValidator,
+            MigrateState,
+            GetCheckpoints,
+            RecomputeTotalStake,
+            GetCheckpointCertificate,
+            ClaimReward,
+            GetPendingReward,
+            GetFeesReceived,
+            EstimateCheckpointSize,
+            SetRewardAddr,
+            RecoverFunds,
+            GetEligibleVoters,
+            JoinAndVote,
+            GetRewardSchedule,
+            SetRewardInterval,
+            GetActivationRequirements,
+            SetCheckpointDelegate,
+            ClearCheckpointDelegate,
+            ClearAllVotes,
+            IsCheckpointCommitted,
+            HasStake,
+            VerifyCheckpoint,
+            ApproveValidator,
+            RevokeValidator,
+            CancelKill,
+            Activate,
+        );
+
+        // methods don't collide and map back to a `Method`
+        let mut unique = methods.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), methods.len());
+        for method in methods.iter() {
+            assert!(Method::from_u64(*method).is_some(), "method {}", method);
+        }
+
+        // exported methods are in the FRC-42 range, so they can't collide with the
+        // reserved method numbers.
+        for method in methods.iter() {
+            if *method != Method::Constructor as u64 {
+                assert!(*method >= 1 << 24, "method {}", method);
+            }
+        }
+    }
+
+    #[test]
+    fn test_constructor_invalid_params() {
+        let cases: Vec<(Box<dyn Fn(&mut ConstructParams)>, &str)> = vec![
+            (
+                Box::new(|p| p.parent = SubnetID::default()),
+                "parent subnet id is empty",
+            ),
+            (Box::new(|p| p.name = String::new()), "subnet name is empty"),
+            (
+                Box::new(|p| p.ipc_gateway_addr = 0),
+                "ipc gateway address is not set",
+            ),
+            (
+                Box::new(|p| p.min_validator_stake = TokenAmount::from_atto(-1)),
+                "min validator stake can't be negative",
+            ),
+            (
+                Box::new(|p| p.finality_threshold = -1),
+                "finality threshold can't be negative",
+            ),
+            (
+                Box::new(|p| p.check_period = -1),
+                "checkpoint period can't be negative",
+            ),
+            (
+                Box::new(|p| p.min_checkpoint_gap = -1),
+                "min checkpoint gap can't be negative",
+            ),
+            (
+                Box::new(|p| p.kill_grace_epochs = -1),
+                "kill grace epochs can't be negative",
+            ),
+            (
+                Box::new(|p| p.rejoin_cooldown_epochs = -1),
+                "rejoin cooldown epochs can't be negative",
+            ),
+        ];
+
+        for (modify, msg) in cases {
+            let mut runtime = new_runtime(Address::new_id(1));
+            runtime.set_caller(*INIT_ACTOR_CODE_ID, INIT_ACTOR_ADDR);
+            runtime.expect_validate_caller_addr(vec![INIT_ACTOR_ADDR]);
+
+            let mut params = std_construct_param();
+            modify(&mut params);
+            let err = runtime
+                .call::<Actor>(
+                    Method::Constructor as u64,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                )
+                .unwrap_err();
+            assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_ARGUMENT);
+            assert_eq!(err.msg(), msg);
+            assert_eq!(runtime.state.is_none(), true);
+        }
+    }
+
     #[test]
     fn test_join_fail_no_min_collateral() {
         let mut runtime = construct_runtime();
@@ -93,6 +256,7 @@ mod test {
         let validator = Address::new_id(100);
         let params = JoinParams {
             validator_net_addr: validator.to_string(),
+            reward_addr: None,
         };
 
         expect_abort(
@@ -104,6 +268,59 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_join_net_addr_validation() {
+        let cases = vec![
+            (
+                NetAddrValidation::Multiaddr,
+                "/ip4/127.0.0.1/tcp/1347",
+                true,
+            ),
+            (NetAddrValidation::Multiaddr, "/dns/validator.ipc.io", true),
+            (NetAddrValidation::Multiaddr, "127.0.0.1:1347", false),
+            (NetAddrValidation::Multiaddr, "/ip4//tcp/1347", false),
+            (NetAddrValidation::Multiaddr, "/ip4", false),
+            (NetAddrValidation::Multiaddr, "", false),
+            (
+                NetAddrValidation::Prefix("/ip4/10.".to_string()),
+                "/ip4/10.0.0.1/tcp/1347",
+                true,
+            ),
+            (
+                NetAddrValidation::Prefix("/ip4/10.".to_string()),
+                "/ip4/127.0.0.1/tcp/1347",
+                false,
+            ),
+            (NetAddrValidation::Disabled, "garbage", true),
+        ];
+
+        for (validation, net_addr, valid) in cases {
+            let mut params = std_construct_param();
+            params.net_addr_validation = validation;
+            let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+            let join_params = JoinParams {
+                validator_net_addr: net_addr.to_string(),
+                reward_addr: None,
+            };
+            runtime.set_value(TokenAmount::from_atto(1));
+            runtime.set_caller(Cid::default(), Address::new_id(10));
+            runtime.expect_validate_caller_any();
+            let ret = runtime.call::<Actor>(
+                Method::Join as u64,
+                IpldBlock::serialize_cbor(&join_params).unwrap(),
+            );
+            runtime.verify();
+            if valid {
+                ret.unwrap();
+                let st: State = runtime.get_state();
+                assert_eq!(st.total_stake, TokenAmount::from_atto(1));
+            } else {
+                expect_abort(ExitCode::USR_ILLEGAL_ARGUMENT, ret);
+            }
+        }
+    }
+
     #[test]
     fn test_join_works() {
         let mut runtime = construct_runtime();
@@ -114,6 +331,7 @@ mod test {
         let start_token_value = 5_u64.pow(18);
         let params = JoinParams {
             validator_net_addr: validator.to_string(),
+            reward_addr: None,
         };
 
         // Part 1. join without enough to be activated
@@ -255,6 +473,79 @@ mod test {
         runtime.verify();
     }
 
+    #[test]
+    fn test_rejoin_cooldown() {
+        let mut params = std_construct_param();
+        params.rejoin_cooldown_epochs = 10;
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        let caller = Address::new_id(10);
+        join_miners(&mut runtime, &[caller]);
+
+        // the miner leaves the subnet
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        runtime.set_epoch(5);
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::ReleaseStake as u64,
+            IpldBlock::serialize_cbor(&FundParams {
+                value: value.clone(),
+            })
+            .unwrap(),
+            TokenAmount::zero(),
+            None,
+            ExitCode::new(0),
+        );
+        runtime.call::<Actor>(Method::Leave as u64, None).unwrap();
+        runtime.verify();
+
+        // rejoining within the cooldown is rejected
+        let params = JoinParams {
+            validator_net_addr: caller.to_string(),
+            reward_addr: None,
+        };
+        runtime.set_epoch(14);
+        runtime.set_value(value.clone());
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(
+                Method::Join as u64,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            ),
+        );
+        runtime.verify();
+
+        // once the cooldown is over the miner can join again
+        runtime.set_epoch(15);
+        runtime.set_value(value.clone());
+        runtime.set_caller(Cid::default(), caller);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            None,
+            value.clone(),
+            None,
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                IpldBlock::serialize_cbor(&params).unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_stake(runtime.store(), &caller).unwrap().unwrap(),
+            value
+        );
+    }
+
     #[test]
     fn test_leave_and_kill() {
         let mut runtime = construct_runtime();
@@ -263,6 +554,7 @@ mod test {
         let validator = Address::new_id(100);
         let params = JoinParams {
             validator_net_addr: validator.to_string(),
+            reward_addr: None,
         };
 
         // first miner joins the subnet
@@ -301,6 +593,7 @@ mod test {
         let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
         let params = JoinParams {
             validator_net_addr: caller.clone().to_string(),
+            reward_addr: None,
         };
         total_stake = total_stake + &value;
         runtime.set_value(value.clone());
@@ -334,6 +627,7 @@ mod test {
         let caller = Address::new_id(30);
         let params = JoinParams {
             validator_net_addr: caller.clone().to_string(),
+            reward_addr: None,
         };
         let value = TokenAmount::from_atto(5u64.pow(18));
         total_stake = total_stake + &value;
@@ -475,6 +769,131 @@ mod test {
         assert_eq!(st.status, Status::Killed);
     }
 
+    #[test]
+    fn test_kill_grace_period() {
+        let mut params = std_construct_param();
+        params.kill_grace_epochs = 10;
+        let mut runtime = construct_runtime_with_params(Address::new_id(9999), params);
+
+        let miner = Address::new_id(10);
+        join_miners(&mut runtime, &[miner]);
+
+        // kill starts the grace period without notifying the gateway
+        runtime.set_epoch(100);
+        runtime.expect_validate_caller_any();
+        runtime.call::<Actor>(Method::Kill as u64, None).unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.status, Status::Terminating);
+        assert_eq!(st.kill_finalize_epoch, Some(110));
+
+        // the subnet can't be killed twice
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            runtime.call::<Actor>(Method::Kill as u64, None),
+        );
+
+        // validators can still withdraw their stake
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        runtime.set_caller(Cid::default(), miner);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::ReleaseStake as u64,
+            IpldBlock::serialize_cbor(&FundParams { value }).unwrap(),
+            TokenAmount::zero(),
+            None,
+            ExitCode::new(0),
+        );
+        runtime.call::<Actor>(Method::Leave as u64, None).unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.status, Status::Terminating);
+        assert_eq!(st.total_stake, TokenAmount::zero());
+
+        // the kill can't be finalized during the grace period
+        runtime.set_balance(TokenAmount::zero());
+        runtime.set_epoch(109);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            runtime.call::<Actor>(Method::FinalizeKill as u64, None),
+        );
+
+        runtime.set_epoch(110);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Kill as u64,
+            None,
+            TokenAmount::zero(),
+            None,
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(Method::FinalizeKill as u64, None)
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.status, Status::Killed);
+        assert_eq!(st.kill_finalize_epoch, None);
+
+        // nothing left to finalize
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            runtime.call::<Actor>(Method::FinalizeKill as u64, None),
+        );
+    }
+
+    #[test]
+    fn test_cancel_kill() {
+        let mut params = std_construct_param();
+        params.kill_grace_epochs = 10;
+        let mut runtime = construct_runtime_with_params(Address::new_id(9999), params);
+
+        let miner = Address::new_id(10);
+        let outsider = Address::new_id(20);
+        join_miners(&mut runtime, &[miner]);
+
+        let call = |runtime: &mut MockRuntime, caller: Address, method: Method| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            let ret = runtime.call::<Actor>(method as u64, None);
+            runtime.verify();
+            ret
+        };
+
+        // a non-validator can't start the grace period of a live subnet
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            call(&mut runtime, outsider, Method::Kill),
+        );
+        let st: State = runtime.get_state();
+        assert_eq!(st.status, Status::Active);
+
+        // nor cancel one started by a validator
+        call(&mut runtime, miner, Method::Kill).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.status, Status::Terminating);
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            call(&mut runtime, outsider, Method::CancelKill),
+        );
+
+        // cancelling restores the subnet
+        call(&mut runtime, miner, Method::CancelKill).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.status, Status::Active);
+        assert_eq!(st.kill_finalize_epoch, None);
+        assert_eq!(st.status_before_kill, None);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            call(&mut runtime, miner, Method::CancelKill),
+        );
+    }
+
     #[test]
     fn test_submit_checkpoint() {
         let test_actor_address = Address::new_id(9999);
@@ -488,6 +907,7 @@ mod test {
         let validator = Address::new_id(100);
         let params = JoinParams {
             validator_net_addr: validator.to_string(),
+            reward_addr: None,
         };
 
         // first miner joins the subnet
@@ -641,6 +1061,2544 @@ mod test {
         assert_eq!(votes.validators, vec![sender.clone()]);
     }
 
+    #[test]
+    fn test_submit_checkpoint_min_gap() {
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.min_checkpoint_gap = 10;
+        let mut runtime = construct_runtime_with_params(test_actor_address.clone(), params);
+
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_miners(&mut runtime, &miners);
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+
+        // first miner starts the voting of a checkpoint
+        runtime.set_epoch(100);
+        let mut checkpoint_0 = Checkpoint::new(subnet.clone(), 10);
+        checkpoint_0.set_signature(sig.clone());
+        let sender = miners.get(0).cloned().unwrap();
+        send_checkpoint(&mut runtime, sender.clone(), &checkpoint_0, false).unwrap();
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.pending_window_start, Some(100));
+
+        // a different checkpoint submitted before the gap is rejected
+        let mut checkpoint_1 = Checkpoint::new(subnet.clone(), 20);
+        checkpoint_1.set_signature(sig.clone());
+        let sender2 = miners.get(1).cloned().unwrap();
+        runtime.set_epoch(105);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            send_checkpoint(&mut runtime, sender2.clone(), &checkpoint_1, false),
+        );
+
+        // voting for the pending checkpoint is still allowed
+        send_checkpoint(&mut runtime, sender2.clone(), &checkpoint_0, true).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.pending_window_start, None);
+
+        // once committed a new checkpoint can be initiated straight away
+        let mut checkpoint_2 = Checkpoint::new(subnet.clone(), 20);
+        checkpoint_2.data.prev_check = TCid::from(checkpoint_0.cid());
+        checkpoint_2.set_signature(sig.clone());
+        send_checkpoint(&mut runtime, sender.clone(), &checkpoint_2, false).unwrap();
+
+        // and after the gap has passed a competing checkpoint is accepted
+        let mut checkpoint_3 = Checkpoint::new(subnet.clone(), 30);
+        checkpoint_3.data.prev_check = TCid::from(checkpoint_0.cid());
+        checkpoint_3.set_signature(sig.clone());
+        runtime.set_epoch(110);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            send_checkpoint(&mut runtime, sender2.clone(), &checkpoint_3, false),
+        );
+        runtime.set_epoch(115);
+        send_checkpoint(&mut runtime, sender2.clone(), &checkpoint_3, false).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.pending_window_start, Some(115));
+    }
+
+    #[test]
+    fn test_jail_inactive_validators() {
+        let test_actor_address = Address::new_id(9999);
+        let mut params = std_construct_param();
+        params.jail_threshold = 1;
+        let mut runtime = construct_runtime_with_params(test_actor_address.clone(), params);
+
+        let miners = vec![
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_miners(&mut runtime, &miners);
+        let inactive = miners[2];
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+
+        // the last miner doesn't vote the committed checkpoint
+        let mut checkpoint_0 = Checkpoint::new(subnet.clone(), 10);
+        checkpoint_0.set_signature(sig.clone());
+        send_checkpoint(&mut runtime, miners[0], &checkpoint_0, false).unwrap();
+        send_checkpoint(&mut runtime, miners[1], &checkpoint_0, true).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_missed_checkpoints(runtime.store(), &inactive)
+                .unwrap(),
+            1
+        );
+
+        // it can't be unjailed if it is not jailed
+        runtime.set_caller(Cid::default(), inactive);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            runtime.call::<Actor>(Method::Unjail as u64, None),
+        );
+
+        runtime.expect_validate_caller_any();
+        let ret = runtime
+            .call::<Actor>(Method::JailInactiveValidators as u64, None)
+            .unwrap();
+        let jailed: Vec<Address> = deserialize_block(ret).unwrap();
+        assert_eq!(jailed, vec![inactive]);
+        let st: State = runtime.get_state();
+        assert!(st.is_jailed(&inactive));
+
+        // jailed validators are excluded from rewards
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+        let reward = TokenAmount::from_atto(10);
+        runtime.set_value(reward.clone());
+        runtime.set_caller(Cid::default(), gateway);
+        runtime.expect_validate_caller_addr(vec![gateway]);
+        for m in &miners[..2] {
+            runtime.expect_send(
+                *m,
+                METHOD_SEND,
+                None,
+                TokenAmount::from_atto(5),
+                None,
+                ExitCode::new(0),
+            );
+        }
+        runtime.call::<Actor>(Method::Reward as u64, None).unwrap();
+        runtime.verify();
+
+        // it can't leave jail until it participates again
+        runtime.set_caller(Cid::default(), inactive);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            runtime.call::<Actor>(Method::Unjail as u64, None),
+        );
+
+        let mut checkpoint_1 = Checkpoint::new(subnet.clone(), 20);
+        checkpoint_1.data.prev_check = TCid::from(checkpoint_0.cid());
+        checkpoint_1.set_signature(sig.clone());
+        send_checkpoint(&mut runtime, inactive, &checkpoint_1, false).unwrap();
+        send_checkpoint(&mut runtime, miners[0], &checkpoint_1, true).unwrap();
+
+        runtime.set_caller(Cid::default(), inactive);
+        runtime.expect_validate_caller_any();
+        runtime.call::<Actor>(Method::Unjail as u64, None).unwrap();
+        let st: State = runtime.get_state();
+        assert!(!st.is_jailed(&inactive));
+
+        // and it is rewarded again
+        let reward = TokenAmount::from_atto(9);
+        runtime.set_value(reward.clone());
+        runtime.set_caller(Cid::default(), gateway);
+        runtime.expect_validate_caller_addr(vec![gateway]);
+        for m in miners.iter() {
+            runtime.expect_send(
+                *m,
+                METHOD_SEND,
+                None,
+                TokenAmount::from_atto(3),
+                None,
+                ExitCode::new(0),
+            );
+        }
+        runtime.call::<Actor>(Method::Reward as u64, None).unwrap();
+        runtime.verify();
+    }
+
+    #[test]
+    fn test_get_checkpoints() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+        let miner = Address::new_id(10);
+        join_miners(&mut runtime, &[miner]);
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+
+        // commit more checkpoints than can be returned in a single query
+        let mut prev = None;
+        for i in 1..=(MAX_CHECKPOINTS_PER_QUERY as i64 + 1) {
+            let mut ch = Checkpoint::new(subnet.clone(), i * 10);
+            if let Some(prev) = prev {
+                ch.data.prev_check = TCid::from(prev);
+            }
+            ch.set_signature(sig.clone());
+            send_checkpoint(&mut runtime, miner, &ch, true).unwrap();
+            prev = Some(ch.cid());
+        }
+
+        let mut get_checkpoints = |from_epoch, to_epoch| -> Vec<i64> {
+            runtime.expect_validate_caller_any();
+            let ret = runtime
+                .call::<Actor>(
+                    Method::GetCheckpoints as u64,
+                    IpldBlock::serialize_cbor(&CheckpointRangeParams {
+                        from_epoch,
+                        to_epoch,
+                    })
+                    .unwrap(),
+                )
+                .unwrap();
+            let checkpoints: Vec<Checkpoint> = deserialize_block(ret).unwrap();
+            checkpoints.iter().map(|ch| ch.epoch()).collect()
+        };
+        assert_eq!(get_checkpoints(0, 30), vec![10, 20, 30]);
+        assert_eq!(get_checkpoints(15, 35), vec![20, 30]);
+        assert_eq!(get_checkpoints(21, 29), Vec::<i64>::new());
+
+        // the number of windows visited is capped
+        let epochs = get_checkpoints(10, 10_000);
+        assert_eq!(epochs.len(), MAX_CHECKPOINTS_PER_QUERY);
+        assert_eq!(epochs[0], 10);
+        assert_eq!(
+            epochs[MAX_CHECKPOINTS_PER_QUERY - 1],
+            MAX_CHECKPOINTS_PER_QUERY as i64 * 10
+        );
+        let epochs = get_checkpoints(0, 10_000);
+        assert_eq!(epochs.len(), MAX_CHECKPOINTS_PER_QUERY - 1);
+        assert_eq!(get_checkpoints(2_000, i64::MAX), Vec::<i64>::new());
+
+        // ranges close to the maximum epoch don't overflow
+        assert_eq!(get_checkpoints(i64::MAX - 5, i64::MAX), Vec::<i64>::new());
+
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::GetCheckpoints as u64,
+                IpldBlock::serialize_cbor(&CheckpointRangeParams {
+                    from_epoch: 20,
+                    to_epoch: 10,
+                })
+                .unwrap(),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_is_checkpoint_committed() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+        let miners = [
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_miners(&mut runtime, &miners);
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+        let mut ch = Checkpoint::new(subnet.clone(), 10);
+        ch.set_signature(sig.clone());
+
+        let is_committed = |runtime: &mut MockRuntime, ch: &Checkpoint| -> bool {
+            runtime.expect_validate_caller_any();
+            let params = IsCheckpointCommittedParams {
+                epoch: ch.epoch(),
+                cid: ch.cid(),
+            };
+            let ret = runtime
+                .call::<Actor>(
+                    Method::IsCheckpointCommitted as u64,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                )
+                .unwrap();
+            runtime.verify();
+            deserialize_block(ret).unwrap()
+        };
+        assert!(!is_committed(&mut runtime, &ch));
+
+        // a checkpoint being voted is not committed yet
+        send_checkpoint(&mut runtime, miners[0], &ch, false).unwrap();
+        assert!(!is_committed(&mut runtime, &ch));
+        send_checkpoint(&mut runtime, miners[1], &ch, true).unwrap();
+        assert!(is_committed(&mut runtime, &ch));
+
+        // while other checkpoints for the same or later epochs are not
+        let mut other = Checkpoint::new(subnet.clone(), 10);
+        other.set_signature(sig.clone());
+        other.data.tip_set = vec![1];
+        assert!(!is_committed(&mut runtime, &other));
+        let mut next = Checkpoint::new(subnet, 20);
+        next.data.prev_check = TCid::from(ch.cid());
+        next.set_signature(sig);
+        assert!(!is_committed(&mut runtime, &next));
+    }
+
+    #[test]
+    fn test_verify_checkpoint() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+        let miners = [
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_miners(&mut runtime, &miners);
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+        let mut ch = Checkpoint::new(subnet.clone(), 10);
+        ch.set_signature(sig.clone());
+
+        // the signature is only checked once the checkpoint passes the rest of checks
+        let verify = |runtime: &mut MockRuntime,
+                      sender: Address,
+                      ch: &Checkpoint,
+                      signed: bool|
+         -> CheckpointVerification {
+            runtime.set_caller(Cid::default(), sender);
+            runtime.expect_validate_caller_any();
+            if signed {
+                runtime.expect_send(
+                    sender,
+                    ipc_sdk::account::PUBKEY_ADDRESS_METHOD as u64,
+                    None,
+                    TokenAmount::zero(),
+                    IpldBlock::serialize_cbor(&sender).unwrap(),
+                    ExitCode::new(0),
+                );
+                runtime.expect_verify_signature(ExpectedVerifySig {
+                    sig: Signature::new_secp256k1(vec![1, 2, 3, 4]),
+                    signer: sender,
+                    plaintext: ch.cid().to_bytes(),
+                    result: Ok(()),
+                });
+            }
+            let ret = runtime
+                .call::<Actor>(
+                    Method::VerifyCheckpoint as u64,
+                    IpldBlock::serialize_cbor(ch).unwrap(),
+                )
+                .unwrap();
+            runtime.verify();
+            deserialize_block(ret).unwrap()
+        };
+
+        let valid = verify(&mut runtime, miners[0], &ch, true);
+        assert!(valid.valid);
+        assert_eq!(valid.reason, None);
+        // no vote is recorded
+        let st: State = runtime.get_state();
+        assert!(st.get_votes(runtime.store(), &ch.cid()).unwrap().is_none());
+
+        // checkpoints rejected by submit_checkpoint are reported as invalid
+        let not_validator = verify(&mut runtime, Address::new_id(40), &ch, false);
+        assert!(!not_validator.valid);
+        assert!(not_validator.reason.unwrap().contains("not validator"));
+        let mut wrong_epoch = Checkpoint::new(subnet.clone(), 11);
+        wrong_epoch.set_signature(sig.clone());
+        assert!(!verify(&mut runtime, miners[0], &wrong_epoch, false).valid);
+
+        // once a validator voted the checkpoint it can't vote it again
+        send_checkpoint(&mut runtime, miners[0], &ch, false).unwrap();
+        let voted = verify(&mut runtime, miners[0], &ch, true);
+        assert!(!voted.valid);
+        assert!(voted.reason.unwrap().contains("already voted"));
+        assert!(verify(&mut runtime, miners[1], &ch, true).valid);
+        send_checkpoint(&mut runtime, miners[1], &ch, true).unwrap();
+
+        // and it can't be verified once committed
+        assert!(!verify(&mut runtime, miners[2], &ch, false).valid);
+    }
+
+    #[test]
+    fn test_checkpoint_errors() {
+        let test_actor_address = Address::new_id(9999);
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+        let checkpoint = |source: &SubnetID, epoch: i64| {
+            let mut ch = Checkpoint::new(source.clone(), epoch);
+            ch.set_signature(sig.clone());
+            ch
+        };
+        let verify = |runtime: &mut MockRuntime, ch: &Checkpoint| {
+            let st: State = runtime.get_state();
+            st.verify_checkpoint(runtime, ch).unwrap_err()
+        };
+
+        // checkpoints can't be submitted before the subnet is active
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+        let ch = checkpoint(&subnet, 10);
+        assert!(matches!(
+            verify(&mut runtime, &ch),
+            CheckpointError::NotActive
+        ));
+
+        let miners = [
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_miners(&mut runtime, &miners);
+
+        assert!(matches!(
+            verify(&mut runtime, &checkpoint(&subnet, 11)),
+            CheckpointError::BadEpoch {
+                epoch: 11,
+                period: 10
+            }
+        ));
+        let foreign = SubnetID::new_from_parent(&root_subnet, Address::new_id(1234));
+        assert!(matches!(
+            verify(&mut runtime, &checkpoint(&foreign, 10)),
+            CheckpointError::BadSource { .. }
+        ));
+        let mut bad_prev = checkpoint(&subnet, 10);
+        bad_prev.data.prev_check = TCid::from(checkpoint(&subnet, 20).cid());
+        assert!(matches!(
+            verify(&mut runtime, &bad_prev),
+            CheckpointError::BadPrevCid { .. }
+        ));
+        let mut bad_meta = checkpoint(&subnet, 10);
+        bad_meta.set_cross_msgs(CrossMsgMeta {
+            value: TokenAmount::from_atto(100),
+            ..Default::default()
+        });
+        assert!(matches!(
+            verify(&mut runtime, &bad_meta),
+            CheckpointError::BadCrossMsgMeta(_)
+        ));
+
+        // the cause is surfaced in the error of the submission
+        runtime.set_caller(Cid::default(), miners[0]);
+        runtime.expect_validate_caller_any();
+        let err = runtime
+            .call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                IpldBlock::serialize_cbor(&checkpoint(&subnet, 11)).unwrap(),
+            )
+            .unwrap_err();
+        runtime.verify();
+        assert_eq!(err.exit_code(), ExitCode::USR_ILLEGAL_STATE);
+        assert!(err.msg().contains("signing window"));
+
+        // committed checkpoints can't be submitted again
+        send_checkpoint(&mut runtime, miners[0], &ch, false).unwrap();
+        send_checkpoint(&mut runtime, miners[1], &ch, true).unwrap();
+        assert!(matches!(
+            verify(&mut runtime, &ch),
+            CheckpointError::AlreadyCommitted(10)
+        ));
+    }
+
+    #[test]
+    fn test_checkpoint_deposit() {
+        let test_actor_address = Address::new_id(9999);
+        let deposit = TokenAmount::from_nano(100);
+        let mut params = std_construct_param();
+        params.checkpoint_deposit = deposit.clone();
+        let mut runtime = construct_runtime_with_params(test_actor_address.clone(), params);
+        let miners = [
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_miners(&mut runtime, &miners);
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+        let mut ch = Checkpoint::new(subnet.clone(), 10);
+        ch.set_signature(sig.clone());
+        let mut fork = Checkpoint::new(subnet.clone(), 10);
+        fork.data.tip_set = vec![1];
+        fork.set_signature(sig.clone());
+        let mut conflicting = Checkpoint::new(subnet, 10);
+        conflicting.data.tip_set = vec![2];
+        conflicting.set_signature(sig);
+
+        // submissions without the deposit are rejected
+        runtime.set_caller(Cid::default(), miners[0]);
+        runtime.set_value(TokenAmount::zero());
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                IpldBlock::serialize_cbor(&ch).unwrap(),
+            ),
+        );
+        runtime.verify();
+
+        // deposits are held until a checkpoint is committed for the epoch.
+        // The second validator votes two different checkpoints for it.
+        runtime.set_value(deposit.clone());
+        runtime.set_balance(deposit.clone());
+        send_checkpoint(&mut runtime, miners[0], &fork, false).unwrap();
+        runtime.set_balance(deposit.clone() * 2);
+        send_checkpoint(&mut runtime, miners[1], &conflicting, false).unwrap();
+        runtime.set_balance(deposit.clone() * 3);
+        send_checkpoint(&mut runtime, miners[1], &ch, false).unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.accounted_funds(runtime.store()).unwrap(),
+            &st.total_stake + deposit.clone() * 3
+        );
+
+        // the deposits of validators that voted a single checkpoint are
+        // refunded, also for the one on a losing fork, while the ones of the
+        // validator that voted conflicting checkpoints are forfeited.
+        runtime.set_balance(deposit.clone() * 4);
+        runtime.set_caller(Cid::default(), miners[2]);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            miners[2],
+            ipc_sdk::account::PUBKEY_ADDRESS_METHOD as u64,
+            None,
+            TokenAmount::zero(),
+            IpldBlock::serialize_cbor(&miners[2]).unwrap(),
+            ExitCode::OK,
+        );
+        runtime.expect_verify_signature(ExpectedVerifySig {
+            sig: Signature::new_secp256k1(vec![1, 2, 3, 4]),
+            signer: miners[2],
+            plaintext: ch.cid().to_bytes(),
+            result: Ok(()),
+        });
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::CommitChildCheckpoint as u64,
+            IpldBlock::serialize_cbor(&ch).unwrap(),
+            TokenAmount::zero(),
+            None,
+            ExitCode::OK,
+        );
+        for miner in [miners[0], miners[2]] {
+            runtime.expect_send(
+                miner,
+                METHOD_SEND,
+                None,
+                deposit.clone(),
+                None,
+                ExitCode::OK,
+            );
+        }
+        runtime.expect_send(
+            BURNT_FUNDS_ACTOR_ADDR,
+            METHOD_SEND,
+            None,
+            deposit.clone() * 2,
+            None,
+            ExitCode::OK,
+        );
+        runtime
+            .call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                IpldBlock::serialize_cbor(&ch).unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.accounted_funds(runtime.store()).unwrap(), st.total_stake);
+    }
+
+    #[test]
+    fn test_checkpoint_deposit_refunded_on_cleared_votes() {
+        let test_actor_address = Address::new_id(9999);
+        let deposit = TokenAmount::from_nano(100);
+        let mut params = std_construct_param();
+        params.checkpoint_deposit = deposit.clone();
+        params.vote_retention_windows = 1;
+        let mut runtime = construct_runtime_with_params(test_actor_address.clone(), params);
+        let miners = [
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_miners(&mut runtime, &miners);
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+        let mut past = Checkpoint::new(subnet.clone(), 10);
+        past.set_signature(sig.clone());
+        let mut current = Checkpoint::new(subnet, 20);
+        current.set_signature(sig);
+
+        // the checkpoint of a past window is never committed
+        runtime.set_value(deposit.clone());
+        runtime.set_balance(deposit.clone());
+        send_checkpoint(&mut runtime, miners[0], &past, false).unwrap();
+        runtime.verify();
+
+        // so its deposit is refunded when its votes become stale
+        runtime.set_balance(deposit.clone() * 2);
+        runtime.set_caller(Cid::default(), miners[1]);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            miners[1],
+            ipc_sdk::account::PUBKEY_ADDRESS_METHOD as u64,
+            None,
+            TokenAmount::zero(),
+            IpldBlock::serialize_cbor(&miners[1]).unwrap(),
+            ExitCode::OK,
+        );
+        runtime.expect_verify_signature(ExpectedVerifySig {
+            sig: Signature::new_secp256k1(vec![1, 2, 3, 4]),
+            signer: miners[1],
+            plaintext: current.cid().to_bytes(),
+            result: Ok(()),
+        });
+        runtime.expect_send(
+            miners[0],
+            METHOD_SEND,
+            None,
+            deposit.clone(),
+            None,
+            ExitCode::OK,
+        );
+        runtime
+            .call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                IpldBlock::serialize_cbor(&current).unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.accounted_funds(runtime.store()).unwrap(),
+            &st.total_stake + deposit.clone()
+        );
+
+        // and the ones left are refunded when all the votes are cleared
+        let mut st: State = runtime.get_state();
+        st.status = Status::Inactive;
+        runtime.replace_state(&st);
+        runtime.set_balance(deposit.clone());
+        runtime.set_caller(Cid::default(), SYSTEM_ACTOR_ADDR);
+        runtime.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+        runtime.expect_send(
+            miners[1],
+            METHOD_SEND,
+            None,
+            deposit.clone(),
+            None,
+            ExitCode::OK,
+        );
+        runtime
+            .call::<Actor>(Method::ClearAllVotes as u64, None)
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.accounted_funds(runtime.store()).unwrap(), st.total_stake);
+    }
+
+    #[test]
+    fn test_checkpoint_certificate() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+        let miners = [
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_miners(&mut runtime, &miners);
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+        let mut ch = Checkpoint::new(subnet, 10);
+        ch.set_signature(sig);
+
+        let get_certificate = |runtime: &mut MockRuntime, epoch: i64| {
+            runtime.expect_validate_caller_any();
+            let ret = runtime
+                .call::<Actor>(
+                    Method::GetCheckpointCertificate as u64,
+                    IpldBlock::serialize_cbor(&epoch).unwrap(),
+                )
+                .unwrap();
+            deserialize_block::<Option<CheckpointCertificate>>(ret).unwrap()
+        };
+        assert_eq!(get_certificate(&mut runtime, 10), None);
+
+        // two out of three validators commit the checkpoint
+        send_checkpoint(&mut runtime, miners[0], &ch, false).unwrap();
+        send_checkpoint(&mut runtime, miners[1], &ch, true).unwrap();
+
+        let certificate = get_certificate(&mut runtime, 10).unwrap();
+        assert_eq!(certificate.checkpoint.cid(), ch.cid());
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        assert_eq!(
+            certificate.voters,
+            vec![
+                CheckpointVoter {
+                    addr: miners[0],
+                    stake: stake.clone(),
+                },
+                CheckpointVoter {
+                    addr: miners[1],
+                    stake: stake.clone(),
+                },
+            ]
+        );
+        // the whole validator set joined before the first checkpoint
+        assert_eq!(
+            certificate.validator_diff,
+            Some(ValidatorSetDiff {
+                joined: miners.to_vec(),
+                left: Vec::new(),
+            })
+        );
+        assert_eq!(get_certificate(&mut runtime, 20), None);
+
+        // a validator leaves and a new one joins before the next checkpoint
+        runtime.set_caller(Cid::default(), miners[2]);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::ReleaseStake as u64,
+            IpldBlock::serialize_cbor(&FundParams {
+                value: stake.clone(),
+            })
+            .unwrap(),
+            TokenAmount::zero(),
+            None,
+            ExitCode::new(0),
+        );
+        runtime.call::<Actor>(Method::Leave as u64, None).unwrap();
+        runtime.verify();
+        let newcomer = Address::new_id(40);
+        runtime.set_value(stake.clone());
+        runtime.set_balance(stake.clone());
+        runtime.set_caller(Cid::default(), newcomer);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            None,
+            stake.clone(),
+            None,
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                IpldBlock::serialize_cbor(&JoinParams {
+                    validator_net_addr: newcomer.to_string(),
+                    reward_addr: None,
+                })
+                .unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        let mut next = Checkpoint::new(ch.source().clone(), 20);
+        next.data.prev_check = TCid::from(ch.cid());
+        next.set_signature(ch.signature().clone());
+        send_checkpoint(&mut runtime, miners[0], &next, false).unwrap();
+        send_checkpoint(&mut runtime, miners[1], &next, true).unwrap();
+
+        let certificate = get_certificate(&mut runtime, 20).unwrap();
+        assert_eq!(
+            certificate.validator_diff,
+            Some(ValidatorSetDiff {
+                joined: vec![newcomer],
+                left: vec![miners[2]],
+            })
+        );
+    }
+
+    #[test]
+    fn test_eligible_voters() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+        let miners = [
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_miners(&mut runtime, &miners);
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+        let mut ch = Checkpoint::new(subnet, 10);
+        ch.set_signature(sig);
+
+        let get_eligible_voters = |runtime: &mut MockRuntime, epoch: i64| {
+            runtime.expect_validate_caller_any();
+            let ret = runtime
+                .call::<Actor>(
+                    Method::GetEligibleVoters as u64,
+                    IpldBlock::serialize_cbor(&epoch).unwrap(),
+                )
+                .unwrap();
+            deserialize_block::<Option<Vec<CheckpointVoter>>>(ret).unwrap()
+        };
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let voters = |addrs: &[Address]| {
+            addrs
+                .iter()
+                .map(|addr| CheckpointVoter {
+                    addr: *addr,
+                    stake: stake.clone(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(get_eligible_voters(&mut runtime, 15), None);
+
+        // the eligible set is snapshotted with the first vote of the window
+        send_checkpoint(&mut runtime, miners[0], &ch, false).unwrap();
+
+        let newcomer = Address::new_id(40);
+        runtime.set_value(stake.clone());
+        runtime.set_balance(stake.clone());
+        runtime.set_caller(Cid::default(), newcomer);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            None,
+            stake.clone(),
+            None,
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                IpldBlock::serialize_cbor(&JoinParams {
+                    validator_net_addr: newcomer.to_string(),
+                    reward_addr: None,
+                })
+                .unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        // the newcomer is only eligible from the next window
+        assert_eq!(get_eligible_voters(&mut runtime, 10), Some(voters(&miners)));
+        let mut all = miners.to_vec();
+        all.push(newcomer);
+        assert_eq!(get_eligible_voters(&mut runtime, 20), Some(voters(&all)));
+
+        // the quorum is computed from the snapshot, so two out of the three
+        // eligible validators commit the checkpoint regardless of the newcomer
+        send_checkpoint(&mut runtime, miners[1], &ch, true).unwrap();
+        let st: State = runtime.get_state();
+        assert!(st
+            .is_checkpoint_committed(runtime.store(), 10, &ch.cid())
+            .unwrap());
+
+        // the snapshot outlives the commitment of the checkpoint
+        assert_eq!(get_eligible_voters(&mut runtime, 10), Some(voters(&miners)));
+    }
+
+    #[test]
+    fn test_checkpoint_delegate() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+        let miners = [
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_miners(&mut runtime, &miners);
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+        let mut ch = Checkpoint::new(subnet, 10);
+        ch.set_signature(sig);
+
+        let set_delegate = |runtime: &mut MockRuntime, caller: Address, delegate: Address| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            let ret = runtime.call::<Actor>(
+                Method::SetCheckpointDelegate as u64,
+                IpldBlock::serialize_cbor(&delegate).unwrap(),
+            );
+            runtime.verify();
+            ret
+        };
+        let clear_delegate = |runtime: &mut MockRuntime, caller: Address| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            let ret = runtime.call::<Actor>(Method::ClearCheckpointDelegate as u64, None);
+            runtime.verify();
+            ret
+        };
+        let reject_vote = |runtime: &mut MockRuntime, caller: Address| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            expect_abort(
+                ExitCode::USR_ILLEGAL_STATE,
+                runtime.call::<Actor>(
+                    Method::SubmitCheckpoint as u64,
+                    IpldBlock::serialize_cbor(&ch).unwrap(),
+                ),
+            );
+            runtime.verify();
+        };
+
+        // only validators can set a delegate, which can't be a validator itself
+        let relayer = Address::new_id(100);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            set_delegate(&mut runtime, Address::new_id(40), relayer),
+        );
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            set_delegate(&mut runtime, miners[0], miners[1]),
+        );
+
+        // unauthorized addresses can't vote
+        reject_vote(&mut runtime, relayer);
+
+        // the vote of the delegate counts for its validator
+        set_delegate(&mut runtime, miners[0], relayer).unwrap();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            set_delegate(&mut runtime, miners[1], relayer),
+        );
+        send_checkpoint(&mut runtime, relayer, &ch, false).unwrap();
+        let st: State = runtime.get_state();
+        let votes = st.get_votes(runtime.store(), &ch.cid()).unwrap().unwrap();
+        assert_eq!(votes.validators, vec![miners[0]]);
+
+        // the delegate can't vote again on behalf of the validator
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            send_checkpoint(&mut runtime, relayer, &ch, false),
+        );
+        runtime.verify();
+
+        // once cleared the delegate can no longer vote
+        clear_delegate(&mut runtime, miners[0]).unwrap();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            clear_delegate(&mut runtime, miners[0]),
+        );
+        reject_vote(&mut runtime, relayer);
+
+        // and other validators can authorize it
+        set_delegate(&mut runtime, miners[1], relayer).unwrap();
+        send_checkpoint(&mut runtime, relayer, &ch, false).unwrap();
+        send_checkpoint(&mut runtime, miners[2], &ch, true).unwrap();
+        runtime.verify();
+    }
+
+    #[test]
+    fn test_join_and_vote() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address.clone());
+        let validator = Address::new_id(10);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+        let params = |checkpoint: &Checkpoint| {
+            IpldBlock::serialize_cbor(&JoinAndVoteParams {
+                join: JoinParams {
+                    validator_net_addr: validator.to_string(),
+                    reward_addr: None,
+                },
+                checkpoint: checkpoint.clone(),
+            })
+            .unwrap()
+        };
+        let expect_join = |runtime: &mut MockRuntime| {
+            runtime.set_value(stake.clone());
+            runtime.set_balance(stake.clone());
+            runtime.set_caller(Cid::default(), validator);
+            runtime.expect_validate_caller_any();
+            runtime.expect_send(
+                Address::new_id(IPC_GATEWAY_ADDR),
+                ipc_gateway::Method::Register as u64,
+                None,
+                stake.clone(),
+                None,
+                ExitCode::new(0),
+            );
+        };
+
+        // a vote for a checkpoint of another subnet rolls back the join
+        let mut invalid = Checkpoint::new(SubnetID::new_from_parent(&root_subnet, validator), 10);
+        invalid.set_signature(sig.clone());
+        expect_join(&mut runtime);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(Method::JoinAndVote as u64, params(&invalid)),
+        );
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.total_stake, TokenAmount::zero());
+        assert_eq!(st.status, Status::Instantiated);
+        assert!(!st.is_validator(&validator));
+
+        // the only validator joins and commits the first checkpoint on its own
+        let mut ch = Checkpoint::new(subnet, 10);
+        ch.set_signature(sig);
+        expect_join(&mut runtime);
+        runtime.expect_send(
+            validator,
+            ipc_sdk::account::PUBKEY_ADDRESS_METHOD as u64,
+            None,
+            TokenAmount::zero(),
+            IpldBlock::serialize_cbor(&validator).unwrap(),
+            ExitCode::new(0),
+        );
+        runtime.expect_verify_signature(ExpectedVerifySig {
+            sig: Signature::new_secp256k1(vec![1, 2, 3, 4]),
+            signer: validator,
+            plaintext: ch.cid().to_bytes(),
+            result: Ok(()),
+        });
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::CommitChildCheckpoint as u64,
+            IpldBlock::serialize_cbor(&ch).unwrap(),
+            TokenAmount::zero(),
+            None,
+            ExitCode::new(0),
+        );
+        runtime
+            .call::<Actor>(Method::JoinAndVote as u64, params(&ch))
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.total_stake, stake);
+        assert!(st.is_validator(&validator));
+        assert_eq!(st.pending_window_start, None);
+    }
+
+    #[test]
+    fn test_join_and_vote_deposit() {
+        let test_actor_address = Address::new_id(9999);
+        let deposit = TokenAmount::from_nano(100);
+        let mut params = std_construct_param();
+        params.checkpoint_deposit = deposit.clone();
+        let mut runtime = construct_runtime_with_params(test_actor_address.clone(), params);
+        let miners = [Address::new_id(10), Address::new_id(20)];
+        join_miners(&mut runtime, &miners);
+        let validator = Address::new_id(30);
+        let stake = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let mut ch = Checkpoint::new(subnet, 10);
+        ch.set_signature(
+            RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+                .unwrap()
+                .bytes()
+                .to_vec(),
+        );
+        let params = IpldBlock::serialize_cbor(&JoinAndVoteParams {
+            join: JoinParams {
+                validator_net_addr: validator.to_string(),
+                reward_addr: None,
+            },
+            checkpoint: ch.clone(),
+        })
+        .unwrap();
+
+        // the value sent needs to cover the deposit and some stake
+        runtime.set_value(deposit.clone());
+        runtime.set_caller(Cid::default(), validator);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(Method::JoinAndVote as u64, params.clone()),
+        );
+        runtime.verify();
+
+        // the deposit is held for the vote and the rest is staked
+        let st: State = runtime.get_state();
+        let total_stake = st.total_stake;
+        runtime.set_value(&stake + &deposit);
+        runtime.set_balance(&total_stake + &stake + &deposit);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            None,
+            stake.clone(),
+            None,
+            ExitCode::OK,
+        );
+        runtime.expect_send(
+            validator,
+            ipc_sdk::account::PUBKEY_ADDRESS_METHOD as u64,
+            None,
+            TokenAmount::zero(),
+            IpldBlock::serialize_cbor(&validator).unwrap(),
+            ExitCode::OK,
+        );
+        runtime.expect_verify_signature(ExpectedVerifySig {
+            sig: Signature::new_secp256k1(vec![1, 2, 3, 4]),
+            signer: validator,
+            plaintext: ch.cid().to_bytes(),
+            result: Ok(()),
+        });
+        runtime
+            .call::<Actor>(Method::JoinAndVote as u64, params)
+            .unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.total_stake, &total_stake + &stake);
+        assert_eq!(
+            st.accounted_funds(runtime.store()).unwrap(),
+            &st.total_stake + &deposit
+        );
+    }
+
+    #[test]
+    fn test_is_validator() {
+        let mut params = std_construct_param();
+        params.consensus = ConsensusType::Delegated;
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        // in delegated consensus only the first staker becomes a validator,
+        // the rest of them are delegators.
+        let validator = Address::new_id(10);
+        let delegator = Address::new_id(20);
+        join_miners(&mut runtime, &[validator, delegator]);
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.validator_set.len(), 1);
+
+        for (addr, expected) in [
+            (validator, true),
+            (delegator, false),
+            (Address::new_id(30), false),
+        ] {
+            runtime.set_caller(Cid::default(), Address::new_id(40));
+            runtime.expect_validate_caller_any();
+            let ret = runtime
+                .call::<Actor>(
+                    Method::IsValidator as u64,
+                    IpldBlock::serialize_cbor(&addr).unwrap(),
+                )
+                .unwrap();
+            runtime.verify();
+            let is_validator: bool = deserialize_block(ret).unwrap();
+            assert_eq!(is_validator, expected);
+        }
+    }
+
+    #[test]
+    fn test_has_stake() {
+        let mut params = std_construct_param();
+        params.consensus = ConsensusType::Delegated;
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        let has_stake = |runtime: &mut MockRuntime, addr: &Address| -> bool {
+            runtime.set_caller(Cid::default(), Address::new_id(40));
+            runtime.expect_validate_caller_any();
+            let ret = runtime
+                .call::<Actor>(
+                    Method::HasStake as u64,
+                    IpldBlock::serialize_cbor(addr).unwrap(),
+                )
+                .unwrap();
+            runtime.verify();
+            deserialize_block(ret).unwrap()
+        };
+
+        // delegators hold stake even if they aren't validators
+        let validator = Address::new_id(10);
+        let delegator = Address::new_id(20);
+        join_miners(&mut runtime, &[validator, delegator]);
+        assert!(has_stake(&mut runtime, &validator));
+        assert!(has_stake(&mut runtime, &delegator));
+        assert!(!has_stake(&mut runtime, &Address::new_id(30)));
+
+        runtime.set_caller(Cid::default(), delegator);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::ReleaseStake as u64,
+            IpldBlock::serialize_cbor(&FundParams {
+                value: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+            })
+            .unwrap(),
+            TokenAmount::zero(),
+            None,
+            ExitCode::new(0),
+        );
+        runtime.call::<Actor>(Method::Leave as u64, None).unwrap();
+        runtime.verify();
+        assert!(has_stake(&mut runtime, &validator));
+        assert!(!has_stake(&mut runtime, &delegator));
+    }
+
+    #[test]
+    fn test_recompute_total_stake() {
+        let mut runtime = construct_runtime();
+        join_miners(&mut runtime, &[Address::new_id(10), Address::new_id(20)]);
+        let expected = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT) * 2;
+
+        // seed an inconsistent total stake
+        let mut st: State = runtime.get_state();
+        assert_eq!(st.total_stake, expected);
+        st.total_stake = TokenAmount::from_atto(1);
+        runtime.replace_state(&st);
+
+        // only the system actor can recompute the stake
+        runtime.set_caller(Cid::default(), Address::new_id(10));
+        runtime.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(Method::RecomputeTotalStake as u64, None),
+        );
+        runtime.verify();
+
+        runtime.set_caller(Cid::default(), SYSTEM_ACTOR_ADDR);
+        runtime.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+        let ret = runtime
+            .call::<Actor>(Method::RecomputeTotalStake as u64, None)
+            .unwrap();
+        runtime.verify();
+        let correction: StakeCorrection = deserialize_block(ret).unwrap();
+        assert_eq!(
+            correction,
+            StakeCorrection {
+                old_total_stake: TokenAmount::from_atto(1),
+                new_total_stake: expected.clone(),
+            }
+        );
+        let st: State = runtime.get_state();
+        assert_eq!(st.total_stake, expected);
+    }
+
+    #[test]
+    fn test_clear_all_votes() {
+        let mut runtime = construct_runtime();
+        let miners = [
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_miners(&mut runtime, &miners);
+        let subnet = SubnetID::from_str("/root/f01000").unwrap();
+        let pending: Vec<Checkpoint> = (1..3)
+            .map(|i| Checkpoint::new(subnet.clone(), i * 10))
+            .collect();
+
+        // seed the votes of checkpoints short of a majority
+        let mut st: State = runtime.get_state();
+        for (ch, miner) in pending.iter().zip(miners.iter()) {
+            let votes = Votes {
+                validators: vec![*miner],
+            };
+            st.set_votes(runtime.store(), &ch.cid(), votes).unwrap();
+        }
+        runtime.replace_state(&st);
+
+        let clear = |runtime: &mut MockRuntime, code: ExitCode| -> u64 {
+            runtime.set_caller(Cid::default(), SYSTEM_ACTOR_ADDR);
+            runtime.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+            if code != ExitCode::OK {
+                expect_abort(
+                    code,
+                    runtime.call::<Actor>(Method::ClearAllVotes as u64, None),
+                );
+                runtime.verify();
+                return 0;
+            }
+            let ret = runtime
+                .call::<Actor>(Method::ClearAllVotes as u64, None)
+                .unwrap();
+            runtime.verify();
+            deserialize_block(ret).unwrap()
+        };
+
+        // only the system actor can clear the votes
+        runtime.set_caller(Cid::default(), Address::new_id(10));
+        runtime.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            runtime.call::<Actor>(Method::ClearAllVotes as u64, None),
+        );
+        runtime.verify();
+
+        // and only while the subnet is inactive
+        clear(&mut runtime, ExitCode::USR_ILLEGAL_STATE);
+        let mut st: State = runtime.get_state();
+        st.status = Status::Inactive;
+        runtime.replace_state(&st);
+
+        // votes of a checkpoint being committed are not cleared
+        let committing = Checkpoint::new(subnet, 30);
+        let votes = Votes {
+            validators: miners.to_vec(),
+        };
+        st.set_votes(runtime.store(), &committing.cid(), votes)
+            .unwrap();
+        runtime.replace_state(&st);
+        clear(&mut runtime, ExitCode::USR_ILLEGAL_STATE);
+        st.remove_votes(runtime.store(), &committing.cid()).unwrap();
+        runtime.replace_state(&st);
+
+        assert_eq!(clear(&mut runtime, ExitCode::OK), 2);
+        let st: State = runtime.get_state();
+        for ch in pending.iter() {
+            assert!(st.get_votes(runtime.store(), &ch.cid()).unwrap().is_none());
+        }
+        assert_eq!(clear(&mut runtime, ExitCode::OK), 0);
+    }
+
+    #[test]
+    fn test_recover_funds() {
+        let atto = |v: u64| TokenAmount::from_atto(v);
+        let mut runtime = construct_runtime();
+        let to = Address::new_id(1000);
+
+        let recover = |runtime: &mut MockRuntime, caller: Address, amount: TokenAmount| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+            let ret = runtime.call::<Actor>(
+                Method::RecoverFunds as u64,
+                IpldBlock::serialize_cbor(&RecoverFundsParams { to, amount }).unwrap(),
+            );
+            runtime.verify();
+            ret
+        };
+
+        // only the system actor can recover funds
+        runtime.set_balance(atto(5));
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            recover(&mut runtime, Address::new_id(10), atto(5)),
+        );
+        runtime.expect_send(to, METHOD_SEND, None, atto(3), None, ExitCode::OK);
+        recover(&mut runtime, SYSTEM_ACTOR_ADDR, atto(3)).unwrap();
+
+        // staked funds can't be recovered
+        join_miners(&mut runtime, &[Address::new_id(10), Address::new_id(20)]);
+        let st: State = runtime.get_state();
+        runtime.set_balance(st.total_stake.clone() + atto(2));
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            recover(&mut runtime, SYSTEM_ACTOR_ADDR, atto(3)),
+        );
+        runtime.set_balance(st.total_stake.clone());
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            recover(&mut runtime, SYSTEM_ACTOR_ADDR, atto(1)),
+        );
+
+        // while the funds over it can
+        runtime.set_balance(st.total_stake.clone() + atto(2));
+        runtime.expect_send(to, METHOD_SEND, None, atto(2), None, ExitCode::OK);
+        recover(&mut runtime, SYSTEM_ACTOR_ADDR, atto(2)).unwrap();
+    }
+
+    #[test]
+    fn test_reward_policies() {
+        let atto = |v: u64| TokenAmount::from_atto(v);
+        let amounts = |v: &[u64]| v.iter().map(|a| atto(*a)).collect::<Vec<_>>();
+        let stakes = amounts(&[1, 4, 9, 16]);
+        let total = atto(100);
+
+        let cases = vec![
+            (RewardPolicy::Even, amounts(&[25, 25, 25, 25])),
+            // the remainder of the split goes to the first validator
+            (RewardPolicy::StakeWeighted, amounts(&[4, 13, 30, 53])),
+            (RewardPolicy::Quadratic, amounts(&[10, 20, 30, 40])),
+            (
+                RewardPolicy::FixedPerValidator(atto(10)),
+                amounts(&[12, 18, 28, 42]),
+            ),
+            // not enough to pay the fixed reward of every validator
+            (
+                RewardPolicy::FixedPerValidator(atto(30)),
+                amounts(&[25, 25, 25, 25]),
+            ),
+        ];
+        for (policy, expected) in cases {
+            let rewards = policy.split(&total, &stakes);
+            assert_eq!(rewards, expected, "policy {:?}", policy);
+            let distributed = rewards.iter().fold(TokenAmount::zero(), |acc, r| acc + r);
+            assert_eq!(distributed, total, "policy {:?}", policy);
+        }
+
+        // repeated splits of uneven amounts never lose any rewards to rounding
+        let policies = [
+            RewardPolicy::Even,
+            RewardPolicy::StakeWeighted,
+            RewardPolicy::Quadratic,
+            RewardPolicy::FixedPerValidator(atto(3)),
+        ];
+        for policy in policies {
+            let mut distributed = TokenAmount::zero();
+            for amount in 1..=100 {
+                let rewards = policy.split(&atto(amount), &stakes);
+                distributed += rewards.iter().fold(TokenAmount::zero(), |acc, r| acc + r);
+            }
+            assert_eq!(distributed, atto(5050), "policy {:?}", policy);
+        }
+
+        // zero stakes fall back to an even split
+        assert_eq!(
+            RewardPolicy::StakeWeighted.split(&atto(10), &amounts(&[0, 0, 0])),
+            amounts(&[4, 3, 3])
+        );
+
+        // the policy selected at construction is used by `Reward`
+        let mut params = std_construct_param();
+        params.reward_policy = RewardPolicy::FixedPerValidator(atto(2));
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+        let validators = [
+            Address::new_id(10),
+            Address::new_id(20),
+            Address::new_id(30),
+        ];
+        join_miners(&mut runtime, &validators);
+
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+        runtime.set_value(atto(10));
+        runtime.set_balance(atto(10));
+        runtime.set_caller(Cid::default(), gateway);
+        runtime.expect_validate_caller_addr(vec![gateway]);
+        for (v, amount) in validators.iter().zip([4, 3, 3]) {
+            runtime.expect_send(*v, METHOD_SEND, None, atto(amount), None, ExitCode::OK);
+        }
+        runtime.call::<Actor>(Method::Reward as u64, None).unwrap();
+        runtime.verify();
+    }
+
+    #[test]
+    fn test_pull_rewards() {
+        let mut params = std_construct_param();
+        params.pull_rewards = true;
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+        let validators = [Address::new_id(10), Address::new_id(20)];
+        join_miners(&mut runtime, &validators);
+
+        let pending = |runtime: &mut MockRuntime, addr: Address| -> TokenAmount {
+            runtime.set_caller(Cid::default(), Address::new_id(1000));
+            runtime.expect_validate_caller_any();
+            let ret = runtime
+                .call::<Actor>(
+                    Method::GetPendingReward as u64,
+                    IpldBlock::serialize_cbor(&addr).unwrap(),
+                )
+                .unwrap();
+            runtime.verify();
+            deserialize_block(ret).unwrap()
+        };
+        assert_eq!(pending(&mut runtime, validators[0]), TokenAmount::zero());
+
+        // rewards are accrued instead of sent
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+        for _ in 0..2 {
+            runtime.set_value(TokenAmount::from_atto(10));
+            runtime.set_caller(Cid::default(), gateway);
+            runtime.expect_validate_caller_addr(vec![gateway]);
+            runtime.call::<Actor>(Method::Reward as u64, None).unwrap();
+            runtime.verify();
+        }
+        for v in validators {
+            assert_eq!(pending(&mut runtime, v), TokenAmount::from_atto(10));
+        }
+
+        // claiming sends the accrued rewards and zeroes them
+        runtime.set_balance(TokenAmount::from_atto(20));
+        runtime.set_caller(Cid::default(), validators[0]);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            validators[0],
+            METHOD_SEND,
+            None,
+            TokenAmount::from_atto(10),
+            None,
+            ExitCode::OK,
+        );
+        runtime
+            .call::<Actor>(Method::ClaimReward as u64, None)
+            .unwrap();
+        runtime.verify();
+        assert_eq!(pending(&mut runtime, validators[0]), TokenAmount::zero());
+        assert_eq!(
+            pending(&mut runtime, validators[1]),
+            TokenAmount::from_atto(10)
+        );
+
+        // there is nothing left to claim
+        runtime.set_caller(Cid::default(), validators[0]);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(Method::ClaimReward as u64, None),
+        );
+        runtime.verify();
+    }
+
+    #[test]
+    fn test_activation_requirements() {
+        let mut runtime = construct_runtime();
+        let min_collateral = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let requirements = |runtime: &mut MockRuntime| -> ActivationRequirements {
+            runtime.set_caller(Cid::default(), Address::new_id(1000));
+            runtime.expect_validate_caller_any();
+            let ret = runtime
+                .call::<Actor>(Method::GetActivationRequirements as u64, None)
+                .unwrap();
+            runtime.verify();
+            deserialize_block(ret).unwrap()
+        };
+        assert_eq!(
+            requirements(&mut runtime),
+            ActivationRequirements {
+                min_collateral: min_collateral.clone(),
+                total_stake: TokenAmount::zero(),
+                remaining: min_collateral.clone(),
+                activation_epoch: None,
+            }
+        );
+
+        let params = JoinParams {
+            validator_net_addr: Address::new_id(100).to_string(),
+            reward_addr: None,
+        };
+        let quarter = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT / 4);
+        let join = |runtime: &mut MockRuntime, caller: u64, value: &TokenAmount| {
+            runtime.set_value(value.clone());
+            runtime.set_balance(value.clone());
+            runtime.set_caller(Cid::default(), Address::new_id(caller));
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::Join as u64,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                )
+                .unwrap();
+            runtime.verify();
+        };
+
+        // the remaining stake decreases as validators join
+        join(&mut runtime, 10, &quarter);
+        let r = requirements(&mut runtime);
+        assert_eq!(r.total_stake, quarter);
+        assert_eq!(r.remaining, &min_collateral - &quarter);
+        join(&mut runtime, 20, &quarter);
+        let r = requirements(&mut runtime);
+        let half = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT / 2);
+        assert_eq!(r.total_stake, half);
+        assert_eq!(r.remaining, &min_collateral - &half);
+
+        // nothing is left once the subnet is registered in the gateway
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            None,
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT + MIN_COLLATERAL_AMOUNT / 2),
+            None,
+            ExitCode::new(0),
+        );
+        join(&mut runtime, 30, &min_collateral);
+        let r = requirements(&mut runtime);
+        assert_eq!(
+            r.total_stake,
+            TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT + MIN_COLLATERAL_AMOUNT / 2)
+        );
+        assert_eq!(r.remaining, TokenAmount::zero());
+    }
+
+    #[test]
+    fn test_activation_epoch() {
+        let mut params = std_construct_param();
+        params.activation_epoch = Some(100);
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        let params = JoinParams {
+            validator_net_addr: Address::new_id(100).to_string(),
+            reward_addr: None,
+        };
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let join = |runtime: &mut MockRuntime, caller: u64| {
+            runtime.set_value(value.clone());
+            runtime.set_balance(value.clone());
+            runtime.set_caller(Cid::default(), Address::new_id(caller));
+            runtime.expect_validate_caller_any();
+            runtime
+                .call::<Actor>(
+                    Method::Join as u64,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                )
+                .unwrap();
+            runtime.verify();
+        };
+
+        // joining before the activation epoch accumulates stake without
+        // registering the subnet, even over the collateral threshold
+        runtime.set_epoch(10);
+        join(&mut runtime, 10);
+        join(&mut runtime, 20);
+        let st: State = runtime.get_state();
+        assert_eq!(st.status, Status::Instantiated);
+        assert_eq!(
+            st.total_stake,
+            TokenAmount::from_atto(2 * MIN_COLLATERAL_AMOUNT)
+        );
+
+        // the first join once the epoch is reached registers all the stake
+        runtime.set_epoch(100);
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            None,
+            TokenAmount::from_atto(3 * MIN_COLLATERAL_AMOUNT),
+            None,
+            ExitCode::new(0),
+        );
+        join(&mut runtime, 30);
+        let st: State = runtime.get_state();
+        assert_eq!(st.status, Status::Active);
+
+        // and the following ones add their stake
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::AddStake as u64,
+            None,
+            value.clone(),
+            None,
+            ExitCode::new(0),
+        );
+        join(&mut runtime, 40);
+    }
+
+    #[test]
+    fn test_activate() {
+        let mut params = std_construct_param();
+        params.activation_epoch = Some(100);
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        let activate = |runtime: &mut MockRuntime, code: ExitCode| {
+            runtime.set_caller(Cid::default(), Address::new_id(1000));
+            runtime.expect_validate_caller_any();
+            if code != ExitCode::OK {
+                expect_abort(code, runtime.call::<Actor>(Method::Activate as u64, None));
+            } else {
+                runtime
+                    .call::<Actor>(Method::Activate as u64, None)
+                    .unwrap();
+            }
+            runtime.verify();
+        };
+
+        // the subnet can't be activated without enough collateral
+        runtime.set_epoch(100);
+        activate(&mut runtime, ExitCode::USR_ILLEGAL_STATE);
+
+        // nor before the activation epoch
+        runtime.set_epoch(10);
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        runtime.set_value(value.clone());
+        runtime.set_balance(value.clone());
+        runtime.set_caller(Cid::default(), Address::new_id(10));
+        runtime.expect_validate_caller_any();
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                IpldBlock::serialize_cbor(&JoinParams {
+                    validator_net_addr: Address::new_id(100).to_string(),
+                    reward_addr: None,
+                })
+                .unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+        activate(&mut runtime, ExitCode::USR_ILLEGAL_STATE);
+        let st: State = runtime.get_state();
+        assert_eq!(st.status, Status::Instantiated);
+
+        // once the epoch is reached anyone can activate it without a new join
+        runtime.set_epoch(100);
+        runtime.set_value(TokenAmount::zero());
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            None,
+            value,
+            None,
+            ExitCode::new(0),
+        );
+        activate(&mut runtime, ExitCode::OK);
+        let st: State = runtime.get_state();
+        assert_eq!(st.status, Status::Active);
+
+        // and it can only be activated once
+        activate(&mut runtime, ExitCode::USR_ILLEGAL_STATE);
+    }
+
+    #[test]
+    fn test_permissioned_join() {
+        let owner = Address::new_id(500);
+        let validator = Address::new_id(10);
+        let mut params = std_construct_param();
+        params.permissioned = true;
+        // permissioned subnets need an owner
+        assert!(params.validate().is_err());
+        params.owner = Some(owner);
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+
+        let join_params = JoinParams {
+            validator_net_addr: Address::new_id(100).to_string(),
+            reward_addr: None,
+        };
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        let join = |runtime: &mut MockRuntime, caller: Address| {
+            runtime.set_value(value.clone());
+            runtime.set_balance(value.clone());
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            let ret = runtime.call::<Actor>(
+                Method::Join as u64,
+                IpldBlock::serialize_cbor(&join_params).unwrap(),
+            );
+            runtime.verify();
+            ret
+        };
+        let manage = |runtime: &mut MockRuntime, caller: Address, method: Method| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_addr(vec![owner]);
+            let ret = runtime.call::<Actor>(
+                method as u64,
+                IpldBlock::serialize_cbor(&validator).unwrap(),
+            );
+            runtime.verify();
+            ret
+        };
+
+        // unapproved addresses can't join
+        expect_abort(ExitCode::USR_FORBIDDEN, join(&mut runtime, validator));
+
+        // only the owner approves validators
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            manage(&mut runtime, validator, Method::ApproveValidator),
+        );
+        manage(&mut runtime, owner, Method::ApproveValidator).unwrap();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            manage(&mut runtime, owner, Method::ApproveValidator),
+        );
+
+        // and approved ones join as usual
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            None,
+            value.clone(),
+            None,
+            ExitCode::new(0),
+        );
+        join(&mut runtime, validator).unwrap();
+        let st: State = runtime.get_state();
+        assert!(st.is_validator(&validator));
+
+        // once revoked the address can't add more stake
+        manage(&mut runtime, owner, Method::RevokeValidator).unwrap();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            manage(&mut runtime, owner, Method::RevokeValidator),
+        );
+        expect_abort(ExitCode::USR_FORBIDDEN, join(&mut runtime, validator));
+
+        // permissionless subnets accept anyone
+        let mut runtime = construct_runtime();
+        runtime.expect_send(
+            Address::new_id(IPC_GATEWAY_ADDR),
+            ipc_gateway::Method::Register as u64,
+            None,
+            value.clone(),
+            None,
+            ExitCode::new(0),
+        );
+        join(&mut runtime, validator).unwrap();
+    }
+
+    #[test]
+    fn test_reward_interval() {
+        let atto = |v: u64| TokenAmount::from_atto(v);
+        let mut params = std_construct_param();
+        params.reward_interval_epochs = 10;
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+        let validators = [Address::new_id(10), Address::new_id(20)];
+        join_miners(&mut runtime, &validators);
+
+        let schedule = |runtime: &mut MockRuntime| -> RewardSchedule {
+            runtime.set_caller(Cid::default(), Address::new_id(1000));
+            runtime.expect_validate_caller_any();
+            let ret = runtime
+                .call::<Actor>(Method::GetRewardSchedule as u64, None)
+                .unwrap();
+            runtime.verify();
+            deserialize_block(ret).unwrap()
+        };
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+        let reward = |runtime: &mut MockRuntime, epoch: i64, paid: Option<u64>| {
+            runtime.set_epoch(epoch);
+            runtime.set_value(atto(10));
+            runtime.set_balance(atto(100));
+            runtime.set_caller(Cid::default(), gateway);
+            runtime.expect_validate_caller_addr(vec![gateway]);
+            if let Some(paid) = paid {
+                for v in validators {
+                    runtime.expect_send(v, METHOD_SEND, None, atto(paid), None, ExitCode::OK);
+                }
+            }
+            runtime.call::<Actor>(Method::Reward as u64, None).unwrap();
+            runtime.verify();
+        };
+
+        // the first rewards are distributed right away
+        reward(&mut runtime, 5, Some(5));
+        assert_eq!(
+            schedule(&mut runtime),
+            RewardSchedule {
+                interval_epochs: 10,
+                next_distribution_epoch: 15,
+                accumulated: TokenAmount::zero(),
+            }
+        );
+
+        // rewards received before the interval elapses are accumulated
+        reward(&mut runtime, 8, None);
+        reward(&mut runtime, 14, None);
+        assert_eq!(schedule(&mut runtime).accumulated, atto(20));
+
+        // and distributed along with the ones received on schedule
+        reward(&mut runtime, 15, Some(15));
+        assert_eq!(
+            schedule(&mut runtime),
+            RewardSchedule {
+                interval_epochs: 10,
+                next_distribution_epoch: 25,
+                accumulated: TokenAmount::zero(),
+            }
+        );
+
+        // only the system actor can change the interval
+        let set_interval = |runtime: &mut MockRuntime, caller: Address, interval: i64| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_addr(vec![SYSTEM_ACTOR_ADDR]);
+            let ret = runtime.call::<Actor>(
+                Method::SetRewardInterval as u64,
+                IpldBlock::serialize_cbor(&interval).unwrap(),
+            );
+            runtime.verify();
+            ret
+        };
+        expect_abort(
+            ExitCode::USR_FORBIDDEN,
+            set_interval(&mut runtime, validators[0], 0),
+        );
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            set_interval(&mut runtime, SYSTEM_ACTOR_ADDR, -1),
+        );
+        set_interval(&mut runtime, SYSTEM_ACTOR_ADDR, 0).unwrap();
+        reward(&mut runtime, 16, Some(5));
+    }
+
+    #[test]
+    fn test_unassigned_rewards() {
+        let atto = |v: u64| TokenAmount::from_atto(v);
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+        let reward = |runtime: &mut MockRuntime, epoch: i64| {
+            runtime.set_epoch(epoch);
+            runtime.set_value(atto(10));
+            runtime.set_balance(atto(100));
+            runtime.set_caller(Cid::default(), gateway);
+            runtime.expect_validate_caller_addr(vec![gateway]);
+            let ret = runtime.call::<Actor>(Method::Reward as u64, None);
+            runtime.verify();
+            ret
+        };
+
+        // by default rewards are rejected while there are no validators
+        let mut runtime = construct_runtime();
+        expect_abort(ExitCode::USR_ILLEGAL_STATE, reward(&mut runtime, 5));
+
+        // or accumulated for the first distribution if configured
+        let mut params = std_construct_param();
+        params.accumulate_unassigned_rewards = true;
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+        reward(&mut runtime, 5).unwrap();
+        reward(&mut runtime, 6).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.accumulated_rewards, atto(20));
+        assert_eq!(st.last_reward_epoch, None);
+
+        let validators = [Address::new_id(10), Address::new_id(20)];
+        join_miners(&mut runtime, &validators);
+        for v in validators {
+            runtime.expect_send(v, METHOD_SEND, None, atto(15), None, ExitCode::OK);
+        }
+        reward(&mut runtime, 7).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(st.accumulated_rewards, TokenAmount::zero());
+        assert_eq!(st.last_reward_epoch, Some(7));
+    }
+
+    #[test]
+    fn test_hybrid_quorum() {
+        let atto = |v: u64| TokenAmount::from_atto(v);
+        let whale = Address::new_id(10);
+        let validators = [whale, Address::new_id(20), Address::new_id(30)];
+        let stakes = [
+            10 * MIN_COLLATERAL_AMOUNT,
+            MIN_COLLATERAL_AMOUNT,
+            MIN_COLLATERAL_AMOUNT,
+        ];
+
+        let quorum = |policy: QuorumPolicy, voters: &[Address]| -> bool {
+            let mut params = std_construct_param();
+            params.quorum_policy = policy;
+            let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+            for (i, (v, stake)) in validators.iter().zip(stakes).enumerate() {
+                runtime.set_value(atto(stake));
+                runtime.set_caller(Cid::default(), *v);
+                runtime.expect_validate_caller_any();
+                let method = if i == 0 {
+                    ipc_gateway::Method::Register as u64
+                } else {
+                    ipc_gateway::Method::AddStake as u64
+                };
+                runtime.expect_send(
+                    Address::new_id(IPC_GATEWAY_ADDR),
+                    method,
+                    None,
+                    atto(stake),
+                    None,
+                    ExitCode::OK,
+                );
+                runtime
+                    .call::<Actor>(
+                        Method::Join as u64,
+                        IpldBlock::serialize_cbor(&JoinParams {
+                            validator_net_addr: v.to_string(),
+                            reward_addr: None,
+                        })
+                        .unwrap(),
+                    )
+                    .unwrap();
+                runtime.verify();
+            }
+            let st: State = runtime.get_state();
+            st.has_majority_vote(
+                runtime.store(),
+                &Votes {
+                    validators: voters.to_vec(),
+                },
+                10,
+            )
+            .unwrap()
+        };
+
+        // the whale alone passes the stake quorum but not the count
+        assert!(quorum(QuorumPolicy::Stake, &validators[..1]));
+        assert!(!quorum(QuorumPolicy::StakeAndCount, &validators[..1]));
+        // the rest of validators pass the count but not the stake quorum
+        assert!(!quorum(QuorumPolicy::Stake, &validators[1..]));
+        assert!(!quorum(QuorumPolicy::StakeAndCount, &validators[1..]));
+        // both need to pass under the hybrid rule
+        assert!(quorum(QuorumPolicy::StakeAndCount, &validators[..2]));
+        assert!(quorum(QuorumPolicy::StakeAndCount, &validators));
+    }
+
+    #[test]
+    fn test_fees_received() {
+        let atto = |v: u64| TokenAmount::from_atto(v);
+        let mut params = std_construct_param();
+        params.reward_policy = RewardPolicy::StakeWeighted;
+        let mut runtime = construct_runtime_with_params(Address::new_id(1), params);
+        let validators = [Address::new_id(10), Address::new_id(20)];
+        join_miners(&mut runtime, &validators);
+
+        let received = |runtime: &mut MockRuntime, addr: Address| -> TokenAmount {
+            runtime.set_caller(Cid::default(), Address::new_id(1000));
+            runtime.expect_validate_caller_any();
+            let ret = runtime
+                .call::<Actor>(
+                    Method::GetFeesReceived as u64,
+                    IpldBlock::serialize_cbor(&addr).unwrap(),
+                )
+                .unwrap();
+            runtime.verify();
+            deserialize_block(ret).unwrap()
+        };
+        for v in validators {
+            assert_eq!(received(&mut runtime, v), TokenAmount::zero());
+        }
+
+        // every distribution adds the share of each validator
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+        let st: State = runtime.get_state();
+        let stakes = validators
+            .iter()
+            .map(|v| st.get_stake(runtime.store(), v).unwrap().unwrap())
+            .collect::<Vec<_>>();
+        let shares = RewardPolicy::StakeWeighted.split(&atto(9), &stakes);
+        for _ in 0..2 {
+            runtime.set_value(atto(9));
+            runtime.set_balance(atto(9));
+            runtime.set_caller(Cid::default(), gateway);
+            runtime.expect_validate_caller_addr(vec![gateway]);
+            for (v, share) in validators.iter().zip(shares.iter()) {
+                runtime.expect_send(*v, METHOD_SEND, None, share.clone(), None, ExitCode::OK);
+            }
+            runtime.call::<Actor>(Method::Reward as u64, None).unwrap();
+            runtime.verify();
+        }
+        for (v, share) in validators.iter().zip(shares.iter()) {
+            assert_eq!(received(&mut runtime, *v), share.clone() * 2);
+        }
+    }
+
+    #[test]
+    fn test_reward_addr() {
+        let atto = |v: u64| TokenAmount::from_atto(v);
+        let mut runtime = construct_runtime();
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+        let validators = [Address::new_id(10), Address::new_id(20)];
+        join_miners(&mut runtime, &validators);
+
+        let set_reward_addr = |runtime: &mut MockRuntime, caller: Address, addr: Address| {
+            runtime.set_caller(Cid::default(), caller);
+            runtime.expect_validate_caller_any();
+            let ret = runtime.call::<Actor>(
+                Method::SetRewardAddr as u64,
+                IpldBlock::serialize_cbor(&addr).unwrap(),
+            );
+            runtime.verify();
+            ret
+        };
+
+        // only stakers can set a reward address
+        expect_abort(
+            ExitCode::USR_ILLEGAL_STATE,
+            set_reward_addr(&mut runtime, Address::new_id(30), Address::new_id(130)),
+        );
+        set_reward_addr(&mut runtime, validators[1], Address::new_id(120)).unwrap();
+
+        // the reward address can also be set when joining
+        let newcomer = Address::new_id(30);
+        runtime.set_value(atto(MIN_COLLATERAL_AMOUNT));
+        runtime.set_caller(Cid::default(), newcomer);
+        runtime.expect_validate_caller_any();
+        runtime.expect_send(
+            gateway,
+            ipc_gateway::Method::AddStake as u64,
+            None,
+            atto(MIN_COLLATERAL_AMOUNT),
+            None,
+            ExitCode::OK,
+        );
+        runtime
+            .call::<Actor>(
+                Method::Join as u64,
+                IpldBlock::serialize_cbor(&JoinParams {
+                    validator_net_addr: newcomer.to_string(),
+                    reward_addr: Some(Address::new_id(130)),
+                })
+                .unwrap(),
+            )
+            .unwrap();
+        runtime.verify();
+
+        // rewards go to the reward addresses
+        let reward = |runtime: &mut MockRuntime, recipients: &[Address]| {
+            runtime.set_value(atto(9));
+            runtime.set_balance(atto(9));
+            runtime.set_caller(Cid::default(), gateway);
+            runtime.expect_validate_caller_addr(vec![gateway]);
+            for r in recipients {
+                runtime.expect_send(*r, METHOD_SEND, None, atto(3), None, ExitCode::OK);
+            }
+            runtime.call::<Actor>(Method::Reward as u64, None).unwrap();
+            runtime.verify();
+        };
+        reward(
+            &mut runtime,
+            &[validators[0], Address::new_id(120), Address::new_id(130)],
+        );
+
+        // while the stake remains tied to the staking address
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_stake(runtime.store(), &validators[1]).unwrap(),
+            Some(atto(MIN_COLLATERAL_AMOUNT))
+        );
+        assert_eq!(
+            st.get_stake(runtime.store(), &Address::new_id(120))
+                .unwrap(),
+            None
+        );
+        assert!(st.is_validator(&validators[1]));
+
+        // setting the staking address back restores the default
+        set_reward_addr(&mut runtime, validators[1], validators[1]).unwrap();
+        let st: State = runtime.get_state();
+        assert_eq!(
+            st.get_reward_addr(runtime.store(), &validators[1]).unwrap(),
+            validators[1]
+        );
+        reward(
+            &mut runtime,
+            &[validators[0], validators[1], Address::new_id(130)],
+        );
+    }
+
+    #[test]
+    fn test_estimate_checkpoint_size() {
+        let mut runtime = construct_runtime_with_receiver(Address::new_id(9999));
+        let shid =
+            SubnetID::new_from_parent(&SubnetID::from_str("/root").unwrap(), Address::new_id(9999));
+
+        let estimate = |runtime: &mut MockRuntime, ch: &Checkpoint| -> CheckpointSize {
+            runtime.set_caller(Cid::default(), Address::new_id(1000));
+            runtime.expect_validate_caller_any();
+            let ret = runtime
+                .call::<Actor>(
+                    Method::EstimateCheckpointSize as u64,
+                    IpldBlock::serialize_cbor(ch).unwrap(),
+                )
+                .unwrap();
+            runtime.verify();
+            deserialize_block(ret).unwrap()
+        };
+        let actual_size =
+            |ch: &Checkpoint| IpldBlock::serialize_cbor(ch).unwrap().unwrap().data.len() as u64;
+
+        // checkpoint without cross-messages
+        let mut ch = Checkpoint::new(shid.clone(), 10);
+        let size = estimate(&mut runtime, &ch);
+        assert_eq!(size.bytes, actual_size(&ch));
+        assert_eq!(size.num_msgs, 0);
+
+        // cross-messages and signatures grow the estimate
+        ch.set_cross_msgs(CrossMsgMeta {
+            value: TokenAmount::from_atto(100),
+            num_msgs: 3,
+            ..Default::default()
+        });
+        ch.set_signature(vec![1; 64]);
+        let bigger = estimate(&mut runtime, &ch);
+        assert_eq!(bigger.bytes, actual_size(&ch));
+        assert!(bigger.bytes > size.bytes);
+        assert_eq!(bigger.num_msgs, 3);
+    }
+
+    #[test]
+    fn test_submit_checkpoint_foreign_source() {
+        let test_actor_address = Address::new_id(9999);
+        let mut runtime = construct_runtime_with_receiver(test_actor_address);
+        let miner = Address::new_id(10);
+        join_miners(&mut runtime, &[miner]);
+
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let foreign = SubnetID::new_from_parent(&root_subnet, Address::new_id(1234));
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+        let mut ch = Checkpoint::new(foreign, 10);
+        ch.set_signature(sig);
+
+        // rejected before verifying the signature or sending it to the gateway
+        runtime.set_caller(Cid::default(), miner);
+        runtime.expect_validate_caller_any();
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            runtime.call::<Actor>(
+                Method::SubmitCheckpoint as u64,
+                IpldBlock::serialize_cbor(&ch).unwrap(),
+            ),
+        );
+        runtime.verify();
+    }
+
+    #[test]
+    fn test_stale_votes_cleanup() {
+        let test_actor_address = Address::new_id(9999);
+        let root_subnet = SubnetID::from_str("/root").unwrap();
+        let subnet = SubnetID::new_from_parent(&root_subnet, test_actor_address);
+        let sig = RawBytes::serialize(Signature::new_secp256k1(vec![1, 2, 3, 4]))
+            .unwrap()
+            .bytes()
+            .to_vec();
+        let mut past = Checkpoint::new(subnet.clone(), 10);
+        past.set_signature(sig.clone());
+        let mut current = Checkpoint::new(subnet, 20);
+        current.set_signature(sig);
+
+        for retention in [0, 1] {
+            let mut params = std_construct_param();
+            params.vote_retention_windows = retention;
+            let mut runtime = construct_runtime_with_params(test_actor_address, params);
+            let miners = [
+                Address::new_id(10),
+                Address::new_id(20),
+                Address::new_id(30),
+            ];
+            join_miners(&mut runtime, &miners);
+
+            // the checkpoint of a past window doesn't get enough votes
+            send_checkpoint(&mut runtime, miners[0], &past, false).unwrap();
+            let st: State = runtime.get_state();
+            assert!(st
+                .get_votes(runtime.store(), &past.cid())
+                .unwrap()
+                .is_some());
+
+            // and its votes are cleared once the checkpoint of a newer
+            // window is submitted, if the cleanup is enabled.
+            send_checkpoint(&mut runtime, miners[1], &current, false).unwrap();
+            let st: State = runtime.get_state();
+            let past_votes = st.get_votes(runtime.store(), &past.cid()).unwrap();
+            assert_eq!(past_votes.is_none(), retention > 0);
+            let votes = st.get_votes(runtime.store(), &current.cid()).unwrap();
+            assert_eq!(votes.unwrap().validators, vec![miners[1]]);
+        }
+    }
+
+    #[test]
+    fn test_distribute_rewards() {
+        let mut runtime = construct_runtime();
+        let gateway = Address::new_id(IPC_GATEWAY_ADDR);
+
+        let validators = [Address::new_id(10), Address::new_id(20)];
+        join_miners(&mut runtime, &validators);
+
+        let rewards = vec![
+            RewardEntry {
+                addr: validators[0],
+                amount: TokenAmount::from_atto(3),
+            },
+            RewardEntry {
+                addr: validators[1],
+                amount: TokenAmount::from_atto(1),
+            },
+        ];
+        let call = |runtime: &mut MockRuntime, rewards: Vec<RewardEntry>, value: u64| {
+            runtime.set_value(TokenAmount::from_atto(value));
+            runtime.set_caller(Cid::default(), gateway);
+            runtime.expect_validate_caller_addr(vec![gateway]);
+            runtime.call::<Actor>(
+                Method::DistributeRewards as u64,
+                IpldBlock::serialize_cbor(&DistributeRewardsParams { rewards }).unwrap(),
+            )
+        };
+
+        // the value received must match the manifest
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            call(&mut runtime, rewards.clone(), 5),
+        );
+        runtime.verify();
+
+        // all the beneficiaries must be validators
+        let mut non_validator = rewards.clone();
+        non_validator[1].addr = Address::new_id(30);
+        expect_abort(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            call(&mut runtime, non_validator, 4),
+        );
+        runtime.verify();
+
+        runtime.set_balance(TokenAmount::from_atto(4));
+        for r in rewards.iter() {
+            runtime.expect_send(
+                r.addr,
+                METHOD_SEND,
+                None,
+                r.amount.clone(),
+                None,
+                ExitCode::new(0),
+            );
+        }
+        call(&mut runtime, rewards.clone(), 4).unwrap();
+        runtime.verify();
+
+        // the rewards of jailed validators are accumulated for the next distribution
+        let mut st: State = runtime.get_state();
+        st.jailed.push(validators[1]);
+        runtime.replace_state(&st);
+        runtime.set_balance(TokenAmount::from_atto(4));
+        runtime.expect_send(
+            validators[0],
+            METHOD_SEND,
+            None,
+            TokenAmount::from_atto(3),
+            None,
+            ExitCode::new(0),
+        );
+        call(&mut runtime, rewards, 4).unwrap();
+        runtime.verify();
+        let st: State = runtime.get_state();
+        assert_eq!(st.accumulated_rewards, TokenAmount::from_atto(1));
+    }
+
+    /// Layout of the checkpoints committed by unversioned subnet actors.
+    #[derive(Serialize)]
+    struct CheckpointV1 {
+        data: CheckDataV1,
+        #[serde(with = "serde_bytes")]
+        sig: Vec<u8>,
+    }
+
+    #[derive(Serialize)]
+    struct CheckDataV1 {
+        source: SubnetID,
+        #[serde(with = "serde_bytes")]
+        tip_set: Vec<u8>,
+        epoch: ChainEpoch,
+        prev_check: TCid<TLink<Checkpoint>>,
+        children: Vec<ChildCheck>,
+        cross_msgs: Option<CrossMsgMetaV1>,
+    }
+
+    #[derive(Serialize)]
+    struct CrossMsgMetaV1 {
+        msgs_cid: TCid<TLink<CrossMsgs>>,
+        nonce: u64,
+        value: TokenAmount,
+        fee: TokenAmount,
+    }
+
+    #[test]
+    fn test_migrate_state() {
+        let mut runtime = construct_runtime();
+        let st: State = runtime.get_state();
+        assert_eq!(st.version, STATE_VERSION);
+
+        let migrate = |runtime: &mut MockRuntime, code: ExitCode| {
+            runtime.set_caller(Cid::default(), Address::new_id(10));
+            runtime.expect_validate_caller_any();
+            let res = runtime.call::<Actor>(Method::MigrateState as u64, None);
+            if code == ExitCode::OK {
+                res.unwrap();
+            } else {
+                expect_abort(code, res);
+            }
+            runtime.verify();
+        };
+
+        // a checkpoint committed before versioning
+        let shid = SubnetID::new_from_parent(&st.parent_id, runtime.receiver);
+        let committed = CheckpointV1 {
+            data: CheckDataV1 {
+                source: shid.clone(),
+                tip_set: Vec::new(),
+                epoch: 20,
+                prev_check: TCid::default(),
+                children: Vec::new(),
+                cross_msgs: Some(CrossMsgMetaV1 {
+                    msgs_cid: TCid::default(),
+                    nonce: 0,
+                    value: TokenAmount::from_atto(5),
+                    fee: TokenAmount::from_atto(5),
+                }),
+            },
+            sig: vec![1, 2, 3],
+        };
+        let committed_cid = Cid::new_v1(
+            fvm_ipld_encoding::DAG_CBOR,
+            Code::Blake2b256.digest(&to_vec(&committed.data).unwrap()),
+        );
+        let mut checkpoints = make_empty_map::<_, CheckpointV1>(runtime.store(), HAMT_BIT_WIDTH);
+        checkpoints
+            .set(20_i64.to_ne_bytes().to_vec().into(), committed)
+            .unwrap();
+
+        // unversioned states are migrated to the current layout
+        let v1 = StateV1 {
+            name: st.name.clone(),
+            parent_id: st.parent_id.clone(),
+            ipc_gateway_addr: st.ipc_gateway_addr,
+            consensus: ConsensusType::Dummy,
+            min_validator_stake: st.min_validator_stake.clone(),
+            total_stake: TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+            stake: st.stake.clone(),
+            status: Status::Active,
+            genesis: vec![1, 2, 3],
+            finality_threshold: 7,
+            check_period: 20,
+            checkpoints: TCid::from(checkpoints.flush().unwrap()),
+            window_checks: st.window_checks.clone(),
+            validator_set: vec![],
+            min_validators: 1,
+        };
+        runtime.state = Some(runtime.store().put_cbor(&v1, Code::Blake2b256).unwrap());
+        migrate(&mut runtime, ExitCode::OK);
+
+        let st: State = runtime.get_state();
+        assert_eq!(st.version, STATE_VERSION);
+        assert_eq!(st.name, v1.name);
+        assert_eq!(st.total_stake, v1.total_stake);
+        assert_eq!(st.status, Status::Active);
+        assert_eq!(st.genesis, v1.genesis);
+        assert_eq!(st.finality_threshold, 7);
+        assert_eq!(st.check_period, 20);
+        assert_eq!(st.min_validators, 1);
+        assert_eq!(st.min_checkpoint_gap, 0);
+        assert_eq!(st.pending_window_start, None);
+        assert_eq!(st.net_addr_validation, NetAddrValidation::Disabled);
+
+        // the committed checkpoint is still loaded, with the same cid
+        let checkpoints = st
+            .get_checkpoints_in_range(runtime.store(), 0, 40, MAX_CHECKPOINTS_PER_QUERY)
+            .unwrap();
+        assert_eq!(checkpoints.len(), 1);
+        assert_eq!(checkpoints[0].cid(), committed_cid);
+        assert_eq!(checkpoints[0].data.applied_topdown_nonce, 0);
+        assert!(checkpoints[0].rewards().is_empty());
+        assert!(st
+            .is_checkpoint_committed(runtime.store(), 20, &committed_cid)
+            .unwrap());
+
+        // migrating an up to date state is a no-op
+        let root = runtime.state.unwrap();
+        migrate(&mut runtime, ExitCode::OK);
+        assert_eq!(runtime.state.unwrap(), root);
+
+        // states in version 2 are migrated keeping the fields they already had
+        let v2 = StateV2 {
+            version: 2,
+            name: v1.name.clone(),
+            parent_id: v1.parent_id.clone(),
+            ipc_gateway_addr: v1.ipc_gateway_addr,
+            consensus: ConsensusType::Dummy,
+            min_validator_stake: v1.min_validator_stake.clone(),
+            total_stake: v1.total_stake.clone(),
+            stake: v1.stake.clone(),
+            status: Status::Active,
+            genesis: v1.genesis.clone(),
+            finality_threshold: 7,
+            check_period: 20,
+            min_checkpoint_gap: 3,
+            pending_window_start: Some(40),
+            net_addr_validation: NetAddrValidation::Multiaddr,
+            checkpoints: v1.checkpoints.clone(),
+            window_checks: v1.window_checks.clone(),
+            validator_set: vec![],
+            min_validators: 1,
+        };
+        runtime.state = Some(runtime.store().put_cbor(&v2, Code::Blake2b256).unwrap());
+        migrate(&mut runtime, ExitCode::OK);
+
+        let mut st: State = runtime.get_state();
+        assert_eq!(st.version, STATE_VERSION);
+        assert_eq!(st.min_checkpoint_gap, 3);
+        assert_eq!(st.pending_window_start, Some(40));
+        assert_eq!(st.net_addr_validation, NetAddrValidation::Multiaddr);
+        assert_eq!(st.checkpoint_deposit, TokenAmount::zero());
+        assert!(st
+            .is_checkpoint_committed(runtime.store(), 20, &committed_cid)
+            .unwrap());
+
+        // unknown future versions are rejected
+        st.version = STATE_VERSION + 1;
+        runtime.replace_state(&st);
+        migrate(&mut runtime, ExitCode::USR_ILLEGAL_STATE);
+    }
+
+    fn join_miners(runtime: &mut MockRuntime, miners: &[Address]) {
+        let params = JoinParams {
+            validator_net_addr: Address::new_id(100).to_string(),
+            reward_addr: None,
+        };
+        let value = TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT);
+        for (i, caller) in miners.iter().enumerate() {
+            runtime.set_value(value.clone());
+            runtime.set_balance(TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT));
+            runtime.set_caller(Cid::default(), caller.clone());
+            runtime.expect_validate_caller_any();
+            let method = if i == 0 {
+                ipc_gateway::Method::Register as u64
+            } else {
+                ipc_gateway::Method::AddStake as u64
+            };
+            runtime.expect_send(
+                Address::new_id(IPC_GATEWAY_ADDR),
+                method,
+                None,
+                TokenAmount::from_atto(MIN_COLLATERAL_AMOUNT),
+                None,
+                ExitCode::new(0),
+            );
+            runtime
+                .call::<Actor>(
+                    Method::Join as u64,
+                    IpldBlock::serialize_cbor(&params).unwrap(),
+                )
+                .unwrap();
+        }
+    }
+
     fn send_checkpoint(
         runtime: &mut MockRuntime,
         sender: Address,